@@ -6,10 +6,14 @@
     holding buffers for the duration of a data transfer."
 )]
 
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
 use esp_hal::clock::CpuClock;
 use esp_hal::gpio::{Level, Output, OutputConfig};
-use esp_hal::main;
-use esp_hal::time::{Duration, Instant};
+use esp_hal::timer::timg::TimerGroup;
+
+use buzzer_effects::buzzer::{self, ActiveBuzzer};
+use buzzer_effects::effect;
 
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
@@ -20,24 +24,41 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
 esp_bootloader_esp_idf::esp_app_desc!();
 
-#[main]
-fn main() -> ! {
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
     // generator version: 1.0.0
 
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
 
-    let mut buzzer = Output::new(peripherals.GPIO33, Level::Low, OutputConfig::default());
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    let buzzer = Output::new(peripherals.GPIO33, Level::Low, OutputConfig::default());
+    // An active buzzer has a fixed tone, so every effect plays as an on/off
+    // pattern. With a passive buzzer use `PassiveBuzzer` and `passive_buzzer_task`.
+    spawner.must_spawn(buzzer::active_buzzer_task(ActiveBuzzer(buzzer)));
+
+    let effects = [
+        effect::CONFIRM,
+        effect::SUCCESS,
+        effect::ERROR,
+        effect::CHIRP,
+        effect::WARBLE,
+        effect::SIREN_UP,
+        effect::SIREN_DOWN,
+    ];
 
     loop {
-        buzzer.set_high();
-        blocking_delay(Duration::from_millis(500));
-        buzzer.set_low();
-        blocking_delay(Duration::from_millis(500));
-    }
-}
+        for effect in effects {
+            buzzer::play(effect);
+            Timer::after(Duration::from_secs(3)).await;
+        }
 
-fn blocking_delay(duration: Duration) {
-    let delay_start = Instant::now();
-    while delay_start.elapsed() < duration {}
+        // Endless effects play until they are stopped or replaced
+        buzzer::play(effect::ALARM);
+        Timer::after(Duration::from_secs(5)).await;
+        buzzer::stop();
+        Timer::after(Duration::from_secs(2)).await;
+    }
 }
@@ -6,10 +6,17 @@
     holding buffers for the duration of a data transfer."
 )]
 
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Instant, Timer};
 use esp_hal::clock::CpuClock;
 use esp_hal::gpio::{Input, InputConfig, Level, Output, OutputConfig, Pull};
-use esp_hal::main;
-use esp_hal::time::{Duration, Instant};
+use esp_hal::timer::timg::TimerGroup;
+
+use buzzer_effects::buzzer::{self, ActiveBuzzer};
+use buzzer_effects::effect;
+
+// Keep sounding this long after the last motion
+const ALARM_HOLD: Duration = Duration::from_secs(5);
 
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
@@ -20,34 +27,44 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
 esp_bootloader_esp_idf::esp_app_desc!();
 
-#[main]
-fn main() -> ! {
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
     // generator version: 1.0.0
 
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
 
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
     let sensor_pin = Input::new(
         peripherals.GPIO33,
         InputConfig::default().with_pull(Pull::Down),
     );
 
-    let mut buzzer_pin = Output::new(peripherals.GPIO18, Level::Low, OutputConfig::default());
+    let buzzer_pin = Output::new(peripherals.GPIO18, Level::Low, OutputConfig::default());
     let mut led = Output::new(peripherals.GPIO2, Level::Low, OutputConfig::default());
 
+    // The buzzer task plays the alarm while this loop keeps watching the sensor
+    spawner.must_spawn(buzzer::active_buzzer_task(ActiveBuzzer(buzzer_pin)));
+
+    // Let the PIR sensor settle, then confirm the alarm is armed
+    Timer::after(Duration::from_secs(2)).await;
+    buzzer::play(effect::CONFIRM);
+
+    let mut last_motion = None;
     loop {
         if sensor_pin.is_high() {
-            buzzer_pin.set_high();
-            led.set_high();
-            blocking_delay(Duration::from_millis(100));
-            buzzer_pin.set_low();
+            if last_motion.is_none() {
+                buzzer::play(effect::ALARM);
+                led.set_high();
+            }
+            last_motion = Some(Instant::now());
+        } else if last_motion.is_some_and(|at| at.elapsed() > ALARM_HOLD) {
+            buzzer::stop();
             led.set_low();
+            last_motion = None;
         }
-        blocking_delay(Duration::from_millis(100));
+        Timer::after(Duration::from_millis(100)).await;
     }
 }
-
-fn blocking_delay(duration: Duration) {
-    let delay_start = Instant::now();
-    while delay_start.elapsed() < duration {}
-}
@@ -0,0 +1,121 @@
+//! Playing [`Effect`]s on a buzzer from an embassy task.
+//!
+//! Call [`play`] from anywhere to start an effect; it replaces whatever is
+//! playing. [`stop`] silences the buzzer.
+//!
+//! Active buzzers have their own oscillator and only need a GPIO switched on
+//! and off ([`ActiveBuzzer`]). Passive buzzers need a square wave at the
+//! note's frequency, which comes from a [`Tone`] ([`PassiveBuzzer`]).
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer as Delay};
+use esp_hal::gpio::Output;
+use esp_hal::ledc::HighSpeed;
+use esp_hal::ledc::channel::{Channel, ChannelHW};
+use esp_hal::ledc::timer::{self, Timer, TimerHW, TimerSpeed};
+
+use crate::effect::Effect;
+use crate::tone::Tone;
+
+// `None` stops the current effect
+static REQUESTS: Signal<CriticalSectionRawMutex, Option<Effect<'static>>> = Signal::new();
+
+/// Start `effect` on the buzzer task, replacing the one that is playing
+pub fn play(effect: Effect<'static>) {
+    REQUESTS.signal(Some(effect));
+}
+
+pub fn stop() {
+    REQUESTS.signal(None);
+}
+
+pub trait Buzzer {
+    /// Can only be switched on and off; effects are played as on/off patterns
+    const FIXED_TONE: bool;
+
+    /// Sound `hz` (any value for fixed-tone buzzers), or be silent on `None`
+    fn sound(&mut self, hz: Option<u32>);
+}
+
+pub struct ActiveBuzzer<'a>(pub Output<'a>);
+
+impl Buzzer for ActiveBuzzer<'_> {
+    const FIXED_TONE: bool = true;
+
+    fn sound(&mut self, hz: Option<u32>) {
+        if hz.is_some() {
+            self.0.set_high();
+        } else {
+            self.0.set_low();
+        }
+    }
+}
+
+/// A passive buzzer driven by a [`Tone`]
+pub struct PassiveBuzzer<'a, S: TimerSpeed>(Tone<'a, S>);
+
+impl<'a, S: TimerSpeed> PassiveBuzzer<'a, S>
+where
+    Timer<'a, S>: TimerHW<S>,
+    Channel<'a, S>: ChannelHW,
+{
+    /// `channel` must already be configured with `timer`
+    pub fn new(timer: &'a Timer<'a, S>, channel: Channel<'a, S>) -> Result<Self, timer::Error> {
+        Tone::new(timer, channel).map(Self)
+    }
+}
+
+impl<'a, S: TimerSpeed> Buzzer for PassiveBuzzer<'a, S>
+where
+    Timer<'a, S>: TimerHW<S>,
+    Channel<'a, S>: ChannelHW,
+{
+    const FIXED_TONE: bool = false;
+
+    fn sound(&mut self, hz: Option<u32>) {
+        match hz {
+            Some(hz) if self.0.set_frequency(hz as f32) => self.0.set_volume(100),
+            _ => self.0.mute(),
+        }
+    }
+}
+
+/// Play `effect` to the end
+pub async fn play_effect<B: Buzzer>(buzzer: &mut B, effect: &Effect<'_>) {
+    for step in effect.steps(B::FIXED_TONE) {
+        buzzer.sound(step.hz);
+        Delay::after(Duration::from_millis(step.ms as u64)).await;
+    }
+    buzzer.sound(None);
+}
+
+/// Play requested effects forever
+pub async fn run<B: Buzzer>(buzzer: &mut B) -> ! {
+    let mut request = None;
+    loop {
+        let effect = match request.take() {
+            Some(effect) => effect,
+            None => REQUESTS.wait().await,
+        };
+        let Some(effect) = effect else {
+            continue;
+        };
+        // a new request cuts the effect short
+        if let Either::Second(next) = select(play_effect(buzzer, &effect), REQUESTS.wait()).await {
+            buzzer.sound(None);
+            request = Some(next);
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn active_buzzer_task(mut buzzer: ActiveBuzzer<'static>) {
+    run(&mut buzzer).await
+}
+
+#[embassy_executor::task]
+pub async fn passive_buzzer_task(mut buzzer: PassiveBuzzer<'static, HighSpeed>) {
+    run(&mut buzzer).await
+}
@@ -0,0 +1,355 @@
+//! Sound effects described as data.
+//!
+//! An [`Effect`] is a list of [`Segment`]s (tones, sweeps, warbles and
+//! rests) played a number of times. [`Effect::steps`] turns it into plain
+//! "this frequency for that long" steps; for active buzzers, which only
+//! have one tone, the same effect becomes an on/off pattern instead.
+//!
+//! ```ignore
+//! const DOORBELL: Effect = Effect::new(&[
+//!     Segment::Tone { hz: 660, ms: 300 },
+//!     Segment::Tone { hz: 520, ms: 500 },
+//! ]);
+//! ```
+
+/// Sweeps are played as a staircase of tones this long
+pub const SWEEP_STEP_MS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Tone {
+        hz: u32,
+        ms: u32,
+    },
+    /// Glide from one frequency to another; a steady tone on active buzzers
+    Sweep {
+        from_hz: u32,
+        to_hz: u32,
+        ms: u32,
+    },
+    /// Alternate between two frequencies every half `period_ms`; beeps at
+    /// the same rate on active buzzers
+    Warble {
+        low_hz: u32,
+        high_hz: u32,
+        period_ms: u32,
+        ms: u32,
+    },
+    Rest {
+        ms: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    Times(u16),
+    /// Until another effect replaces it or it is stopped
+    Forever,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Effect<'a> {
+    pub segments: &'a [Segment],
+    pub repeat: Repeat,
+}
+
+/// Play `hz` (or be silent on `None`) for `ms`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub hz: Option<u32>,
+    pub ms: u32,
+}
+
+impl<'a> Effect<'a> {
+    /// Play `segments` once
+    pub const fn new(segments: &'a [Segment]) -> Self {
+        Self {
+            segments,
+            repeat: Repeat::Times(1),
+        }
+    }
+
+    pub const fn repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Steps for a tone buzzer, or on/off steps when `fixed_tone` is set.
+    /// On/off steps use `Some(0)` for "on".
+    pub fn steps(&self, fixed_tone: bool) -> Steps<'a> {
+        Steps {
+            segments: self.segments,
+            repeat: self.repeat,
+            fixed_tone,
+            round: 0,
+            segment: 0,
+            elapsed: 0,
+            sounded: false,
+        }
+    }
+
+    /// Length of one round, or `None` when it repeats forever
+    pub fn duration_ms(&self) -> Option<u32> {
+        let round: u32 = self.segments.iter().map(|s| s.duration_ms()).sum();
+        match self.repeat {
+            Repeat::Times(n) => Some(round * n as u32),
+            Repeat::Forever => None,
+        }
+    }
+}
+
+impl Segment {
+    pub const fn duration_ms(&self) -> u32 {
+        match *self {
+            Segment::Tone { ms, .. }
+            | Segment::Sweep { ms, .. }
+            | Segment::Warble { ms, .. }
+            | Segment::Rest { ms } => ms,
+        }
+    }
+
+    /// The step starting `elapsed` ms into the segment
+    fn step(&self, elapsed: u32, fixed_tone: bool) -> Step {
+        let remaining = self.duration_ms() - elapsed;
+        let on = |hz| if fixed_tone { Some(0) } else { Some(hz) };
+        match *self {
+            Segment::Tone { hz, .. } => Step {
+                hz: on(hz),
+                ms: remaining,
+            },
+            Segment::Rest { .. } => Step {
+                hz: None,
+                ms: remaining,
+            },
+            Segment::Sweep { .. } if fixed_tone => Step {
+                hz: Some(0),
+                ms: remaining,
+            },
+            Segment::Sweep { from_hz, to_hz, ms } => {
+                let span = to_hz as i64 - from_hz as i64;
+                let hz = from_hz as i64 + span * elapsed as i64 / ms as i64;
+                Step {
+                    hz: Some(hz as u32),
+                    ms: remaining.min(SWEEP_STEP_MS),
+                }
+            }
+            Segment::Warble {
+                low_hz,
+                high_hz,
+                period_ms,
+                ..
+            } => {
+                let half = (period_ms / 2).max(1);
+                let high = (elapsed / half).is_multiple_of(2);
+                let hz = match (high, fixed_tone) {
+                    (true, _) => on(high_hz),
+                    (false, true) => None,
+                    (false, false) => Some(low_hz),
+                };
+                Step {
+                    hz,
+                    ms: remaining.min(half - elapsed % half),
+                }
+            }
+        }
+    }
+}
+
+pub struct Steps<'a> {
+    segments: &'a [Segment],
+    repeat: Repeat,
+    fixed_tone: bool,
+    round: u16,
+    segment: usize,
+    /// Time already played of the current segment
+    elapsed: u32,
+    /// Something was produced this round, so repeating won't spin forever
+    sounded: bool,
+}
+
+impl Iterator for Steps<'_> {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Step> {
+        loop {
+            let Some(segment) = self.segments.get(self.segment) else {
+                self.round = self.round.saturating_add(1);
+                let more = match self.repeat {
+                    Repeat::Times(n) => self.round < n,
+                    Repeat::Forever => self.sounded,
+                };
+                if !more {
+                    return None;
+                }
+                self.segment = 0;
+                self.sounded = false;
+                continue;
+            };
+            if self.elapsed >= segment.duration_ms() {
+                self.segment += 1;
+                self.elapsed = 0;
+                continue;
+            }
+            let step = segment.step(self.elapsed, self.fixed_tone);
+            self.elapsed += step.ms;
+            self.sounded = true;
+            return Some(step);
+        }
+    }
+}
+
+/// Rising siren, for example when arming
+pub const SIREN_UP: Effect = Effect::new(&[Segment::Sweep {
+    from_hz: 600,
+    to_hz: 1400,
+    ms: 800,
+}]);
+
+pub const SIREN_DOWN: Effect = Effect::new(&[Segment::Sweep {
+    from_hz: 1400,
+    to_hz: 600,
+    ms: 800,
+}]);
+
+/// Police-style up and down siren
+pub const SIREN: Effect = Effect::new(&[
+    Segment::Sweep {
+        from_hz: 600,
+        to_hz: 1400,
+        ms: 1000,
+    },
+    Segment::Sweep {
+        from_hz: 1400,
+        to_hz: 600,
+        ms: 1000,
+    },
+])
+.repeat(Repeat::Forever);
+
+/// Two-tone warble, like an ambulance but faster
+pub const WARBLE: Effect = Effect::new(&[Segment::Warble {
+    low_hz: 900,
+    high_hz: 1200,
+    period_ms: 200,
+    ms: 2000,
+}]);
+
+/// Short bird-like chirp
+pub const CHIRP: Effect = Effect::new(&[
+    Segment::Sweep {
+        from_hz: 2000,
+        to_hz: 4000,
+        ms: 50,
+    },
+    Segment::Rest { ms: 30 },
+]);
+
+/// Three short beeps, for "done" or "accepted"
+pub const CONFIRM: Effect =
+    Effect::new(&[Segment::Tone { hz: 2000, ms: 80 }, Segment::Rest { ms: 70 }])
+        .repeat(Repeat::Times(3));
+
+/// Rising two-note jingle
+pub const SUCCESS: Effect = Effect::new(&[
+    Segment::Tone { hz: 1319, ms: 100 },
+    Segment::Rest { ms: 20 },
+    Segment::Tone { hz: 1760, ms: 200 },
+]);
+
+/// Low, harsh buzz
+pub const ERROR: Effect = Effect::new(&[
+    Segment::Tone { hz: 300, ms: 150 },
+    Segment::Rest { ms: 50 },
+    Segment::Tone { hz: 200, ms: 400 },
+]);
+
+/// Loud beeps and a fast sweep, until stopped
+pub const ALARM: Effect = Effect::new(&[
+    Segment::Tone { hz: 2500, ms: 150 },
+    Segment::Rest { ms: 50 },
+    Segment::Tone { hz: 2500, ms: 150 },
+    Segment::Rest { ms: 50 },
+    Segment::Sweep {
+        from_hz: 1000,
+        to_hz: 3000,
+        ms: 400,
+    },
+    Segment::Rest { ms: 100 },
+])
+.repeat(Repeat::Forever);
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn step(hz: Option<u32>, ms: u32) -> Step {
+        Step { hz, ms }
+    }
+
+    #[test]
+    fn confirm_beeps_three_times() {
+        let steps: Vec<_> = CONFIRM.steps(false).collect();
+        assert_eq!(steps.len(), 6);
+        assert_eq!(steps[0], step(Some(2000), 80));
+        assert_eq!(steps[1], step(None, 70));
+        assert_eq!(steps[4], step(Some(2000), 80));
+        assert_eq!(CONFIRM.duration_ms(), Some(450));
+    }
+
+    #[test]
+    fn sweep_is_a_staircase() {
+        let steps: Vec<_> = SIREN_UP.steps(false).collect();
+        assert_eq!(steps.len(), 80);
+        assert_eq!(steps[0], step(Some(600), 10));
+        assert_eq!(steps[40], step(Some(1000), 10));
+        assert_eq!(steps[79], step(Some(1390), 10));
+        let down: Vec<_> = SIREN_DOWN.steps(false).collect();
+        assert_eq!(down[0], step(Some(1400), 10));
+        assert_eq!(down[79], step(Some(610), 10));
+    }
+
+    #[test]
+    fn warble_alternates() {
+        let steps: Vec<_> = WARBLE.steps(false).take(3).collect();
+        assert_eq!(
+            steps,
+            [
+                step(Some(1200), 100),
+                step(Some(900), 100),
+                step(Some(1200), 100)
+            ]
+        );
+        assert_eq!(WARBLE.steps(false).map(|s| s.ms).sum::<u32>(), 2000);
+    }
+
+    #[test]
+    fn fixed_tone_becomes_on_off() {
+        let steps: Vec<_> = SIREN_UP.steps(true).collect();
+        assert_eq!(steps, [step(Some(0), 800)]);
+
+        let steps: Vec<_> = WARBLE.steps(true).take(2).collect();
+        assert_eq!(steps, [step(Some(0), 100), step(None, 100)]);
+
+        let steps: Vec<_> = ERROR.steps(true).collect();
+        assert_eq!(
+            steps,
+            [step(Some(0), 150), step(None, 50), step(Some(0), 400)]
+        );
+    }
+
+    #[test]
+    fn forever_keeps_going() {
+        assert_eq!(ALARM.duration_ms(), None);
+        let first_round = ALARM.segments.len() + 40 - 1;
+        let steps: Vec<_> = ALARM.steps(false).take(first_round + 1).collect();
+        assert_eq!(steps[first_round], step(Some(2500), 150));
+
+        // nothing to play must not loop forever
+        let empty = Effect::new(&[Segment::Rest { ms: 0 }]).repeat(Repeat::Forever);
+        assert_eq!(empty.steps(false).next(), None);
+        assert_eq!(Effect::new(&[]).steps(false).next(), None);
+    }
+}
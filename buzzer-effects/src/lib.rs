@@ -0,0 +1,10 @@
+//! Tones and sound effects for buzzers, shared by the buzzer projects.
+//!
+//! - [`tone`]: a square wave on one LEDC timer and channel
+//! - [`effect`]: sound effects described as data
+//! - [`buzzer`]: an embassy task playing effects on an active or passive
+//!   buzzer
+#![no_std]
+pub mod buzzer;
+pub mod effect;
+pub mod tone;
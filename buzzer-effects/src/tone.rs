@@ -0,0 +1,64 @@
+//! Square wave tone on one LEDC timer and channel.
+//!
+//! The timer and channel are configured once; changing the note only
+//! rewrites the timer divider, so there is no glitch from reconfiguring.
+
+use esp_hal::ledc::channel::{Channel, ChannelHW};
+use esp_hal::ledc::timer::{self, Timer, TimerHW, TimerIFace, TimerSpeed};
+
+// Highest divider the LEDC timer accepts (10.8 fixed point)
+const MAX_DIVISOR: u64 = 0x3FFFF;
+
+pub struct Tone<'a, S: TimerSpeed> {
+    timer: &'a Timer<'a, S>,
+    channel: Channel<'a, S>,
+    max_duty: u32,
+}
+
+impl<'a, S: TimerSpeed> Tone<'a, S>
+where
+    Timer<'a, S>: TimerHW<S>,
+    Channel<'a, S>: ChannelHW,
+{
+    /// `channel` must already be configured with `timer`; starts silent
+    pub fn new(timer: &'a Timer<'a, S>, channel: Channel<'a, S>) -> Result<Self, timer::Error> {
+        let duty = timer.duty().ok_or(timer::Error::FrequencyUnset)?;
+        let tone = Self {
+            timer,
+            channel,
+            max_duty: 1 << duty as u32,
+        };
+        tone.mute();
+        Ok(tone)
+    }
+
+    /// Retune the timer; false if the LEDC can't reach `frequency`
+    pub fn set_frequency(&self, frequency: f32) -> bool {
+        let Some(source) = self.timer.freq() else {
+            return false;
+        };
+        // Same calculation as `TimerIFace::configure`, without needing `&mut`
+        let divisor =
+            ((source.as_hz() as u64) << 8) / (frequency as u64).max(1) / self.max_duty as u64;
+        if !(256..=MAX_DIVISOR).contains(&divisor) {
+            return false;
+        }
+        self.timer.configure_hw(divisor as u32);
+        self.timer.update_hw();
+        true
+    }
+
+    /// Loudness in percent; 100 is a 50% duty square wave
+    pub fn set_volume(&self, volume: u8) {
+        let duty = self.max_duty / 2 * volume.min(100) as u32 / 100;
+        self.channel.set_duty_hw(duty);
+    }
+
+    pub fn mute(&self) {
+        self.channel.set_duty_hw(0);
+    }
+
+    pub fn release(self) -> Channel<'a, S> {
+        self.channel
+    }
+}
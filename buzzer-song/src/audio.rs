@@ -0,0 +1,157 @@
+//! WAV playback as an embassy task.
+//!
+//! Works like the tone [`player`](crate::player): other tasks send
+//! [`Command`]s to [`COMMANDS`] and wait on [`EVENTS`]. Samples are read from
+//! the file a block at a time, resampled to the rate of the [`Output`] (the
+//! built-in DAC in `dac.rs` or an I2S amplifier in `i2s.rs`), scaled by the
+//! volume and queued.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+
+use crate::midi::ByteSource;
+use crate::resample::{Resampler, apply_volume};
+use crate::wav::{self, Wav};
+
+// Samples handled per round; commands are checked between rounds
+const BLOCK_LEN: usize = 256;
+
+pub static COMMANDS: Channel<CriticalSectionRawMutex, Command, 4> = Channel::new();
+pub static EVENTS: Signal<CriticalSectionRawMutex, Event> = Signal::new();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Play a file from the root directory, replacing the one that is playing
+    Play(&'static str),
+    Stop,
+    /// Loudness in percent of the recorded level
+    SetVolume(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// The file played to the end
+    Finished,
+    /// Playback was stopped or replaced before the end
+    Stopped,
+    /// The file does not exist
+    NotFound,
+    /// The file is not a WAV file we can play, or reading it failed
+    Failed(wav::Error<()>),
+}
+
+/// Somewhere to send samples
+pub trait Output {
+    /// Samples per second the output plays; files are resampled to this
+    fn sample_rate(&self) -> u32;
+
+    /// Queue `samples`, waiting while the output buffer is full
+    fn write(&mut self, samples: &[i16]) -> impl Future<Output = ()>;
+
+    /// Wait until the queued samples have played, then go quiet
+    fn drain(&mut self) -> impl Future<Output = ()>;
+
+    /// Go quiet now, dropping queued samples
+    fn stop(&mut self);
+}
+
+// How a file ended, and the command that ended it if it must still run
+type Outcome = (Event, Option<Command>);
+
+pub struct Player<O> {
+    output: O,
+    volume: u8,
+}
+
+impl<O: Output> Player<O> {
+    pub fn new(output: O) -> Self {
+        Self {
+            output,
+            volume: 100,
+        }
+    }
+
+    /// Wait for commands and play files, forever. `open` looks up a file by
+    /// name, for example in the root directory of the SD card.
+    pub async fn run<S: ByteSource>(&mut self, mut open: impl FnMut(&str) -> Option<S>) -> ! {
+        let mut pending = None;
+        loop {
+            let command = match pending.take() {
+                Some(command) => command,
+                None => COMMANDS.receive().await,
+            };
+            match command {
+                Command::Play(name) => {
+                    let (event, next) = match open(name) {
+                        Some(file) => self.play(file).await,
+                        None => (Event::NotFound, None),
+                    };
+                    EVENTS.signal(event);
+                    pending = next;
+                }
+                Command::SetVolume(volume) => self.volume = volume.min(100),
+                Command::Stop => {}
+            }
+        }
+    }
+
+    async fn play<S: ByteSource>(&mut self, file: S) -> Outcome {
+        let mut wav = match Wav::open(file) {
+            Ok(wav) => wav,
+            Err(e) => return (Event::Failed(without_io(e)), None),
+        };
+        let mut resampler = Resampler::new(wav.format().sample_rate, self.output.sample_rate());
+
+        let mut input = [0i16; BLOCK_LEN];
+        let mut output = [0i16; BLOCK_LEN];
+        // unused part of `input`
+        let (mut start, mut end) = (0, 0);
+        loop {
+            if let Ok(command) = COMMANDS.try_receive() {
+                match command {
+                    Command::SetVolume(volume) => self.volume = volume.min(100),
+                    Command::Stop => {
+                        self.output.stop();
+                        return (Event::Stopped, None);
+                    }
+                    Command::Play(_) => {
+                        self.output.stop();
+                        return (Event::Stopped, Some(command));
+                    }
+                }
+            }
+
+            if start == end {
+                end = match wav.read(&mut input) {
+                    Ok(0) => break,
+                    Ok(len) => len,
+                    Err(e) => {
+                        self.output.stop();
+                        return (Event::Failed(without_io(e)), None);
+                    }
+                };
+                start = 0;
+            }
+
+            let (used, written) = resampler.process(&input[start..end], &mut output);
+            start += used;
+            let samples = &mut output[..written];
+            apply_volume(samples, self.volume);
+            self.output.write(samples).await;
+        }
+
+        self.output.drain().await;
+        (Event::Finished, None)
+    }
+}
+
+// Events are shared between tasks, so the file system error can't be kept
+fn without_io<E>(e: wav::Error<E>) -> wav::Error<()> {
+    match e {
+        wav::Error::Io(_) => wav::Error::Io(()),
+        wav::Error::NotWav => wav::Error::NotWav,
+        wav::Error::Unsupported => wav::Error::Unsupported,
+        wav::Error::Truncated => wav::Error::Truncated,
+    }
+}
@@ -6,13 +6,14 @@
     holding buffers for the duration of a data transfer."
 )]
 
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
 use esp_hal::clock::CpuClock;
-use esp_hal::main;
-use esp_hal::time::{Duration, Instant};
+use esp_hal::timer::timg::TimerGroup;
 
 use buzzer_song::{
-    music::{self, Song},
-    pink_panther,
+    mk_static, pink_panther,
+    player::{self, Command, EVENTS, Player, Tune},
 };
 
 // LEDC
@@ -37,58 +38,74 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
 esp_bootloader_esp_idf::esp_app_desc!();
 
-#[main]
-fn main() -> ! {
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
     // generator version: 1.0.0
 
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
 
-    let mut buzzer = peripherals.GPIO33;
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    let buzzer = peripherals.GPIO33;
 
     let ledc = Ledc::new(peripherals.LEDC);
 
-    let song = Song::new(pink_panther::TEMPO);
-
-    for (note, duration_type) in pink_panther::MELODY {
-        let note_duration = song.calc_note_duration(duration_type) as u64;
-        let pause_duration = note_duration / 10; // 10% of note_duration
-        if note == music::REST {
-            blocking_delay(Duration::from_millis(note_duration));
-            continue;
-        }
-        let freq = Rate::from_hz(note as u32);
-
-        let mut hstimer0 = ledc.timer::<HighSpeed>(timer::Number::Timer0);
-        hstimer0
-            .configure(timer::config::Config {
-                duty: timer::config::Duty::Duty10Bit,
-                clock_source: timer::HSClockSource::APBClk,
-                frequency: freq,
-            })
-            .unwrap();
-
-        let mut channel0 = ledc.channel(channel::Number::Channel0, buzzer.reborrow());
-        channel0
-            .configure(channel::config::Config {
-                timer: &hstimer0,
-                duty_pct: 50,
-                drive_mode: DriveMode::PushPull,
-            })
-            .unwrap();
-
-        blocking_delay(Duration::from_millis(note_duration - pause_duration)); // play 90%
-
-        channel0.set_duty(0).unwrap();
-        blocking_delay(Duration::from_millis(pause_duration)); // Pause for 10%
-    }
+    // The player task keeps the timer for the whole program, so it has to be static.
+    // It only retunes the frequency between notes.
+    let hstimer0 = mk_static!(
+        timer::Timer<'static, HighSpeed>,
+        ledc.timer::<HighSpeed>(timer::Number::Timer0)
+    );
+    hstimer0
+        .configure(timer::config::Config {
+            duty: timer::config::Duty::Duty10Bit,
+            clock_source: timer::HSClockSource::APBClk,
+            frequency: Rate::from_hz(440),
+        })
+        .unwrap();
+    let hstimer0 = &*hstimer0;
+
+    let mut channel0 = ledc.channel(channel::Number::Channel0, buzzer);
+    channel0
+        .configure(channel::config::Config {
+            timer: hstimer0,
+            duty_pct: 0,
+            drive_mode: DriveMode::PushPull,
+        })
+        .unwrap();
+
+    let player = Player::new(hstimer0, channel0).unwrap();
+    spawner.must_spawn(player::player_task(player));
+
+    let melody = Tune::Melody {
+        notes: &pink_panther::MELODY,
+        tempo: pink_panther::TEMPO,
+    };
+    let ringtone = Tune::Rtttl(pink_panther::RTTTL);
 
     loop {
-        blocking_delay(Duration::from_millis(5));
+        player::COMMANDS.send(Command::Play(melody)).await;
+
+        // The main task is free while the song plays
+        Timer::after(Duration::from_secs(5)).await;
+        player::COMMANDS.send(Command::Pause).await;
+        Timer::after(Duration::from_secs(1)).await;
+        player::COMMANDS.send(Command::SetVolume(30)).await;
+        player::COMMANDS.send(Command::Resume).await;
+
+        // Wait for the "finished" event
+        EVENTS.wait().await;
+        player::COMMANDS.send(Command::SetVolume(100)).await;
+        Timer::after(Duration::from_secs(1)).await;
+
+        // Same tune again, parsed from an RTTTL string and a bit faster
+        player::COMMANDS.send(Command::SetTempo(200)).await;
+        player::COMMANDS.send(Command::Play(ringtone)).await;
+        EVENTS.wait().await;
+        player::COMMANDS.send(Command::SetTempo(0)).await;
+
+        Timer::after(Duration::from_secs(3)).await;
     }
 }
-
-fn blocking_delay(duration: Duration) {
-    let delay_start = Instant::now();
-    while delay_start.elapsed() < duration {}
-}
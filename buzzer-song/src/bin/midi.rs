@@ -0,0 +1,146 @@
+#![no_std]
+#![no_main]
+#![deny(
+    clippy::mem_forget,
+    reason = "mem::forget is generally not safe to do with esp_hal types, especially those \
+    holding buffers for the duration of a data transfer."
+)]
+
+use embassy_executor::Spawner;
+use embassy_time::{Delay, Duration, Instant, Timer};
+use embedded_hal_bus::spi::ExclusiveDevice;
+use esp_hal::clock::CpuClock;
+use esp_hal::gpio::{Level, Output, OutputConfig};
+use esp_hal::spi;
+use esp_hal::spi::master::Spi;
+use esp_hal::timer::timg::TimerGroup;
+
+use buzzer_song::{
+    midi::{Monophonic, Smf},
+    mk_static,
+    music::Pitch,
+    player::{self, Command, Player},
+};
+
+// LEDC
+use esp_hal::gpio::DriveMode;
+use esp_hal::ledc::HighSpeed;
+use esp_hal::ledc::timer;
+use esp_hal::{
+    ledc::{
+        Ledc,
+        channel::{self, ChannelIFace},
+        timer::TimerIFace,
+    },
+    time::Rate,
+};
+
+// SD card reader
+use embedded_sdmmc::{SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+
+// File in the root directory of the card; FAT needs an 8.3 name
+const SONG_FILE: &str = "SONG.MID";
+// MIDI channel 1; the highest note held on it is played
+const CHANNEL: u8 = 0;
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+// This creates a default app-descriptor required by the esp-idf bootloader.
+// For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
+esp_bootloader_esp_idf::esp_app_desc!();
+
+/// A dummy timesource, we only read files
+#[derive(Default)]
+pub struct DummyTimesource();
+
+impl TimeSource for DummyTimesource {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 0,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
+    // generator version: 1.0.0
+
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    // Buzzer, driven by the player task
+    let ledc = Ledc::new(peripherals.LEDC);
+    let hstimer0 = mk_static!(
+        timer::Timer<'static, HighSpeed>,
+        ledc.timer::<HighSpeed>(timer::Number::Timer0)
+    );
+    hstimer0
+        .configure(timer::config::Config {
+            duty: timer::config::Duty::Duty10Bit,
+            clock_source: timer::HSClockSource::APBClk,
+            frequency: Rate::from_hz(440),
+        })
+        .unwrap();
+    let hstimer0 = &*hstimer0;
+
+    let mut channel0 = ledc.channel(channel::Number::Channel0, peripherals.GPIO33);
+    channel0
+        .configure(channel::config::Config {
+            timer: hstimer0,
+            duty_pct: 0,
+            drive_mode: DriveMode::PushPull,
+        })
+        .unwrap();
+
+    let player = Player::new(hstimer0, channel0).unwrap();
+    spawner.must_spawn(player::player_task(player));
+
+    // SD card
+    let spi_bus = Spi::new(
+        peripherals.SPI2,
+        spi::master::Config::default()
+            .with_frequency(Rate::from_khz(400))
+            .with_mode(spi::Mode::_0),
+    )
+    .unwrap()
+    .with_sck(peripherals.GPIO18)
+    .with_mosi(peripherals.GPIO23)
+    .with_miso(peripherals.GPIO19)
+    .into_async();
+
+    let sd_cs = Output::new(peripherals.GPIO5, Level::High, OutputConfig::default());
+    let spi_dev = ExclusiveDevice::new(spi_bus, sd_cs, Delay).unwrap();
+    let sdcard = SdCard::new(spi_dev, Delay);
+
+    let volume_mgr = VolumeManager::new(sdcard, DummyTimesource::default());
+    let volume0 = volume_mgr.open_volume(VolumeIdx(0)).unwrap();
+    let root_dir = volume0.open_root_dir().unwrap();
+
+    loop {
+        // The file is read a little at a time while the song plays
+        let song = root_dir
+            .open_file_in_dir(SONG_FILE, embedded_sdmmc::Mode::ReadOnly)
+            .unwrap();
+        let mut melody = Monophonic::new(Smf::open(song).unwrap(), CHANNEL);
+
+        let start = Instant::now();
+        while let Some(change) = melody.next_change().unwrap() {
+            Timer::at(start + Duration::from_millis(change.at_ms as u64)).await;
+            let pitch = change.key.map(|key| Pitch::from_midi(key as i16));
+            player::COMMANDS.send(Command::Tone(pitch)).await;
+        }
+
+        Timer::after(Duration::from_secs(3)).await;
+    }
+}
@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+#![deny(
+    clippy::mem_forget,
+    reason = "mem::forget is generally not safe to do with esp_hal types, especially those \
+    holding buffers for the duration of a data transfer."
+)]
+
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
+use esp_hal::clock::CpuClock;
+use esp_hal::timer::timg::TimerGroup;
+
+use buzzer_song::{mk_static, music::*, ode_to_joy, synth::Synth, tone::Tone};
+
+// LEDC
+use esp_hal::gpio::DriveMode;
+use esp_hal::ledc::HighSpeed;
+use esp_hal::ledc::timer;
+use esp_hal::{
+    ledc::{
+        Ledc,
+        channel::{self, ChannelIFace},
+        timer::TimerIFace,
+    },
+    time::Rate,
+};
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+// This creates a default app-descriptor required by the esp-idf bootloader.
+// For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[esp_rtos::main]
+async fn main(_spawner: Spawner) -> ! {
+    // generator version: 1.0.0
+
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    let ledc = Ledc::new(peripherals.LEDC);
+
+    // One timer per voice, so every buzzer can play its own note
+    let timers = mk_static!(
+        [timer::Timer<'static, HighSpeed>; 4],
+        [
+            ledc.timer::<HighSpeed>(timer::Number::Timer0),
+            ledc.timer::<HighSpeed>(timer::Number::Timer1),
+            ledc.timer::<HighSpeed>(timer::Number::Timer2),
+            ledc.timer::<HighSpeed>(timer::Number::Timer3),
+        ]
+    );
+    for timer in timers.iter_mut() {
+        timer
+            .configure(timer::config::Config {
+                duty: timer::config::Duty::Duty10Bit,
+                clock_source: timer::HSClockSource::APBClk,
+                frequency: Rate::from_hz(440),
+            })
+            .unwrap();
+    }
+    let [t0, t1, t2, t3] = &*timers;
+
+    let channel_config = |timer| channel::config::Config {
+        timer,
+        duty_pct: 0,
+        drive_mode: DriveMode::PushPull,
+    };
+    let mut channel0 = ledc.channel(channel::Number::Channel0, peripherals.GPIO33);
+    channel0.configure(channel_config(t0)).unwrap();
+    let mut channel1 = ledc.channel(channel::Number::Channel1, peripherals.GPIO25);
+    channel1.configure(channel_config(t1)).unwrap();
+    let mut channel2 = ledc.channel(channel::Number::Channel2, peripherals.GPIO26);
+    channel2.configure(channel_config(t2)).unwrap();
+    let mut channel3 = ledc.channel(channel::Number::Channel3, peripherals.GPIO27);
+    channel3.configure(channel_config(t3)).unwrap();
+
+    let mut synth = Synth::new([
+        Tone::new(t0, channel0).unwrap(),
+        Tone::new(t1, channel1).unwrap(),
+        Tone::new(t2, channel2).unwrap(),
+        Tone::new(t3, channel3).unwrap(),
+    ]);
+
+    loop {
+        // Three part arrangement: melody, inner voice and bass
+        synth.play(&ode_to_joy::SCORE).await;
+        Timer::after(Duration::from_secs(1)).await;
+
+        // A C major chord built up one note at a time, then released
+        for pitch in [NOTE_C4, NOTE_E4, NOTE_G4, NOTE_C5] {
+            synth.note_on(pitch);
+            Timer::after(Duration::from_millis(400)).await;
+        }
+        Timer::after(Duration::from_secs(1)).await;
+        synth.all_off();
+
+        Timer::after(Duration::from_secs(3)).await;
+    }
+}
@@ -0,0 +1,163 @@
+#![no_std]
+#![no_main]
+#![deny(
+    clippy::mem_forget,
+    reason = "mem::forget is generally not safe to do with esp_hal types, especially those \
+    holding buffers for the duration of a data transfer."
+)]
+
+use embassy_executor::Spawner;
+use embassy_time::{Delay, Duration, Timer};
+use embedded_hal_bus::spi::ExclusiveDevice;
+use esp_hal::clock::CpuClock;
+use esp_hal::gpio::{Level, Output, OutputConfig};
+use esp_hal::spi;
+use esp_hal::spi::master::Spi;
+use esp_hal::time::Rate;
+use esp_hal::timer::PeriodicTimer;
+use esp_hal::timer::timg::TimerGroup;
+
+use buzzer_song::audio::{self, Command, EVENTS, Player};
+use buzzer_song::dac::DacOutput;
+use buzzer_song::i2s::{self, I2sOutput};
+
+// Audio output
+use esp_hal::analog::dac::Dac;
+use esp_hal::dma_circular_buffers_chunk_size;
+use esp_hal::i2s::master::{Channels, Config, DataFormat, I2s};
+
+// SD card reader
+use embedded_sdmmc::{SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+
+// Play through an I2S amplifier instead of the DAC on GPIO25
+const USE_I2S: bool = false;
+// Files are resampled to this rate
+const SAMPLE_RATE: u32 = 16_000;
+// 128 ms of audio at 16 kHz, in 4 DMA descriptors
+const I2S_BUFFER_LEN: usize = 4096;
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+// This creates a default app-descriptor required by the esp-idf bootloader.
+// For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
+esp_bootloader_esp_idf::esp_app_desc!();
+
+/// A dummy timesource, we only read files
+#[derive(Default)]
+pub struct DummyTimesource();
+
+impl TimeSource for DummyTimesource {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 0,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
+    // generator version: 1.0.0
+
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    // SD card
+    let spi_bus = Spi::new(
+        peripherals.SPI2,
+        spi::master::Config::default()
+            .with_frequency(Rate::from_khz(400))
+            .with_mode(spi::Mode::_0),
+    )
+    .unwrap()
+    .with_sck(peripherals.GPIO18)
+    .with_mosi(peripherals.GPIO23)
+    .with_miso(peripherals.GPIO19)
+    .into_async();
+
+    let sd_cs = Output::new(peripherals.GPIO5, Level::High, OutputConfig::default());
+    let spi_dev = ExclusiveDevice::new(spi_bus, sd_cs, Delay).unwrap();
+    let sdcard = SdCard::new(spi_dev, Delay);
+
+    // The card must be initialized at 400 kHz; audio needs it much faster
+    sdcard.num_bytes().unwrap();
+    sdcard.spi(|dev| {
+        dev.bus_mut()
+            .apply_config(
+                &spi::master::Config::default()
+                    .with_frequency(Rate::from_mhz(20))
+                    .with_mode(spi::Mode::_0),
+            )
+            .unwrap()
+    });
+
+    let volume_mgr = VolumeManager::new(sdcard, DummyTimesource::default());
+    let volume0 = volume_mgr.open_volume(VolumeIdx(0)).unwrap();
+    let root_dir = volume0.open_root_dir().unwrap();
+    let open = |name: &str| {
+        root_dir
+            .open_file_in_dir(name, embedded_sdmmc::Mode::ReadOnly)
+            .ok()
+    };
+
+    spawner.must_spawn(demo());
+
+    // The player runs here, since it borrows the SD card
+    if USE_I2S {
+        let (_, _, buffer, descriptors) = dma_circular_buffers_chunk_size!(0, I2S_BUFFER_LEN, 1024);
+        let i2s = I2s::new(
+            peripherals.I2S0,
+            peripherals.DMA_I2S0,
+            Config::new_tdm_philips()
+                .with_sample_rate(Rate::from_hz(SAMPLE_RATE))
+                .with_data_format(DataFormat::Data16Channel16)
+                .with_channels(Channels::MONO),
+        )
+        .unwrap()
+        .into_async();
+        let tx = i2s
+            .i2s_tx
+            .with_bclk(peripherals.GPIO27)
+            .with_ws(peripherals.GPIO14)
+            .with_dout(peripherals.GPIO26)
+            .build(descriptors);
+        let (output, feeder) = I2sOutput::new(tx, buffer, SAMPLE_RATE).unwrap();
+        spawner.must_spawn(i2s::i2s_task(feeder));
+        Player::new(output).run(open).await
+    } else {
+        let dac = Dac::new(peripherals.DAC1, peripherals.GPIO25);
+        let timg1 = TimerGroup::new(peripherals.TIMG1);
+        let timer = PeriodicTimer::new(timg1.timer0);
+        let output = DacOutput::new(dac, timer, SAMPLE_RATE);
+        Player::new(output).run(open).await
+    }
+}
+
+#[embassy_executor::task]
+async fn demo() {
+    loop {
+        audio::COMMANDS.send(Command::Play("HELLO.WAV")).await;
+        EVENTS.wait().await;
+        Timer::after(Duration::from_secs(1)).await;
+
+        // Quieter, and cut off after 5 seconds
+        audio::COMMANDS.send(Command::SetVolume(50)).await;
+        audio::COMMANDS.send(Command::Play("MUSIC.WAV")).await;
+        Timer::after(Duration::from_secs(5)).await;
+        audio::COMMANDS.send(Command::Stop).await;
+        EVENTS.wait().await;
+        audio::COMMANDS.send(Command::SetVolume(100)).await;
+
+        Timer::after(Duration::from_secs(3)).await;
+    }
+}
@@ -0,0 +1,152 @@
+//! Audio output on the ESP32's built-in 8-bit DAC (GPIO25 or GPIO26).
+//!
+//! The DAC has no DMA in esp-hal, so a hardware timer interrupt writes one
+//! sample per period from a queue that [`DacOutput::write`] fills. Connect
+//! the pin to an amplifier through a capacitor; the DAC idles at half scale.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use esp_hal::Blocking;
+use esp_hal::analog::dac::Dac;
+use esp_hal::handler;
+use esp_hal::peripherals::DAC1;
+use esp_hal::time::Duration;
+use esp_hal::timer::PeriodicTimer;
+
+use crate::audio::Output;
+
+// Samples queued for the interrupt, about 60 ms at 16 kHz
+const QUEUE_LEN: usize = 1024;
+
+// DAC value for silence
+const MIDPOINT: u8 = 128;
+
+struct Queue {
+    samples: [u8; QUEUE_LEN],
+    head: usize,
+    len: usize,
+}
+
+impl Queue {
+    const fn new() -> Self {
+        Self {
+            samples: [MIDPOINT; QUEUE_LEN],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, sample: u8) -> bool {
+        if self.len == QUEUE_LEN {
+            return false;
+        }
+        self.samples[(self.head + self.len) % QUEUE_LEN] = sample;
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let sample = self.samples[self.head];
+        self.head = (self.head + 1) % QUEUE_LEN;
+        self.len -= 1;
+        Some(sample)
+    }
+}
+
+static QUEUE: Mutex<RefCell<Queue>> = Mutex::new(RefCell::new(Queue::new()));
+static DAC: Mutex<RefCell<Option<Dac<'static, DAC1<'static>>>>> = Mutex::new(RefCell::new(None));
+static TIMER: Mutex<RefCell<Option<PeriodicTimer<'static, Blocking>>>> =
+    Mutex::new(RefCell::new(None));
+// Raised by the interrupt when the queue is half empty
+static SPACE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+#[handler]
+fn on_sample() {
+    critical_section::with(|cs| {
+        if let Some(timer) = TIMER.borrow_ref_mut(cs).as_mut() {
+            timer.clear_interrupt();
+        }
+        let mut queue = QUEUE.borrow_ref_mut(cs);
+        // when the queue runs dry the DAC keeps the last value
+        if let (Some(sample), Some(dac)) = (queue.pop(), DAC.borrow_ref_mut(cs).as_mut()) {
+            dac.write(sample);
+        }
+        if queue.len <= QUEUE_LEN / 2 {
+            SPACE.signal(());
+        }
+    });
+}
+
+pub struct DacOutput {
+    sample_rate: u32,
+}
+
+impl DacOutput {
+    /// Start the timer interrupt at `sample_rate` (8000..=32000 is sensible);
+    /// the actual rate is rounded to a whole number of microseconds
+    pub fn new(
+        mut dac: Dac<'static, DAC1<'static>>,
+        mut timer: PeriodicTimer<'static, Blocking>,
+        sample_rate: u32,
+    ) -> Self {
+        let period_us = (1_000_000 / sample_rate.max(1)).max(1);
+        dac.write(MIDPOINT);
+        timer.set_interrupt_handler(on_sample);
+        timer
+            .start(Duration::from_micros(period_us as u64))
+            .unwrap();
+        timer.listen();
+        critical_section::with(|cs| {
+            DAC.borrow_ref_mut(cs).replace(dac);
+            TIMER.borrow_ref_mut(cs).replace(timer);
+        });
+        Self {
+            sample_rate: 1_000_000 / period_us,
+        }
+    }
+}
+
+impl Output for DacOutput {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    async fn write(&mut self, samples: &[i16]) {
+        let mut samples = samples.iter().map(|&s| ((s >> 8) + 128) as u8).peekable();
+        while samples.peek().is_some() {
+            critical_section::with(|cs| {
+                let mut queue = QUEUE.borrow_ref_mut(cs);
+                while let Some(&sample) = samples.peek() {
+                    if !queue.push(sample) {
+                        break;
+                    }
+                    samples.next();
+                }
+            });
+            if samples.peek().is_some() {
+                SPACE.wait().await;
+            }
+        }
+    }
+
+    async fn drain(&mut self) {
+        while critical_section::with(|cs| QUEUE.borrow_ref(cs).len > 0) {
+            SPACE.wait().await;
+        }
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        critical_section::with(|cs| {
+            let mut queue = QUEUE.borrow_ref_mut(cs);
+            *queue = Queue::new();
+            queue.push(MIDPOINT);
+        });
+    }
+}
@@ -0,0 +1,125 @@
+//! Audio output to an I2S amplifier or DAC (MAX98357A, PCM5102, ...).
+//!
+//! The samples play from a circular DMA buffer split over several
+//! descriptors: the DMA plays one part while the next ones are refilled, so
+//! nothing has to keep time on the CPU. esp-hal can't stop such a transfer
+//! and start it again, and gives up on it for good once the CPU falls a
+//! whole buffer behind, so it runs for as long as the program does:
+//! [`i2s_task`] keeps the buffer topped up from a queue that
+//! [`I2sOutput::write`] fills, and with silence while the queue is empty.
+//!
+//! Configure the I2S peripheral for mono 16-bit data at the rate passed to
+//! [`I2sOutput::new`].
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pipe::Pipe;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, with_timeout};
+use esp_hal::Async;
+use esp_hal::i2s::master::{Error, I2sTx, asynch::I2sWriteDmaTransferAsync};
+
+use crate::audio::Output;
+
+// Bytes queued for the DMA, 64 ms at 16 kHz
+const QUEUE_LEN: usize = 2048;
+
+// Most bytes moved into the DMA buffer at a time
+const CHUNK_LEN: usize = 256;
+
+static QUEUE: Pipe<CriticalSectionRawMutex, QUEUE_LEN> = Pipe::new();
+// Raised by the feeder when it ran out of samples and started on silence
+static DRAINED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// The running DMA transfer, for [`i2s_task`]
+pub struct Feeder {
+    transfer: I2sWriteDmaTransferAsync<'static, &'static mut [u8]>,
+    buffer_len: usize,
+    bytes_per_second: u32,
+}
+
+impl Feeder {
+    /// Move queued samples into the DMA buffer as it plays. Waits for
+    /// samples as long as the DMA has more than a chunk left to play, then
+    /// fills in silence.
+    pub async fn run(&mut self) -> Result<(), Error> {
+        let mut chunk = [0u8; CHUNK_LEN];
+        loop {
+            let space = self.transfer.available().await?;
+            let len = space.min(CHUNK_LEN);
+            let left = (self.buffer_len - space).saturating_sub(CHUNK_LEN);
+            let patience =
+                Duration::from_micros(left as u64 * 1_000_000 / self.bytes_per_second as u64);
+            let len = match with_timeout(patience, QUEUE.read(&mut chunk[..len])).await {
+                Ok(read) => read,
+                Err(_) => {
+                    chunk[..len].fill(0);
+                    DRAINED.signal(());
+                    len
+                }
+            };
+            self.transfer.push(&chunk[..len]).await?;
+        }
+    }
+}
+
+/// Feed the I2S DMA forever
+#[embassy_executor::task]
+pub async fn i2s_task(mut feeder: Feeder) {
+    // only fails when the task was starved of CPU for a whole buffer, and
+    // then the transfer can't be restarted
+    feeder.run().await.unwrap();
+}
+
+pub struct I2sOutput {
+    sample_rate: u32,
+}
+
+impl I2sOutput {
+    /// Start playing `buffer`, which must be the buffer of the descriptors
+    /// `tx` was built with, for example from
+    /// `dma_circular_buffers_chunk_size!(0, len, chunk)`. It starts out
+    /// silent; spawn [`i2s_task`] with the [`Feeder`] to keep it playing.
+    pub fn new(
+        tx: I2sTx<'static, Async>,
+        buffer: &'static mut [u8],
+        sample_rate: u32,
+    ) -> Result<(Self, Feeder), Error> {
+        buffer.fill(0);
+        let buffer_len = buffer.len();
+        let feeder = Feeder {
+            transfer: tx.write_dma_circular_async(buffer)?,
+            buffer_len,
+            bytes_per_second: sample_rate.max(1) * 2,
+        };
+        Ok((Self { sample_rate }, feeder))
+    }
+}
+
+impl Output for I2sOutput {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    async fn write(&mut self, samples: &[i16]) {
+        let mut bytes = [0u8; 64];
+        for chunk in samples.chunks(bytes.len() / 2) {
+            for (pair, sample) in bytes.chunks_exact_mut(2).zip(chunk) {
+                pair.copy_from_slice(&sample.to_le_bytes());
+            }
+            QUEUE.write_all(&bytes[..chunk.len() * 2]).await;
+        }
+    }
+
+    async fn drain(&mut self) {
+        // Silence starts once the queue is empty and the DMA has at most a
+        // chunk of samples left
+        DRAINED.reset();
+        DRAINED.wait().await;
+    }
+
+    /// Queued samples are dropped; what is already in the DMA buffer still
+    /// plays
+    fn stop(&mut self) {
+        QUEUE.clear();
+    }
+}
@@ -1,3 +1,27 @@
 #![no_std]
-pub mod music;
+pub mod audio;
+pub mod dac;
+pub mod i2s;
+pub mod ode_to_joy;
 pub mod pink_panther;
+pub mod player;
+pub mod synth;
+
+pub use buzzer_effects::tone;
+pub use song_common::midi;
+pub use song_common::music;
+pub use song_common::resample;
+pub use song_common::rtttl;
+pub use song_common::score;
+pub use song_common::voices;
+pub use song_common::wav;
+
+#[macro_export]
+macro_rules! mk_static {
+    ($t:ty,$val:expr) => {{
+        static STATIC_CELL: static_cell::StaticCell<$t> = static_cell::StaticCell::new();
+        #[deny(unused_attributes)]
+        let x = STATIC_CELL.uninit().write(($val));
+        x
+    }};
+}
@@ -0,0 +1,80 @@
+use crate::music::Base::*;
+use crate::music::NoteLength::*;
+use crate::music::*;
+use crate::score::Score;
+
+pub const TEMPO: u16 = 120;
+
+// Ode to Joy (Beethoven, Symphony No. 9), first eight bars in C major
+// arranged for three buzzers: melody, inner voice and bass
+pub const MELODY: [Note; 30] = [
+    // bar 1
+    Note::tone(NOTE_E5, Plain(Quarter)),
+    Note::tone(NOTE_E5, Plain(Quarter)),
+    Note::tone(NOTE_F5, Plain(Quarter)),
+    Note::tone(NOTE_G5, Plain(Quarter)),
+    // bar 2
+    Note::tone(NOTE_G5, Plain(Quarter)),
+    Note::tone(NOTE_F5, Plain(Quarter)),
+    Note::tone(NOTE_E5, Plain(Quarter)),
+    Note::tone(NOTE_D5, Plain(Quarter)),
+    // bar 3
+    Note::tone(NOTE_C5, Plain(Quarter)),
+    Note::tone(NOTE_C5, Plain(Quarter)),
+    Note::tone(NOTE_D5, Plain(Quarter)),
+    Note::tone(NOTE_E5, Plain(Quarter)),
+    // bar 4
+    Note::tone(NOTE_E5, Dotted(Quarter)),
+    Note::tone(NOTE_D5, Plain(Eighth)),
+    Note::tone(NOTE_D5, Plain(Half)),
+    // bar 5
+    Note::tone(NOTE_E5, Plain(Quarter)),
+    Note::tone(NOTE_E5, Plain(Quarter)),
+    Note::tone(NOTE_F5, Plain(Quarter)),
+    Note::tone(NOTE_G5, Plain(Quarter)),
+    // bar 6
+    Note::tone(NOTE_G5, Plain(Quarter)),
+    Note::tone(NOTE_F5, Plain(Quarter)),
+    Note::tone(NOTE_E5, Plain(Quarter)),
+    Note::tone(NOTE_D5, Plain(Quarter)),
+    // bar 7
+    Note::tone(NOTE_C5, Plain(Quarter)),
+    Note::tone(NOTE_C5, Plain(Quarter)),
+    Note::tone(NOTE_D5, Plain(Quarter)),
+    Note::tone(NOTE_E5, Plain(Quarter)),
+    // bar 8
+    Note::tone(NOTE_D5, Dotted(Quarter)),
+    Note::tone(NOTE_C5, Plain(Eighth)),
+    Note::tone(NOTE_C5, Plain(Half)),
+];
+
+pub const INNER: [Note; 14] = [
+    Note::tone(NOTE_G4, Plain(Whole)),
+    Note::tone(NOTE_B4, Plain(Half)),
+    Note::tone(NOTE_G4, Plain(Half)),
+    Note::tone(NOTE_E4, Plain(Half)),
+    Note::tone(NOTE_G4, Plain(Half)),
+    Note::tone(NOTE_G4, Plain(Half)),
+    Note::tone(NOTE_B4, Plain(Half)),
+    Note::tone(NOTE_G4, Plain(Whole)),
+    Note::tone(NOTE_B4, Plain(Half)),
+    Note::tone(NOTE_G4, Plain(Half)),
+    Note::tone(NOTE_E4, Plain(Half)),
+    Note::tone(NOTE_G4, Plain(Half)),
+    Note::tone(NOTE_B4, Plain(Half)),
+    Note::tone(NOTE_E4, Plain(Half)),
+];
+
+pub const BASS: [Note; 9] = [
+    Note::tone(NOTE_C3, Plain(Whole)),
+    Note::tone(NOTE_G2, Plain(Whole)),
+    Note::tone(NOTE_C3, Plain(Whole)),
+    Note::tone(NOTE_G2, Plain(Whole)),
+    Note::tone(NOTE_C3, Plain(Whole)),
+    Note::tone(NOTE_G2, Plain(Whole)),
+    Note::tone(NOTE_C3, Plain(Whole)),
+    Note::tone(NOTE_G2, Plain(Half)),
+    Note::tone(NOTE_C3, Plain(Half)),
+];
+
+pub const SCORE: Score<'static, 3> = Score::new(TEMPO, [&MELODY, &INNER, &BASS]);
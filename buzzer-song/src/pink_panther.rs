@@ -1,3 +1,5 @@
+use crate::music::Base::*;
+use crate::music::NoteLength::*;
 use crate::music::*;
 
 // change this to make the song slower or faster
@@ -6,93 +8,96 @@ pub const TEMPO: u16 = 120;
 // Pink Panther theme
 // Score available at https://musescore.com/benedictsong/the-pink-panther
 // Theme by Masato Nakamura, arranged by Teddy Mason
-pub const MELODY: [(f64, i16); 88] = [
-    (REST, 2),
-    (REST, 4),
-    (REST, 8),
-    (NOTE_DS4, 8),
-    (NOTE_E4, -4),
-    (REST, 8),
-    (NOTE_FS4, 8),
-    (NOTE_G4, -4),
-    (REST, 8),
-    (NOTE_DS4, 8),
-    (NOTE_E4, -8),
-    (NOTE_FS4, 8),
-    (NOTE_G4, -8),
-    (NOTE_C5, 8),
-    (NOTE_B4, -8),
-    (NOTE_E4, 8),
-    (NOTE_G4, -8),
-    (NOTE_B4, 8),
-    (NOTE_AS4, 2),
-    (NOTE_A4, -16),
-    (NOTE_G4, -16),
-    (NOTE_E4, -16),
-    (NOTE_D4, -16),
-    (NOTE_E4, 2),
-    (REST, 4),
-    (REST, 8),
-    (NOTE_DS4, 4),
-    (NOTE_E4, -4),
-    (REST, 8),
-    (NOTE_FS4, 8),
-    (NOTE_G4, -4),
-    (REST, 8),
-    (NOTE_DS4, 8),
-    (NOTE_E4, -8),
-    (NOTE_FS4, 8),
-    (NOTE_G4, -8),
-    (NOTE_C5, 8),
-    (NOTE_B4, -8),
-    (NOTE_G4, 8),
-    (NOTE_B4, -8),
-    (NOTE_E5, 8),
-    (NOTE_DS5, 1),
-    (NOTE_D5, 2),
-    (REST, 4),
-    (REST, 8),
-    (NOTE_DS4, 8),
-    (NOTE_E4, -4),
-    (REST, 8),
-    (NOTE_FS4, 8),
-    (NOTE_G4, -4),
-    (REST, 8),
-    (NOTE_DS4, 8),
-    (NOTE_E4, -8),
-    (NOTE_FS4, 8),
-    (NOTE_G4, -8),
-    (NOTE_C5, 8),
-    (NOTE_B4, -8),
-    (NOTE_E4, 8),
-    (NOTE_G4, -8),
-    (NOTE_B4, 8),
-    (NOTE_AS4, 2),
-    (NOTE_A4, -16),
-    (NOTE_G4, -16),
-    (NOTE_E4, -16),
-    (NOTE_D4, -16),
-    (NOTE_E4, -4),
-    (REST, 4),
-    (REST, 4),
-    (NOTE_E5, -8),
-    (NOTE_D5, 8),
-    (NOTE_B4, -8),
-    (NOTE_A4, 8),
-    (NOTE_G4, -8),
-    (NOTE_E4, -8),
-    (NOTE_AS4, 16),
-    (NOTE_A4, -8),
-    (NOTE_AS4, 16),
-    (NOTE_A4, -8),
-    (NOTE_AS4, 16),
-    (NOTE_A4, -8),
-    (NOTE_AS4, 16),
-    (NOTE_A4, -8),
-    (NOTE_G4, -16),
-    (NOTE_E4, -16),
-    (NOTE_D4, -16),
-    (NOTE_E4, 16),
-    (NOTE_E4, 16),
-    (NOTE_E4, 2),
+pub const MELODY: [Note; 88] = [
+    Note::rest(Plain(Half)),
+    Note::rest(Plain(Quarter)),
+    Note::rest(Plain(Eighth)),
+    Note::tone(NOTE_DS4, Plain(Eighth)),
+    Note::tone(NOTE_E4, Dotted(Quarter)),
+    Note::rest(Plain(Eighth)),
+    Note::tone(NOTE_FS4, Plain(Eighth)),
+    Note::tone(NOTE_G4, Dotted(Quarter)),
+    Note::rest(Plain(Eighth)),
+    Note::tone(NOTE_DS4, Plain(Eighth)),
+    Note::tone(NOTE_E4, Dotted(Eighth)),
+    Note::tone(NOTE_FS4, Plain(Eighth)),
+    Note::tone(NOTE_G4, Dotted(Eighth)),
+    Note::tone(NOTE_C5, Plain(Eighth)),
+    Note::tone(NOTE_B4, Dotted(Eighth)),
+    Note::tone(NOTE_E4, Plain(Eighth)),
+    Note::tone(NOTE_G4, Dotted(Eighth)),
+    Note::tone(NOTE_B4, Plain(Eighth)),
+    Note::tone(NOTE_AS4, Plain(Half)),
+    Note::tone(NOTE_A4, Dotted(Sixteenth)),
+    Note::tone(NOTE_G4, Dotted(Sixteenth)),
+    Note::tone(NOTE_E4, Dotted(Sixteenth)),
+    Note::tone(NOTE_D4, Dotted(Sixteenth)),
+    Note::tone(NOTE_E4, Plain(Half)),
+    Note::rest(Plain(Quarter)),
+    Note::rest(Plain(Eighth)),
+    Note::tone(NOTE_DS4, Plain(Quarter)),
+    Note::tone(NOTE_E4, Dotted(Quarter)),
+    Note::rest(Plain(Eighth)),
+    Note::tone(NOTE_FS4, Plain(Eighth)),
+    Note::tone(NOTE_G4, Dotted(Quarter)),
+    Note::rest(Plain(Eighth)),
+    Note::tone(NOTE_DS4, Plain(Eighth)),
+    Note::tone(NOTE_E4, Dotted(Eighth)),
+    Note::tone(NOTE_FS4, Plain(Eighth)),
+    Note::tone(NOTE_G4, Dotted(Eighth)),
+    Note::tone(NOTE_C5, Plain(Eighth)),
+    Note::tone(NOTE_B4, Dotted(Eighth)),
+    Note::tone(NOTE_G4, Plain(Eighth)),
+    Note::tone(NOTE_B4, Dotted(Eighth)),
+    Note::tone(NOTE_E5, Plain(Eighth)),
+    Note::tone(NOTE_DS5, Plain(Whole)),
+    Note::tone(NOTE_D5, Plain(Half)),
+    Note::rest(Plain(Quarter)),
+    Note::rest(Plain(Eighth)),
+    Note::tone(NOTE_DS4, Plain(Eighth)),
+    Note::tone(NOTE_E4, Dotted(Quarter)),
+    Note::rest(Plain(Eighth)),
+    Note::tone(NOTE_FS4, Plain(Eighth)),
+    Note::tone(NOTE_G4, Dotted(Quarter)),
+    Note::rest(Plain(Eighth)),
+    Note::tone(NOTE_DS4, Plain(Eighth)),
+    Note::tone(NOTE_E4, Dotted(Eighth)),
+    Note::tone(NOTE_FS4, Plain(Eighth)),
+    Note::tone(NOTE_G4, Dotted(Eighth)),
+    Note::tone(NOTE_C5, Plain(Eighth)),
+    Note::tone(NOTE_B4, Dotted(Eighth)),
+    Note::tone(NOTE_E4, Plain(Eighth)),
+    Note::tone(NOTE_G4, Dotted(Eighth)),
+    Note::tone(NOTE_B4, Plain(Eighth)),
+    Note::tone(NOTE_AS4, Plain(Half)),
+    Note::tone(NOTE_A4, Dotted(Sixteenth)),
+    Note::tone(NOTE_G4, Dotted(Sixteenth)),
+    Note::tone(NOTE_E4, Dotted(Sixteenth)),
+    Note::tone(NOTE_D4, Dotted(Sixteenth)),
+    Note::tone(NOTE_E4, Dotted(Quarter)),
+    Note::rest(Plain(Quarter)),
+    Note::rest(Plain(Quarter)),
+    Note::tone(NOTE_E5, Dotted(Eighth)),
+    Note::tone(NOTE_D5, Plain(Eighth)),
+    Note::tone(NOTE_B4, Dotted(Eighth)),
+    Note::tone(NOTE_A4, Plain(Eighth)),
+    Note::tone(NOTE_G4, Dotted(Eighth)),
+    Note::tone(NOTE_E4, Dotted(Eighth)),
+    Note::tone(NOTE_AS4, Plain(Sixteenth)),
+    Note::tone(NOTE_A4, Dotted(Eighth)),
+    Note::tone(NOTE_AS4, Plain(Sixteenth)),
+    Note::tone(NOTE_A4, Dotted(Eighth)),
+    Note::tone(NOTE_AS4, Plain(Sixteenth)),
+    Note::tone(NOTE_A4, Dotted(Eighth)),
+    Note::tone(NOTE_AS4, Plain(Sixteenth)),
+    Note::tone(NOTE_A4, Dotted(Eighth)),
+    Note::tone(NOTE_G4, Dotted(Sixteenth)),
+    Note::tone(NOTE_E4, Dotted(Sixteenth)),
+    Note::tone(NOTE_D4, Dotted(Sixteenth)),
+    Note::tone(NOTE_E4, Plain(Sixteenth)),
+    Note::tone(NOTE_E4, Plain(Sixteenth)),
+    Note::tone(NOTE_E4, Plain(Half)),
 ];
+
+// The same theme as an RTTTL ring tone, see `rtttl.rs`
+pub const RTTTL: &str = "PinkPanther:d=4,o=5,b=160:8d#,8e,2p,8f#,8g,2p,8d#,8e,16p,8f#,8g,16p,8c6,8b,16p,8d#,8e,16p,8b,2a#,2p,16a,16g,16e,16d,2e";
@@ -0,0 +1,239 @@
+//! Non-blocking music player running as an embassy task.
+//!
+//! Other tasks send [`Command`]s to [`COMMANDS`] and can wait on [`EVENTS`] to
+//! learn when a tune has finished. Notes are played with a [`Tone`], which
+//! only retunes the LEDC timer between notes.
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel as CommandChannel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer as Delay};
+use esp_hal::ledc::HighSpeed;
+use esp_hal::ledc::channel::{Channel, ChannelHW};
+use esp_hal::ledc::timer::{self, Timer, TimerHW, TimerSpeed};
+
+use crate::music::{A4_HZ, Note, Pitch, Song};
+use crate::rtttl::{self, Rtttl};
+use crate::tone::Tone;
+
+pub static COMMANDS: CommandChannel<CriticalSectionRawMutex, Command, 4> = CommandChannel::new();
+pub static EVENTS: Signal<CriticalSectionRawMutex, Event> = Signal::new();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tune {
+    /// Hand-written melody like `pink_panther::MELODY`
+    Melody { notes: &'static [Note], tempo: u16 },
+    /// RTTTL ring tone, see `rtttl.rs`
+    Rtttl(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Start a tune, replacing the one that is playing
+    Play(Tune),
+    Stop,
+    Pause,
+    Resume,
+    /// Override the tune's tempo (beats per minute) from the next note on;
+    /// 0 goes back to the tune's own tempo
+    SetTempo(u16),
+    /// Loudness in percent; 100 is a 50% duty square wave
+    SetVolume(u8),
+    /// Sound `pitch` until the next command, or go silent with `None`.
+    /// For callers that keep their own time, such as the MIDI player; it
+    /// stops a tune that is playing.
+    Tone(Option<Pitch>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// The tune played to the end
+    Finished,
+    /// The tune was stopped or replaced before the end
+    Stopped,
+    /// The tune could not be parsed
+    Failed(rtttl::Error),
+}
+
+// How a tune ended, and the command that ended it if it must still run
+type Outcome = (Event, Option<Command>);
+
+pub struct Player<'a, S: TimerSpeed> {
+    tone: Tone<'a, S>,
+    volume: u8,
+    tempo: Option<u16>,
+    /// A `Command::Tone` is sounding
+    holding: bool,
+}
+
+impl<'a, S: TimerSpeed> Player<'a, S>
+where
+    Timer<'a, S>: TimerHW<S>,
+    Channel<'a, S>: ChannelHW,
+{
+    /// `channel` must already be configured with `timer`
+    pub fn new(timer: &'a Timer<'a, S>, channel: Channel<'a, S>) -> Result<Self, timer::Error> {
+        Ok(Self {
+            tone: Tone::new(timer, channel)?,
+            volume: 100,
+            tempo: None,
+            holding: false,
+        })
+    }
+
+    /// Wait for commands and play tunes, forever
+    pub async fn run(&mut self) -> ! {
+        let mut pending = None;
+        loop {
+            let command = match pending.take() {
+                Some(command) => command,
+                None => COMMANDS.receive().await,
+            };
+            match command {
+                Command::Play(tune) => {
+                    let (event, next) = self.play(tune).await;
+                    EVENTS.signal(event);
+                    pending = next;
+                }
+                Command::SetTempo(bpm) => self.tempo = (bpm > 0).then_some(bpm),
+                Command::SetVolume(volume) => {
+                    self.volume = volume.min(100);
+                    if self.holding {
+                        self.unmute();
+                    }
+                }
+                Command::Tone(pitch) => self.hold(pitch),
+                Command::Stop => self.hold(None),
+                Command::Pause | Command::Resume => {}
+            }
+        }
+    }
+
+    async fn play(&mut self, tune: Tune) -> Outcome {
+        self.holding = false;
+        let outcome = match tune {
+            Tune::Melody { notes, tempo } => {
+                self.play_notes(notes.iter().map(|&n| Ok(n)), tempo).await
+            }
+            Tune::Rtttl(text) => match Rtttl::parse(text) {
+                Ok(tune) => self.play_notes(tune.notes(), tune.tempo()).await,
+                Err(e) => (Event::Failed(e), None),
+            },
+        };
+        self.mute();
+        outcome
+    }
+
+    async fn play_notes(
+        &mut self,
+        notes: impl Iterator<Item = Result<Note, rtttl::Error>>,
+        tempo: u16,
+    ) -> Outcome {
+        for note in notes {
+            let note = match note {
+                Ok(note) => note,
+                Err(e) => return (Event::Failed(e), None),
+            };
+            let song = Song::new(self.tempo.unwrap_or(tempo));
+            let note_duration = song.duration_ms(note.length) as u64;
+            let pause_duration = note_duration / 10; // 10% of note_duration
+
+            let sounding = note
+                .pitch
+                .is_some_and(|pitch| self.tone.set_frequency(song.frequency(pitch)));
+            if sounding {
+                self.unmute();
+                let play = Duration::from_millis(note_duration - pause_duration);
+                if let Some(outcome) = self.wait(play, true).await {
+                    return outcome;
+                }
+                self.mute();
+                if let Some(outcome) = self
+                    .wait(Duration::from_millis(pause_duration), false)
+                    .await
+                {
+                    return outcome;
+                }
+            } else if let Some(outcome) =
+                self.wait(Duration::from_millis(note_duration), false).await
+            {
+                return outcome;
+            }
+        }
+        (Event::Finished, None)
+    }
+
+    /// Sleep for `duration` while handling commands; `Some` ends the tune
+    async fn wait(&mut self, duration: Duration, sounding: bool) -> Option<Outcome> {
+        let mut deadline = Instant::now() + duration;
+        loop {
+            let command = match select(Delay::at(deadline), COMMANDS.receive()).await {
+                Either::First(()) => return None,
+                Either::Second(command) => command,
+            };
+            match command {
+                Command::Stop => return Some((Event::Stopped, None)),
+                Command::Play(_) | Command::Tone(_) => {
+                    return Some((Event::Stopped, Some(command)));
+                }
+                Command::SetTempo(bpm) => self.tempo = (bpm > 0).then_some(bpm),
+                Command::SetVolume(volume) => {
+                    self.volume = volume.min(100);
+                    if sounding {
+                        self.unmute();
+                    }
+                }
+                Command::Pause => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    self.mute();
+                    if let Some(outcome) = self.paused().await {
+                        return Some(outcome);
+                    }
+                    if sounding {
+                        self.unmute();
+                    }
+                    deadline = Instant::now() + remaining;
+                }
+                Command::Resume => {}
+            }
+        }
+    }
+
+    async fn paused(&mut self) -> Option<Outcome> {
+        loop {
+            match COMMANDS.receive().await {
+                Command::Resume => return None,
+                Command::Stop => return Some((Event::Stopped, None)),
+                command @ (Command::Play(_) | Command::Tone(_)) => {
+                    return Some((Event::Stopped, Some(command)));
+                }
+                Command::SetTempo(bpm) => self.tempo = (bpm > 0).then_some(bpm),
+                Command::SetVolume(volume) => self.volume = volume.min(100),
+                Command::Pause => {}
+            }
+        }
+    }
+
+    fn hold(&mut self, pitch: Option<Pitch>) {
+        self.holding = pitch.is_some_and(|pitch| self.tone.set_frequency(pitch.frequency(A4_HZ)));
+        if self.holding {
+            self.unmute();
+        } else {
+            self.mute();
+        }
+    }
+
+    fn unmute(&self) {
+        self.tone.set_volume(self.volume);
+    }
+
+    fn mute(&self) {
+        self.tone.mute();
+    }
+}
+
+#[embassy_executor::task]
+pub async fn player_task(mut player: Player<'static, HighSpeed>) {
+    player.run().await
+}
@@ -0,0 +1,104 @@
+//! Polyphonic output: one [`Tone`] per voice, each on its own LEDC timer,
+//! channel and buzzer pin.
+//!
+//! The ESP32 has four LEDC timers per speed mode, so up to four notes can
+//! sound at once. New notes go to a free voice; when all are busy the oldest
+//! note is cut off (see [`VoiceAllocator`]).
+
+use embassy_time::{Duration, Instant, Timer as Delay};
+use esp_hal::ledc::channel::{Channel, ChannelHW};
+use esp_hal::ledc::timer::{Timer, TimerHW, TimerSpeed};
+
+use crate::music::{A4_HZ, Pitch};
+use crate::score::{Event, Score};
+use crate::tone::Tone;
+use crate::voices::VoiceAllocator;
+
+// Notes played with `note_on` count as part 0
+const LIVE_PART: usize = 0;
+
+pub struct Synth<'a, S: TimerSpeed, const N: usize> {
+    tones: [Tone<'a, S>; N],
+    /// Keyed by part and MIDI key, so two parts can play the same pitch
+    voices: VoiceAllocator<(usize, i16), N>,
+    volume: u8,
+}
+
+impl<'a, S: TimerSpeed, const N: usize> Synth<'a, S, N>
+where
+    Timer<'a, S>: TimerHW<S>,
+    Channel<'a, S>: ChannelHW,
+{
+    /// Every tone needs its own timer, otherwise the voices retune each other
+    pub fn new(tones: [Tone<'a, S>; N]) -> Self {
+        Self {
+            tones,
+            voices: VoiceAllocator::new(),
+            volume: 100,
+        }
+    }
+
+    /// Loudness of every voice in percent
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume.min(100);
+        for (voice, tone) in self.tones.iter().enumerate() {
+            if self.voices.key(voice).is_some() {
+                tone.set_volume(self.volume);
+            }
+        }
+    }
+
+    /// Start sounding `pitch` until `note_off`
+    pub fn note_on(&mut self, pitch: Pitch) {
+        self.start((LIVE_PART, pitch.midi()), pitch.frequency(A4_HZ));
+    }
+
+    pub fn note_off(&mut self, pitch: Pitch) {
+        self.stop((LIVE_PART, pitch.midi()));
+    }
+
+    /// Silence every voice
+    pub fn all_off(&mut self) {
+        self.voices.all_off();
+        for tone in &self.tones {
+            tone.mute();
+        }
+    }
+
+    /// Play `score` to the end; parts beyond the number of voices steal
+    /// voices from older notes
+    pub async fn play<const P: usize>(&mut self, score: &Score<'_, P>) {
+        let song = score.song();
+        let start = Instant::now();
+        for event in score.events() {
+            Delay::at(start + Duration::from_millis(event.at_ms as u64)).await;
+            match event.event {
+                Event::NoteOn { part, pitch } => {
+                    self.start((part, pitch.midi()), song.frequency(pitch))
+                }
+                Event::NoteOff { part, pitch } => self.stop((part, pitch.midi())),
+            }
+        }
+        self.all_off();
+    }
+
+    fn start(&mut self, key: (usize, i16), frequency: f32) {
+        let Some(allocation) = self.voices.note_on(key) else {
+            return;
+        };
+        let tone = &self.tones[allocation.voice];
+        if tone.set_frequency(frequency) {
+            tone.set_volume(self.volume);
+        } else {
+            // out of the LEDC range, leave the voice free
+            tone.mute();
+            self.voices.note_off(key);
+        }
+    }
+
+    fn stop(&mut self, key: (usize, i16)) {
+        if let Some(voice) = self.voices.note_off(key) {
+            self.tones[voice].mute();
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Animations and power limiting for LED strips.
+
+use crate::rng::XorShift;
+use crate::ws2812::Pixel;
+
+/// Current drawn by one color channel at full brightness
+pub const MA_PER_CHANNEL: u32 = 20;
+
+/// Brightness (0..=255) that keeps the strip under `max_ma` milliamps.
+///
+/// Assumes every channel draws `MA_PER_CHANNEL` at full duty and scales
+/// linearly; that is a bit pessimistic, which is fine for a limit.
+pub fn limit_brightness(pixels: &[Pixel], brightness: u8, max_ma: u32) -> u8 {
+    let total: u32 = pixels
+        .iter()
+        .map(|p| p.r as u32 + p.g as u32 + p.b as u32 + p.w as u32)
+        .sum();
+    let draw_ma = total * MA_PER_CHANNEL / 255 * brightness as u32 / 255;
+    if draw_ma <= max_ma {
+        return brightness;
+    }
+    (brightness as u32 * max_ma / draw_ma) as u8
+}
+
+/// Color wheel: red -> green -> blue -> red as `position` goes 0..=255
+pub const fn wheel(position: u8) -> Pixel {
+    let position = position as u16;
+    match position {
+        0..=84 => Pixel::rgb((255 - position * 3) as u8, (position * 3) as u8, 0),
+        85..=169 => {
+            let p = position - 85;
+            Pixel::rgb(0, (255 - p * 3) as u8, (p * 3) as u8)
+        }
+        _ => {
+            let p = position - 170;
+            Pixel::rgb((p * 3) as u8, 0, (255 - p * 3) as u8)
+        }
+    }
+}
+
+pub trait Animation {
+    /// Draw the next frame
+    fn render(&mut self, pixels: &mut [Pixel]);
+}
+
+/// Rainbow spread over the strip, moving along every frame
+pub struct Rainbow {
+    offset: u8,
+    speed: u8,
+}
+
+impl Rainbow {
+    pub const fn new(speed: u8) -> Self {
+        Self { offset: 0, speed }
+    }
+}
+
+impl Animation for Rainbow {
+    fn render(&mut self, pixels: &mut [Pixel]) {
+        let len = pixels.len().max(1);
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let position = (i * 256 / len) as u8;
+            *pixel = wheel(position.wrapping_add(self.offset));
+        }
+        self.offset = self.offset.wrapping_add(self.speed);
+    }
+}
+
+/// Lit pixels every `spacing` LEDs, moving one step per frame (theater chase)
+pub struct Chase {
+    color: Pixel,
+    spacing: usize,
+    step: usize,
+}
+
+impl Chase {
+    pub const fn new(color: Pixel, spacing: usize) -> Self {
+        Self {
+            color,
+            spacing: if spacing == 0 { 1 } else { spacing },
+            step: 0,
+        }
+    }
+}
+
+impl Animation for Chase {
+    fn render(&mut self, pixels: &mut [Pixel]) {
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = if i % self.spacing == self.step {
+                self.color
+            } else {
+                Pixel::OFF
+            };
+        }
+        self.step = (self.step + 1) % self.spacing;
+    }
+}
+
+/// Flames rising from the start of the strip (after the Fire2012 sketch)
+pub struct Fire<const N: usize> {
+    heat: [u8; N],
+    cooling: u8,
+    sparking: u8,
+    rng: XorShift,
+}
+
+impl<const N: usize> Fire<N> {
+    /// `cooling`: how fast the flames cool down (20..=100 looks good);
+    /// `sparking`: chance out of 255 for a new spark every frame (50..=200)
+    pub const fn new(cooling: u8, sparking: u8, seed: u32) -> Self {
+        Self {
+            heat: [0; N],
+            cooling,
+            sparking,
+            rng: XorShift::new(seed),
+        }
+    }
+
+    fn random(&mut self) -> u8 {
+        (self.rng.next_u32() >> 24) as u8
+    }
+
+    fn random_below(&mut self, max: u32) -> u32 {
+        self.random() as u32 * max / 256
+    }
+
+    /// Black -> red -> yellow -> white
+    fn heat_color(heat: u8) -> Pixel {
+        // scale 0..=255 to 0..=191 so there are three ramps of 64 steps
+        let t = (heat as u16 * 191 / 255) as u8;
+        let ramp = (t & 0x3F) << 2;
+        match t {
+            0..=63 => Pixel::rgb(ramp, 0, 0),
+            64..=127 => Pixel::rgb(255, ramp, 0),
+            _ => Pixel::rgb(255, 255, ramp),
+        }
+    }
+}
+
+impl<const N: usize> Animation for Fire<N> {
+    fn render(&mut self, pixels: &mut [Pixel]) {
+        let len = pixels.len().min(N);
+        if len == 0 {
+            return;
+        }
+
+        // 1. every cell cools down a little
+        let max_cooling = self.cooling as u32 * 10 / len as u32 + 2;
+        for i in 0..len {
+            let cooldown = self.random_below(max_cooling) as u8;
+            self.heat[i] = self.heat[i].saturating_sub(cooldown);
+        }
+
+        // 2. heat drifts up and diffuses
+        for i in (2..len).rev() {
+            let sum = self.heat[i - 1] as u16 + 2 * self.heat[i - 2] as u16;
+            self.heat[i] = (sum / 3) as u8;
+        }
+
+        // 3. sometimes ignite a new spark near the bottom
+        if self.random() < self.sparking {
+            let i = self.random_below(len.min(7) as u32) as usize;
+            let spark = 160 + self.random_below(96) as u8;
+            self.heat[i] = self.heat[i].saturating_add(spark);
+        }
+
+        for (pixel, &heat) in pixels.iter_mut().zip(self.heat.iter()) {
+            *pixel = Self::heat_color(heat);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brightness_limited_to_budget() {
+        // 10 white pixels draw 600 mA at full brightness
+        let pixels = [Pixel::rgb(255, 255, 255); 10];
+        assert_eq!(limit_brightness(&pixels, 255, 1000), 255);
+        assert_eq!(limit_brightness(&pixels, 255, 300), 127);
+        assert_eq!(limit_brightness(&pixels, 100, 300), 100);
+        assert_eq!(limit_brightness(&[Pixel::OFF; 10], 255, 0), 255);
+    }
+
+    #[test]
+    fn wheel_hits_primaries() {
+        assert_eq!(wheel(0), Pixel::rgb(255, 0, 0));
+        assert_eq!(wheel(85), Pixel::rgb(0, 255, 0));
+        assert_eq!(wheel(170), Pixel::rgb(0, 0, 255));
+        assert_eq!(wheel(255), Pixel::rgb(255, 0, 0));
+    }
+
+    #[test]
+    fn rainbow_moves() {
+        let mut pixels = [Pixel::OFF; 3];
+        let mut rainbow = Rainbow::new(85);
+        rainbow.render(&mut pixels);
+        assert_eq!(pixels, [wheel(0), wheel(85), wheel(170)]);
+        rainbow.render(&mut pixels);
+        assert_eq!(pixels, [wheel(85), wheel(170), wheel(255)]);
+    }
+
+    #[test]
+    fn chase_steps_along() {
+        let red = Pixel::rgb(255, 0, 0);
+        let mut pixels = [Pixel::OFF; 6];
+        let mut chase = Chase::new(red, 3);
+        chase.render(&mut pixels);
+        assert_eq!(
+            pixels.map(|p| p == red),
+            [true, false, false, true, false, false]
+        );
+        chase.render(&mut pixels);
+        assert_eq!(
+            pixels.map(|p| p == red),
+            [false, true, false, false, true, false]
+        );
+    }
+
+    #[test]
+    fn fire_glows_and_stays_warm() {
+        let mut pixels = [Pixel::OFF; 30];
+        let mut fire = Fire::<30>::new(55, 120, 42);
+        for _ in 0..100 {
+            fire.render(&mut pixels);
+        }
+        assert!(pixels.iter().any(|p| p.r > 0));
+        // fire never produces blue-only or green-only colors
+        assert!(pixels.iter().all(|p| p.r >= p.g && p.g >= p.b));
+    }
+}
@@ -0,0 +1,291 @@
+//! Color types and conversions for RGB LEDs.
+//!
+//! Hue is in degrees (0..360); saturation, value and lightness use the full
+//! `u8` range (0..=255). Everything is integer math except the per-channel
+//! gamma curve.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    pub const BLACK: Rgb = Rgb::new(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::new(255, 255, 255);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Hsv {
+    pub h: u16,
+    pub s: u8,
+    pub v: u8,
+}
+
+impl Hsv {
+    pub const fn new(h: u16, s: u8, v: u8) -> Self {
+        Self { h: h % 360, s, v }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Hsl {
+    pub h: u16,
+    pub s: u8,
+    pub l: u8,
+}
+
+impl Hsl {
+    pub const fn new(h: u16, s: u8, l: u8) -> Self {
+        Self { h: h % 360, s, l }
+    }
+}
+
+impl From<Hsv> for Rgb {
+    fn from(hsv: Hsv) -> Self {
+        let v = hsv.v as u32;
+        if hsv.s == 0 {
+            return Rgb::new(hsv.v, hsv.v, hsv.v);
+        }
+        let s = hsv.s as u32;
+        let h = (hsv.h % 360) as u32;
+        let region = h / 60;
+        // position inside the 60 degree region, scaled to 0..=255
+        let rem = (h % 60) * 255 / 60;
+
+        let p = (v * (255 - s) / 255) as u8;
+        let q = (v * (255 - s * rem / 255) / 255) as u8;
+        let t = (v * (255 - s * (255 - rem) / 255) / 255) as u8;
+        let v = v as u8;
+
+        match region {
+            0 => Rgb::new(v, t, p),
+            1 => Rgb::new(q, v, p),
+            2 => Rgb::new(p, v, t),
+            3 => Rgb::new(p, q, v),
+            4 => Rgb::new(t, p, v),
+            _ => Rgb::new(v, p, q),
+        }
+    }
+}
+
+impl From<Rgb> for Hsv {
+    fn from(rgb: Rgb) -> Self {
+        let max = rgb.r.max(rgb.g).max(rgb.b);
+        let min = rgb.r.min(rgb.g).min(rgb.b);
+        let delta = (max - min) as u32;
+        if max == 0 {
+            return Hsv::new(0, 0, 0);
+        }
+        let s = (delta * 255 + max as u32 / 2) / max as u32;
+        Hsv::new(hue(rgb, max, delta), s as u8, max)
+    }
+}
+
+impl From<Hsl> for Rgb {
+    fn from(hsl: Hsl) -> Self {
+        let l = hsl.l as i32;
+        let s = hsl.s as i32;
+        // chroma, scaled to 0..=255
+        let c = (255 - (2 * l - 255).abs()) * s / 255;
+        let h = (hsl.h % 360) as i32;
+        // x = c * (1 - |(h / 60) mod 2 - 1|), done with h in 1/60 units
+        let x = c * (60 - ((h % 120) - 60).abs()) / 60;
+        let m = l - c / 2;
+
+        let (r, g, b) = match h / 60 {
+            0 => (c, x, 0),
+            1 => (x, c, 0),
+            2 => (0, c, x),
+            3 => (0, x, c),
+            4 => (x, 0, c),
+            _ => (c, 0, x),
+        };
+        let channel = |value: i32| (value + m).clamp(0, 255) as u8;
+        Rgb::new(channel(r), channel(g), channel(b))
+    }
+}
+
+impl From<Rgb> for Hsl {
+    fn from(rgb: Rgb) -> Self {
+        let max = rgb.r.max(rgb.g).max(rgb.b);
+        let min = rgb.r.min(rgb.g).min(rgb.b);
+        let delta = (max - min) as u32;
+        let sum = max as u32 + min as u32;
+        let l = (sum / 2) as u8;
+        if delta == 0 {
+            return Hsl::new(0, 0, l);
+        }
+        let divisor = 255 - (sum as i32 - 255).unsigned_abs();
+        let s = (delta * 255 + divisor / 2) / divisor;
+        Hsl::new(hue(rgb, max, delta), s.min(255) as u8, l)
+    }
+}
+
+fn hue(rgb: Rgb, max: u8, delta: u32) -> u16 {
+    if delta == 0 {
+        return 0;
+    }
+    let (r, g, b) = (rgb.r as i32, rgb.g as i32, rgb.b as i32);
+    let delta = delta as i32;
+    let h = if max == rgb.r {
+        60 * (g - b) / delta
+    } else if max == rgb.g {
+        120 + 60 * (b - r) / delta
+    } else {
+        240 + 60 * (r - g) / delta
+    };
+    h.rem_euclid(360) as u16
+}
+
+/// Blend two colors in HSV space; `t` goes from 0 (`from`) to 1000 (`to`).
+///
+/// The hue takes the shorter way around the color wheel, so going from red to
+/// magenta does not pass through green.
+pub fn lerp_hsv(from: Hsv, to: Hsv, t: u16) -> Hsv {
+    let t = t.min(1000) as i32;
+    let lerp = |a: i32, b: i32| a + (b - a) * t / 1000;
+
+    let mut diff = to.h as i32 - from.h as i32;
+    if diff > 180 {
+        diff -= 360;
+    } else if diff < -180 {
+        diff += 360;
+    }
+    // Gray has no real hue; keep the other one so the blend doesn't swing
+    let (from_h, diff) = match (from.s, to.s) {
+        (0, _) => (to.h as i32, 0),
+        (_, 0) => (from.h as i32, 0),
+        _ => (from.h as i32, diff),
+    };
+    let h = (from_h + diff * t / 1000).rem_euclid(360);
+
+    Hsv::new(
+        h as u16,
+        lerp(from.s as i32, to.s as i32) as u8,
+        lerp(from.v as i32, to.v as i32) as u8,
+    )
+}
+
+/// Per-channel correction: gamma curve and white-balance trim
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelTrim {
+    /// Gamma exponent; 1.0 is linear, around 2.2 looks natural for LEDs
+    pub gamma: f32,
+    /// Scales the channel to balance the LED colors (255 = no change)
+    pub balance: u8,
+}
+
+impl ChannelTrim {
+    pub const DEFAULT: ChannelTrim = ChannelTrim {
+        gamma: 2.2,
+        balance: 255,
+    };
+
+    /// Duty for a channel value, for a LEDC channel with `max_duty` steps
+    pub fn duty(&self, value: u8, max_duty: u32) -> u32 {
+        if value == 0 || self.balance == 0 {
+            return 0;
+        }
+        let scaled = value as f32 * self.balance as f32 / (255.0 * 255.0);
+        let corrected = libm::powf(scaled, self.gamma);
+        (corrected * max_duty as f32 + 0.5) as u32
+    }
+}
+
+impl Default for ChannelTrim {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Rgb, b: Rgb) -> bool {
+        a.r.abs_diff(b.r) <= 3 && a.g.abs_diff(b.g) <= 3 && a.b.abs_diff(b.b) <= 3
+    }
+
+    #[test]
+    fn hsv_primaries() {
+        assert_eq!(Rgb::from(Hsv::new(0, 255, 255)), Rgb::new(255, 0, 0));
+        assert_eq!(Rgb::from(Hsv::new(120, 255, 255)), Rgb::new(0, 255, 0));
+        assert_eq!(Rgb::from(Hsv::new(240, 255, 255)), Rgb::new(0, 0, 255));
+        assert_eq!(Rgb::from(Hsv::new(60, 255, 255)), Rgb::new(255, 255, 0));
+        assert_eq!(Rgb::from(Hsv::new(90, 0, 128)), Rgb::new(128, 128, 128));
+    }
+
+    #[test]
+    fn hsl_primaries() {
+        assert_eq!(Rgb::from(Hsl::new(0, 255, 128)), Rgb::new(255, 1, 1));
+        assert_eq!(Rgb::from(Hsl::new(240, 255, 128)), Rgb::new(1, 1, 255));
+        assert_eq!(Rgb::from(Hsl::new(0, 0, 255)), Rgb::WHITE);
+        assert_eq!(Rgb::from(Hsl::new(0, 255, 0)), Rgb::BLACK);
+    }
+
+    #[test]
+    fn rgb_to_hsv_and_hsl() {
+        assert_eq!(Hsv::from(Rgb::new(255, 0, 0)), Hsv::new(0, 255, 255));
+        assert_eq!(Hsv::from(Rgb::new(0, 0, 128)), Hsv::new(240, 255, 128));
+        assert_eq!(Hsv::from(Rgb::new(255, 0, 255)), Hsv::new(300, 255, 255));
+        assert_eq!(Hsl::from(Rgb::new(0, 255, 0)), Hsl::new(120, 255, 127));
+        assert_eq!(Hsl::from(Rgb::new(100, 100, 100)), Hsl::new(0, 0, 100));
+    }
+
+    #[test]
+    fn round_trips() {
+        for rgb in [
+            Rgb::new(12, 200, 99),
+            Rgb::new(250, 128, 3),
+            Rgb::new(40, 40, 220),
+            Rgb::new(201, 17, 180),
+        ] {
+            assert!(close(Rgb::from(Hsv::from(rgb)), rgb), "hsv {rgb:?}");
+            assert!(close(Rgb::from(Hsl::from(rgb)), rgb), "hsl {rgb:?}");
+        }
+    }
+
+    #[test]
+    fn hue_takes_short_way() {
+        let red = Hsv::new(10, 255, 255);
+        let magenta = Hsv::new(300, 255, 255);
+        assert_eq!(lerp_hsv(red, magenta, 500).h, 335);
+        assert_eq!(lerp_hsv(magenta, red, 500).h, 335);
+        assert_eq!(lerp_hsv(red, magenta, 0), red);
+        assert_eq!(lerp_hsv(red, magenta, 1000), magenta);
+    }
+
+    #[test]
+    fn fading_from_black_keeps_hue() {
+        let black = Hsv::new(0, 0, 0);
+        let blue = Hsv::new(240, 255, 255);
+        assert_eq!(lerp_hsv(black, blue, 500), Hsv::new(240, 127, 127));
+    }
+
+    #[test]
+    fn trim_applies_gamma_and_balance() {
+        let linear = ChannelTrim {
+            gamma: 1.0,
+            balance: 255,
+        };
+        assert_eq!(linear.duty(255, 8192), 8192);
+        assert_eq!(linear.duty(0, 8192), 0);
+        assert_eq!(linear.duty(128, 1000), 502);
+
+        let default = ChannelTrim::DEFAULT;
+        assert_eq!(default.duty(255, 8192), 8192);
+        assert!(default.duty(128, 8192) < 8192 / 4);
+
+        let dimmed = ChannelTrim {
+            gamma: 1.0,
+            balance: 128,
+        };
+        assert_eq!(dimmed.duty(255, 1000), 502);
+    }
+}
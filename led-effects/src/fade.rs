@@ -0,0 +1,133 @@
+//! Brightness math for LEDs: CIE1931 lightness correction and LEDC hardware
+//! fade parameters.
+//!
+//! The eye does not perceive light linearly, so a linear duty fade looks like
+//! it jumps up quickly and then hardly changes. Brightness levels (0..=255) are
+//! therefore mapped through the CIE1931 lightness curve before being turned
+//! into a duty cycle.
+
+/// Number of brightness levels
+pub const LEVELS: usize = 256;
+
+/// Relative luminance (0..=65535) for each perceived brightness level (0..=255)
+pub static CIE1931: [u16; LEVELS] = cie1931_table();
+
+const fn cie1931_table() -> [u16; LEVELS] {
+    let mut table = [0u16; LEVELS];
+    let mut i = 0;
+    while i < LEVELS {
+        // lightness L* in 0..=100
+        let l = i as f32 * 100.0 / (LEVELS - 1) as f32;
+        let y = if l <= 8.0 {
+            l / 903.3
+        } else {
+            let t = (l + 16.0) / 116.0;
+            t * t * t
+        };
+        table[i] = (y * 65535.0 + 0.5) as u16;
+        i += 1;
+    }
+    table
+}
+
+/// Duty value for a perceived brightness level, for a channel with `max_duty` steps
+pub fn level_to_duty(level: u8, max_duty: u32) -> u32 {
+    (CIE1931[level as usize] as u32 * max_duty + 32767) / 65535
+}
+
+// The ESP32 LEDC fade registers (duty_num, duty_cycle, duty_scale) are 10 bits wide
+const MAX_FADE_FIELD: u32 = 1023;
+
+/// Parameters for `ChannelHW::start_duty_fade_hw`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HwFade {
+    pub start_duty: u32,
+    pub duty_inc: bool,
+    pub duty_steps: u16,
+    pub cycles_per_step: u16,
+    pub duty_per_cycle: u16,
+}
+
+impl HwFade {
+    /// Work out how to fade from `start` to `end` duty in about `duration_ms`.
+    ///
+    /// Returns `None` if there is nothing to fade (equal duties or zero
+    /// duration); set the end duty directly in that case. Because of integer
+    /// steps the hardware may stop slightly short of `end`, so set the end duty
+    /// once the fade has finished.
+    pub fn new(start: u32, end: u32, duration_ms: u32, frequency_hz: u32) -> Option<Self> {
+        let diff = start.abs_diff(end);
+        let pwm_cycles = duration_ms * frequency_hz / 1000;
+        if diff == 0 || pwm_cycles == 0 {
+            return None;
+        }
+
+        let duty_steps = diff.min(pwm_cycles).min(MAX_FADE_FIELD);
+        let duty_per_cycle = (diff / duty_steps).min(MAX_FADE_FIELD);
+        let cycles_per_step = (pwm_cycles / duty_steps).clamp(1, MAX_FADE_FIELD);
+
+        Some(Self {
+            start_duty: start,
+            duty_inc: end > start,
+            duty_steps: duty_steps as u16,
+            cycles_per_step: cycles_per_step as u16,
+            duty_per_cycle: duty_per_cycle as u16,
+        })
+    }
+
+    /// Approximate time the hardware needs for this fade
+    pub fn duration_ms(&self, frequency_hz: u32) -> u32 {
+        self.duty_steps as u32 * self.cycles_per_step as u32 * 1000 / frequency_hz
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_endpoints_and_monotonic() {
+        assert_eq!(CIE1931[0], 0);
+        assert_eq!(CIE1931[255], 65535);
+        assert!(CIE1931.windows(2).all(|w| w[0] <= w[1]));
+        // half perceived brightness is only about 18% luminance
+        let half = CIE1931[128] as u32 * 100 / 65535;
+        assert_eq!(half, 18);
+    }
+
+    #[test]
+    fn level_scales_to_duty() {
+        assert_eq!(level_to_duty(0, 8192), 0);
+        assert_eq!(level_to_duty(255, 8192), 8192);
+        assert_eq!(level_to_duty(1, 8192), 4);
+    }
+
+    #[test]
+    fn fade_with_fewer_steps_than_cycles() {
+        // 100 duty steps over 1 s at 5 kHz => 50 cycles per step
+        let fade = HwFade::new(0, 100, 1000, 5000).unwrap();
+        assert_eq!(fade.duty_steps, 100);
+        assert_eq!(fade.cycles_per_step, 50);
+        assert_eq!(fade.duty_per_cycle, 1);
+        assert!(fade.duty_inc);
+        assert_eq!(fade.duration_ms(5000), 1000);
+    }
+
+    #[test]
+    fn fade_with_more_steps_than_fit() {
+        // 8000 duty steps over 100 ms at 5 kHz => 500 cycles available
+        let fade = HwFade::new(8000, 0, 100, 5000).unwrap();
+        assert!(!fade.duty_inc);
+        assert_eq!(fade.duty_steps, 500);
+        assert_eq!(fade.duty_per_cycle, 16);
+        assert_eq!(fade.cycles_per_step, 1);
+    }
+
+    #[test]
+    fn fade_fields_stay_in_range() {
+        let fade = HwFade::new(0, 10, 60_000, 5000).unwrap();
+        assert_eq!(fade.cycles_per_step, 1023);
+        assert!(HwFade::new(5, 5, 1000, 5000).is_none());
+        assert!(HwFade::new(0, 5, 0, 5000).is_none());
+    }
+}
@@ -0,0 +1,112 @@
+//! Gamma corrected LED on a LEDC channel, with async fades and patterns.
+
+use embassy_time::{Duration, Timer};
+use esp_hal::ledc::channel::{Channel, ChannelHW, ChannelIFace};
+use esp_hal::ledc::timer::{self, TimerIFace, TimerSpeed};
+
+use crate::fade::{HwFade, level_to_duty};
+use crate::pattern::Pattern;
+
+// A perceptual fade is played as this many linear hardware fades
+const FADE_SEGMENTS: u32 = 8;
+
+// How often to check whether a hardware fade has finished
+const FADE_POLL: Duration = Duration::from_millis(2);
+
+pub struct Led<'a, S: TimerSpeed> {
+    channel: Channel<'a, S>,
+    max_duty: u32,
+    frequency_hz: u32,
+    level: u8,
+}
+
+impl<'a, S: TimerSpeed> Led<'a, S>
+where
+    Channel<'a, S>: ChannelHW + ChannelIFace<'a, S>,
+{
+    /// `channel` must already be configured with `timer`
+    pub fn new(channel: Channel<'a, S>, timer: &dyn TimerIFace<S>) -> Result<Self, timer::Error> {
+        let duty = timer.duty().ok_or(timer::Error::FrequencyUnset)?;
+        let mut led = Self {
+            channel,
+            max_duty: 1 << duty as u32,
+            frequency_hz: timer.frequency(),
+            level: 0,
+        };
+        led.set_level(0);
+        Ok(led)
+    }
+
+    /// Current perceived brightness level
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// Jump to a brightness level without fading
+    pub fn set_level(&mut self, level: u8) {
+        self.channel
+            .set_duty_hw(level_to_duty(level, self.max_duty));
+        self.level = level;
+    }
+
+    /// Fade to a brightness level, completing when the hardware fade is done
+    pub async fn fade_to(&mut self, level: u8, duration_ms: u16) {
+        let from = self.level as u32;
+        let to = level as u32;
+        let segment_ms = duration_ms as u32 / FADE_SEGMENTS;
+        let mut duty = level_to_duty(self.level, self.max_duty);
+
+        for segment in 1..=FADE_SEGMENTS {
+            let segment_level = if to >= from {
+                from + (to - from) * segment / FADE_SEGMENTS
+            } else {
+                from - (from - to) * segment / FADE_SEGMENTS
+            };
+            let next_duty = level_to_duty(segment_level as u8, self.max_duty);
+
+            if let Some(fade) = HwFade::new(duty, next_duty, segment_ms, self.frequency_hz) {
+                self.channel.start_duty_fade_hw(
+                    fade.start_duty,
+                    fade.duty_inc,
+                    fade.duty_steps,
+                    fade.cycles_per_step,
+                    fade.duty_per_cycle,
+                );
+                self.wait_fade().await;
+            } else if segment_ms > 0 {
+                // Too small a change to fade, but keep the timing
+                Timer::after(Duration::from_millis(segment_ms as u64)).await;
+            }
+
+            // The hardware may stop a bit short of the target
+            self.channel.set_duty_hw(next_duty);
+            duty = next_duty;
+        }
+
+        self.level = level;
+    }
+
+    /// Run a pattern; only returns for patterns that end (e.g. `Pattern::Solid`)
+    pub async fn run(&mut self, pattern: Pattern) {
+        for step in pattern.steps() {
+            if step.fade_ms == 0 {
+                self.set_level(step.level);
+            } else {
+                self.fade_to(step.level, step.fade_ms).await;
+            }
+            if step.hold_ms > 0 {
+                Timer::after(Duration::from_millis(step.hold_ms as u64)).await;
+            }
+        }
+    }
+
+    pub fn release(self) -> Channel<'a, S> {
+        self.channel
+    }
+
+    async fn wait_fade(&self) {
+        while self.channel.is_duty_fade_running() {
+            Timer::after(FADE_POLL).await;
+        }
+    }
+}
@@ -0,0 +1,25 @@
+//! LED brightness, color and effect code shared by the LED projects.
+//!
+//! - [`fade`]: CIE1931 lightness correction and LEDC hardware fade
+//!   parameters
+//! - [`pattern`]: breathe, heartbeat, blink code, candle and strobe
+//! - [`color`]: RGB, HSV and HSL colors with per-channel gamma and trim
+//! - `led`: an LED on a LEDC channel playing fades and patterns
+//! - `rgb`: an RGB LED on three LEDC channels
+//! - [`ws2812`]: WS2812B / SK6812 pixels as RMT pulse codes
+//! - [`animation`]: rainbow, chase and fire for LED strips, and current
+//!   limiting
+//!
+//! Only `led` and `rgb` touch esp-hal, and they need the `ledc` feature. The
+//! rest is plain math, so `cargo test` runs it on the host.
+#![no_std]
+pub mod animation;
+pub mod color;
+pub mod fade;
+#[cfg(feature = "ledc")]
+pub mod led;
+pub mod pattern;
+#[cfg(feature = "ledc")]
+pub mod rgb;
+mod rng;
+pub mod ws2812;
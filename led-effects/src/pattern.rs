@@ -0,0 +1,257 @@
+//! LED effect patterns.
+//!
+//! A pattern is turned into an endless series of [`Step`]s: fade to a
+//! brightness level, then hold it for a while. Levels are perceived
+//! brightness (0..=255) and get gamma corrected when they are played.
+//!
+//! Every step of an endless pattern takes at least [`MIN_STEP_MS`], so
+//! whatever plays it waits on every step, even for a `Strobe` with zero
+//! times.
+
+use crate::rng::XorShift;
+
+/// Shortest fade plus hold of a step, in ms
+pub const MIN_STEP_MS: u16 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    /// Target brightness level
+    pub level: u8,
+    /// Time to fade from the current level to `level`
+    pub fade_ms: u16,
+    /// Time to stay at `level` after the fade
+    pub hold_ms: u16,
+}
+
+impl Step {
+    const fn new(level: u8, fade_ms: u16, hold_ms: u16) -> Self {
+        Self {
+            level,
+            fade_ms,
+            hold_ms,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pattern {
+    /// Stay at one level
+    Solid(u8),
+    /// Slowly fade in and out
+    Breathe { period_ms: u16, max: u8 },
+    /// Two quick pulses ("lub-dub") followed by a rest
+    Heartbeat { period_ms: u16, max: u8 },
+    /// Blink `count` times, then pause; handy for error codes
+    BlinkCode {
+        count: u8,
+        on_ms: u16,
+        off_ms: u16,
+        pause_ms: u16,
+        level: u8,
+    },
+    /// Random flicker like a candle flame
+    Candle { seed: u32 },
+    /// Hard on/off flashes without fading
+    Strobe { on_ms: u16, off_ms: u16, level: u8 },
+}
+
+impl Pattern {
+    pub fn steps(self) -> Steps {
+        Steps {
+            pattern: self,
+            index: 0,
+            rng: match self {
+                Pattern::Candle { seed } => XorShift::new(seed),
+                _ => XorShift::new(0),
+            },
+        }
+    }
+}
+
+/// Endless iterator over the steps of a pattern
+pub struct Steps {
+    pattern: Pattern,
+    index: u32,
+    rng: XorShift,
+}
+
+impl Iterator for Steps {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Step> {
+        let index = self.index;
+        self.index = self.index.wrapping_add(1);
+
+        let mut step = match self.pattern {
+            Pattern::Solid(level) => return (index == 0).then_some(Step::new(level, 0, 0)),
+            Pattern::Breathe { period_ms, max } => {
+                let half = period_ms / 2;
+                if index.is_multiple_of(2) {
+                    Step::new(max, half, 0)
+                } else {
+                    Step::new(0, half, 0)
+                }
+            }
+            Pattern::Heartbeat { period_ms, max } => {
+                // the two beats take 460 ms, the rest of the period is a pause
+                let rest = period_ms.saturating_sub(460);
+                match index % 4 {
+                    0 => Step::new(max, 50, 0),
+                    1 => Step::new(0, 100, 80),
+                    2 => Step::new((max as u16 * 3 / 4) as u8, 50, 0),
+                    _ => Step::new(0, 180, rest),
+                }
+            }
+            Pattern::BlinkCode {
+                count,
+                on_ms,
+                off_ms,
+                pause_ms,
+                level,
+            } => {
+                let count = count.max(1) as u32;
+                let blink = (index / 2) % count;
+                if index.is_multiple_of(2) {
+                    Step::new(level, 0, on_ms)
+                } else if blink == count - 1 {
+                    Step::new(0, 0, pause_ms)
+                } else {
+                    Step::new(0, 0, off_ms)
+                }
+            }
+            Pattern::Candle { .. } => {
+                let level = self.rng.range(110, 255) as u8;
+                let fade_ms = self.rng.range(30, 130) as u16;
+                let hold_ms = self.rng.range(0, 60) as u16;
+                Step::new(level, fade_ms, hold_ms)
+            }
+            Pattern::Strobe {
+                on_ms,
+                off_ms,
+                level,
+            } => {
+                if index.is_multiple_of(2) {
+                    Step::new(level, 0, on_ms)
+                } else {
+                    Step::new(0, 0, off_ms)
+                }
+            }
+        };
+
+        let time = step.fade_ms.saturating_add(step.hold_ms);
+        if time < MIN_STEP_MS {
+            step.hold_ms = MIN_STEP_MS - step.fade_ms;
+        }
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solid_has_single_step() {
+        let mut steps = Pattern::Solid(42).steps();
+        assert_eq!(steps.next(), Some(Step::new(42, 0, 0)));
+        assert_eq!(steps.next(), None);
+    }
+
+    #[test]
+    fn breathe_alternates() {
+        let mut steps = Pattern::Breathe {
+            period_ms: 2000,
+            max: 200,
+        }
+        .steps();
+        assert_eq!(steps.next(), Some(Step::new(200, 1000, 0)));
+        assert_eq!(steps.next(), Some(Step::new(0, 1000, 0)));
+        assert_eq!(steps.next(), Some(Step::new(200, 1000, 0)));
+    }
+
+    #[test]
+    fn heartbeat_fills_period() {
+        let steps = Pattern::Heartbeat {
+            period_ms: 1000,
+            max: 255,
+        }
+        .steps();
+        let total: u32 = steps
+            .take(4)
+            .map(|s| s.fade_ms as u32 + s.hold_ms as u32)
+            .sum();
+        assert_eq!(total, 1000);
+    }
+
+    #[test]
+    fn blink_code_pauses_after_count() {
+        let steps = Pattern::BlinkCode {
+            count: 3,
+            on_ms: 200,
+            off_ms: 300,
+            pause_ms: 1500,
+            level: 255,
+        }
+        .steps();
+        let holds: [u16; 8] = core::array::from_fn({
+            let mut steps = steps;
+            move |_| steps.next().unwrap().hold_ms
+        });
+        assert_eq!(holds, [200, 300, 200, 300, 200, 1500, 200, 300]);
+    }
+
+    #[test]
+    fn candle_stays_in_range() {
+        for step in (Pattern::Candle { seed: 0 }).steps().take(500) {
+            assert!(step.level >= 110);
+            assert!((30..=130).contains(&step.fade_ms));
+            assert!(step.hold_ms <= 60);
+        }
+    }
+
+    #[test]
+    fn strobe_does_not_fade() {
+        let mut steps = Pattern::Strobe {
+            on_ms: 20,
+            off_ms: 80,
+            level: 255,
+        }
+        .steps();
+        assert_eq!(steps.next(), Some(Step::new(255, 0, 20)));
+        assert_eq!(steps.next(), Some(Step::new(0, 0, 80)));
+    }
+
+    #[test]
+    fn zero_times_still_take_time() {
+        let patterns = [
+            Pattern::Breathe {
+                period_ms: 1,
+                max: 255,
+            },
+            Pattern::Strobe {
+                on_ms: 0,
+                off_ms: 0,
+                level: 255,
+            },
+            Pattern::BlinkCode {
+                count: 2,
+                on_ms: 0,
+                off_ms: 3,
+                pause_ms: 0,
+                level: 255,
+            },
+        ];
+        for pattern in patterns {
+            for step in pattern.steps().take(8) {
+                assert_eq!(step.fade_ms + step.hold_ms, MIN_STEP_MS, "{:?}", pattern);
+            }
+        }
+        // longer steps are left alone
+        let mut steps = Pattern::Breathe {
+            period_ms: 16,
+            max: 255,
+        }
+        .steps();
+        assert_eq!(steps.next(), Some(Step::new(255, 8, 2)));
+    }
+}
@@ -0,0 +1,117 @@
+//! RGB LED driven by three LEDC channels.
+
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::ledc::channel::{Channel, ChannelHW};
+use esp_hal::ledc::timer::{self, TimerIFace, TimerSpeed};
+
+use crate::color::{ChannelTrim, Hsl, Hsv, Rgb, lerp_hsv};
+
+// Update rate for color transitions
+const TRANSITION_STEP: Duration = Duration::from_millis(20);
+
+/// How the LED is wired
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wiring {
+    /// Shared cathode to GND, channels drive the anodes (high = on)
+    CommonCathode,
+    /// Shared anode to 3.3V, channels sink the cathodes (low = on)
+    CommonAnode,
+}
+
+pub struct RgbLed<'a, S: TimerSpeed> {
+    channels: [Channel<'a, S>; 3],
+    trims: [ChannelTrim; 3],
+    wiring: Wiring,
+    max_duty: u32,
+    color: Hsv,
+}
+
+impl<'a, S: TimerSpeed> RgbLed<'a, S>
+where
+    Channel<'a, S>: ChannelHW,
+{
+    /// The red, green and blue channels must already be configured with `timer`
+    pub fn new(
+        red: Channel<'a, S>,
+        green: Channel<'a, S>,
+        blue: Channel<'a, S>,
+        timer: &dyn TimerIFace<S>,
+        wiring: Wiring,
+    ) -> Result<Self, timer::Error> {
+        let duty = timer.duty().ok_or(timer::Error::FrequencyUnset)?;
+        let mut led = Self {
+            channels: [red, green, blue],
+            trims: [ChannelTrim::DEFAULT; 3],
+            wiring,
+            max_duty: 1 << duty as u32,
+            color: Hsv::default(),
+        };
+        led.set_hsv(Hsv::default());
+        Ok(led)
+    }
+
+    /// Set gamma and white-balance for the red, green and blue channels
+    pub fn with_trims(mut self, red: ChannelTrim, green: ChannelTrim, blue: ChannelTrim) -> Self {
+        self.trims = [red, green, blue];
+        let color = self.color;
+        self.set_hsv(color);
+        self
+    }
+
+    pub fn color(&self) -> Hsv {
+        self.color
+    }
+
+    pub fn set_rgb(&mut self, rgb: Rgb) {
+        self.write(rgb);
+        self.color = Hsv::from(rgb);
+    }
+
+    pub fn set_hsv(&mut self, hsv: Hsv) {
+        self.write(Rgb::from(hsv));
+        self.color = hsv;
+    }
+
+    pub fn set_hsl(&mut self, hsl: Hsl) {
+        self.set_rgb(Rgb::from(hsl));
+    }
+
+    /// Blend from the current color to `to` in HSV space
+    pub async fn transition_to(&mut self, to: Hsv, duration_ms: u32) {
+        let from = self.color;
+        let start = Instant::now();
+        let duration = Duration::from_millis(duration_ms as u64);
+
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed >= duration {
+                break;
+            }
+            let t = elapsed.as_millis() * 1000 / duration.as_millis();
+            self.set_hsv(lerp_hsv(from, to, t as u16));
+            Timer::after(TRANSITION_STEP).await;
+        }
+
+        self.set_hsv(to);
+    }
+
+    pub fn release(self) -> [Channel<'a, S>; 3] {
+        self.channels
+    }
+
+    fn write(&mut self, rgb: Rgb) {
+        for ((channel, trim), value) in self
+            .channels
+            .iter()
+            .zip(self.trims.iter())
+            .zip([rgb.r, rgb.g, rgb.b])
+        {
+            let duty = trim.duty(value, self.max_duty);
+            let duty = match self.wiring {
+                Wiring::CommonCathode => duty,
+                Wiring::CommonAnode => self.max_duty - duty,
+            };
+            channel.set_duty_hw(duty);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Xorshift random numbers for the flickering effects.
+
+/// Marsaglia's 32-bit xorshift: fast and small, good enough for flames
+#[derive(Debug, Clone, Copy)]
+pub struct XorShift(u32);
+
+impl XorShift {
+    pub const fn new(seed: u32) -> Self {
+        // zero would stay zero forever
+        Self(seed | 1)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    /// A number in `min..=max`
+    pub fn range(&mut self, min: u32, max: u32) -> u32 {
+        min + self.next_u32() % (max - min + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_seed_still_varies() {
+        let mut rng = XorShift::new(0);
+        let first = rng.next_u32();
+        assert_ne!(first, 0);
+        assert_ne!(rng.next_u32(), first);
+        for _ in 0..100 {
+            assert!((3..=5).contains(&rng.range(3, 5)));
+        }
+    }
+}
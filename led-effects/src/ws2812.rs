@@ -0,0 +1,276 @@
+//! Turns pixel colors into RMT pulse codes for WS2812B / SK6812 LEDs.
+//!
+//! Every data bit is one RMT pulse code: a high period followed by a low
+//! period, where a "1" has a longer high time than a "0". The codes are plain
+//! `u32` values in the RMT RAM layout, so they convert straight into
+//! `esp_hal::rmt::PulseCode` and can be checked on the host:
+//!
+//! ```text
+//! bit 31: level2 | bits 16..=30: length2 | bit 15: level1 | bits 0..=14: length1
+//! ```
+
+/// RMT clock after the divider; one tick is 25 ns
+pub const TICK_HZ: u32 = 40_000_000;
+
+const LEVEL1: u32 = 1 << 15;
+const LENGTH2_SHIFT: u32 = 16;
+const MAX_LENGTH: u32 = 0x7FFF;
+
+/// The RMT stops at a code with a zero length
+pub const END_MARKER: u32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// Pulse buffer is too small for the number of pixels
+    BufferTooSmall,
+}
+
+/// High and low times of the data bits, in RMT ticks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timing {
+    pub t0h: u16,
+    pub t0l: u16,
+    pub t1h: u16,
+    pub t1l: u16,
+    /// Low time after a frame, so the LEDs latch the new colors
+    pub reset: u16,
+}
+
+const fn ticks(ns: u32) -> u16 {
+    (ns * (TICK_HZ / 1_000_000) / 1000) as u16
+}
+
+/// WS2812B: 0.4/0.85 us for a zero, 0.8/0.45 us for a one, 280 us reset
+pub const WS2812B_TIMING: Timing = Timing {
+    t0h: ticks(400),
+    t0l: ticks(850),
+    t1h: ticks(800),
+    t1l: ticks(450),
+    reset: ticks(280_000),
+};
+
+/// SK6812: 0.3/0.9 us for a zero, 0.6/0.6 us for a one, 80 us reset
+pub const SK6812_TIMING: Timing = Timing {
+    t0h: ticks(300),
+    t0l: ticks(900),
+    t1h: ticks(600),
+    t1l: ticks(600),
+    reset: ticks(80_000),
+};
+
+/// LED chip type, which decides the timing and the bytes per pixel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LedKind {
+    /// WS2812B, 3 bytes per pixel in GRB order
+    Ws2812b,
+    /// SK6812 RGB, 3 bytes per pixel in GRB order
+    Sk6812Rgb,
+    /// SK6812 RGBW, 4 bytes per pixel in GRBW order
+    Sk6812Rgbw,
+}
+
+impl LedKind {
+    pub const fn timing(self) -> Timing {
+        match self {
+            LedKind::Ws2812b => WS2812B_TIMING,
+            LedKind::Sk6812Rgb | LedKind::Sk6812Rgbw => SK6812_TIMING,
+        }
+    }
+
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            LedKind::Ws2812b | LedKind::Sk6812Rgb => 3,
+            LedKind::Sk6812Rgbw => 4,
+        }
+    }
+}
+
+/// Pulse codes needed for a frame of `pixels` LEDs (including reset and end marker)
+pub const fn buffer_len(kind: LedKind, pixels: usize) -> usize {
+    pixels * kind.bytes_per_pixel() * 8 + 2
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Pixel {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// White channel, only sent to RGBW LEDs
+    pub w: u8,
+}
+
+impl Pixel {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, w: 0 }
+    }
+
+    pub const fn rgbw(r: u8, g: u8, b: u8, w: u8) -> Self {
+        Self { r, g, b, w }
+    }
+
+    pub const OFF: Pixel = Pixel::rgb(0, 0, 0);
+
+    /// Scale every channel by `brightness` / 255
+    pub const fn scale(self, brightness: u8) -> Self {
+        const fn scale(value: u8, brightness: u8) -> u8 {
+            ((value as u16 * brightness as u16 + 127) / 255) as u8
+        }
+        Self {
+            r: scale(self.r, brightness),
+            g: scale(self.g, brightness),
+            b: scale(self.b, brightness),
+            w: scale(self.w, brightness),
+        }
+    }
+}
+
+/// A pulse code with a high and then a low period
+pub const fn pulse(high: u16, low: u16) -> u32 {
+    (high as u32 & MAX_LENGTH) | LEVEL1 | ((low as u32 & MAX_LENGTH) << LENGTH2_SHIFT)
+}
+
+/// A pulse code that keeps the line low for both periods
+const fn idle(length1: u16, length2: u16) -> u32 {
+    (length1 as u32 & MAX_LENGTH) | ((length2 as u32 & MAX_LENGTH) << LENGTH2_SHIFT)
+}
+
+/// Encode one byte, most significant bit first
+pub fn encode_byte(byte: u8, timing: &Timing, out: &mut [u32; 8]) {
+    let zero = pulse(timing.t0h, timing.t0l);
+    let one = pulse(timing.t1h, timing.t1l);
+    for (bit, code) in out.iter_mut().enumerate() {
+        *code = if byte & (0x80 >> bit) != 0 { one } else { zero };
+    }
+}
+
+/// Encode a whole frame, scaled by `brightness`; returns the number of codes written
+pub fn encode_frame<T: From<u32>>(
+    kind: LedKind,
+    pixels: &[Pixel],
+    brightness: u8,
+    out: &mut [T],
+) -> Result<usize, Error> {
+    let len = buffer_len(kind, pixels.len());
+    let out = out.get_mut(..len).ok_or(Error::BufferTooSmall)?;
+    let timing = kind.timing();
+    let bytes_per_pixel = kind.bytes_per_pixel();
+
+    let (data, tail) = out.split_at_mut(len - 2);
+    for (pixel, codes) in pixels
+        .iter()
+        .zip(data.chunks_exact_mut(bytes_per_pixel * 8))
+    {
+        let pixel = pixel.scale(brightness);
+        let bytes = [pixel.g, pixel.r, pixel.b, pixel.w];
+        for (&byte, byte_codes) in bytes.iter().zip(codes.chunks_exact_mut(8)) {
+            let mut encoded = [0; 8];
+            encode_byte(byte, &timing, &mut encoded);
+            for (code, encoded) in byte_codes.iter_mut().zip(encoded) {
+                *code = encoded.into();
+            }
+        }
+    }
+
+    // Keep the line low long enough for the LEDs to latch, then stop
+    let half = timing.reset / 2;
+    tail[0] = idle(half, half).into();
+    tail[1] = END_MARKER.into();
+
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn high(code: u32) -> (bool, u32) {
+        (code & LEVEL1 != 0, code & MAX_LENGTH)
+    }
+
+    fn low(code: u32) -> (bool, u32) {
+        (code >> 31 != 0, (code >> LENGTH2_SHIFT) & MAX_LENGTH)
+    }
+
+    #[test]
+    fn timing_in_ticks() {
+        assert_eq!(WS2812B_TIMING.t0h, 16);
+        assert_eq!(WS2812B_TIMING.t0l, 34);
+        assert_eq!(WS2812B_TIMING.t1h, 32);
+        assert_eq!(WS2812B_TIMING.t1l, 18);
+        assert_eq!(WS2812B_TIMING.reset, 11_200);
+        assert_eq!(SK6812_TIMING.t1h, 24);
+    }
+
+    #[test]
+    fn pulse_layout() {
+        let code = pulse(32, 18);
+        assert_eq!(high(code), (true, 32));
+        assert_eq!(low(code), (false, 18));
+    }
+
+    #[test]
+    fn byte_is_msb_first() {
+        let mut out = [0; 8];
+        encode_byte(0b1010_0001, &WS2812B_TIMING, &mut out);
+        let highs: [u32; 8] = core::array::from_fn(|i| high(out[i]).1);
+        assert_eq!(highs, [32, 16, 32, 16, 16, 16, 16, 32]);
+    }
+
+    #[test]
+    fn frame_is_grb_with_reset() {
+        let mut out = [0xFFFF_FFFF; buffer_len(LedKind::Ws2812b, 2)];
+        let pixels = [Pixel::rgb(0xFF, 0x00, 0x0F), Pixel::OFF];
+        let len = encode_frame(LedKind::Ws2812b, &pixels, 255, &mut out).unwrap();
+        assert_eq!(len, 2 * 24 + 2);
+
+        let one = pulse(32, 18);
+        let zero = pulse(16, 34);
+        // green first
+        assert!(out[..8].iter().all(|&c| c == zero));
+        // then red
+        assert!(out[8..16].iter().all(|&c| c == one));
+        // then blue 0x0F
+        assert_eq!(&out[16..24], &[zero, zero, zero, zero, one, one, one, one]);
+        // second pixel is all zeros
+        assert!(out[24..48].iter().all(|&c| c == zero));
+
+        // reset keeps the line low
+        assert_eq!(high(out[48]), (false, 5600));
+        assert_eq!(low(out[48]), (false, 5600));
+        assert_eq!(out[49], END_MARKER);
+    }
+
+    #[test]
+    fn rgbw_sends_white_last() {
+        let mut out = [0u32; buffer_len(LedKind::Sk6812Rgbw, 1)];
+        let len = encode_frame(
+            LedKind::Sk6812Rgbw,
+            &[Pixel::rgbw(0, 0, 0, 0x80)],
+            255,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(len, 34);
+        assert_eq!(high(out[24]).1, SK6812_TIMING.t1h as u32);
+        assert!(
+            out[25..32]
+                .iter()
+                .all(|&c| high(c).1 == SK6812_TIMING.t0h as u32)
+        );
+    }
+
+    #[test]
+    fn brightness_scales_pixels() {
+        assert_eq!(Pixel::rgb(255, 128, 0).scale(128), Pixel::rgb(128, 64, 0));
+        assert_eq!(Pixel::rgb(255, 255, 255).scale(0), Pixel::OFF);
+    }
+
+    #[test]
+    fn small_buffer_is_rejected() {
+        let mut out = [0u32; 10];
+        assert_eq!(
+            encode_frame(LedKind::Ws2812b, &[Pixel::OFF], 255, &mut out),
+            Err(Error::BufferTooSmall)
+        );
+    }
+}
@@ -6,16 +6,25 @@
     holding buffers for the duration of a data transfer."
 )]
 
+use embassy_executor::Spawner;
+use embassy_futures::join::join4;
+use embassy_time::{Duration, Timer};
 use esp_hal::clock::CpuClock;
 use esp_hal::gpio::DriveMode;
-use esp_hal::main;
 use esp_hal::time::Rate;
+use esp_hal::timer::timg::TimerGroup;
 
 // For LEDC
 use esp_hal::ledc::channel::ChannelIFace;
 use esp_hal::ledc::timer::TimerIFace;
 use esp_hal::ledc::{LSGlobalClkSource, Ledc, LowSpeed, channel, timer};
 
+// LED effects
+use led_effects::color::Hsv;
+use led_effects::led::Led;
+use led_effects::pattern::Pattern;
+use led_effects::rgb::{RgbLed, Wiring};
+
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
     loop {}
@@ -25,40 +34,81 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
 esp_bootloader_esp_idf::esp_app_desc!();
 
-#[main]
-fn main() -> ! {
+#[esp_rtos::main]
+async fn main(_spawner: Spawner) -> ! {
     // generator version: 1.0.0
 
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
 
-    let led = peripherals.GPIO2;
-    // let led = peripherals.GPIO5;
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
 
     let mut ledc = Ledc::new(peripherals.LEDC);
     ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+    // 13 bit resolution gives the gamma curve enough room at low brightness
     let mut lstimer0 = ledc.timer::<LowSpeed>(timer::Number::Timer0);
     lstimer0
         .configure(timer::config::Config {
-            duty: timer::config::Duty::Duty5Bit,
+            duty: timer::config::Duty::Duty13Bit,
             clock_source: timer::LSClockSource::APBClk,
-            frequency: Rate::from_khz(24),
+            frequency: Rate::from_khz(5),
         })
         .unwrap();
 
-    let mut channel0 = ledc.channel(channel::Number::Channel0, led);
-    channel0
-        .configure(channel::config::Config {
-            timer: &lstimer0,
-            duty_pct: 10,
-            drive_mode: DriveMode::PushPull,
-        })
-        .unwrap();
+    // Every channel runs its own pattern at the same time
+    let mut channel0 = ledc.channel(channel::Number::Channel0, peripherals.GPIO2);
+    let mut channel1 = ledc.channel(channel::Number::Channel1, peripherals.GPIO5);
+    let mut channel2 = ledc.channel(channel::Number::Channel2, peripherals.GPIO18);
+    // RGB LED (common cathode)
+    let mut red = ledc.channel(channel::Number::Channel3, peripherals.GPIO25);
+    let mut green = ledc.channel(channel::Number::Channel4, peripherals.GPIO26);
+    let mut blue = ledc.channel(channel::Number::Channel5, peripherals.GPIO27);
+    for channel in [
+        &mut channel0,
+        &mut channel1,
+        &mut channel2,
+        &mut red,
+        &mut green,
+        &mut blue,
+    ] {
+        channel
+            .configure(channel::config::Config {
+                timer: &lstimer0,
+                duty_pct: 0,
+                drive_mode: DriveMode::PushPull,
+            })
+            .unwrap();
+    }
+
+    let mut breathe = Led::new(channel0, &lstimer0).unwrap();
+    let mut heartbeat = Led::new(channel1, &lstimer0).unwrap();
+    let mut candle = Led::new(channel2, &lstimer0).unwrap();
+    let mut rgb = RgbLed::new(red, green, blue, &lstimer0, Wiring::CommonCathode).unwrap();
+
+    let rainbow = async {
+        loop {
+            for hue in [0, 120, 240] {
+                rgb.transition_to(Hsv::new(hue, 255, 255), 2000).await;
+            }
+        }
+    };
+
+    join4(
+        breathe.run(Pattern::Breathe {
+            period_ms: 3000,
+            max: 255,
+        }),
+        heartbeat.run(Pattern::Heartbeat {
+            period_ms: 1000,
+            max: 255,
+        }),
+        candle.run(Pattern::Candle { seed: 0x1234_5678 }),
+        rainbow,
+    )
+    .await;
 
     loop {
-        channel0.start_duty_fade(0, 100, 1000).unwrap();
-        while channel0.is_duty_fade_running() {}
-        channel0.start_duty_fade(100, 0, 1000).unwrap();
-        while channel0.is_duty_fade_running() {}
+        Timer::after(Duration::from_secs(1)).await;
     }
 }
@@ -6,16 +6,22 @@
     holding buffers for the duration of a data transfer."
 )]
 
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
 use esp_hal::clock::CpuClock;
 use esp_hal::gpio::DriveMode;
-use esp_hal::main;
 use esp_hal::time::Rate;
+use esp_hal::timer::timg::TimerGroup;
 
 // For LEDC
 use esp_hal::ledc::channel::ChannelIFace;
 use esp_hal::ledc::timer::TimerIFace;
 use esp_hal::ledc::{HighSpeed, Ledc, channel, timer};
 
+// LED effects
+use led_effects::led::Led;
+use led_effects::pattern::Pattern;
+
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
     loop {}
@@ -25,24 +31,28 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
 esp_bootloader_esp_idf::esp_app_desc!();
 
-#[main]
-fn main() -> ! {
+#[esp_rtos::main]
+async fn main(_spawner: Spawner) -> ! {
     // generator version: 1.0.0
 
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
 
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
     // let led = peripherals.GPIO2;
     let led = peripherals.GPIO5;
 
     let ledc = Ledc::new(peripherals.LEDC);
 
+    // 13 bit resolution gives the gamma curve enough room at low brightness
     let mut hstimer0 = ledc.timer::<HighSpeed>(timer::Number::Timer0);
     hstimer0
         .configure(timer::config::Config {
-            duty: timer::config::Duty::Duty5Bit,
+            duty: timer::config::Duty::Duty13Bit,
             clock_source: timer::HSClockSource::APBClk,
-            frequency: Rate::from_khz(24),
+            frequency: Rate::from_khz(5),
         })
         .unwrap();
 
@@ -50,15 +60,21 @@ fn main() -> ! {
     channel0
         .configure(channel::config::Config {
             timer: &hstimer0,
-            duty_pct: 10,
+            duty_pct: 0,
             drive_mode: DriveMode::PushPull,
         })
         .unwrap();
 
+    // Fades in and out in 2 s, like the linear fade it replaces, but gamma
+    // corrected and waiting on the hardware without spinning
+    let mut led = Led::new(channel0, &hstimer0).unwrap();
+    led.run(Pattern::Breathe {
+        period_ms: 2000,
+        max: 255,
+    })
+    .await;
+
     loop {
-        channel0.start_duty_fade(0, 100, 1000).unwrap();
-        while channel0.is_duty_fade_running() {}
-        channel0.start_duty_fade(100, 0, 1000).unwrap();
-        while channel0.is_duty_fade_running() {}
+        Timer::after(Duration::from_secs(1)).await;
     }
 }
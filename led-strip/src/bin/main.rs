@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+#![deny(
+    clippy::mem_forget,
+    reason = "mem::forget is generally not safe to do with esp_hal types, especially those \
+    holding buffers for the duration of a data transfer."
+)]
+
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Instant, Ticker};
+use esp_hal::clock::CpuClock;
+use esp_hal::time::Rate;
+use esp_hal::timer::timg::TimerGroup;
+
+// For RMT
+use esp_hal::rmt::{Rmt, TxChannelConfig, TxChannelCreator};
+
+// LED strip
+use led_effects::animation::{Animation, Chase, Fire, Rainbow};
+use led_effects::ws2812::{LedKind, Pixel, buffer_len};
+use led_strip::strip::LedStrip;
+
+const NUM_LEDS: usize = 30;
+const KIND: LedKind = LedKind::Ws2812b;
+// Keep the strip within what a USB port can supply
+const MAX_CURRENT_MA: u32 = 400;
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+// This creates a default app-descriptor required by the esp-idf bootloader.
+// For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[esp_rtos::main]
+async fn main(_spawner: Spawner) -> ! {
+    // generator version: 1.0.0
+
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    // 80 MHz / 2 = 40 MHz, the tick rate the encoder expects
+    let rmt = Rmt::new(peripherals.RMT, Rate::from_mhz(80))
+        .unwrap()
+        .into_async();
+    let channel = rmt
+        .channel0
+        .configure_tx(
+            peripherals.GPIO13,
+            TxChannelConfig::default().with_clk_divider(2),
+        )
+        .unwrap();
+
+    let mut strip =
+        LedStrip::<NUM_LEDS, { buffer_len(KIND, NUM_LEDS) }>::new(channel, KIND).unwrap();
+    strip.set_brightness(128);
+    strip.set_current_limit(Some(MAX_CURRENT_MA));
+
+    let mut rainbow = Rainbow::new(2);
+    let mut chase = Chase::new(Pixel::rgb(0, 80, 255), 3);
+    let mut fire = Fire::<NUM_LEDS>::new(55, 120, 0x1234_5678);
+    let mut animations: [(&mut dyn Animation, Duration); 3] = [
+        (&mut rainbow, Duration::from_millis(20)),
+        (&mut chase, Duration::from_millis(100)),
+        (&mut fire, Duration::from_millis(16)),
+    ];
+
+    let mut pixels = [Pixel::OFF; NUM_LEDS];
+    loop {
+        // Every animation runs for 10 seconds
+        for (animation, frame_time) in animations.iter_mut() {
+            let start = Instant::now();
+            let mut ticker = Ticker::every(*frame_time);
+            while start.elapsed() < Duration::from_secs(10) {
+                animation.render(&mut pixels);
+                strip.write(&pixels).await.unwrap();
+                ticker.next().await;
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! WS2812B / SK6812 strip on an async RMT TX channel.
+
+use esp_hal::Async;
+use esp_hal::rmt::{self, Channel, PulseCode, Tx};
+
+use led_effects::animation::limit_brightness;
+use led_effects::ws2812::{self, LedKind, Pixel, buffer_len};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    Encode(ws2812::Error),
+    Rmt(rmt::Error),
+}
+
+impl From<ws2812::Error> for Error {
+    fn from(e: ws2812::Error) -> Self {
+        Error::Encode(e)
+    }
+}
+
+impl From<rmt::Error> for Error {
+    fn from(e: rmt::Error) -> Self {
+        Error::Rmt(e)
+    }
+}
+
+/// `N` is the number of LEDs and `B` the pulse buffer length, which must be
+/// at least `buffer_len(kind, N)`
+pub struct LedStrip<'a, const N: usize, const B: usize> {
+    channel: Channel<'a, Async, Tx>,
+    kind: LedKind,
+    pulses: [PulseCode; B],
+    brightness: u8,
+    max_ma: Option<u32>,
+}
+
+impl<'a, const N: usize, const B: usize> LedStrip<'a, N, B> {
+    /// The channel must be clocked at `ws2812::TICK_HZ`
+    pub fn new(channel: Channel<'a, Async, Tx>, kind: LedKind) -> Result<Self, Error> {
+        if B < buffer_len(kind, N) {
+            return Err(Error::Encode(ws2812::Error::BufferTooSmall));
+        }
+        Ok(Self {
+            channel,
+            kind,
+            pulses: [PulseCode::default(); B],
+            brightness: 255,
+            max_ma: None,
+        })
+    }
+
+    /// Global brightness applied to every frame
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Dim frames that would draw more than `max_ma` milliamps
+    pub fn set_current_limit(&mut self, max_ma: Option<u32>) {
+        self.max_ma = max_ma;
+    }
+
+    /// Send a frame; returns once the reset time after it has passed.
+    ///
+    /// The RMT refills its RAM from the pulse buffer while sending, so frames
+    /// can be longer than the channel memory.
+    pub async fn write(&mut self, pixels: &[Pixel]) -> Result<(), Error> {
+        let pixels = &pixels[..pixels.len().min(N)];
+        let brightness = match self.max_ma {
+            Some(max_ma) => limit_brightness(pixels, self.brightness, max_ma),
+            None => self.brightness,
+        };
+        let len = ws2812::encode_frame(self.kind, pixels, brightness, &mut self.pulses)?;
+        self.channel.transmit(&self.pulses[..len]).await?;
+        Ok(())
+    }
+
+    /// Turn every LED off
+    pub async fn clear(&mut self) -> Result<(), Error> {
+        self.write(&[Pixel::OFF; N]).await
+    }
+
+    pub fn release(self) -> Channel<'a, Async, Tx> {
+        self.channel
+    }
+}
@@ -24,6 +24,9 @@ use embedded_hal_bus::spi::ExclusiveDevice;
 use esp_hal::delay::Delay;
 use mfrc522::Mfrc522;
 use mfrc522::comm::blocking::spi::SpiInterface;
+use rfid_change_key::access::AccessConditions;
+use rfid_change_key::reader::{self, Error};
+use rfid_change_key::rotate::{self, Rotation};
 
 use esp_println::{self as _, print, println};
 
@@ -72,76 +75,64 @@ async fn main(spawner: Spawner) -> ! {
     let mut rfid = Mfrc522::new(spi_interface).init().unwrap();
 
     let target_sector = 1;
-    let rel_block = 3; //relative block within the sector (4th block within the sector 1)
-    const DATA: [u8; 16] = [
-        0x52, 0x75, 0x73, 0x74, 0x65, 0x64, // Key A: "Rusted"
-        0xFF, 0x07, 0x80, 0x69, // Access bits and trailer byte
-        0x46, 0x65, 0x72, 0x72, 0x69, 0x73, // Key B: "Ferris"
-    ];
-    let current_key = &[0xFF; 6];
+    const KEY_A: [u8; 6] = *b"Rusted";
+    const KEY_B: [u8; 6] = *b"Ferris";
+    let current_key = [0xFF; 6];
     // reset to 0xFF, if you want
-    // const DATA: [u8; 16] = [
-    //     0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, // Key A: "Rusted"
-    //     0xFF, 0x07, 0x80, 0x69, // Access bits and trailer byte
-    //     0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, // Key B: "Ferris"
-    // ];
-    // let current_key = &[0x52, 0x75, 0x73, 0x74, 0x65, 0x64];
-    let new_key: &[u8; 6] = &DATA[..6].try_into().unwrap(); // First 6 bytes of the block
+    // const KEY_A: [u8; 6] = [0xFF; 6];
+    // const KEY_B: [u8; 6] = [0xFF; 6];
+    // let current_key = *b"Rusted";
+
+    // Factory access conditions: key A can still change everything later.
+    // Conditions that would freeze the sector are refused.
+    let conditions = AccessConditions::TRANSPORT;
+    println!("New access conditions:\r\n{}", conditions);
+    let rotation = Rotation {
+        sectors: target_sector..=target_sector,
+        old_key_a: current_key,
+        old_key_b: None,
+        key_a: KEY_A,
+        key_b: KEY_B,
+        access: Some(conditions),
+    };
 
     loop {
-        if let Ok(atqa) = rfid.reqa() {
-            println!("Got atqa");
-            Timer::after(Duration::from_millis(50)).await;
-            if let Ok(uid) = rfid.select(&atqa) {
-                println!("\r\n----Before Write----");
-                read_sector(&uid, target_sector, &mut rfid, current_key);
-
-                write_block(&uid, target_sector, rel_block, DATA, &mut rfid, current_key);
-
-                println!("\r\n----After Write----");
-                read_sector(&uid, target_sector, &mut rfid, new_key);
-                rfid.hlta().unwrap();
-                rfid.stop_crypto1().unwrap();
-            }
+        let result = reader::with_card(&mut rfid, |uid, rfid| {
+            println!("\r\n----Before Write----");
+            read_sector(uid, target_sector, rfid, &current_key)?;
+
+            // the old trailer is backed up and put back if the new keys fail
+            let outcome = rotate::rotate_sector(uid, target_sector, &rotation, rfid);
+            println!("\r\nSector {}: {}", target_sector, outcome);
+
+            println!("\r\n----After Write----");
+            let key = if outcome.is_rotated() {
+                &KEY_A
+            } else {
+                &current_key
+            };
+            read_sector(uid, target_sector, rfid, key)
+        });
+        match result {
+            Ok(()) => {}
+            // nothing in the field
+            Err(Error::NoCard) => {}
+            Err(e) => println!("{}", e),
         }
+        Timer::after(Duration::from_millis(50)).await;
     }
 }
 
-fn write_block<E, COMM: mfrc522::comm::Interface<Error = E>>(
-    uid: &mfrc522::Uid,
-    sector: u8,
-    rel_block: u8,
-    data: [u8; 16],
-    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-    auth_key: &[u8; 6], //additional argument for the auth key
-) {
-    let block_offset = sector * 4;
-    let abs_block = block_offset + rel_block;
-
-    rfid.mf_authenticate(uid, block_offset, auth_key)
-        .map_err(|_| "Auth failed")
-        .unwrap();
-
-    rfid.mf_write(abs_block, data)
-        .map_err(|_| "Write failed")
-        .unwrap();
-}
-
 fn read_sector<E, COMM: mfrc522::comm::Interface<Error = E>>(
     uid: &mfrc522::Uid,
     sector: u8,
     rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
     auth_key: &[u8; 6], //additional argument for the auth key
-) {
-    let block_offset = sector * 4;
-    rfid.mf_authenticate(uid, block_offset, auth_key)
-        .map_err(|_| "Auth failed")
-        .unwrap();
-
-    for abs_block in block_offset..block_offset + 4 {
-        let data = rfid.mf_read(abs_block).map_err(|_| "Read failed").unwrap();
+) -> Result<(), Error> {
+    for data in reader::read_sector(uid, sector, auth_key, rfid)? {
         print_hex_bytes(&data);
     }
+    Ok(())
 }
 
 fn print_hex_bytes(data: &[u8]) {
@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+#![deny(
+    clippy::mem_forget,
+    reason = "mem::forget is generally not safe to do with esp_hal types, especially those \
+    holding buffers for the duration of a data transfer."
+)]
+
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
+use esp_hal::clock::CpuClock;
+use esp_hal::timer::timg::TimerGroup;
+use esp_println as _;
+
+// SPI
+use esp_hal::gpio::{Level, Output, OutputConfig};
+use esp_hal::spi;
+use esp_hal::spi::master::Spi;
+use esp_hal::time::Rate;
+
+// RFID Reader
+use embedded_hal_bus::spi::ExclusiveDevice;
+use esp_hal::delay::Delay;
+use mfrc522::Mfrc522;
+use mfrc522::comm::blocking::spi::SpiInterface;
+use rfid_change_key::reader::{self, Error};
+use rfid_change_key::rotate::{self, Outcome};
+
+use esp_println::{self as _, println};
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+// This creates a default app-descriptor required by the esp-idf bootloader.
+// For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
+esp_bootloader_esp_idf::esp_app_desc!();
+
+// The sectors and keys to rotate, see keys.txt
+const KEY_FILE: &str = include_str!("../../keys.txt");
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
+    // generator version: 1.0.0
+
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    info!("Embassy initialized!");
+
+    // TODO: Spawn some tasks
+    let _ = spawner;
+
+    let spi_bus = Spi::new(
+        peripherals.SPI2,
+        spi::master::Config::default()
+            .with_frequency(Rate::from_mhz(5))
+            .with_mode(spi::Mode::_0),
+    )
+    .unwrap()
+    .with_sck(peripherals.GPIO18)
+    .with_mosi(peripherals.GPIO23)
+    .with_miso(peripherals.GPIO19)
+    .into_async();
+
+    let sd_cs = Output::new(peripherals.GPIO5, Level::High, OutputConfig::default());
+
+    let delay = Delay::new();
+    let spi_dev = ExclusiveDevice::new(spi_bus, sd_cs, delay).unwrap();
+
+    let spi_interface = SpiInterface::new(spi_dev);
+    let mut rfid = Mfrc522::new(spi_interface).init().unwrap();
+
+    for (line, e) in rotate::parse(KEY_FILE).filter_map(Result::err) {
+        println!("keys.txt line {}: {:?}, ignored", line, e);
+    }
+
+    loop {
+        let result = reader::with_card(&mut rfid, |uid, rfid| {
+            let mut rotated = 0;
+            let mut other = 0;
+            for rotation in rotate::parse(KEY_FILE).filter_map(Result::ok) {
+                rotate::rotate(uid, &rotation, rfid, |sector, outcome| {
+                    println!("Sector {:>2}: {}", sector, outcome);
+                    match outcome {
+                        Outcome::Rotated => rotated += 1,
+                        _ => other += 1,
+                    }
+                })?;
+            }
+            println!("{} sectors rotated, {} not", rotated, other);
+            Ok(())
+        });
+        match result {
+            Ok(()) => println!("Present the next card to rotate it too"),
+            // nothing in the field
+            Err(Error::NoCard) => {}
+            Err(e) => println!("{}", e),
+        }
+        Timer::after(Duration::from_millis(50)).await;
+    }
+}
@@ -1 +1,4 @@
 #![no_std]
+pub use rfid_common::access;
+pub use rfid_common::reader;
+pub use rfid_common::rotate;
@@ -0,0 +1,354 @@
+//! MIFARE Classic access conditions.
+//!
+//! Bytes 6 to 8 of a sector trailer hold three bits, C1, C2 and C3, for each
+//! of the three data blocks and for the trailer itself. Every bit is stored
+//! twice, once inverted; a card that finds the two copies disagreeing blocks
+//! the whole sector for good. [`AccessConditions`] does the bit shuffling and
+//! [`AccessConditions::trailer`] refuses to build trailers that can never be
+//! changed again unless told to.
+//!
+//! On 4K cards the sectors above 31 have 15 data blocks; there each data
+//! condition covers a group of five blocks.
+
+use core::fmt;
+
+/// What the card stores in byte 9 of a fresh trailer. It is free for the
+/// application to use.
+pub const DEFAULT_USER_BYTE: u8 = 0x69;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The inverted copies of the bits do not match
+    Inconsistent,
+    /// Nobody could ever change the access bits of the sector again
+    Locked,
+}
+
+/// The C1, C2 and C3 bits of one block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub c1: bool,
+    pub c2: bool,
+    pub c3: bool,
+}
+
+/// Which keys may do something
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keys {
+    Never,
+    A,
+    B,
+    Both,
+}
+
+/// What the keys may do with a data block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataPermissions {
+    pub read: Keys,
+    pub write: Keys,
+    pub increment: Keys,
+    /// Decrement, transfer and restore
+    pub decrement: Keys,
+}
+
+/// What the keys may do with the sector trailer. Key A can never be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrailerPermissions {
+    pub key_a_write: Keys,
+    pub access_read: Keys,
+    pub access_write: Keys,
+    pub key_b_read: Keys,
+    pub key_b_write: Keys,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessConditions {
+    pub data: [Condition; 3],
+    pub trailer: Condition,
+}
+
+impl Condition {
+    pub const fn new(c1: bool, c2: bool, c3: bool) -> Self {
+        Self { c1, c2, c3 }
+    }
+
+    // pick one block's bits out of the C1, C2 and C3 nibbles
+    const fn of_block(bits: [u8; 3], block: u8) -> Self {
+        Self::new(
+            bits[0] >> block & 1 == 1,
+            bits[1] >> block & 1 == 1,
+            bits[2] >> block & 1 == 1,
+        )
+    }
+
+    // row in the datasheet tables, C1 C2 C3 read as a binary number
+    const fn index(&self) -> usize {
+        (self.c1 as usize) << 2 | (self.c2 as usize) << 1 | self.c3 as usize
+    }
+
+    /// Permissions when this condition is used for a data block
+    pub const fn data(&self) -> DataPermissions {
+        use Keys::*;
+        let (read, write, increment, decrement) = match self.index() {
+            0b000 => (Both, Both, Both, Both),
+            0b010 => (Both, Never, Never, Never),
+            0b100 => (Both, B, Never, Never),
+            0b110 => (Both, B, B, Both),
+            0b001 => (Both, Never, Never, Both),
+            0b011 => (B, B, Never, Never),
+            0b101 => (B, Never, Never, Never),
+            _ => (Never, Never, Never, Never),
+        };
+        DataPermissions {
+            read,
+            write,
+            increment,
+            decrement,
+        }
+    }
+
+    /// Permissions when this condition is used for the sector trailer
+    pub const fn trailer(&self) -> TrailerPermissions {
+        use Keys::*;
+        let (key_a_write, access_read, access_write, key_b_read, key_b_write) = match self.index() {
+            0b000 => (A, A, Never, A, A),
+            0b010 => (Never, A, Never, A, Never),
+            0b100 => (B, Both, Never, Never, B),
+            0b001 => (A, A, A, A, A),
+            0b011 => (B, Both, B, Never, B),
+            0b101 => (Never, Both, B, Never, Never),
+            _ => (Never, Both, Never, Never, Never),
+        };
+        TrailerPermissions {
+            key_a_write,
+            access_read,
+            access_write,
+            key_b_read,
+            key_b_write,
+        }
+    }
+}
+
+impl Keys {
+    const fn without_b(self) -> Self {
+        match self {
+            Keys::Both => Keys::A,
+            Keys::B => Keys::Never,
+            keys => keys,
+        }
+    }
+}
+
+impl DataPermissions {
+    const fn without_b(self) -> Self {
+        Self {
+            read: self.read.without_b(),
+            write: self.write.without_b(),
+            increment: self.increment.without_b(),
+            decrement: self.decrement.without_b(),
+        }
+    }
+}
+
+impl AccessConditions {
+    /// How cards leave the factory: key A or B can do anything with the
+    /// data blocks, key A manages the trailer (`FF 07 80`)
+    pub const TRANSPORT: Self = Self {
+        data: [Condition::new(false, false, false); 3],
+        trailer: Condition::new(false, false, true),
+    };
+
+    /// Decode bytes 6 to 8 of a sector trailer
+    pub const fn decode(bytes: &[u8; 3]) -> Result<Self, Error> {
+        let [b6, b7, b8] = *bytes;
+        let (c1, c2, c3) = (b7 >> 4, b8 & 0x0F, b8 >> 4);
+        if b6 & 0x0F != !c1 & 0x0F || b6 >> 4 != !c2 & 0x0F || b7 & 0x0F != !c3 & 0x0F {
+            return Err(Error::Inconsistent);
+        }
+        let bits = [c1, c2, c3];
+        Ok(Self {
+            data: [
+                Condition::of_block(bits, 0),
+                Condition::of_block(bits, 1),
+                Condition::of_block(bits, 2),
+            ],
+            trailer: Condition::of_block(bits, 3),
+        })
+    }
+
+    /// Bytes 6 to 8 of a sector trailer, inverted copies included
+    pub const fn encode(&self) -> [u8; 3] {
+        let blocks = [self.data[0], self.data[1], self.data[2], self.trailer];
+        let (mut c1, mut c2, mut c3) = (0u8, 0u8, 0u8);
+        let mut block = 0;
+        while block < 4 {
+            c1 |= (blocks[block].c1 as u8) << block;
+            c2 |= (blocks[block].c2 as u8) << block;
+            c3 |= (blocks[block].c3 as u8) << block;
+            block += 1;
+        }
+        [
+            (!c2 & 0x0F) << 4 | (!c1 & 0x0F),
+            c1 << 4 | (!c3 & 0x0F),
+            c3 << 4 | c2,
+        ]
+    }
+
+    /// Whether nobody can change the access bits any more. The keys might
+    /// still be writable, but the conditions themselves are final.
+    pub const fn is_locked(&self) -> bool {
+        matches!(self.trailer.trailer().access_write, Keys::Never)
+    }
+
+    /// Whether key B can be read from the trailer. The card then refuses to
+    /// authenticate data blocks with it.
+    pub const fn key_b_readable(&self) -> bool {
+        !matches!(self.trailer.trailer().key_b_read, Keys::Never)
+    }
+
+    /// Permissions of data block `block` (0 to 2), taking into account
+    /// that a readable key B can't be used
+    pub const fn data_permissions(&self, block: usize) -> DataPermissions {
+        let permissions = self.data[block].data();
+        if self.key_b_readable() {
+            permissions.without_b()
+        } else {
+            permissions
+        }
+    }
+
+    /// A whole sector trailer block with these conditions. Conditions that
+    /// would lock the sector are refused unless `force` is set.
+    pub fn trailer(
+        &self,
+        key_a: &[u8; 6],
+        key_b: &[u8; 6],
+        force: bool,
+    ) -> Result<[u8; 16], Error> {
+        if self.is_locked() && !force {
+            return Err(Error::Locked);
+        }
+        let mut block = [0u8; 16];
+        block[..6].copy_from_slice(key_a);
+        block[6..9].copy_from_slice(&self.encode());
+        block[9] = DEFAULT_USER_BYTE;
+        block[10..].copy_from_slice(key_b);
+        Ok(block)
+    }
+}
+
+impl fmt::Display for Keys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys = match self {
+            Keys::Never => "-",
+            Keys::A => "A",
+            Keys::B => "B",
+            Keys::Both => "A|B",
+        };
+        f.pad(keys)
+    }
+}
+
+/// Permission table, one line per block
+impl fmt::Display for AccessConditions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "          read  write  incr  decr")?;
+        for block in 0..3 {
+            let p = self.data_permissions(block);
+            writeln!(
+                f,
+                "block {}   {:<5} {:<6} {:<5} {}",
+                block, p.read, p.write, p.increment, p.decrement
+            )?;
+        }
+        let t = self.trailer.trailer();
+        writeln!(f, "          key A  access  key B  (read/write)")?;
+        write!(
+            f,
+            "trailer   -/{:<4} {}/{:<4} {}/{}",
+            t.key_a_write, t.access_read, t.access_write, t.key_b_read, t.key_b_write
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn transport_bytes() {
+        assert_eq!(AccessConditions::TRANSPORT.encode(), [0xFF, 0x07, 0x80]);
+        assert_eq!(
+            AccessConditions::decode(&[0xFF, 0x07, 0x80]),
+            Ok(AccessConditions::TRANSPORT)
+        );
+        assert!(!AccessConditions::TRANSPORT.is_locked());
+        assert!(AccessConditions::TRANSPORT.key_b_readable());
+    }
+
+    #[test]
+    fn round_trips_every_combination() {
+        for bits in 0..4096u16 {
+            let condition = |block: u16| {
+                let c = bits >> (block * 3);
+                Condition::new(c & 4 != 0, c & 2 != 0, c & 1 != 0)
+            };
+            let conditions = AccessConditions {
+                data: [condition(0), condition(1), condition(2)],
+                trailer: condition(3),
+            };
+            assert_eq!(
+                AccessConditions::decode(&conditions.encode()),
+                Ok(conditions)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_flipped_bits() {
+        let bytes = AccessConditions::TRANSPORT.encode();
+        for byte in 0..3 {
+            for bit in 0..8 {
+                let mut bad = bytes;
+                bad[byte] ^= 1 << bit;
+                assert_eq!(AccessConditions::decode(&bad), Err(Error::Inconsistent));
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_locking_trailers() {
+        // read-only data, trailer frozen: 07 87 8F
+        let frozen = AccessConditions {
+            data: [Condition::new(false, true, false); 3],
+            trailer: Condition::new(true, true, true),
+        };
+        assert!(frozen.is_locked());
+        assert_eq!(frozen.trailer(&[0; 6], &[0; 6], false), Err(Error::Locked));
+        assert!(frozen.trailer(&[0; 6], &[0; 6], true).is_ok());
+
+        // key B manages the sector: fine
+        let key_b = AccessConditions {
+            data: [Condition::new(true, false, false); 3],
+            trailer: Condition::new(false, true, true),
+        };
+        let block = key_b.trailer(&[0xA; 6], &[0xB; 6], false).unwrap();
+        assert_eq!(block[..6], [0xA; 6]);
+        assert_eq!(block[6..10], [0x78, 0x77, 0x88, DEFAULT_USER_BYTE]);
+        assert_eq!(block[10..], [0xB; 6]);
+    }
+
+    #[test]
+    fn readable_key_b_is_useless_for_data() {
+        let p = AccessConditions::TRANSPORT.data_permissions(0);
+        assert_eq!(p.read, Keys::A);
+        assert_eq!(p.write, Keys::A);
+
+        let table = AccessConditions::TRANSPORT.to_string();
+        assert!(table.contains("block 2   A     A      A     A"));
+        assert!(table.ends_with("trailer   -/A    A/A    A/A"));
+    }
+}
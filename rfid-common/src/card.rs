@@ -0,0 +1,356 @@
+//! Card identification from ATQA and SAK.
+//!
+//! `mfrc522` keeps the ATQA and SAK bytes to itself and only guesses a
+//! type from the SAK. [`select`] replaces `Mfrc522::select`: once the
+//! driver has resolved the UID, it halts the card and selects it again by
+//! hand to see both bytes, then asks Ultralight-like tags for their version
+//! to tell NTAGs apart.
+//!
+//! ```ignore
+//! let atqa = rfid.reqa()?;
+//! let selected = card::select(&atqa, &mut rfid)?;
+//! println!("{}", selected.card);
+//! rfid.mf_authenticate(&card::auth_uid(&selected.uid), 4, &[0xFF; 6])?;
+//! ```
+//!
+//! Cards with 7- and 10-byte UIDs answer authentication with the last four
+//! bytes of their UID, which [`auth_uid`] picks out.
+
+use core::fmt;
+
+use mfrc522::{AtqA, GenericUid, Mfrc522, Uid};
+
+use crate::crc::crc_a;
+
+// ISO/IEC 14443-3 commands
+const WUPA: u8 = 0x52;
+const SELECT: [u8; 3] = [0x93, 0x95, 0x97];
+// number of valid bits of a SELECT with the complete UID of a level
+const NVB_COMPLETE: u8 = 0x70;
+// first byte of a cascade level that isn't the last
+const CASCADE_TAG: u8 = 0x88;
+// NTAG and Ultralight EV1
+const GET_VERSION: u8 = 0x60;
+
+// SAK bits
+const SAK_UID_INCOMPLETE: u8 = 0x04;
+const SAK_ISO14443_4: u8 = 0x20;
+
+// ATQA of MIFARE DESFire, 0x0344, as received: low byte first
+const ATQA_DESFIRE: [u8; 2] = [0x44, 0x03];
+
+// vendor byte of NXP and product types in the GET_VERSION answer
+const VENDOR_NXP: u8 = 0x04;
+const PRODUCT_ULTRALIGHT: u8 = 0x03;
+const PRODUCT_NTAG: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Card {
+    ClassicMini,
+    Classic1K,
+    Classic4K,
+    /// MIFARE Plus in security level 2
+    Plus,
+    /// Ultralight or Ultralight C, which don't answer GET_VERSION
+    Ultralight,
+    UltralightEv1,
+    /// NTAG21x, with the model when the memory size is a known one
+    Ntag(Option<u16>),
+    Desfire,
+    /// Another smart card speaking ISO/IEC 14443-4
+    Iso14443_4,
+    Unknown,
+}
+
+impl Card {
+    /// Work out the card from what it answered. `version` is the answer to
+    /// GET_VERSION, for cards that gave one.
+    pub fn identify(atqa: [u8; 2], sak: u8, version: Option<&[u8; 8]>) -> Self {
+        // bit 8 of the SAK is set by some non-NXP cards, ignore it
+        match sak & 0x7F {
+            0x09 => Card::ClassicMini,
+            // with bit 6 the card also speaks ISO/IEC 14443-4, like a
+            // Plus in security level 1
+            0x08 | 0x28 => Card::Classic1K,
+            0x18 | 0x38 => Card::Classic4K,
+            0x10 | 0x11 => Card::Plus,
+            0x00 => match version {
+                Some(v) if v[1] == VENDOR_NXP && v[2] == PRODUCT_NTAG => Card::Ntag(match v[6] {
+                    0x0B => Some(210),
+                    0x0E => Some(212),
+                    0x0F => Some(213),
+                    0x11 => Some(215),
+                    0x13 => Some(216),
+                    _ => None,
+                }),
+                Some(v) if v[1] == VENDOR_NXP && v[2] == PRODUCT_ULTRALIGHT => Card::UltralightEv1,
+                _ => Card::Ultralight,
+            },
+            sak if sak & SAK_ISO14443_4 != 0 && atqa == ATQA_DESFIRE => Card::Desfire,
+            sak if sak & SAK_ISO14443_4 != 0 => Card::Iso14443_4,
+            _ => Card::Unknown,
+        }
+    }
+
+    /// Whether the card has sectors opened with MIFARE Classic keys
+    pub fn is_classic(&self) -> bool {
+        matches!(self, Card::ClassicMini | Card::Classic1K | Card::Classic4K)
+    }
+
+    /// Number of 16-byte blocks of a MIFARE Classic card, 0 for the others
+    pub fn blocks(&self) -> u16 {
+        match self {
+            Card::ClassicMini => 20,
+            Card::Classic1K => 64,
+            Card::Classic4K => 256,
+            _ => 0,
+        }
+    }
+
+    /// Number of sectors of a MIFARE Classic card, 0 for the others. On 4K
+    /// cards the last 8 sectors have 16 blocks instead of 4.
+    pub fn sectors(&self) -> u8 {
+        match self {
+            Card::ClassicMini => 5,
+            Card::Classic1K => 16,
+            Card::Classic4K => 40,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Card::ClassicMini | Card::Classic1K | Card::Classic4K => {
+                let name = match self {
+                    Card::ClassicMini => "Mini",
+                    Card::Classic1K => "1K",
+                    _ => "4K",
+                };
+                write!(
+                    f,
+                    "MIFARE Classic {} ({} sectors, {} bytes)",
+                    name,
+                    self.sectors(),
+                    self.blocks() * 16
+                )
+            }
+            Card::Plus => write!(f, "MIFARE Plus (security level 2)"),
+            Card::Ultralight => write!(f, "MIFARE Ultralight or Ultralight C"),
+            Card::UltralightEv1 => write!(f, "MIFARE Ultralight EV1"),
+            Card::Ntag(Some(model)) => write!(f, "NTAG{}", model),
+            Card::Ntag(None) => write!(f, "NTAG21x"),
+            Card::Desfire => write!(f, "MIFARE DESFire"),
+            Card::Iso14443_4 => write!(f, "ISO/IEC 14443-4 smart card"),
+            Card::Unknown => write!(f, "unknown card"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error<E> {
+    Driver(mfrc522::Error<E>),
+}
+
+impl<E> From<mfrc522::Error<E>> for Error<E> {
+    fn from(e: mfrc522::Error<E>) -> Self {
+        Error::Driver(e)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Driver(e) => write!(f, "reader error: {:?}", e),
+        }
+    }
+}
+
+/// A selected card
+pub struct Selected {
+    pub uid: Uid,
+    pub atqa: [u8; 2],
+    pub sak: u8,
+    pub card: Card,
+}
+
+/// The UID to authenticate with. Cards with 7- and 10-byte UIDs use the
+/// last four bytes, while the driver sends the first four. Only the bytes
+/// are used, the SAK doesn't matter.
+pub fn auth_uid(uid: &Uid) -> Uid {
+    let bytes = uid.as_bytes();
+    let mut last = [0u8; 4];
+    last.copy_from_slice(&bytes[bytes.len() - 4..]);
+    Uid::Single(GenericUid::new(last, 0x08))
+}
+
+/// Select the card that answered `atqa` and identify it
+pub fn select<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    atqa: &AtqA,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<Selected, Error<E>> {
+    let uid = rfid.select(atqa)?;
+
+    // halt the card and wake it up by hand to see the ATQA
+    rfid.hlta()?;
+    let answer = rfid.transceive::<2>(&[WUPA], 7, 0)?;
+    if answer.valid_bytes != 2 {
+        return Err(Error::Driver(mfrc522::Error::IncompleteFrame));
+    }
+    let atqa_bytes = answer.buffer;
+    let sak = select_uid(uid.as_bytes(), rfid)?;
+
+    let version = if sak & 0x7F == 0 {
+        let version = get_version(rfid);
+        if version.is_none() {
+            // cards that don't know the command go back to idle
+            let atqa = rfid.wupa()?;
+            rfid.select(&atqa)?;
+        }
+        version
+    } else {
+        None
+    };
+
+    Ok(Selected {
+        card: Card::identify(atqa_bytes, sak, version.as_ref()),
+        uid,
+        atqa: atqa_bytes,
+        sak,
+    })
+}
+
+/// The SELECT frames of each cascade level for a 4, 7 or 10-byte UID, and
+/// how many there are
+fn select_frames(uid: &[u8]) -> ([[u8; 9]; 3], usize) {
+    let levels = match uid.len() {
+        4 => 1,
+        7 => 2,
+        _ => 3,
+    };
+    let mut frames = [[0u8; 9]; 3];
+    let mut rest = uid;
+    for (level, frame) in frames.iter_mut().enumerate().take(levels) {
+        frame[0] = SELECT[level];
+        frame[1] = NVB_COMPLETE;
+        if level + 1 < levels {
+            frame[2] = CASCADE_TAG;
+            frame[3..6].copy_from_slice(&rest[..3]);
+            rest = &rest[3..];
+        } else {
+            frame[2..6].copy_from_slice(&rest[..4]);
+        }
+        frame[6] = frame[2..6].iter().fold(0, |bcc, b| bcc ^ b);
+        let crc = crc_a(&frame[..7]);
+        frame[7..].copy_from_slice(&crc);
+    }
+    (frames, levels)
+}
+
+// Select the card with its known UID and return the final SAK
+fn select_uid<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &[u8],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<u8, Error<E>> {
+    let (frames, levels) = select_frames(uid);
+    let mut sak = 0;
+    for frame in &frames[..levels] {
+        let answer = rfid.transceive::<3>(frame, 0, 0)?;
+        if answer.valid_bytes != 3 || crc_a(&answer.buffer[..1]) != answer.buffer[1..] {
+            return Err(Error::Driver(mfrc522::Error::Crc));
+        }
+        sak = answer.buffer[0];
+    }
+    if sak & SAK_UID_INCOMPLETE != 0 {
+        return Err(Error::Driver(mfrc522::Error::IncompleteFrame));
+    }
+    Ok(sak)
+}
+
+// The 8-byte version of NTAG and Ultralight EV1 tags
+fn get_version<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Option<[u8; 8]> {
+    let crc = crc_a(&[GET_VERSION]);
+    let answer = rfid
+        .transceive::<10>(&[GET_VERSION, crc[0], crc[1]], 0, 0)
+        .ok()?;
+    let mut version = [0u8; 8];
+    version.copy_from_slice(&answer.buffer[..8]);
+    (answer.valid_bytes == 10 && crc_a(&version) == answer.buffer[8..]).then_some(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifies_common_cards() {
+        assert_eq!(Card::identify([0x04, 0x00], 0x08, None), Card::Classic1K);
+        assert_eq!(Card::identify([0x02, 0x00], 0x18, None), Card::Classic4K);
+        assert_eq!(Card::identify([0x04, 0x00], 0x09, None), Card::ClassicMini);
+        // Infineon cards set bit 8
+        assert_eq!(Card::identify([0x04, 0x00], 0x88, None), Card::Classic1K);
+        assert_eq!(Card::identify([0x44, 0x03], 0x20, None), Card::Desfire);
+        assert_eq!(Card::identify([0x08, 0x00], 0x20, None), Card::Iso14443_4);
+        assert_eq!(Card::identify([0x44, 0x00], 0x00, None), Card::Ultralight);
+        assert_eq!(Card::identify([0x04, 0x00], 0x53, None), Card::Unknown);
+    }
+
+    #[test]
+    fn tells_tags_apart_by_version() {
+        let ntag215 = [0x00, 0x04, 0x04, 0x02, 0x01, 0x00, 0x11, 0x03];
+        let ul_ev1 = [0x00, 0x04, 0x03, 0x01, 0x01, 0x00, 0x0B, 0x03];
+        let atqa = [0x44, 0x00];
+        assert_eq!(
+            Card::identify(atqa, 0x00, Some(&ntag215)),
+            Card::Ntag(Some(215))
+        );
+        assert_eq!(
+            Card::identify(atqa, 0x00, Some(&ul_ev1)),
+            Card::UltralightEv1
+        );
+    }
+
+    #[test]
+    fn builds_cascade_frames() {
+        let (frames, levels) = select_frames(&[0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(levels, 1);
+        assert_eq!(frames[0][..7], [0x93, 0x70, 0xDE, 0xAD, 0xBE, 0xEF, 0x22]);
+
+        let uid = [0x04, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+        let (frames, levels) = select_frames(&uid);
+        assert_eq!(levels, 2);
+        assert_eq!(frames[0][..7], [0x93, 0x70, 0x88, 0x04, 0x11, 0x22, 0xBF]);
+        assert_eq!(frames[1][..7], [0x95, 0x70, 0x33, 0x44, 0x55, 0x66, 0x44]);
+        assert_eq!(frames[1][7..], crc_a(&frames[1][..7]));
+
+        let (frames, levels) = select_frames(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(levels, 3);
+        assert_eq!(frames[1][2..6], [CASCADE_TAG, 4, 5, 6]);
+        assert_eq!(frames[2][..6], [0x97, 0x70, 7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn authenticates_with_last_uid_bytes() {
+        let single = Uid::Single(GenericUid::new([1, 2, 3, 4], 0x08));
+        assert_eq!(auth_uid(&single).as_bytes(), [1, 2, 3, 4]);
+        let double = Uid::Double(GenericUid::new([4, 5, 6, 7, 8, 9, 10], 0x08));
+        assert_eq!(auth_uid(&double).as_bytes(), [7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn describes_cards() {
+        extern crate std;
+        use std::string::ToString;
+
+        assert_eq!(
+            Card::Classic1K.to_string(),
+            "MIFARE Classic 1K (16 sectors, 1024 bytes)"
+        );
+        assert_eq!(Card::Ntag(Some(213)).to_string(), "NTAG213");
+        let e: Error<()> = Error::Driver(mfrc522::Error::Crc);
+        assert_eq!(e.to_string(), "reader error: Crc");
+    }
+}
@@ -0,0 +1,23 @@
+//! The CRC_A of ISO/IEC 14443-3, which ends every frame after anticollision.
+
+/// CRC_A of `data`, low byte first, the order it goes on the air
+pub fn crc_a(data: &[u8]) -> [u8; 2] {
+    let crc = data.iter().fold(0x6363u16, |crc, &b| {
+        let b = b ^ crc as u8;
+        let b = b ^ (b << 4);
+        (crc >> 8) ^ ((b as u16) << 8) ^ ((b as u16) << 3) ^ ((b as u16) >> 4)
+    });
+    crc.to_le_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_known_frames() {
+        assert_eq!(crc_a(&[0x30, 0x00]), [0x02, 0xA8]);
+        assert_eq!(crc_a(&[0x50, 0x00]), [0x57, 0xCD]);
+        assert_eq!(crc_a(&[]), [0x63, 0x63]);
+    }
+}
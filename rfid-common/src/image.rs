@@ -0,0 +1,724 @@
+//! Card images in the formats other tools use.
+//!
+//! A [`Dump`] holds every block of a MIFARE Classic Mini, 1K or 4K card.
+//! It can be saved as a plain `.mfd` image (the blocks one after another,
+//! as written by libnfc's `nfc-mfclassic`) or as Proxmark3-style JSON, and
+//! loaded again from either. Blocks that could not be read are dashes in
+//! JSON and zeros in `.mfd` images, which have no other way to mark them;
+//! so an `.mfd` image loads its all-zero blocks as unread. Unread blocks are
+//! never written back.
+//!
+//! Readers take the file a piece at a time, so a 4K image doesn't have to
+//! fit in memory twice:
+//!
+//! ```ignore
+//! let mut reader = JsonReader::new(&mut dump);
+//! while let Ok(len @ 1..) = file.read(&mut buf) {
+//!     reader.feed(&buf[..len])?;
+//! }
+//! reader.finish()?;
+//! ```
+
+use core::fmt::{self, Write};
+
+use crate::access::AccessConditions;
+use crate::keys::Key;
+
+pub const BLOCK_LEN: usize = 16;
+pub const MAX_BLOCKS: usize = 256;
+pub const MAX_SECTORS: usize = 40;
+
+pub type Block = [u8; BLOCK_LEN];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Not the size of a Mini, 1K or 4K card
+    Size,
+    /// Malformed JSON, or a value that isn't hex of the right length
+    Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardSize {
+    Mini,
+    K1,
+    K4,
+}
+
+impl CardSize {
+    pub const fn blocks(self) -> usize {
+        match self {
+            CardSize::Mini => 20,
+            CardSize::K1 => 64,
+            CardSize::K4 => 256,
+        }
+    }
+
+    pub const fn sectors(self) -> u8 {
+        match self {
+            CardSize::Mini => 5,
+            CardSize::K1 => 16,
+            CardSize::K4 => 40,
+        }
+    }
+
+    /// The size of the card that answered with `uid`, from its SAK. Cards
+    /// other than MIFARE Classic are [`Error::Size`].
+    pub fn of(uid: &mfrc522::Uid) -> Result<Self, Error> {
+        match uid.get_type() {
+            mfrc522::Type::MifareMini => Ok(CardSize::Mini),
+            mfrc522::Type::Mifare1k => Ok(CardSize::K1),
+            mfrc522::Type::Mifare4k => Ok(CardSize::K4),
+            _ => Err(Error::Size),
+        }
+    }
+
+    pub const fn from_blocks(blocks: usize) -> Result<Self, Error> {
+        match blocks {
+            20 => Ok(CardSize::Mini),
+            64 => Ok(CardSize::K1),
+            256 => Ok(CardSize::K4),
+            _ => Err(Error::Size),
+        }
+    }
+}
+
+/// First block of `sector`. The first 32 sectors have 4 blocks, the 8
+/// above them on 4K cards have 16.
+pub const fn first_block(sector: u8) -> u8 {
+    if sector < 32 {
+        sector * 4
+    } else {
+        128 + (sector - 32) * 16
+    }
+}
+
+pub const fn sector_of(block: u8) -> u8 {
+    if block < 128 {
+        block / 4
+    } else {
+        32 + (block - 128) / 16
+    }
+}
+
+/// The last block of every sector holds its keys and access bits
+pub const fn is_trailer(block: u8) -> bool {
+    if block < 128 {
+        block % 4 == 3
+    } else {
+        block % 16 == 15
+    }
+}
+
+pub const fn trailer_of(sector: u8) -> u8 {
+    if sector < 32 {
+        first_block(sector) + 3
+    } else {
+        first_block(sector) + 15
+    }
+}
+
+/// What may be written back by [`Dump::check_write`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RestoreOptions {
+    /// Write sector trailers, changing keys and access conditions
+    pub trailers: bool,
+    /// Write the manufacturer block, which only "magic" cards allow
+    pub block_0: bool,
+    /// Write trailers even when they lock the sector for good
+    pub force: bool,
+}
+
+/// Why a block is not written back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    /// Not in the image
+    Missing,
+    Block0,
+    Trailer,
+    /// The trailer's access bits are invalid and would destroy the sector
+    BadAccessBits,
+    /// The trailer would lock the sector; see [`RestoreOptions::force`]
+    Locked,
+}
+
+pub struct Dump {
+    size: CardSize,
+    uid: [u8; 10],
+    uid_len: usize,
+    pub atqa: [u8; 2],
+    pub sak: u8,
+    blocks: [Block; MAX_BLOCKS],
+    present: [bool; MAX_BLOCKS],
+}
+
+impl Dump {
+    /// An empty image
+    pub const fn new(size: CardSize) -> Self {
+        Self {
+            size,
+            uid: [0; 10],
+            uid_len: 0,
+            atqa: [0; 2],
+            sak: 0,
+            blocks: [[0; BLOCK_LEN]; MAX_BLOCKS],
+            present: [false; MAX_BLOCKS],
+        }
+    }
+
+    /// Empty the image, for example before loading another one into it
+    pub fn clear(&mut self, size: CardSize) {
+        *self = Self::new(size);
+    }
+
+    pub fn size(&self) -> CardSize {
+        self.size
+    }
+
+    pub fn uid(&self) -> &[u8] {
+        &self.uid[..self.uid_len]
+    }
+
+    /// Set the UID; 4, 7 or 10 bytes
+    pub fn set_uid(&mut self, uid: &[u8]) {
+        let len = uid.len().min(self.uid.len());
+        self.uid[..len].copy_from_slice(&uid[..len]);
+        self.uid_len = len;
+    }
+
+    pub fn block(&self, block: u8) -> Option<&Block> {
+        let block = block as usize;
+        (block < self.size.blocks() && self.present[block]).then(|| &self.blocks[block])
+    }
+
+    pub fn set_block(&mut self, block: u8, data: Block) {
+        let block = block as usize;
+        if block < self.size.blocks() {
+            self.blocks[block] = data;
+            self.present[block] = true;
+        }
+    }
+
+    /// Put the keys found for `sector` into its trailer. Cards never give
+    /// out Key A, so a trailer read from one has zeros there.
+    pub fn set_keys(&mut self, sector: u8, key_a: Option<Key>, key_b: Option<Key>) {
+        let trailer = trailer_of(sector) as usize;
+        if trailer >= self.size.blocks() || !self.present[trailer] {
+            return;
+        }
+        if let Some(key) = key_a {
+            self.blocks[trailer][..6].copy_from_slice(&key);
+        }
+        if let Some(key) = key_b {
+            self.blocks[trailer][10..].copy_from_slice(&key);
+        }
+    }
+
+    /// Take the UID, SAK and ATQA from the manufacturer block, where NXP
+    /// cards with 4-byte UIDs keep them
+    pub fn identify(&mut self) {
+        let Some(&block) = self.block(0) else {
+            return;
+        };
+        let bcc = block[..4].iter().fold(0, |bcc, b| bcc ^ b);
+        if bcc == block[4] {
+            self.set_uid(&block[..4]);
+            self.sak = block[5];
+            self.atqa = [block[6], block[7]];
+        }
+    }
+
+    /// 8.3 file name for the image: the first four UID bytes in hex
+    pub fn file_name(&self, extension: &str) -> FileName {
+        let mut name = FileName {
+            bytes: [0; 12],
+            len: 0,
+        };
+        let hex = b"0123456789ABCDEF";
+        for &b in self.uid().iter().take(4) {
+            name.push(hex[b as usize >> 4]);
+            name.push(hex[b as usize & 0x0F]);
+        }
+        if name.len == 0 {
+            name.bytes[..4].copy_from_slice(b"CARD");
+            name.len = 4;
+        }
+        name.push(b'.');
+        extension.bytes().take(3).for_each(|b| name.push(b));
+        name
+    }
+
+    /// The `.mfd` image: all blocks, with zeros for missing ones
+    pub fn mfd(&self) -> &[u8] {
+        self.blocks[..self.size.blocks()].as_flattened()
+    }
+
+    /// Start loading a `.mfd` image of `len` bytes
+    pub fn mfd_reader(&mut self, len: usize) -> Result<MfdReader<'_>, Error> {
+        if !len.is_multiple_of(BLOCK_LEN) {
+            return Err(Error::Size);
+        }
+        self.clear(CardSize::from_blocks(len / BLOCK_LEN)?);
+        Ok(MfdReader { dump: self, pos: 0 })
+    }
+
+    /// Whether `block` may be written back with `options`, and what to write
+    pub fn check_write(&self, block: u8, options: &RestoreOptions) -> Result<&Block, Skip> {
+        let data = self.block(block).ok_or(Skip::Missing)?;
+        if block == 0 && !options.block_0 {
+            return Err(Skip::Block0);
+        }
+        if is_trailer(block) {
+            if !options.trailers {
+                return Err(Skip::Trailer);
+            }
+            let conditions = AccessConditions::decode(&[data[6], data[7], data[8]])
+                .map_err(|_| Skip::BadAccessBits)?;
+            if conditions.is_locked() && !options.force {
+                return Err(Skip::Locked);
+            }
+        }
+        Ok(data)
+    }
+
+    /// Write the image as Proxmark3-style JSON
+    pub fn write_json(&self, out: &mut impl Write) -> fmt::Result {
+        out.write_str("{\n  \"Created\": \"rfid-dump\",\n  \"FileType\": \"mfcard\",\n")?;
+        out.write_str("  \"Card\": {\n    \"UID\": \"")?;
+        write_hex(out, self.uid())?;
+        out.write_str("\",\n    \"ATQA\": \"")?;
+        write_hex(out, &self.atqa)?;
+        out.write_str("\",\n    \"SAK\": \"")?;
+        write_hex(out, &[self.sak])?;
+        out.write_str("\"\n  },\n  \"blocks\": {")?;
+        for block in 0..self.size.blocks() {
+            let separator = if block == 0 { "" } else { "," };
+            write!(out, "{}\n    \"{}\": \"", separator, block)?;
+            match self.block(block as u8) {
+                Some(data) => write_hex(out, data)?,
+                None => out.write_str(MISSING)?,
+            }
+            out.write_char('"')?;
+        }
+        out.write_str("\n  },\n  \"SectorKeys\": {")?;
+        let mut first = true;
+        for sector in 0..self.size.sectors() {
+            let Some(trailer) = self.block(trailer_of(sector)) else {
+                continue;
+            };
+            let separator = if first { "" } else { "," };
+            first = false;
+            write!(
+                out,
+                "{}\n    \"{}\": {{\n      \"KeyA\": \"",
+                separator, sector
+            )?;
+            write_hex(out, &trailer[..6])?;
+            out.write_str("\",\n      \"KeyB\": \"")?;
+            write_hex(out, &trailer[10..])?;
+            out.write_str("\",\n      \"AccessConditions\": \"")?;
+            write_hex(out, &trailer[6..10])?;
+            out.write_str("\"\n    }")?;
+        }
+        out.write_str("\n  }\n}\n")
+    }
+}
+
+pub struct FileName {
+    bytes: [u8; 12],
+    len: usize,
+}
+
+impl FileName {
+    pub fn as_str(&self) -> &str {
+        // only ever holds ASCII
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+
+    fn push(&mut self, b: u8) {
+        if self.len < self.bytes.len() && b.is_ascii() {
+            self.bytes[self.len] = b;
+            self.len += 1;
+        }
+    }
+}
+
+// what a block that could not be read looks like in JSON
+const MISSING: &str = "--------------------------------";
+
+fn write_hex(out: &mut impl Write, bytes: &[u8]) -> fmt::Result {
+    bytes.iter().try_for_each(|b| write!(out, "{:02X}", b))
+}
+
+/// Decode hex into `out`, which it must fill exactly
+fn parse_hex(text: &[u8], out: &mut [u8]) -> Option<()> {
+    if text.len() != out.len() * 2 {
+        return None;
+    }
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    for (byte, pair) in out.iter_mut().zip(text.chunks_exact(2)) {
+        *byte = digit(pair[0])? << 4 | digit(pair[1])?;
+    }
+    Some(())
+}
+
+/// Loads a `.mfd` image into a [`Dump`], see [`Dump::mfd_reader`]
+pub struct MfdReader<'a> {
+    dump: &'a mut Dump,
+    pos: usize,
+}
+
+impl MfdReader<'_> {
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let image = self.dump.blocks[..self.dump.size.blocks()].as_flattened_mut();
+        let end = self.pos + bytes.len();
+        image
+            .get_mut(self.pos..end)
+            .ok_or(Error::Size)?
+            .copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), Error> {
+        let blocks = self.dump.size.blocks();
+        if self.pos != blocks * BLOCK_LEN {
+            return Err(Error::Size);
+        }
+        for (present, block) in self.dump.present[..blocks]
+            .iter_mut()
+            .zip(&self.dump.blocks)
+        {
+            *present = block.iter().any(|&b| b != 0);
+        }
+        self.dump.identify();
+        Ok(())
+    }
+}
+
+// The JSON reader only cares about strings and where they are
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Other,
+    Card,
+    Blocks,
+}
+
+/// Loads Proxmark3-style JSON into a [`Dump`]. Only the `Card` and
+/// `blocks` objects are used; everything else is skipped.
+pub struct JsonReader<'a> {
+    dump: &'a mut Dump,
+    depth: usize,
+    section: Section,
+    in_string: bool,
+    escaped: bool,
+    /// The last string was a key and its value is next
+    after_colon: bool,
+    text: [u8; 40],
+    text_len: usize,
+    key: [u8; 40],
+    key_len: usize,
+    /// One past the highest block seen
+    blocks: usize,
+}
+
+impl<'a> JsonReader<'a> {
+    pub fn new(dump: &'a mut Dump) -> Self {
+        dump.clear(CardSize::K4);
+        Self {
+            dump,
+            depth: 0,
+            section: Section::Other,
+            in_string: false,
+            escaped: false,
+            after_colon: false,
+            text: [0; 40],
+            text_len: 0,
+            key: [0; 40],
+            key_len: 0,
+            blocks: 0,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        bytes.iter().try_for_each(|&b| self.byte(b))
+    }
+
+    /// Check the image is complete and set its size
+    pub fn finish(self) -> Result<(), Error> {
+        if self.depth != 0 || self.in_string {
+            return Err(Error::Format);
+        }
+        let size = CardSize::from_blocks(self.blocks)?;
+        self.dump.size = size;
+        self.dump.present[size.blocks()..].fill(false);
+        Ok(())
+    }
+
+    fn byte(&mut self, b: u8) -> Result<(), Error> {
+        if self.in_string {
+            match b {
+                _ if self.escaped => self.escaped = false,
+                b'\\' => self.escaped = true,
+                b'"' => {
+                    self.in_string = false;
+                    return self.string_done();
+                }
+                _ => {}
+            }
+            // long strings are cut short; none we care about are
+            if self.text_len < self.text.len() {
+                self.text[self.text_len] = b;
+                self.text_len += 1;
+            }
+            return Ok(());
+        }
+        match b {
+            b'"' => {
+                self.in_string = true;
+                self.text_len = 0;
+            }
+            b':' => self.after_colon = true,
+            b',' => self.after_colon = false,
+            b'{' | b'[' => {
+                if self.depth == 1 && self.after_colon {
+                    self.section = match &self.key[..self.key_len] {
+                        b"Card" => Section::Card,
+                        b"blocks" => Section::Blocks,
+                        _ => Section::Other,
+                    };
+                }
+                self.depth += 1;
+                self.after_colon = false;
+            }
+            b'}' | b']' => {
+                self.depth = self.depth.checked_sub(1).ok_or(Error::Format)?;
+                self.after_colon = false;
+                if self.depth <= 1 {
+                    self.section = Section::Other;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn string_done(&mut self) -> Result<(), Error> {
+        if !self.after_colon {
+            self.key[..self.text_len].copy_from_slice(&self.text[..self.text_len]);
+            self.key_len = self.text_len;
+            return Ok(());
+        }
+        self.after_colon = false;
+        if self.depth != 2 {
+            return Ok(());
+        }
+        let key = &self.key[..self.key_len];
+        let value = &self.text[..self.text_len];
+        match self.section {
+            Section::Blocks => {
+                let block = core::str::from_utf8(key)
+                    .ok()
+                    .and_then(|key| key.parse::<u8>().ok())
+                    .ok_or(Error::Format)?;
+                self.blocks = self.blocks.max(block as usize + 1);
+                let mut data = [0; BLOCK_LEN];
+                // blocks that could not be read have no hex
+                if parse_hex(value, &mut data).is_some() {
+                    self.dump.set_block(block, data);
+                } else if value.len() != BLOCK_LEN * 2 {
+                    return Err(Error::Format);
+                }
+            }
+            Section::Card => match key {
+                b"UID" => {
+                    let mut uid = [0; 10];
+                    let len = value.len() / 2;
+                    if !matches!(len, 4 | 7 | 10) {
+                        return Err(Error::Format);
+                    }
+                    parse_hex(value, &mut uid[..len]).ok_or(Error::Format)?;
+                    self.dump.set_uid(&uid[..len]);
+                }
+                b"ATQA" => parse_hex(value, &mut self.dump.atqa).ok_or(Error::Format)?,
+                b"SAK" => {
+                    let mut sak = [0];
+                    parse_hex(value, &mut sak).ok_or(Error::Format)?;
+                    self.dump.sak = sak[0];
+                }
+                _ => {}
+            },
+            Section::Other => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::boxed::Box;
+    use std::string::String;
+
+    use super::*;
+
+    fn sample() -> Box<Dump> {
+        let mut dump = Box::new(Dump::new(CardSize::K1));
+        let mut block_0 = [0; BLOCK_LEN];
+        block_0[..8].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF, 0x22, 0x08, 0x04, 0x00]);
+        dump.set_block(0, block_0);
+        dump.identify();
+        dump.set_block(1, [0x11; 16]);
+        for sector in 0..16 {
+            let trailer = AccessConditions::TRANSPORT
+                .trailer(&[0; 6], &[0xFF; 6], false)
+                .unwrap();
+            dump.set_block(trailer_of(sector), trailer);
+        }
+        dump.set_keys(1, Some(*b"Rusted"), None);
+        dump
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(first_block(1), 4);
+        assert_eq!(first_block(32), 128);
+        assert_eq!(first_block(39), 240);
+        assert_eq!(trailer_of(39), 255);
+        assert_eq!(sector_of(127), 31);
+        assert_eq!(sector_of(143), 32);
+        assert_eq!(sector_of(144), 33);
+        assert!(is_trailer(7) && is_trailer(143) && !is_trailer(131));
+    }
+
+    #[test]
+    fn sizes_cards_by_sak() {
+        let uid = |sak| mfrc522::Uid::Single(mfrc522::GenericUid::new([1, 2, 3, 4], sak));
+        assert_eq!(CardSize::of(&uid(0x09)), Ok(CardSize::Mini));
+        assert_eq!(CardSize::of(&uid(0x08)), Ok(CardSize::K1));
+        assert_eq!(CardSize::of(&uid(0x18)), Ok(CardSize::K4));
+        // Ultralight and DESFire
+        assert_eq!(CardSize::of(&uid(0x00)), Err(Error::Size));
+        assert_eq!(CardSize::of(&uid(0x20)), Err(Error::Size));
+    }
+
+    #[test]
+    fn identifies_from_block_0() {
+        let dump = sample();
+        assert_eq!(dump.uid(), [0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(dump.sak, 0x08);
+        assert_eq!(dump.atqa, [0x04, 0x00]);
+        assert_eq!(&dump.block(7).unwrap()[..6], b"Rusted");
+        assert_eq!(dump.file_name("MFD").as_str(), "DEADBEEF.MFD");
+        assert_eq!(
+            Dump::new(CardSize::K1).file_name("json").as_str(),
+            "CARD.jso"
+        );
+    }
+
+    #[test]
+    fn mfd_round_trip() {
+        let dump = sample();
+        let image = dump.mfd();
+        assert_eq!(image.len(), 1024);
+        assert_eq!(image[16..32], [0x11; 16]);
+
+        let mut loaded = Box::new(Dump::new(CardSize::Mini));
+        let mut reader = loaded.mfd_reader(image.len()).unwrap();
+        for chunk in image.chunks(100) {
+            reader.feed(chunk).unwrap();
+        }
+        reader.finish().unwrap();
+        assert_eq!(loaded.size(), CardSize::K1);
+        assert_eq!(loaded.uid(), dump.uid());
+        assert_eq!(loaded.mfd(), image);
+        // the block that wasn't read stays out of a restore
+        assert_eq!(loaded.block(1), Some(&[0x11; 16]));
+        assert_eq!(loaded.block(2), None);
+        assert_eq!(
+            loaded.check_write(2, &RestoreOptions::default()),
+            Err(Skip::Missing)
+        );
+
+        assert_eq!(loaded.mfd_reader(1000).err(), Some(Error::Size));
+        let mut reader = loaded.mfd_reader(320).unwrap();
+        reader.feed(&[0; 100]).unwrap();
+        assert_eq!(reader.finish(), Err(Error::Size));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let dump = sample();
+        let mut json = String::new();
+        dump.write_json(&mut json).unwrap();
+        assert!(json.contains("\"UID\": \"DEADBEEF\""));
+        assert!(json.contains("\"2\": \"--------------------------------\""));
+        assert!(json.contains("\"KeyA\": \"527573746564\""));
+
+        let mut loaded = Box::new(Dump::new(CardSize::Mini));
+        let mut reader = JsonReader::new(&mut loaded);
+        for chunk in json.as_bytes().chunks(7) {
+            reader.feed(chunk).unwrap();
+        }
+        reader.finish().unwrap();
+        assert_eq!(loaded.size(), CardSize::K1);
+        assert_eq!(loaded.uid(), dump.uid());
+        assert_eq!(loaded.atqa, dump.atqa);
+        assert_eq!(loaded.sak, 0x08);
+        assert_eq!(loaded.block(2), None);
+        assert_eq!(loaded.mfd(), dump.mfd());
+    }
+
+    #[test]
+    fn json_from_other_tools() {
+        // compact, with extra fields and an escaped quote
+        let json = br#"{"Created":"pm3 \"x\"","Card":{"UID":"01020304","SAK":"08","ATQA":"0400"},
+            "blocks":{"0":"0102030404080400626364656667686A","19":"FFFFFFFFFFFFFF078069FFFFFFFFFFFF"},
+            "SectorKeys":{"0":{"KeyA":"FFFFFFFFFFFF"}}}"#;
+        let mut dump = Box::new(Dump::new(CardSize::K1));
+        let mut reader = JsonReader::new(&mut dump);
+        reader.feed(json).unwrap();
+        reader.finish().unwrap();
+        assert_eq!(dump.size(), CardSize::Mini);
+        assert_eq!(dump.uid(), [1, 2, 3, 4]);
+        assert_eq!(dump.block(19).unwrap()[6..10], [0xFF, 0x07, 0x80, 0x69]);
+        assert_eq!(dump.block(1), None);
+
+        let mut reader = JsonReader::new(&mut dump);
+        reader.feed(br#"{"blocks":{"0":"12"}}"#).unwrap_err();
+        let mut reader = JsonReader::new(&mut dump);
+        reader.feed(br#"{"blocks":{"0":"#).unwrap();
+        assert_eq!(reader.finish(), Err(Error::Format));
+    }
+
+    #[test]
+    fn restore_checks() {
+        let mut dump = sample();
+        let options = RestoreOptions::default();
+        assert_eq!(dump.check_write(0, &options), Err(Skip::Block0));
+        assert_eq!(dump.check_write(1, &options), Ok(&[0x11; 16]));
+        assert_eq!(dump.check_write(2, &options), Err(Skip::Missing));
+        assert_eq!(dump.check_write(3, &options), Err(Skip::Trailer));
+
+        let trailers = RestoreOptions {
+            trailers: true,
+            ..options
+        };
+        assert!(dump.check_write(3, &trailers).is_ok());
+        let mut bad = *dump.block(3).unwrap();
+        bad[7] ^= 1;
+        dump.set_block(3, bad);
+        assert_eq!(dump.check_write(3, &trailers), Err(Skip::BadAccessBits));
+
+        // read-only everything
+        let mut locked = bad;
+        locked[6..9].copy_from_slice(&[0x07, 0x87, 0x8F]);
+        dump.set_block(3, locked);
+        assert_eq!(dump.check_write(3, &trailers), Err(Skip::Locked));
+        let force = RestoreOptions {
+            force: true,
+            ..trailers
+        };
+        assert!(dump.check_write(3, &force).is_ok());
+    }
+}
@@ -0,0 +1,327 @@
+//! Key dictionary and Key B authentication.
+//!
+//! `mfrc522` only authenticates with Key A. The command byte is the only
+//! difference between the two, so [`KeySelect`] sits between the driver and
+//! the SPI bus and swaps it on the way to the reader's FIFO when
+//! [`authenticate`] asks for Key B. The two share a [`KeyChoice`]:
+//!
+//! ```ignore
+//! let choice = KeyChoice::new();
+//! let spi_dev = KeySelect::new(ExclusiveDevice::new(spi_bus, cs, delay).unwrap(), &choice);
+//! let mut rfid = Mfrc522::new(SpiInterface::new(spi_dev)).init().unwrap();
+//! authenticate(&uid, 4, KeyType::B, &key, &choice, &mut rfid)?;
+//! ```
+
+use core::cell::Cell;
+
+use crate::card::auth_uid;
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+use mfrc522::Mfrc522;
+
+pub type Key = [u8; 6];
+
+/// Keys found on many cards: factory defaults, the MAD and NDEF keys, and
+/// those of well-known transport and hotel systems
+pub const DEFAULT_KEYS: &[Key] = &[
+    [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+    [0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5],
+    [0xD3, 0xF7, 0xD3, 0xF7, 0xD3, 0xF7],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0xB0, 0xB1, 0xB2, 0xB3, 0xB4, 0xB5],
+    [0x4D, 0x3A, 0x99, 0xC3, 0x51, 0xDD],
+    [0x1A, 0x98, 0x2C, 0x7E, 0x45, 0x9A],
+    [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF],
+    [0x71, 0x4C, 0x5C, 0x88, 0x6E, 0x97],
+    [0x58, 0x7E, 0xE5, 0xF9, 0x35, 0x0F],
+    [0xA0, 0x47, 0x8C, 0xC3, 0x90, 0x91],
+    [0x53, 0x3C, 0xB6, 0xC7, 0x23, 0xF6],
+    [0x8F, 0xD0, 0xA4, 0xF2, 0x56, 0xE9],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    A,
+    B,
+}
+
+/// The keys found for a sector
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SectorKeys {
+    pub a: Option<Key>,
+    pub b: Option<Key>,
+}
+
+impl SectorKeys {
+    /// A key that opens the sector, Key A first
+    pub fn any(&self) -> Option<(KeyType, Key)> {
+        match (self.a, self.b) {
+            (Some(key), _) => Some((KeyType::A, key)),
+            (None, Some(key)) => Some((KeyType::B, key)),
+            (None, None) => None,
+        }
+    }
+}
+
+// MFAuthent command bytes and the FIFO data register address as sent over SPI
+const AUTH_KEY_A: u8 = 0x60;
+const AUTH_KEY_B: u8 = 0x61;
+const AUTH_LEN: usize = 12;
+const FIFO_WRITE: u8 = 0x09 << 1;
+
+/// The key the authentications going through a [`KeySelect`] use. Key A
+/// unless [`authenticate`] is asking for Key B.
+#[derive(Debug)]
+pub struct KeyChoice(Cell<KeyType>);
+
+impl KeyChoice {
+    pub const fn new() -> Self {
+        Self(Cell::new(KeyType::A))
+    }
+}
+
+impl Default for KeyChoice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// SPI device that turns Key A authentications into Key B ones while its
+/// [`KeyChoice`] says so
+pub struct KeySelect<'a, SPI> {
+    spi: SPI,
+    choice: &'a KeyChoice,
+}
+
+impl<'a, SPI> KeySelect<'a, SPI> {
+    pub fn new(spi: SPI, choice: &'a KeyChoice) -> Self {
+        Self { spi, choice }
+    }
+
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+}
+
+impl<SPI: ErrorType> ErrorType for KeySelect<'_, SPI> {
+    type Error = SPI::Error;
+}
+
+impl<SPI: SpiDevice> SpiDevice for KeySelect<'_, SPI> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        let key_b = match operations {
+            [Operation::Write([FIFO_WRITE]), Operation::Write(command)]
+                if self.choice.0.get() == KeyType::B
+                    && command.len() == AUTH_LEN
+                    && command[0] == AUTH_KEY_A =>
+            {
+                let mut key_b = [0u8; AUTH_LEN];
+                key_b.copy_from_slice(command);
+                key_b[0] = AUTH_KEY_B;
+                key_b
+            }
+            _ => return self.spi.transaction(operations),
+        };
+        self.spi
+            .transaction(&mut [Operation::Write(&[FIFO_WRITE]), Operation::Write(&key_b)])
+    }
+}
+
+/// Authenticate the sector of `block` with either key. Key B only works
+/// when the reader talks through a [`KeySelect`] sharing `choice`. `uid`
+/// is the whole UID; the card checks the bytes [`auth_uid`] picks out.
+pub fn authenticate<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &mfrc522::Uid,
+    block: u8,
+    key_type: KeyType,
+    key: &Key,
+    choice: &KeyChoice,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), mfrc522::Error<E>> {
+    choice.0.set(key_type);
+    let result = rfid.mf_authenticate(&auth_uid(uid), block, key);
+    choice.0.set(KeyType::A);
+    result
+}
+
+/// Try every key of `dictionaries` as Key A and as Key B on the sector
+/// that starts at `block`
+pub fn find_keys<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &mfrc522::Uid,
+    block: u8,
+    dictionaries: &[&[Key]],
+    choice: &KeyChoice,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> SectorKeys {
+    let mut found = SectorKeys::default();
+    for (key_type, slot) in [(KeyType::A, &mut found.a), (KeyType::B, &mut found.b)] {
+        *slot = dictionaries
+            .iter()
+            .copied()
+            .flatten()
+            .find(|key| {
+                let opened = authenticate(uid, block, key_type, key, choice, rfid).is_ok();
+                // a failed attempt halts the card, and a successful one must
+                // end before the next; start over either way
+                reselect(rfid);
+                opened
+            })
+            .copied();
+    }
+    found
+}
+
+/// Halt the card and wake it up again, leaving it selected but not
+/// authenticated
+pub fn reselect<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) {
+    let _ = rfid.hlta();
+    let _ = rfid.stop_crypto1();
+    if let Ok(atqa) = rfid.wupa() {
+        let _ = rfid.select(&atqa);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use mfrc522::comm::blocking::spi::SpiInterface;
+    use rfid_emulator::{Card, Emulator, FACTORY_TRAILER};
+
+    use super::*;
+    use crate::image::{CardSize, Dump};
+    use crate::reader;
+
+    // records the bytes of every write
+    #[derive(Default)]
+    struct Recorder {
+        writes: Vec<Vec<u8>>,
+    }
+
+    impl ErrorType for Recorder {
+        type Error = core::convert::Infallible;
+    }
+
+    impl SpiDevice for Recorder {
+        fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+            for operation in operations {
+                if let Operation::Write(bytes) = operation {
+                    self.writes.push(bytes.to_vec());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn auth_command(command: u8) -> [u8; AUTH_LEN] {
+        [command, 4, 1, 2, 3, 4, 5, 6, 0xDE, 0xAD, 0xBE, 0xEF]
+    }
+
+    fn send(spi: &mut KeySelect<'_, Recorder>, bytes: &[u8]) {
+        spi.transaction(&mut [Operation::Write(&[FIFO_WRITE]), Operation::Write(bytes)])
+            .unwrap();
+    }
+
+    #[test]
+    fn swaps_only_key_a_authentication() {
+        let choice = KeyChoice::new();
+        let mut spi = KeySelect::new(Recorder::default(), &choice);
+        send(&mut spi, &auth_command(AUTH_KEY_A));
+        choice.0.set(KeyType::B);
+        send(&mut spi, &auth_command(AUTH_KEY_A));
+        // other FIFO writes, like a 16-byte block, are left alone
+        send(&mut spi, &[AUTH_KEY_A; 16]);
+        choice.0.set(KeyType::A);
+
+        let writes = spi.release().writes;
+        assert_eq!(writes[1], auth_command(AUTH_KEY_A));
+        assert_eq!(writes[3], auth_command(AUTH_KEY_B));
+        assert_eq!(writes[5], [AUTH_KEY_A; 16]);
+    }
+
+    #[test]
+    fn prefers_key_a() {
+        let none = SectorKeys::default();
+        assert_eq!(none.any(), None);
+        let b_only = SectorKeys {
+            a: None,
+            b: Some([1; 6]),
+        };
+        assert_eq!(b_only.any(), Some((KeyType::B, [1; 6])));
+        let both = SectorKeys {
+            a: Some([2; 6]),
+            ..b_only
+        };
+        assert_eq!(both.any(), Some((KeyType::A, [2; 6])));
+    }
+
+    #[test]
+    fn dumps_a_card_with_the_keys_it_finds() {
+        let mut card = Card::new([0xDE, 0xAD, 0xBE, 0xEF]);
+        card.set_trailer(1, DEFAULT_KEYS[1], [0xFF, 0x07, 0x80], DEFAULT_KEYS[5]);
+        // Key A unknown, Key B manages the trailer and doesn't read back
+        card.set_trailer(2, [0x12; 6], [0x7F, 0x07, 0x88], DEFAULT_KEYS[2]);
+        card.set_block(8, [0xAB; 16]);
+        card.set_trailer(3, [0x12; 6], [0xFF, 0x07, 0x80], [0x34; 6]);
+        let mut chip = Emulator::new(card);
+        let choice = KeyChoice::new();
+        let spi_dev = KeySelect::new(&mut chip, &choice);
+        let mut rfid = Mfrc522::new(SpiInterface::new(spi_dev)).init().unwrap();
+        let uid = reader::detect(&mut rfid).unwrap();
+
+        let mut dump = Box::new(Dump::new(CardSize::K1));
+        for sector in 0..16 {
+            let first = sector * 4;
+            let keys = find_keys(&uid, first, &[DEFAULT_KEYS], &choice, &mut rfid);
+            if let Some((key_type, key)) = keys.any() {
+                for block in first..first + 4 {
+                    let data = reader::retry(&uid, &mut rfid, |rfid| {
+                        authenticate(&uid, first, key_type, &key, &choice, rfid)
+                            .map_err(|_| reader::Error::AuthFailed { sector })?;
+                        rfid.mf_read(block).map_err(|e| {
+                            reader::Error::from_driver(e, reader::Error::ReadFailed { block })
+                        })
+                    });
+                    dump.set_block(block, data.unwrap());
+                }
+            }
+            dump.set_keys(sector, keys.a, keys.b);
+        }
+        dump.identify();
+
+        let card = chip.card().unwrap();
+        assert_eq!(dump.uid(), [0xDE, 0xAD, 0xBE, 0xEF]);
+        for block in 0..11 {
+            assert_eq!(
+                dump.block(block),
+                Some(card.block(block)),
+                "block {}",
+                block
+            );
+        }
+        // everything but the Key A nobody knows
+        assert_eq!(dump.block(11).unwrap()[..6], [0; 6]);
+        assert_eq!(dump.block(11).unwrap()[6..], card.block(11)[6..]);
+        assert_eq!(dump.block(12), None);
+        assert_eq!(dump.block(63), Some(&FACTORY_TRAILER));
+    }
+
+    #[test]
+    fn finds_both_keys_of_a_7_byte_uid_card() {
+        let mut card = Card::double([0x04, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+        card.set_trailer(1, DEFAULT_KEYS[1], [0xFF, 0x07, 0x80], DEFAULT_KEYS[5]);
+        let mut chip = Emulator::new(card);
+        let choice = KeyChoice::new();
+        let spi_dev = KeySelect::new(&mut chip, &choice);
+        let mut rfid = Mfrc522::new(SpiInterface::new(spi_dev)).init().unwrap();
+        let uid = reader::detect(&mut rfid).unwrap();
+        assert_eq!(uid.as_bytes().len(), 7);
+
+        let keys = find_keys(&uid, 4, &[DEFAULT_KEYS], &choice, &mut rfid);
+        assert_eq!(keys.a, Some(DEFAULT_KEYS[1]));
+        assert_eq!(keys.b, Some(DEFAULT_KEYS[5]));
+    }
+}
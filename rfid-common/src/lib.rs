@@ -0,0 +1,16 @@
+//! MFRC522 and MIFARE Classic code shared by the RFID projects. Nothing
+//! here touches esp-hal, so the tests run on the host with `cargo test`.
+#![no_std]
+pub mod access;
+pub mod card;
+pub mod crc;
+pub mod image;
+pub mod keys;
+pub mod mad;
+pub mod ndef;
+pub mod payload;
+pub mod presence;
+pub mod reader;
+pub mod rotate;
+pub mod tlv;
+pub mod value;
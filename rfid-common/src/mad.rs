@@ -0,0 +1,179 @@
+//! MIFARE Application Directory, which tells phones where the NDEF message
+//! of a Classic card is.
+//!
+//! Blocks 1 and 2 of sector 0 hold a CRC, an info byte and one two-byte
+//! application ID per sector. Sectors marked [`NDEF_AID`] hold the message
+//! in their data blocks, as TLVs (see [`crate::tlv`]). Phones read the
+//! directory with [`MAD_KEY_A`] and the NDEF sectors with [`NDEF_KEY_A`],
+//! so a card written with [`MAD_TRAILER`] and [`NDEF_TRAILER`] opens on any
+//! of them. Only MAD version 1, covering the 15 sectors of a 1K card, is
+//! handled.
+
+pub type Key = [u8; 6];
+
+/// Key A of sector 0 on every MAD card
+pub const MAD_KEY_A: Key = [0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5];
+/// Key A of the NDEF sectors
+pub const NDEF_KEY_A: Key = [0xD3, 0xF7, 0xD3, 0xF7, 0xD3, 0xF7];
+
+/// Application ID of a sector holding NDEF data
+pub const NDEF_AID: u16 = 0x03E1;
+pub const FREE_AID: u16 = 0x0000;
+
+/// Sectors a version 1 directory describes, sector 0 excepted
+pub const SECTORS: usize = 15;
+
+/// Sector 0 trailer: MAD key A with read access to the directory, key B
+/// left at the factory default for writing it, general purpose byte `C1`
+/// (MAD version 1, multi-application card)
+pub const MAD_TRAILER: [u8; 16] = [
+    0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0x78, 0x77, 0x88, 0xC1, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+/// NDEF sector trailer: NDEF key A may read and write the data, key B manages
+/// the sector, general purpose byte `40` (NDEF version 1.0, read and write)
+pub const NDEF_TRAILER: [u8; 16] = [
+    0xD3, 0xF7, 0xD3, 0xF7, 0xD3, 0xF7, 0x7F, 0x07, 0x88, 0x40, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The CRC doesn't match: there is no directory
+    Crc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mad {
+    /// Sector of the card publisher, 0 for none
+    pub info: u8,
+    /// Application of sectors 1 to 15
+    pub aids: [u16; SECTORS],
+}
+
+impl Mad {
+    /// A directory giving the first `sectors` sectors after sector 0 to
+    /// NDEF and leaving the others free
+    pub fn ndef(sectors: usize) -> Self {
+        let mut aids = [FREE_AID; SECTORS];
+        aids[..sectors.min(SECTORS)].fill(NDEF_AID);
+        Self { info: 0, aids }
+    }
+
+    /// Blocks 1 and 2 of sector 0
+    pub fn encode(&self) -> [[u8; 16]; 2] {
+        let mut bytes = [0u8; 32];
+        bytes[1] = self.info;
+        for (i, aid) in self.aids.iter().enumerate() {
+            bytes[2 + 2 * i..4 + 2 * i].copy_from_slice(&aid.to_be_bytes());
+        }
+        bytes[0] = crc8(&bytes[1..]);
+
+        let mut blocks = [[0u8; 16]; 2];
+        blocks[0].copy_from_slice(&bytes[..16]);
+        blocks[1].copy_from_slice(&bytes[16..]);
+        blocks
+    }
+
+    pub fn decode(blocks: &[[u8; 16]; 2]) -> Result<Self, Error> {
+        let mut bytes = [0u8; 32];
+        bytes[..16].copy_from_slice(&blocks[0]);
+        bytes[16..].copy_from_slice(&blocks[1]);
+        if bytes[0] != crc8(&bytes[1..]) {
+            return Err(Error::Crc);
+        }
+
+        let mut aids = [FREE_AID; SECTORS];
+        for (i, aid) in aids.iter_mut().enumerate() {
+            *aid = u16::from_be_bytes([bytes[2 + 2 * i], bytes[3 + 2 * i]]);
+        }
+        Ok(Self {
+            info: bytes[1] & 0x3F,
+            aids,
+        })
+    }
+
+    /// The sectors of the NDEF message, in order. The message continues
+    /// only as long as the sectors follow each other.
+    pub fn ndef_sectors(&self) -> impl Iterator<Item = u8> + '_ {
+        let first = self.aids.iter().position(|&aid| aid == NDEF_AID);
+        let start = first.unwrap_or(SECTORS);
+        self.aids[start..]
+            .iter()
+            .take_while(|&&aid| aid == NDEF_AID)
+            .enumerate()
+            .map(move |(i, _)| (start + i + 1) as u8)
+    }
+}
+
+/// CRC-8 of the directory: polynomial `1D`, starting from `C7`
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0xC7, |crc, &b| {
+        (0..8).fold(crc ^ b, |crc, _| {
+            if crc & 0x80 != 0 {
+                crc << 1 ^ 0x1D
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+
+    #[test]
+    fn matches_cards_formatted_by_phones() {
+        // sector 0 of a 1K card that NXP TagWriter gave all 15 sectors to NDEF
+        let blocks = [
+            [
+                0x14, 0x01, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1,
+                0x03, 0xE1,
+            ],
+            [
+                0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1, 0x03, 0xE1,
+                0x03, 0xE1,
+            ],
+        ];
+        let mad = Mad::decode(&blocks).unwrap();
+        assert_eq!(mad.info, 1);
+        assert_eq!(mad.aids, [NDEF_AID; SECTORS]);
+        assert_eq!(
+            mad.ndef_sectors().collect::<Vec<_>>(),
+            (1..=15).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Mad {
+                info: 1,
+                ..Mad::ndef(15)
+            }
+            .encode(),
+            blocks
+        );
+    }
+
+    #[test]
+    fn round_trips_and_checks_crc() {
+        let mad = Mad::ndef(3);
+        let mut blocks = mad.encode();
+        assert_eq!(Mad::decode(&blocks), Ok(mad));
+        assert_eq!(mad.ndef_sectors().collect::<Vec<_>>(), [1, 2, 3]);
+
+        blocks[1][15] ^= 1;
+        assert_eq!(Mad::decode(&blocks), Err(Error::Crc));
+        // a factory card has zeros there
+        assert_eq!(Mad::decode(&[[0; 16]; 2]), Err(Error::Crc));
+    }
+
+    #[test]
+    fn message_ends_at_first_gap() {
+        let mut mad = Mad::ndef(0);
+        assert_eq!(mad.ndef_sectors().count(), 0);
+        mad.aids[4] = NDEF_AID;
+        mad.aids[5] = NDEF_AID;
+        mad.aids[7] = NDEF_AID;
+        assert_eq!(mad.ndef_sectors().collect::<Vec<_>>(), [5, 6]);
+    }
+}
@@ -0,0 +1,469 @@
+//! NDEF messages, the format phones read from and write to tags.
+//!
+//! A message is a list of records, each with a type and a payload. Phones
+//! open URI records in the browser, show Text records and hand MIME records
+//! to an app that handles the media type. Records are encoded into and
+//! decoded from byte slices, without allocating:
+//!
+//! ```ignore
+//! let mut buf = [0u8; 64];
+//! let len = ndef::encode(&[Record::uri("https://www.rust-lang.org")], &mut buf)?;
+//! for record in ndef::records(&buf[..len]) {
+//!     println!("{}", record?);
+//! }
+//! ```
+//!
+//! How a message is stored on a tag is up to [`crate::tlv`] and
+//! [`crate::mad`].
+
+use core::fmt;
+
+// header flags
+const MESSAGE_BEGIN: u8 = 0x80;
+const MESSAGE_END: u8 = 0x40;
+const CHUNK: u8 = 0x20;
+const SHORT_RECORD: u8 = 0x10;
+const ID_LENGTH: u8 = 0x08;
+const TNF_MASK: u8 = 0x07;
+
+/// Type Name Format: how to read the type of a record
+pub const TNF_EMPTY: u8 = 0x00;
+pub const TNF_WELL_KNOWN: u8 = 0x01;
+pub const TNF_MIME: u8 = 0x02;
+
+const TYPE_URI: &[u8] = b"U";
+const TYPE_TEXT: &[u8] = b"T";
+
+// Text status byte: bit 7 is set for UTF-16, the low 6 bits give the
+// length of the language code
+const TEXT_UTF16: u8 = 0x80;
+const TEXT_LANGUAGE_MASK: u8 = 0x3F;
+
+/// Abbreviations of the URI record, the first payload byte indexing this table
+const URI_PREFIXES: [&str; 36] = [
+    "",
+    "http://www.",
+    "https://www.",
+    "http://",
+    "https://",
+    "tel:",
+    "mailto:",
+    "ftp://anonymous:anonymous@",
+    "ftp://ftp.",
+    "ftps://",
+    "sftp://",
+    "smb://",
+    "nfs://",
+    "ftp://",
+    "dav://",
+    "news:",
+    "telnet://",
+    "imap:",
+    "rtsp://",
+    "urn:",
+    "pop:",
+    "sip:",
+    "sips:",
+    "tftp:",
+    "btspp://",
+    "btl2cap://",
+    "btgoep://",
+    "tcpobex://",
+    "irdaobex://",
+    "file://",
+    "urn:epc:id:",
+    "urn:epc:tag:",
+    "urn:epc:pat:",
+    "urn:epc:raw:",
+    "urn:epc:",
+    "urn:nfc:",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The message doesn't fit the buffer
+    BufferTooSmall,
+    /// A record claims more bytes than the message holds
+    Truncated,
+    /// Chunked records and UTF-16 text aren't supported
+    Unsupported,
+    /// A field isn't what its record type requires, like a URI that isn't
+    /// UTF-8
+    Invalid,
+}
+
+// a first byte, then two slices
+type Payload<'a> = (Option<u8>, &'a [u8], &'a [u8]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Record<'a> {
+    /// A link; phones open it without asking for an app
+    Uri { prefix: &'static str, rest: &'a str },
+    /// Plain text with its IETF language code, like "en"
+    Text { language: &'a str, text: &'a str },
+    /// Data for the app that handles `media_type`, like "text/vcard"
+    Mime {
+        media_type: &'a str,
+        payload: &'a [u8],
+    },
+    /// Any other record, left undecoded
+    Other {
+        tnf: u8,
+        record_type: &'a [u8],
+        payload: &'a [u8],
+    },
+}
+
+impl<'a> Record<'a> {
+    /// A URI record, abbreviated with the longest prefix that fits
+    pub fn uri(uri: &'a str) -> Self {
+        let prefix = URI_PREFIXES
+            .iter()
+            .filter(|prefix| uri.starts_with(*prefix))
+            .max_by_key(|prefix| prefix.len())
+            .unwrap_or(&URI_PREFIXES[0]);
+        Record::Uri {
+            prefix,
+            rest: &uri[prefix.len()..],
+        }
+    }
+
+    pub fn text(language: &'a str, text: &'a str) -> Self {
+        Record::Text { language, text }
+    }
+
+    pub fn mime(media_type: &'a str, payload: &'a [u8]) -> Self {
+        Record::Mime {
+            media_type,
+            payload,
+        }
+    }
+
+    fn tnf(&self) -> u8 {
+        match self {
+            Record::Uri { .. } | Record::Text { .. } => TNF_WELL_KNOWN,
+            Record::Mime { .. } => TNF_MIME,
+            Record::Other { tnf, .. } => *tnf,
+        }
+    }
+
+    fn record_type(&self) -> &'a [u8] {
+        match self {
+            Record::Uri { .. } => TYPE_URI,
+            Record::Text { .. } => TYPE_TEXT,
+            Record::Mime { media_type, .. } => media_type.as_bytes(),
+            Record::Other { record_type, .. } => record_type,
+        }
+    }
+
+    // the payload in up to three pieces, so URI and Text records don't
+    // need a buffer to prepend their first byte
+    fn payload(&self) -> Result<Payload<'a>, Error> {
+        Ok(match self {
+            Record::Uri { prefix, rest } => {
+                let code = URI_PREFIXES
+                    .iter()
+                    .position(|p| p == prefix)
+                    .ok_or(Error::Invalid)?;
+                (Some(code as u8), &[], rest.as_bytes())
+            }
+            Record::Text { language, text } => {
+                if language.len() > TEXT_LANGUAGE_MASK as usize {
+                    return Err(Error::Invalid);
+                }
+                (
+                    Some(language.len() as u8),
+                    language.as_bytes(),
+                    text.as_bytes(),
+                )
+            }
+            Record::Mime { payload, .. } | Record::Other { payload, .. } => (None, &[], payload),
+        })
+    }
+
+    fn decode(tnf: u8, record_type: &'a [u8], payload: &'a [u8]) -> Result<Self, Error> {
+        let utf8 = |bytes| core::str::from_utf8(bytes).map_err(|_| Error::Invalid);
+        Ok(match (tnf, record_type) {
+            (TNF_WELL_KNOWN, TYPE_URI) => {
+                let (&code, rest) = payload.split_first().ok_or(Error::Invalid)?;
+                Record::Uri {
+                    prefix: URI_PREFIXES.get(code as usize).ok_or(Error::Invalid)?,
+                    rest: utf8(rest)?,
+                }
+            }
+            (TNF_WELL_KNOWN, TYPE_TEXT) => {
+                let (&status, rest) = payload.split_first().ok_or(Error::Invalid)?;
+                if status & TEXT_UTF16 != 0 {
+                    return Err(Error::Unsupported);
+                }
+                let language_len = (status & TEXT_LANGUAGE_MASK) as usize;
+                if language_len > rest.len() {
+                    return Err(Error::Invalid);
+                }
+                let (language, text) = rest.split_at(language_len);
+                Record::Text {
+                    language: utf8(language)?,
+                    text: utf8(text)?,
+                }
+            }
+            (TNF_MIME, media_type) => Record::Mime {
+                media_type: utf8(media_type)?,
+                payload,
+            },
+            _ => Record::Other {
+                tnf,
+                record_type,
+                payload,
+            },
+        })
+    }
+}
+
+impl fmt::Display for Record<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Record::Uri { prefix, rest } => write!(f, "URI {}{}", prefix, rest),
+            Record::Text { language, text } => write!(f, "Text ({}) {}", language, text),
+            Record::Mime {
+                media_type,
+                payload,
+            } => write!(f, "MIME {}, {} bytes", media_type, payload.len()),
+            Record::Other {
+                tnf,
+                record_type,
+                payload,
+            } => write!(
+                f,
+                "TNF {} type {:02x?}, {} bytes",
+                tnf,
+                record_type,
+                payload.len()
+            ),
+        }
+    }
+}
+
+/// Encode `records` as one message into `buf` and return its length
+pub fn encode(records: &[Record], buf: &mut [u8]) -> Result<usize, Error> {
+    let mut len = 0;
+    for (i, record) in records.iter().enumerate() {
+        let record_type = record.record_type();
+        let (first, head, tail) = record.payload()?;
+        let payload_len = first.is_some() as usize + head.len() + tail.len();
+        if record_type.len() > u8::MAX as usize || payload_len > u32::MAX as usize {
+            return Err(Error::Invalid);
+        }
+
+        let mut header = record.tnf() & TNF_MASK;
+        if i == 0 {
+            header |= MESSAGE_BEGIN;
+        }
+        if i == records.len() - 1 {
+            header |= MESSAGE_END;
+        }
+        let short = payload_len <= u8::MAX as usize;
+        if short {
+            header |= SHORT_RECORD;
+        }
+
+        let mut put = |bytes: &[u8]| {
+            let end = len + bytes.len();
+            buf.get_mut(len..end)
+                .ok_or(Error::BufferTooSmall)?
+                .copy_from_slice(bytes);
+            len = end;
+            Ok(())
+        };
+        put(&[header, record_type.len() as u8])?;
+        if short {
+            put(&[payload_len as u8])?;
+        } else {
+            put(&(payload_len as u32).to_be_bytes())?;
+        }
+        put(record_type)?;
+        if let Some(first) = first {
+            put(&[first])?;
+        }
+        put(head)?;
+        put(tail)?;
+    }
+    Ok(len)
+}
+
+/// The records of `message`, up to the one flagged as last
+pub fn records(message: &[u8]) -> Records<'_> {
+    Records {
+        message,
+        done: false,
+    }
+}
+
+pub struct Records<'a> {
+    message: &'a [u8],
+    done: bool,
+}
+
+impl<'a> Records<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.message.len() {
+            return Err(Error::Truncated);
+        }
+        let (taken, rest) = self.message.split_at(len);
+        self.message = rest;
+        Ok(taken)
+    }
+
+    // split off the next record: header, type and payload
+    fn next_record(&mut self) -> Result<(u8, &'a [u8], &'a [u8]), Error> {
+        let start = self.take(2)?;
+        let (header, type_len) = (start[0], start[1] as usize);
+        if header & CHUNK != 0 {
+            return Err(Error::Unsupported);
+        }
+        let payload_len = if header & SHORT_RECORD != 0 {
+            self.take(1)?[0] as usize
+        } else {
+            let len = self.take(4)?;
+            u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize
+        };
+        let id_len = if header & ID_LENGTH != 0 {
+            self.take(1)?[0] as usize
+        } else {
+            0
+        };
+        let record_type = self.take(type_len)?;
+        self.take(id_len)?;
+        let payload = self.take(payload_len)?;
+        Ok((header, record_type, payload))
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.message.is_empty() {
+            return None;
+        }
+        match self.next_record() {
+            Ok((header, record_type, payload)) => {
+                self.done = header & MESSAGE_END != 0;
+                Some(Record::decode(header & TNF_MASK, record_type, payload))
+            }
+            // without a length to trust, the rest can't be split into records
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn decode_all(message: &[u8]) -> Vec<Result<Record<'_>, Error>> {
+        records(message).collect()
+    }
+
+    #[test]
+    fn encodes_uri_like_phones_do() {
+        let mut buf = [0u8; 32];
+        let len = encode(&[Record::uri("https://implrust.com")], &mut buf).unwrap();
+        assert_eq!(buf[..5], [0xD1, 0x01, 0x0D, b'U', 0x04]);
+        assert_eq!(&buf[5..len], b"implrust.com");
+
+        assert_eq!(
+            decode_all(&buf[..len]),
+            [Ok(Record::uri("https://implrust.com"))]
+        );
+    }
+
+    #[test]
+    fn picks_longest_uri_prefix() {
+        assert_eq!(
+            Record::uri("https://www.rust-lang.org"),
+            Record::Uri {
+                prefix: "https://www.",
+                rest: "rust-lang.org"
+            }
+        );
+        assert_eq!(
+            Record::uri("urn:epc:id:sgtin"),
+            Record::Uri {
+                prefix: "urn:epc:id:",
+                rest: "sgtin"
+            }
+        );
+        assert_eq!(
+            Record::uri("geo:0,0"),
+            Record::Uri {
+                prefix: "",
+                rest: "geo:0,0"
+            }
+        );
+    }
+
+    #[test]
+    fn round_trips_messages() {
+        let long = [0x5A; 300];
+        let message = [
+            Record::text("en", "Hello, Ferris"),
+            Record::mime("application/octet-stream", &long),
+            Record::Other {
+                tnf: 4,
+                record_type: b"android.com:pkg",
+                payload: b"com.example",
+            },
+            Record::uri("tel:+15551234"),
+        ];
+        let mut buf = [0u8; 512];
+        let len = encode(&message, &mut buf).unwrap();
+
+        // text record: status byte with the language length, then the language
+        assert_eq!(buf[..7], [0x91, 0x01, 0x10, b'T', 0x02, b'e', b'n']);
+        // only the first record begins and only the last one ends the message
+        assert_eq!(buf[0] & (MESSAGE_BEGIN | MESSAGE_END), MESSAGE_BEGIN);
+
+        let decoded: Vec<_> = decode_all(&buf[..len])
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(decoded, message);
+
+        assert_eq!(
+            encode(&message, &mut buf[..len - 1]),
+            Err(Error::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn stops_at_message_end() {
+        let mut buf = [0u8; 32];
+        let len = encode(&[Record::uri("tel:1")], &mut buf).unwrap();
+        // the zeros after the message are padding, not records
+        assert_eq!(decode_all(&buf).len(), 1);
+        assert_eq!(decode_all(&buf[..len - 1]), [Err(Error::Truncated)]);
+    }
+
+    #[test]
+    fn skips_record_ids() {
+        // short well-known record with a one-byte ID
+        let message = [0xD9, 0x01, 0x02, 0x01, b'U', b'#', 0x05, b'1'];
+        assert_eq!(decode_all(&message), [Ok(Record::uri("tel:1"))]);
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_decode() {
+        let utf16 = [0xD1, 0x01, 0x03, b'T', 0x82, b'e', b'n'];
+        assert_eq!(decode_all(&utf16), [Err(Error::Unsupported)]);
+        let bad_prefix = [0xD1, 0x01, 0x01, b'U', 0x99];
+        assert_eq!(decode_all(&bad_prefix), [Err(Error::Invalid)]);
+        let chunked = [0xF1, 0x01, 0x00, b'U'];
+        assert_eq!(decode_all(&chunked), [Err(Error::Unsupported)]);
+    }
+}
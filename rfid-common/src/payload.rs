@@ -0,0 +1,381 @@
+//! Payloads longer than a block, spread over the data blocks of a range of
+//! sectors.
+//!
+//! Block 0 and the sector trailers are skipped, which leaves 47 blocks of a
+//! 1K card. The payload starts with a 4-byte header, its length and the
+//! CRC_A of the payload, so a blank card or a half-written payload is
+//! refused instead of read as garbage. Every block is read back as soon as
+//! it is written.
+//!
+//! ```ignore
+//! payload::write(&uid, 1..16, &[0xFF; 6], br#"{"name":"Ferris"}"#, &mut rfid)?;
+//! let mut buf = [0u8; 128];
+//! let len = payload::read(&uid, 1..16, &[0xFF; 6], &mut buf, &mut rfid)?;
+//! ```
+
+use core::fmt;
+use core::ops::Range;
+
+use crate::crc::crc_a;
+use mfrc522::{Mfrc522, Uid};
+
+use crate::reader::{self, Key};
+
+pub type Block = [u8; 16];
+
+/// Length and CRC
+pub const HEADER_LEN: usize = 4;
+
+// Data blocks of a 1K sector
+const DATA_BLOCKS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Reader(reader::Error),
+    /// The payload doesn't fit in the sectors
+    TooLong {
+        capacity: usize,
+    },
+    /// The stored payload doesn't fit in the buffer
+    BufferTooSmall {
+        len: usize,
+    },
+    /// The header is longer than the sectors: no payload there
+    NoPayload,
+    /// The CRC doesn't match: blank card, or a write that didn't finish
+    Corrupt,
+}
+
+impl From<reader::Error> for Error {
+    fn from(e: reader::Error) -> Self {
+        Error::Reader(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Reader(e) => write!(f, "{}", e),
+            Error::TooLong { capacity } => write!(f, "payload longer than {} bytes", capacity),
+            Error::BufferTooSmall { len } => write!(f, "payload of {} bytes too long", len),
+            Error::NoPayload => write!(f, "no payload on the card"),
+            Error::Corrupt => write!(f, "payload damaged or incomplete"),
+        }
+    }
+}
+
+/// The blocks a payload may use in `sectors`, in order
+pub fn data_blocks(sectors: Range<u8>) -> impl Iterator<Item = u8> + Clone {
+    sectors
+        .flat_map(|sector| sector * 4..sector * 4 + DATA_BLOCKS as u8)
+        .filter(|&block| block != 0)
+}
+
+/// The longest payload `sectors` hold
+pub fn capacity(sectors: Range<u8>) -> usize {
+    (data_blocks(sectors).count() * 16).saturating_sub(HEADER_LEN)
+}
+
+fn header(payload: &[u8]) -> [u8; HEADER_LEN] {
+    let len = (payload.len() as u16).to_be_bytes();
+    let crc = crc_a(payload);
+    [len[0], len[1], crc[0], crc[1]]
+}
+
+/// The blocks to write for `payload`: header, payload, zeros to the end of
+/// the last block
+#[derive(Clone)]
+pub struct Image<'a> {
+    header: [u8; HEADER_LEN],
+    payload: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Image<'a> {
+    pub fn new(payload: &'a [u8]) -> Self {
+        Self {
+            header: header(payload),
+            payload,
+            offset: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        HEADER_LEN + self.payload.len()
+    }
+}
+
+impl Iterator for Image<'_> {
+    type Item = Block;
+
+    fn next(&mut self) -> Option<Block> {
+        if self.offset >= self.len() {
+            return None;
+        }
+        let mut block = [0u8; 16];
+        for (i, byte) in block.iter_mut().enumerate() {
+            let at = self.offset + i;
+            *byte = if at < HEADER_LEN {
+                self.header[at]
+            } else {
+                self.payload.get(at - HEADER_LEN).copied().unwrap_or(0)
+            };
+        }
+        self.offset += 16;
+        Some(block)
+    }
+}
+
+/// Puts a payload back together from the blocks of an [`Image`]
+pub struct Assembler<'a> {
+    buf: &'a mut [u8],
+    capacity: usize,
+    header: Option<[u8; HEADER_LEN]>,
+    received: usize,
+}
+
+impl<'a> Assembler<'a> {
+    /// `capacity` is that of the sectors being read, see [`capacity`]
+    pub fn new(buf: &'a mut [u8], capacity: usize) -> Self {
+        Self {
+            buf,
+            capacity,
+            header: None,
+            received: 0,
+        }
+    }
+
+    /// Bytes of payload the header announced
+    fn len(&self) -> Option<usize> {
+        self.header
+            .map(|header| u16::from_be_bytes([header[0], header[1]]) as usize)
+    }
+
+    /// Whether every block of the payload came in
+    pub fn is_complete(&self) -> bool {
+        self.len().is_some_and(|len| self.received >= len)
+    }
+
+    /// Take the next block
+    pub fn feed(&mut self, block: &Block) -> Result<(), Error> {
+        let data = match self.header {
+            Some(_) => &block[..],
+            None => {
+                let mut header = [0u8; HEADER_LEN];
+                header.copy_from_slice(&block[..HEADER_LEN]);
+                self.header = Some(header);
+                let len = self.len().unwrap_or(0);
+                if len > self.capacity {
+                    return Err(Error::NoPayload);
+                }
+                if len > self.buf.len() {
+                    return Err(Error::BufferTooSmall { len });
+                }
+                &block[HEADER_LEN..]
+            }
+        };
+        let len = self.len().unwrap_or(0);
+        let take = data.len().min(len.saturating_sub(self.received));
+        self.buf[self.received..self.received + take].copy_from_slice(&data[..take]);
+        self.received += take;
+        Ok(())
+    }
+
+    /// Check the CRC and return the length of the payload
+    pub fn finish(self) -> Result<usize, Error> {
+        let (Some(header), Some(len)) = (self.header, self.len()) else {
+            return Err(Error::NoPayload);
+        };
+        if self.received < len {
+            return Err(Error::NoPayload);
+        }
+        let expected = Image::new(&self.buf[..len]).header;
+        if expected != header {
+            return Err(Error::Corrupt);
+        }
+        Ok(len)
+    }
+}
+
+/// Write `payload` to the data blocks of `sectors`, Key A `key` opening
+/// all of them, and read every block back
+pub fn write<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    sectors: Range<u8>,
+    key: &Key,
+    payload: &[u8],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), Error> {
+    let capacity = capacity(sectors.clone()).min(u16::MAX as usize);
+    if payload.len() > capacity {
+        return Err(Error::TooLong { capacity });
+    }
+
+    let mut image = Image::new(payload);
+    for sector in sectors {
+        if image.offset >= image.len() {
+            break;
+        }
+        // a retry writes the whole sector again
+        let start = image.clone();
+        image = reader::retry(uid, rfid, |rfid| {
+            let mut image = start.clone();
+            reader::authenticate(uid, sector * 4, key, rfid)?;
+            for (block, data) in data_blocks(sector..sector + 1).zip(image.by_ref()) {
+                rfid.mf_write(block, data).map_err(|e| {
+                    reader::Error::from_driver(e, reader::Error::WriteFailed { block })
+                })?;
+                let back = rfid.mf_read(block).map_err(|e| {
+                    reader::Error::from_driver(e, reader::Error::ReadFailed { block })
+                })?;
+                if back != data {
+                    return Err(reader::Error::WriteFailed { block });
+                }
+            }
+            Ok(image)
+        })?;
+    }
+    Ok(())
+}
+
+/// Read the payload stored in `sectors` into `buf`, returning its length
+pub fn read<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    sectors: Range<u8>,
+    key: &Key,
+    buf: &mut [u8],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<usize, Error> {
+    let mut payload = Assembler::new(buf, capacity(sectors.clone()));
+    for sector in sectors {
+        if payload.is_complete() {
+            break;
+        }
+        let blocks = reader::retry(uid, rfid, |rfid| {
+            reader::authenticate(uid, sector * 4, key, rfid)?;
+            let mut blocks = [[0u8; 16]; DATA_BLOCKS];
+            for (block, data) in data_blocks(sector..sector + 1).zip(blocks.iter_mut()) {
+                *data = rfid.mf_read(block).map_err(|e| {
+                    reader::Error::from_driver(e, reader::Error::ReadFailed { block })
+                })?;
+            }
+            Ok(blocks)
+        })?;
+        // block 0 isn't part of the payload
+        let skip = DATA_BLOCKS - data_blocks(sector..sector + 1).count();
+        for block in &blocks[..DATA_BLOCKS - skip] {
+            if payload.is_complete() {
+                break;
+            }
+            payload.feed(block)?;
+        }
+    }
+    payload.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use rfid_emulator::{Card, Emulator, connect};
+
+    use super::*;
+
+    // what the card holds after a write, block by block
+    fn store(sectors: Range<u8>, payload: &[u8]) -> Vec<(u8, Block)> {
+        data_blocks(sectors).zip(Image::new(payload)).collect()
+    }
+
+    fn load(blocks: &[(u8, Block)], capacity: usize, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut payload = Assembler::new(buf, capacity);
+        for (_, block) in blocks {
+            if payload.is_complete() {
+                break;
+            }
+            payload.feed(block)?;
+        }
+        payload.finish()
+    }
+
+    #[test]
+    fn skips_block_0_and_trailers() {
+        let blocks: Vec<u8> = data_blocks(0..2).collect();
+        assert_eq!(blocks, [1, 2, 4, 5, 6]);
+        assert_eq!(data_blocks(0..16).count(), 47);
+        assert_eq!(capacity(0..16), 47 * 16 - HEADER_LEN);
+        assert_eq!(capacity(1..2), 44);
+    }
+
+    #[test]
+    fn round_trips_across_sectors() {
+        let payload: Vec<u8> = (0..100).collect();
+        let blocks = store(0..16, &payload);
+        // 104 bytes take 7 blocks, block 0 and the trailers 3 and 7 skipped
+        let used: Vec<u8> = blocks.iter().map(|&(block, _)| block).collect();
+        assert_eq!(used, [1, 2, 4, 5, 6, 8, 9]);
+        assert_eq!(blocks[0].1[..2], [0, 100]);
+        assert_eq!(blocks[6].1[8..], [0; 8]);
+
+        let mut buf = [0u8; 128];
+        let len = load(&blocks, capacity(0..16), &mut buf).unwrap();
+        assert_eq!(&buf[..len], &payload[..]);
+
+        let mut buf = [0u8; 4];
+        assert_eq!(load(&store(1..2, &[]), 44, &mut buf), Ok(0));
+    }
+
+    #[test]
+    fn refuses_bad_payloads() {
+        let mut buf = [0u8; 64];
+        let mut blocks = store(1..3, b"Ferris the crab");
+        blocks[0].1[10] ^= 1;
+        assert_eq!(load(&blocks, 92, &mut buf), Err(Error::Corrupt));
+
+        // a blank card
+        assert_eq!(load(&[(4, [0; 16])], 92, &mut buf), Err(Error::Corrupt));
+        assert_eq!(
+            load(&[(4, [0xFF; 16])], 92, &mut buf),
+            Err(Error::NoPayload)
+        );
+
+        let blocks = store(1..3, &[7; 80]);
+        assert_eq!(
+            load(&blocks, 92, &mut buf),
+            Err(Error::BufferTooSmall { len: 80 })
+        );
+        // the last block is missing
+        assert_eq!(
+            load(&blocks[..5], 92, &mut [0u8; 128]),
+            Err(Error::NoPayload)
+        );
+    }
+
+    #[test]
+    fn stores_on_a_card() {
+        let key = [0xFF; 6];
+        let mut chip = Emulator::new(Card::new([0xDE, 0xAD, 0xBE, 0xEF]));
+        let mut rfid = connect(&mut chip);
+        let uid = reader::detect(&mut rfid).unwrap();
+        let mut buf = [0u8; 128];
+        assert_eq!(
+            read(&uid, 1..16, &key, &mut buf, &mut rfid),
+            Err(Error::Corrupt)
+        );
+
+        let payload: Vec<u8> = (0..100).collect();
+        write(&uid, 1..16, &key, &payload, &mut rfid).unwrap();
+        assert_eq!(read(&uid, 1..16, &key, &mut buf, &mut rfid), Ok(100));
+        assert_eq!(&buf[..100], &payload[..]);
+
+        // the card leaves during the second block
+        let chip = rfid.release().release();
+        chip.remove_after(5);
+        let mut rfid = connect(chip);
+        assert_eq!(
+            write(&uid, 1..16, &key, &[1; 200], &mut rfid),
+            Err(Error::Reader(reader::Error::NoCard))
+        );
+        assert!(chip.card().is_none());
+    }
+}
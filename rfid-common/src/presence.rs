@@ -0,0 +1,455 @@
+//! Card detection that lets the reader sleep.
+//!
+//! Sending REQA in a loop keeps the field on and the CPU busy. The MFRC522
+//! has no low-power card detection of its own (the MFRC630 family does), so
+//! [`Detector`] pulses the field instead: every poll interval it wakes the
+//! chip from soft power-down, switches the antenna on, gives a card time to
+//! power up and sends WUPA. The answer isn't polled for over SPI: the chip
+//! pulls its IRQ pin low when a frame comes in and the task sleeps on the
+//! pin until then. Without an answer the antenna goes off and the chip back
+//! to sleep until the next burst.
+//!
+//! The driver and the detector take turns on the SPI device through
+//! [`Shared`]:
+//!
+//! ```ignore
+//! let spi_dev = RefCell::new(ExclusiveDevice::new(spi_bus, cs, delay).unwrap());
+//! let mut rfid = Mfrc522::new(SpiInterface::new(Shared::new(&spi_dev))).init().unwrap();
+//! let irq = Input::new(peripherals.GPIO21, InputConfig::default().with_pull(Pull::Up));
+//! let mut detector = Detector::new(Shared::new(&spi_dev), irq, Config::default());
+//! loop {
+//!     detector.wait_for_card().await.unwrap();
+//!     let atqa = rfid.reqa()?;
+//!     ...
+//! }
+//! ```
+//!
+//! The field is off between bursts, which resets any card left on the
+//! reader, so HLTA can't keep it quiet: the detector reports a card when it
+//! arrives, and not again until a burst found the field empty.
+//!
+//! A chip that doesn't wake up or finish sending within [`CHIP_TIMEOUT`]
+//! fails the burst with [`Error::Timeout`] instead of hanging the task.
+
+use core::cell::RefCell;
+
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+use embedded_hal_async::digital::Wait;
+
+// registers
+const COMMAND: u8 = 0x01;
+const COM_IEN: u8 = 0x02;
+const DIV_IEN: u8 = 0x03;
+const COM_IRQ: u8 = 0x04;
+const FIFO_DATA: u8 = 0x09;
+const FIFO_LEVEL: u8 = 0x0A;
+const BIT_FRAMING: u8 = 0x0D;
+const TX_CONTROL: u8 = 0x14;
+
+// CommandReg
+const IDLE: u8 = 0x00;
+const TRANSCEIVE: u8 = 0x0C;
+const POWER_DOWN: u8 = 0x10;
+
+// the IRQ pin follows RxIRq, active low, driven both ways
+const IRQ_INVERTED: u8 = 0x80;
+const RX_IEN: u8 = 0x20;
+const IRQ_PUSH_PULL: u8 = 0x80;
+
+const CLEAR_IRQS: u8 = 0x7F;
+const TX_IRQ: u8 = 0x40;
+const FLUSH_BUFFER: u8 = 0x80;
+const START_SEND: u8 = 0x80;
+const ANTENNA_ON: u8 = 0x03;
+
+// WUPA wakes halted cards as well, in 7 bits
+const WUPA: u8 = 0x52;
+// HLTA with its CRC
+const HLTA: [u8; 4] = [0x50, 0x00, 0x57, 0xCD];
+
+/// A card answers WUPA in about 100 µs
+const ANSWER_TIMEOUT: Duration = Duration::from_millis(2);
+
+/// The oscillator starts within a millisecond after power-down, and a
+/// frame goes out in well under one
+pub const CHIP_TIMEOUT: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<E> {
+    Spi(E),
+    /// The chip didn't wake up or finish sending in time
+    Timeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// Time between bursts while cards come and go
+    pub interval: Duration,
+    /// Time between bursts once no card came for `idle_after`
+    pub idle_interval: Duration,
+    pub idle_after: Duration,
+    /// How long the field is on before WUPA; ISO/IEC 14443 gives cards 5 ms
+    /// to power up
+    pub power_up: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(100),
+            idle_interval: Duration::from_millis(500),
+            idle_after: Duration::from_secs(30),
+            power_up: Duration::from_millis(5),
+        }
+    }
+}
+
+/// The SPI device of the reader, shared by the driver and a [`Detector`].
+/// They never use it at the same time, the borrow only lasts a
+/// transaction.
+pub struct Shared<'a, SPI> {
+    spi: &'a RefCell<SPI>,
+}
+
+impl<'a, SPI> Shared<'a, SPI> {
+    pub fn new(spi: &'a RefCell<SPI>) -> Self {
+        Self { spi }
+    }
+}
+
+impl<SPI: ErrorType> ErrorType for Shared<'_, SPI> {
+    type Error = SPI::Error;
+}
+
+impl<SPI: SpiDevice> SpiDevice for Shared<'_, SPI> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.spi.borrow_mut().transaction(operations)
+    }
+}
+
+// Time to the next burst: shorter while cards come and go
+#[derive(Debug)]
+struct Schedule {
+    config: Config,
+    // since the last card
+    quiet: Duration,
+}
+
+impl Schedule {
+    fn next(&mut self) -> Duration {
+        let interval = if self.quiet >= self.config.idle_after {
+            self.config.idle_interval
+        } else {
+            self.config.interval
+        };
+        self.quiet += interval;
+        interval
+    }
+
+    fn card(&mut self) {
+        self.quiet = Duration::from_ticks(0);
+    }
+}
+
+pub struct Detector<'a, SPI, IRQ> {
+    spi: Shared<'a, SPI>,
+    irq: IRQ,
+    schedule: Schedule,
+    // whether a card answered the last burst
+    present: bool,
+}
+
+impl<'a, SPI: SpiDevice, IRQ: Wait> Detector<'a, SPI, IRQ> {
+    pub fn new(spi: Shared<'a, SPI>, irq: IRQ, config: Config) -> Self {
+        Self {
+            spi,
+            irq,
+            schedule: Schedule {
+                config,
+                quiet: Duration::from_ticks(0),
+            },
+            present: false,
+        }
+    }
+
+    /// Burst every poll interval until a card arrives. The reader is left
+    /// awake with the field on, the card idle and ready for REQA.
+    pub async fn wait_for_card(&mut self) -> Result<(), Error<SPI::Error>> {
+        while !self.burst().await? {
+            Timer::after(self.interval()).await;
+        }
+        Ok(())
+    }
+
+    /// Look for a card once, returning whether one arrived. Otherwise the
+    /// reader goes back to sleep.
+    pub async fn burst(&mut self) -> Result<bool, Error<SPI::Error>> {
+        self.wake()?;
+        Timer::after(self.schedule.config.power_up).await;
+
+        self.transmit(&[WUPA], 7)?;
+        let answered = matches!(
+            with_timeout(ANSWER_TIMEOUT, self.irq.wait_for_low()).await,
+            Ok(Ok(()))
+        );
+        let arrived = answered && !self.present;
+        self.present = answered;
+        if !arrived {
+            self.sleep()?;
+            return Ok(false);
+        }
+
+        // HLTA isn't valid before select, so it sends the card back to idle
+        self.transmit(&HLTA, 0)?;
+        self.poll(COM_IRQ, |irqs| irqs & TX_IRQ != 0)?;
+        self.write(COMMAND, IDLE)?;
+        self.write(COM_IRQ, CLEAR_IRQS)?;
+        self.schedule.card();
+        Ok(true)
+    }
+
+    /// Time to wait before the next [`burst`](Self::burst): the poll
+    /// interval, or the idle one once no card came for a while
+    pub fn interval(&mut self) -> Duration {
+        self.schedule.next()
+    }
+
+    fn wake(&mut self) -> Result<(), Error<SPI::Error>> {
+        self.write(COMMAND, IDLE)?;
+        self.poll(COMMAND, |command| command & POWER_DOWN == 0)?;
+        self.write(COM_IEN, IRQ_INVERTED | RX_IEN)?;
+        self.write(DIV_IEN, IRQ_PUSH_PULL)?;
+        let tx_control = self.read(TX_CONTROL)?;
+        self.write(TX_CONTROL, tx_control | ANTENNA_ON)
+    }
+
+    fn sleep(&mut self) -> Result<(), Error<SPI::Error>> {
+        let tx_control = self.read(TX_CONTROL)?;
+        self.write(TX_CONTROL, tx_control & !ANTENNA_ON)?;
+        self.write(COM_IRQ, CLEAR_IRQS)?;
+        self.write(COMMAND, POWER_DOWN | IDLE)
+    }
+
+    // Start sending `frame`, `bits` bits of its last byte, 0 for all
+    fn transmit(&mut self, frame: &[u8], bits: u8) -> Result<(), Error<SPI::Error>> {
+        self.write(COMMAND, IDLE)?;
+        self.write(COM_IRQ, CLEAR_IRQS)?;
+        self.write(FIFO_LEVEL, FLUSH_BUFFER)?;
+        self.spi
+            .transaction(&mut [Operation::Write(&[FIFO_DATA << 1]), Operation::Write(frame)])
+            .map_err(Error::Spi)?;
+        self.write(COMMAND, TRANSCEIVE)?;
+        self.write(BIT_FRAMING, START_SEND | bits)
+    }
+
+    // Read `register` until `done` says so, for at most CHIP_TIMEOUT
+    fn poll(&mut self, register: u8, done: impl Fn(u8) -> bool) -> Result<(), Error<SPI::Error>> {
+        let deadline = Instant::now() + CHIP_TIMEOUT;
+        while !done(self.read(register)?) {
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout);
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, register: u8) -> Result<u8, Error<SPI::Error>> {
+        let mut buf = [(register << 1) | 0x80, 0];
+        self.spi.transfer_in_place(&mut buf).map_err(Error::Spi)?;
+        Ok(buf[1])
+    }
+
+    fn write(&mut self, register: u8, value: u8) -> Result<(), Error<SPI::Error>> {
+        self.spi.write(&[register << 1, value]).map_err(Error::Spi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use core::convert::Infallible;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+
+    // Registers and FIFO of an MFRC522, and whether a card is in its field
+    #[derive(Default)]
+    struct Chip {
+        registers: [u8; 0x20],
+        fifo: Vec<u8>,
+        card: bool,
+        // a card answered the last frame
+        answered: bool,
+        // frames that went out
+        sent: Vec<Vec<u8>>,
+        // never leaves power-down
+        stuck_asleep: bool,
+        // never finishes sending
+        stuck_sending: bool,
+    }
+
+    impl Chip {
+        fn asleep(&self) -> bool {
+            self.registers[COMMAND as usize] & POWER_DOWN != 0
+                && self.registers[TX_CONTROL as usize] & ANTENNA_ON == 0
+        }
+
+        fn set(&mut self, register: u8, value: u8) {
+            let register = register as usize;
+            match register as u8 {
+                COM_IRQ if value & 0x80 == 0 => self.registers[register] &= !value,
+                COM_IRQ => self.registers[register] |= value & 0x7F,
+                FIFO_LEVEL if value & FLUSH_BUFFER != 0 => self.fifo.clear(),
+                BIT_FRAMING => {
+                    self.registers[register] = value;
+                    if value & START_SEND != 0 && self.registers[COMMAND as usize] == TRANSCEIVE {
+                        self.send();
+                    }
+                }
+                _ => self.registers[register] = value,
+            }
+        }
+
+        fn send(&mut self) {
+            let frame = core::mem::take(&mut self.fifo);
+            let field = self.registers[TX_CONTROL as usize] & ANTENNA_ON == ANTENNA_ON;
+            self.answered = field && self.card && frame == [WUPA];
+            if !self.stuck_sending {
+                self.registers[COM_IRQ as usize] |= TX_IRQ;
+            }
+            self.sent.push(frame);
+        }
+
+        fn get(&self, register: u8) -> u8 {
+            let value = self.registers[register as usize];
+            if register == COMMAND && self.stuck_asleep {
+                value | POWER_DOWN
+            } else {
+                value
+            }
+        }
+    }
+
+    impl ErrorType for Chip {
+        type Error = Infallible;
+    }
+
+    impl SpiDevice for Chip {
+        fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+            match operations {
+                [Operation::TransferInPlace(buf)] => buf[1] = self.get(buf[0] >> 1 & 0x3F),
+                [Operation::Write([register, value])] => self.set(register >> 1, *value),
+                [Operation::Write([address]), Operation::Write(frame)]
+                    if *address == FIFO_DATA << 1 =>
+                {
+                    self.fifo.extend_from_slice(frame)
+                }
+                _ => unreachable!("unexpected transaction"),
+            }
+            Ok(())
+        }
+    }
+
+    // The IRQ pin, low once a card answered
+    struct Irq<'a>(&'a RefCell<Chip>);
+
+    impl embedded_hal::digital::ErrorType for Irq<'_> {
+        type Error = Infallible;
+    }
+
+    impl Wait for Irq<'_> {
+        async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+            if !self.0.borrow().answered {
+                core::future::pending::<()>().await;
+            }
+            Ok(())
+        }
+
+        async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+            unreachable!()
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+            unreachable!()
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+            unreachable!()
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn reports_cards_as_they_arrive() {
+        let chip = RefCell::new(Chip::default());
+        let mut detector = Detector::new(Shared::new(&chip), Irq(&chip), Config::default());
+
+        assert_eq!(block_on(detector.burst()), Ok(false));
+        assert!(chip.borrow().asleep());
+
+        chip.borrow_mut().card = true;
+        chip.borrow_mut().sent.clear();
+        assert_eq!(block_on(detector.burst()), Ok(true));
+        {
+            // awake with the field on, the card back in idle
+            let chip = chip.borrow();
+            assert_eq!(chip.sent, [&[WUPA][..], &HLTA]);
+            assert_eq!(chip.registers[COMMAND as usize], IDLE);
+            assert_eq!(chip.registers[TX_CONTROL as usize] & ANTENNA_ON, ANTENNA_ON);
+        }
+
+        // a card left on the reader isn't reported again
+        assert_eq!(block_on(detector.burst()), Ok(false));
+        assert!(chip.borrow().asleep());
+        chip.borrow_mut().card = false;
+        assert_eq!(block_on(detector.burst()), Ok(false));
+        chip.borrow_mut().card = true;
+        assert_eq!(block_on(detector.burst()), Ok(true));
+    }
+
+    #[test]
+    fn gives_up_on_a_chip_that_doesnt_wake() {
+        let chip = RefCell::new(Chip {
+            stuck_asleep: true,
+            ..Chip::default()
+        });
+        let mut detector = Detector::new(Shared::new(&chip), Irq(&chip), Config::default());
+        assert_eq!(block_on(detector.burst()), Err(Error::Timeout));
+        assert_eq!(block_on(detector.wait_for_card()), Err(Error::Timeout));
+    }
+
+    #[test]
+    fn gives_up_on_a_frame_that_doesnt_go_out() {
+        let chip = RefCell::new(Chip {
+            card: true,
+            stuck_sending: true,
+            ..Chip::default()
+        });
+        let mut detector = Detector::new(Shared::new(&chip), Irq(&chip), Config::default());
+        assert_eq!(block_on(detector.burst()), Err(Error::Timeout));
+    }
+
+    #[test]
+    fn slows_down_when_no_card_comes() {
+        let config = Config {
+            idle_after: Duration::from_millis(300),
+            ..Config::default()
+        };
+        let mut schedule = Schedule {
+            config,
+            quiet: Duration::from_ticks(0),
+        };
+        let intervals: [u64; 5] = core::array::from_fn(|_| schedule.next().as_millis());
+        assert_eq!(intervals, [100, 100, 100, 500, 500]);
+
+        schedule.card();
+        assert_eq!(schedule.next(), config.interval);
+    }
+}
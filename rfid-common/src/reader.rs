@@ -0,0 +1,307 @@
+//! Card access that survives the card leaving the field.
+//!
+//! Every operation returns an [`Error`] instead of panicking. A failed
+//! authentication or read halts the card, so [`retry`] selects it again
+//! with WUPA before the next attempt, and gives up with [`Error::NoCard`]
+//! once it's gone. [`with_card`] wraps a whole exchange and always halts the
+//! card and switches encryption off afterwards, so the reader is ready for
+//! the next one:
+//!
+//! ```ignore
+//! match reader::with_card(&mut rfid, |uid, rfid| {
+//!     reader::write_block(uid, 5, &[0xFF; 6], data, rfid)?;
+//!     reader::read_sector(uid, 1, &[0xFF; 6], rfid)
+//! }) {
+//!     Ok(blocks) => ...,
+//!     Err(Error::NoCard) => {}
+//!     Err(e) => println!("{}", e),
+//! }
+//! ```
+
+use core::fmt;
+
+use mfrc522::{Mfrc522, Uid};
+
+use crate::card::auth_uid;
+
+pub type Key = [u8; 6];
+
+/// Tries of an operation before giving up
+pub const ATTEMPTS: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No card answered, or the card left the field
+    NoCard,
+    /// More than one card answered
+    Collision,
+    /// Wrong key, or the key may not access the sector
+    AuthFailed {
+        sector: u8,
+    },
+    ReadFailed {
+        block: u8,
+    },
+    /// The card refused the write: the access bits don't allow it
+    WriteFailed {
+        block: u8,
+    },
+    /// A frame came back damaged
+    Crc,
+    /// The card stopped answering halfway
+    Timeout,
+}
+
+impl Error {
+    /// What driver error `e` means for an operation that fails as `failed`
+    pub fn from_driver<E>(e: mfrc522::Error<E>, failed: Error) -> Self {
+        match e {
+            mfrc522::Error::Collision => Error::Collision,
+            mfrc522::Error::Crc => Error::Crc,
+            mfrc522::Error::Timeout => Error::Timeout,
+            _ => failed,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoCard => write!(f, "no card"),
+            Error::Collision => write!(f, "more than one card in the field"),
+            Error::AuthFailed { sector } => write!(f, "authentication failed on sector {}", sector),
+            Error::ReadFailed { block } => write!(f, "can't read block {}", block),
+            Error::WriteFailed { block } => write!(f, "can't write block {}", block),
+            Error::Crc => write!(f, "CRC error"),
+            Error::Timeout => write!(f, "the card stopped answering"),
+        }
+    }
+}
+
+/// Wake and select a card
+pub fn detect<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<Uid, Error> {
+    let atqa = rfid.reqa().map_err(|e| match e {
+        mfrc522::Error::Collision => Error::Collision,
+        _ => Error::NoCard,
+    })?;
+    rfid.select(&atqa)
+        .map_err(|e| Error::from_driver(e, Error::NoCard))
+}
+
+/// Run `exchange` with the next card, then halt it whatever happened.
+/// `exchange` may return its own error type, as long as an [`Error`]
+/// converts into it.
+pub fn with_card<E, COMM, T, X, F>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    exchange: F,
+) -> Result<T, X>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+    X: From<Error>,
+    F: FnOnce(&Uid, &mut Mfrc522<COMM, mfrc522::Initialized>) -> Result<T, X>,
+{
+    let uid = detect(rfid)?;
+    let result = exchange(&uid, rfid);
+    release(rfid);
+    result
+}
+
+/// Halt the card and end the encrypted session
+pub fn release<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) {
+    let _ = rfid.hlta();
+    let _ = rfid.stop_crypto1();
+}
+
+/// Bring the card with `uid` back to the selected state after a failure.
+/// Another card taking its place counts as no card.
+pub fn recover<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), Error> {
+    release(rfid);
+    let atqa = rfid.wupa().map_err(|_| Error::NoCard)?;
+    match rfid.select(&atqa) {
+        Ok(again) if again.as_bytes() == uid.as_bytes() => Ok(()),
+        _ => Err(Error::NoCard),
+    }
+}
+
+/// Run `op` up to [`ATTEMPTS`] times, recovering the card between tries.
+/// The card is left selected even when every try failed.
+pub fn retry<E, COMM, T, F>(
+    uid: &Uid,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    mut op: F,
+) -> Result<T, Error>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+    F: FnMut(&mut Mfrc522<COMM, mfrc522::Initialized>) -> Result<T, Error>,
+{
+    let mut attempt = 1;
+    loop {
+        let e = match op(rfid) {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        recover(uid, rfid)?;
+        if attempt == ATTEMPTS {
+            return Err(e);
+        }
+        attempt += 1;
+    }
+}
+
+/// Authenticate the sector of `block` with Key A, once
+pub fn authenticate<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    block: u8,
+    key: &Key,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), Error> {
+    rfid.mf_authenticate(&auth_uid(uid), block, key)
+        .map_err(|_| Error::AuthFailed {
+            sector: sector_of(block),
+        })
+}
+
+pub fn read_block<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    block: u8,
+    key: &Key,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<[u8; 16], Error> {
+    retry(uid, rfid, |rfid| {
+        authenticate(uid, block, key, rfid)?;
+        rfid.mf_read(block)
+            .map_err(|e| Error::from_driver(e, Error::ReadFailed { block }))
+    })
+}
+
+/// Read the four blocks of a 1K sector, trailer included
+pub fn read_sector<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    sector: u8,
+    key: &Key,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<[[u8; 16]; 4], Error> {
+    let first = sector * 4;
+    retry(uid, rfid, |rfid| {
+        authenticate(uid, first, key, rfid)?;
+        let mut blocks = [[0u8; 16]; 4];
+        for (block, data) in (first..).zip(blocks.iter_mut()) {
+            *data = rfid
+                .mf_read(block)
+                .map_err(|e| Error::from_driver(e, Error::ReadFailed { block }))?;
+        }
+        Ok(blocks)
+    })
+}
+
+pub fn write_block<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    block: u8,
+    key: &Key,
+    data: [u8; 16],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), Error> {
+    retry(uid, rfid, |rfid| {
+        authenticate(uid, block, key, rfid)?;
+        rfid.mf_write(block, data)
+            .map_err(|e| Error::from_driver(e, Error::WriteFailed { block }))
+    })
+}
+
+/// Sector of `block` on a 1K card
+pub const fn sector_of(block: u8) -> u8 {
+    block / 4
+}
+
+#[cfg(test)]
+mod tests {
+    use rfid_emulator::{Card, Emulator, FACTORY_TRAILER, State, connect};
+
+    use super::*;
+
+    const UID: [u8; 4] = [0xDE, 0xAD, 0xBE, 0xEF];
+    const KEY: Key = [0xFF; 6];
+
+    #[test]
+    fn sorts_driver_errors() {
+        let failed = Error::ReadFailed { block: 6 };
+        assert_eq!(
+            Error::from_driver(mfrc522::Error::<()>::Timeout, failed),
+            Error::Timeout
+        );
+        assert_eq!(
+            Error::from_driver(mfrc522::Error::<()>::Collision, failed),
+            Error::Collision
+        );
+        assert_eq!(
+            Error::from_driver(mfrc522::Error::<()>::Crc, failed),
+            Error::Crc
+        );
+        assert_eq!(
+            Error::from_driver(mfrc522::Error::<()>::Nak, failed),
+            failed
+        );
+        assert_eq!(Error::from_driver(mfrc522::Error::Comm(()), failed), failed);
+    }
+
+    #[test]
+    fn finds_sectors() {
+        assert_eq!(sector_of(3), 0);
+        assert_eq!(sector_of(7), 1);
+    }
+
+    #[test]
+    fn halts_the_card_after_an_exchange() {
+        let mut chip = Emulator::new(Card::new(UID));
+        let mut rfid = connect(&mut chip);
+        let blocks = with_card(&mut rfid, |uid, rfid| {
+            write_block(uid, 5, &KEY, [7; 16], rfid)?;
+            read_sector(uid, 1, &KEY, rfid)
+        })
+        .unwrap();
+        assert_eq!(blocks[1], [7; 16]);
+        // Key A doesn't read back
+        assert_eq!(blocks[3][..6], [0; 6]);
+        assert_eq!(blocks[3][6..], FACTORY_TRAILER[6..]);
+
+        // a halted card ignores REQA
+        assert_eq!(detect(&mut rfid).err(), Some(Error::NoCard));
+        assert_eq!(chip.card().unwrap().state(), State::Halt);
+    }
+
+    #[test]
+    fn retries_until_the_card_is_gone() {
+        let mut chip = Emulator::new(Card::new(UID));
+        let mut rfid = connect(&mut chip);
+        let uid = detect(&mut rfid).unwrap();
+        let chip = rfid.release().release();
+        chip.lose_frames(1);
+        let mut rfid = connect(chip);
+        assert_eq!(read_block(&uid, 4, &KEY, &mut rfid), Ok([0; 16]));
+
+        // a wrong key fails every try, and leaves the card selected
+        assert_eq!(
+            read_block(&uid, 4, &[0; 6], &mut rfid),
+            Err(Error::AuthFailed { sector: 1 })
+        );
+        assert_eq!(read_block(&uid, 0, &KEY, &mut rfid).unwrap()[..4], UID);
+
+        let chip = rfid.release().release();
+        chip.remove();
+        let mut rfid = connect(chip);
+        assert_eq!(read_block(&uid, 4, &KEY, &mut rfid), Err(Error::NoCard));
+
+        // another card in its place is no card either
+        let chip = rfid.release().release();
+        chip.insert(Card::new([1, 2, 3, 4]));
+        let mut rfid = connect(chip);
+        assert_eq!(read_block(&uid, 4, &KEY, &mut rfid), Err(Error::NoCard));
+    }
+}
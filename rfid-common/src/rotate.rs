@@ -0,0 +1,439 @@
+//! Key rotation that can't lock a sector by accident.
+//!
+//! [`rotate_sector`] reads the trailer with the old Key A and keeps it as a
+//! backup, writes the new keys, then authenticates with the new Key A and
+//! reads the access bits back. When that fails, the backup goes back on the
+//! card, written with whichever key opens the sector. Key A never reads
+//! back, so the backup takes the old one from the [`Rotation`], and Key B
+//! from the trailer when the access bits let Key A read it.
+//!
+//! A key file rotates many sectors in one go, one line per sector or range
+//! of sectors, keys in hex:
+//!
+//! ```text
+//! # sectors  old key A     new key A     new key B     [old key B]
+//! 1-15       FFFFFFFFFFFF  527573746564  466572726973
+//! ```
+
+use core::fmt;
+use core::ops::RangeInclusive;
+
+use mfrc522::{Mfrc522, Uid};
+
+use crate::access::{AccessConditions, Keys};
+use crate::reader::{self, Key};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rotation {
+    pub sectors: RangeInclusive<u8>,
+    pub old_key_a: Key,
+    /// Needed for the backup when Key A can't read Key B
+    pub old_key_b: Option<Key>,
+    pub key_a: Key,
+    pub key_b: Key,
+    /// New access conditions, `None` keeping the current ones
+    pub access: Option<AccessConditions>,
+}
+
+/// A key file line that doesn't parse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// A line needs sectors and three keys, and may add the old Key B
+    Fields,
+    /// Sectors are a number or a range like `1-15`, up to 31: the sectors
+    /// of four blocks
+    Sectors,
+    /// Keys are 12 hex digits
+    Key,
+}
+
+/// Why a sector wasn't touched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    /// The old Key A can't read the trailer
+    Reader(reader::Error),
+    /// The trailer holds access bits the card would refuse
+    BadAccessBits,
+    /// The access bits don't let Key A write what needs changing
+    NotAllowed,
+    /// Key B isn't readable and the key file doesn't give it
+    UnknownKeyB,
+    /// The new access conditions would lock the sector
+    Locked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The new keys work
+    Rotated,
+    /// Nothing was written
+    Skipped(Skip),
+    /// The write failed and the old keys still work
+    Unchanged(reader::Error),
+    /// The new keys didn't verify, the backup is back on the card
+    RolledBack,
+    /// Neither the old nor the new Key A opens the sector any more
+    Failed,
+}
+
+impl Outcome {
+    pub fn is_rotated(&self) -> bool {
+        matches!(self, Outcome::Rotated)
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Rotated => write!(f, "rotated"),
+            Outcome::Skipped(Skip::Reader(e)) => write!(f, "skipped: {}", e),
+            Outcome::Skipped(Skip::BadAccessBits) => write!(f, "skipped: invalid access bits"),
+            Outcome::Skipped(Skip::NotAllowed) => {
+                write!(f, "skipped: Key A may not change the trailer")
+            }
+            Outcome::Skipped(Skip::UnknownKeyB) => write!(f, "skipped: old Key B unknown"),
+            Outcome::Skipped(Skip::Locked) => {
+                write!(f, "skipped: the new conditions would lock the sector")
+            }
+            Outcome::Unchanged(e) => write!(f, "unchanged: {}", e),
+            Outcome::RolledBack => write!(f, "rolled back to the old keys"),
+            Outcome::Failed => write!(f, "FAILED, neither key opens the sector"),
+        }
+    }
+}
+
+/// The trailer to restore and the one to write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plan {
+    pub backup: [u8; 16],
+    pub new: [u8; 16],
+}
+
+/// Work out the backup and the new trailer from the trailer as Key A
+/// `rotation.old_key_a` reads it
+pub fn plan(read: &[u8; 16], rotation: &Rotation) -> Result<Plan, Skip> {
+    let current =
+        AccessConditions::decode(&[read[6], read[7], read[8]]).map_err(|_| Skip::BadAccessBits)?;
+
+    let mut backup = *read;
+    backup[..6].copy_from_slice(&rotation.old_key_a);
+    if !current.key_b_readable() {
+        let old_key_b = rotation.old_key_b.ok_or(Skip::UnknownKeyB)?;
+        backup[10..].copy_from_slice(&old_key_b);
+    }
+
+    let new = match rotation.access {
+        Some(access) => access
+            .trailer(&rotation.key_a, &rotation.key_b, false)
+            .map_err(|_| Skip::Locked)?,
+        None => {
+            let mut new = backup;
+            new[..6].copy_from_slice(&rotation.key_a);
+            new[10..].copy_from_slice(&rotation.key_b);
+            new
+        }
+    };
+
+    let permissions = current.trailer.trailer();
+    let key_a_may = |keys| matches!(keys, Keys::A | Keys::Both);
+    let access_kept = new[6..9] == backup[6..9];
+    if !key_a_may(permissions.key_a_write)
+        || !key_a_may(permissions.key_b_write)
+        || !(access_kept || key_a_may(permissions.access_write))
+    {
+        return Err(Skip::NotAllowed);
+    }
+    Ok(Plan { backup, new })
+}
+
+/// Give `sector` the keys of `rotation`, restoring the old trailer if the
+/// new keys don't work
+pub fn rotate_sector<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    sector: u8,
+    rotation: &Rotation,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Outcome {
+    let trailer = sector * 4 + 3;
+
+    // 1. back up the current trailer
+    let read = match reader::read_block(uid, trailer, &rotation.old_key_a, rfid) {
+        Ok(read) => read,
+        Err(e) => return Outcome::Skipped(Skip::Reader(e)),
+    };
+    let plan = match plan(&read, rotation) {
+        Ok(plan) => plan,
+        Err(skip) => return Outcome::Skipped(skip),
+    };
+
+    // 2. write the new one. The card may take a write whose answer got
+    // lost, so the new key is tried whatever the result.
+    let written = reader::write_block(uid, trailer, &rotation.old_key_a, plan.new, rfid);
+
+    // 3. verify with the new key
+    if opens(uid, trailer, &rotation.key_a, &plan.new, rfid) {
+        return Outcome::Rotated;
+    }
+    match written {
+        Err(e) if opens(uid, trailer, &rotation.old_key_a, &plan.backup, rfid) => {
+            return Outcome::Unchanged(e);
+        }
+        _ => {}
+    }
+
+    // 4. put the backup back with whichever key still works
+    for key in [&rotation.key_a, &rotation.old_key_a] {
+        if reader::write_block(uid, trailer, key, plan.backup, rfid).is_ok()
+            && opens(uid, trailer, &rotation.old_key_a, &plan.backup, rfid)
+        {
+            return Outcome::RolledBack;
+        }
+    }
+    Outcome::Failed
+}
+
+/// Rotate every sector of `rotation`, reporting each through `report`.
+/// Stops early when the card leaves.
+pub fn rotate<E, COMM, F>(
+    uid: &Uid,
+    rotation: &Rotation,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    mut report: F,
+) -> Result<(), reader::Error>
+where
+    COMM: mfrc522::comm::Interface<Error = E>,
+    F: FnMut(u8, Outcome),
+{
+    for sector in rotation.sectors.clone() {
+        let outcome = rotate_sector(uid, sector, rotation, rfid);
+        report(sector, outcome);
+        if outcome == Outcome::Skipped(Skip::Reader(reader::Error::NoCard)) {
+            return Err(reader::Error::NoCard);
+        }
+    }
+    Ok(())
+}
+
+// Whether `key` opens the trailer and the access bits read back as in
+// `expected`
+fn opens<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    trailer: u8,
+    key: &Key,
+    expected: &[u8; 16],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> bool {
+    reader::read_block(uid, trailer, key, rfid).is_ok_and(|read| read[6..10] == expected[6..10])
+}
+
+/// The rotations of a key file, with the number of any line that doesn't
+/// parse
+pub fn parse(text: &str) -> impl Iterator<Item = Result<Rotation, (usize, ParseError)>> + '_ {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split('#').next().unwrap_or("").trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(number, line)| parse_line(line).map_err(|e| (number, e)))
+}
+
+fn parse_line(line: &str) -> Result<Rotation, ParseError> {
+    let mut fields = line.split_whitespace();
+    let mut next = || fields.next().ok_or(ParseError::Fields);
+    let sectors = parse_sectors(next()?).ok_or(ParseError::Sectors)?;
+    let old_key_a = parse_key(next()?)?;
+    let key_a = parse_key(next()?)?;
+    let key_b = parse_key(next()?)?;
+    let old_key_b = match fields.next() {
+        Some(key) => Some(parse_key(key)?),
+        None => None,
+    };
+    if fields.next().is_some() {
+        return Err(ParseError::Fields);
+    }
+    Ok(Rotation {
+        sectors,
+        old_key_a,
+        old_key_b,
+        key_a,
+        key_b,
+        access: None,
+    })
+}
+
+fn parse_sectors(text: &str) -> Option<RangeInclusive<u8>> {
+    let (first, last) = match text.split_once('-') {
+        Some((first, last)) => (first.parse().ok()?, last.parse().ok()?),
+        None => {
+            let sector = text.parse().ok()?;
+            (sector, sector)
+        }
+    };
+    (first <= last && last < 32).then_some(first..=last)
+}
+
+fn parse_key(text: &str) -> Result<Key, ParseError> {
+    if text.len() != 12 {
+        return Err(ParseError::Key);
+    }
+    let mut key = [0u8; 6];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = text
+            .get(2 * i..2 * i + 2)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or(ParseError::Key)?;
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use rfid_emulator::{Card, Emulator, connect};
+
+    use super::*;
+    use crate::access::Condition;
+
+    const FACTORY: [u8; 16] = [
+        0, 0, 0, 0, 0, 0, 0xFF, 0x07, 0x80, 0x69, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    ];
+
+    fn rotation() -> Rotation {
+        Rotation {
+            sectors: 1..=1,
+            old_key_a: [0xFF; 6],
+            old_key_b: None,
+            key_a: *b"Rusted",
+            key_b: *b"Ferris",
+            access: None,
+        }
+    }
+
+    #[test]
+    fn parses_key_files() {
+        let text = "# sectors old A new A new B\n\
+                    1-15 FFFFFFFFFFFF 527573746564 466572726973\n\
+                    \n\
+                    20 ffffffffffff 000000000000 000000000000 A0A1A2A3A4A5 # MAD\n\
+                    3 FFFFFFFFFFFF 5275737465\n\
+                    40 FFFFFFFFFFFF 527573746564 466572726973\n\
+                    7 FFFFFFFFFFFF\n";
+        let rotations: Vec<_> = parse(text).collect();
+        assert_eq!(
+            rotations[0],
+            Ok(Rotation {
+                sectors: 1..=15,
+                ..rotation()
+            })
+        );
+        let second = rotations[1].as_ref().unwrap();
+        assert_eq!(second.sectors, 20..=20);
+        assert_eq!(second.old_key_b, Some([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5]));
+        assert_eq!(rotations[2], Err((5, ParseError::Key)));
+        assert_eq!(rotations[3], Err((6, ParseError::Sectors)));
+        assert_eq!(rotations[4], Err((7, ParseError::Fields)));
+    }
+
+    #[test]
+    fn plans_backup_and_new_trailer() {
+        let plan = plan(&FACTORY, &rotation()).unwrap();
+        assert_eq!(plan.backup[..6], [0xFF; 6]);
+        assert_eq!(plan.backup[6..], FACTORY[6..]);
+        assert_eq!(&plan.new[..6], b"Rusted");
+        assert_eq!(plan.new[6..10], FACTORY[6..10]);
+        assert_eq!(&plan.new[10..], b"Ferris");
+    }
+
+    #[test]
+    fn refuses_what_it_cant_undo() {
+        assert_eq!(plan(&[0; 16], &rotation()), Err(Skip::BadAccessBits));
+
+        // key B manages the trailer and can't be read
+        let mut by_b = FACTORY;
+        let conditions = AccessConditions {
+            trailer: Condition::new(false, true, true),
+            ..AccessConditions::TRANSPORT
+        };
+        by_b[6..9].copy_from_slice(&conditions.encode());
+        assert_eq!(plan(&by_b, &rotation()), Err(Skip::UnknownKeyB));
+        let with_b = Rotation {
+            old_key_b: Some([0xFF; 6]),
+            ..rotation()
+        };
+        assert_eq!(plan(&by_b, &with_b), Err(Skip::NotAllowed));
+
+        let frozen = AccessConditions {
+            trailer: Condition::new(true, true, true),
+            ..AccessConditions::TRANSPORT
+        };
+        let locking = Rotation {
+            access: Some(frozen),
+            ..rotation()
+        };
+        assert_eq!(plan(&FACTORY, &locking), Err(Skip::Locked));
+    }
+
+    #[test]
+    fn rotates_the_sectors_of_a_card() {
+        let by_b = AccessConditions {
+            trailer: Condition::new(false, true, true),
+            ..AccessConditions::TRANSPORT
+        };
+        let mut card = Card::new([0xDE, 0xAD, 0xBE, 0xEF]);
+        card.set_trailer(
+            3,
+            *b"Other!",
+            AccessConditions::TRANSPORT.encode(),
+            [0xFF; 6],
+        );
+        card.set_trailer(4, [0xFF; 6], by_b.encode(), [0x42; 6]);
+        let mut chip = Emulator::new(card);
+        let mut rfid = connect(&mut chip);
+        let uid = reader::detect(&mut rfid).unwrap();
+
+        let rotation = Rotation {
+            sectors: 1..=4,
+            ..rotation()
+        };
+        // the card misses a frame halfway, which a retry makes up for
+        let chip = rfid.release().release();
+        chip.lose_frames(1);
+        let mut rfid = connect(chip);
+        let mut outcomes = Vec::new();
+        rotate(&uid, &rotation, &mut rfid, |sector, outcome| {
+            outcomes.push((sector, outcome))
+        })
+        .unwrap();
+        assert_eq!(
+            outcomes,
+            [
+                (1, Outcome::Rotated),
+                (2, Outcome::Rotated),
+                (
+                    3,
+                    Outcome::Skipped(Skip::Reader(reader::Error::AuthFailed { sector: 3 }))
+                ),
+                (4, Outcome::Skipped(Skip::UnknownKeyB)),
+            ]
+        );
+
+        // the old key no longer opens a rotated sector
+        assert_eq!(
+            rotate_sector(&uid, 1, &rotation, &mut rfid),
+            Outcome::Skipped(Skip::Reader(reader::Error::AuthFailed { sector: 1 }))
+        );
+        let chip = rfid.release().release();
+        let card = chip.remove().unwrap();
+        assert_eq!(&card.block(7)[..6], b"Rusted");
+        assert_eq!(card.block(7)[6..10], FACTORY[6..10]);
+        assert_eq!(&card.block(11)[10..], b"Ferris");
+        assert_eq!(&card.block(19)[..6], &[0xFF; 6]);
+
+        let mut rfid = connect(chip);
+        assert_eq!(
+            rotate(&uid, &rotation, &mut rfid, |_, _| {}),
+            Err(reader::Error::NoCard)
+        );
+    }
+}
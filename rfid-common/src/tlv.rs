@@ -0,0 +1,240 @@
+//! TLV blocks, which hold the NDEF message in the data area of a tag.
+//!
+//! NTAG21x and Ultralight tags (NFC Forum Type 2) keep their data area from
+//! page 4 on, described by the capability container in page 3. MIFARE
+//! Classic cards use the same TLVs in the data blocks of their NDEF sectors.
+//! Each TLV is a tag byte, a length of one byte or `FF` and two bytes, and
+//! the value; a terminator TLV ends the area.
+
+/// Padding, without length
+pub const NULL: u8 = 0x00;
+pub const LOCK_CONTROL: u8 = 0x01;
+pub const MEMORY_CONTROL: u8 = 0x02;
+pub const NDEF: u8 = 0x03;
+pub const PROPRIETARY: u8 = 0xFD;
+/// End of the area, without length
+pub const TERMINATOR: u8 = 0xFE;
+
+// first byte of a three-byte length
+const LONG_LENGTH: u8 = 0xFF;
+
+/// Magic number of the capability container
+pub const CC_MAGIC: u8 = 0xE1;
+/// Page of the capability container on Type 2 tags
+pub const CC_PAGE: u8 = 3;
+/// First page of the data area on Type 2 tags
+pub const DATA_PAGE: u8 = 4;
+pub const PAGE_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A TLV claims more bytes than the area holds
+    Truncated,
+    /// The area ends without an NDEF TLV
+    NoNdef,
+    /// The message doesn't fit the area
+    TooLong,
+    /// Page 3 doesn't describe an NDEF tag
+    NotFormatted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    /// Where the TLV starts in the area
+    pub offset: usize,
+    pub value: &'a [u8],
+}
+
+/// The TLVs of a data area up to the terminator, without the padding
+pub fn tlvs(area: &[u8]) -> Tlvs<'_> {
+    Tlvs { area, offset: 0 }
+}
+
+pub struct Tlvs<'a> {
+    area: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for Tlvs<'a> {
+    type Item = Result<Tlv<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let offset = self.offset;
+            let tag = *self.area.get(offset)?;
+            match tag {
+                NULL => self.offset += 1,
+                TERMINATOR => return None,
+                _ => {
+                    let tlv = self.read(offset, tag);
+                    // nothing after a broken length can be trusted
+                    if tlv.is_err() {
+                        self.offset = self.area.len();
+                    }
+                    return Some(tlv);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Tlvs<'a> {
+    fn read(&mut self, offset: usize, tag: u8) -> Result<Tlv<'a>, Error> {
+        let byte = |i: usize| self.area.get(i).copied().ok_or(Error::Truncated);
+        let (len, start) = match byte(offset + 1)? {
+            LONG_LENGTH => (
+                u16::from_be_bytes([byte(offset + 2)?, byte(offset + 3)?]) as usize,
+                offset + 4,
+            ),
+            len => (len as usize, offset + 2),
+        };
+        let value = self.area.get(start..start + len).ok_or(Error::Truncated)?;
+        self.offset = start + len;
+        Ok(Tlv { tag, offset, value })
+    }
+}
+
+/// The first NDEF TLV of a data area
+pub fn find_ndef(area: &[u8]) -> Result<Tlv<'_>, Error> {
+    for tlv in tlvs(area) {
+        let tlv = tlv?;
+        if tlv.tag == NDEF {
+            return Ok(tlv);
+        }
+    }
+    Err(Error::NoNdef)
+}
+
+/// Where a new NDEF TLV goes: in place of the current one, or after the
+/// lock and memory control TLVs that must stay
+pub fn ndef_offset(area: &[u8]) -> usize {
+    let mut offset = 0;
+    for tlv in tlvs(area) {
+        match tlv {
+            Ok(tlv) if tlv.tag == LOCK_CONTROL || tlv.tag == MEMORY_CONTROL => {
+                offset = tlv.offset + tlv_len(tlv.value.len());
+            }
+            Ok(tlv) if tlv.tag == NDEF => return tlv.offset,
+            _ => break,
+        }
+    }
+    offset
+}
+
+/// Bytes taken by a TLV holding `len` bytes
+pub fn tlv_len(len: usize) -> usize {
+    if len < LONG_LENGTH as usize {
+        2 + len
+    } else {
+        4 + len
+    }
+}
+
+/// Wrap `message` in an NDEF TLV followed by a terminator, filling `area`
+/// from its start. Returns the bytes used; the rest of `area` is zeroed.
+pub fn write_ndef(message: &[u8], area: &mut [u8]) -> Result<usize, Error> {
+    let len = tlv_len(message.len());
+    if message.len() > u16::MAX as usize || len >= area.len() {
+        return Err(Error::TooLong);
+    }
+    area.fill(0);
+    area[0] = NDEF;
+    let start = if len == 2 + message.len() {
+        area[1] = message.len() as u8;
+        2
+    } else {
+        area[1] = LONG_LENGTH;
+        area[2..4].copy_from_slice(&(message.len() as u16).to_be_bytes());
+        4
+    };
+    area[start..len].copy_from_slice(message);
+    area[len] = TERMINATOR;
+    Ok(len + 1)
+}
+
+/// Page 3 of a Type 2 tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapabilityContainer {
+    /// Major version in the high nibble, minor in the low one
+    pub version: u8,
+    /// Size of the data area in bytes
+    pub data_size: usize,
+    pub writable: bool,
+}
+
+impl CapabilityContainer {
+    pub fn decode(page: &[u8; PAGE_LEN]) -> Result<Self, Error> {
+        if page[0] != CC_MAGIC {
+            return Err(Error::NotFormatted);
+        }
+        Ok(Self {
+            version: page[1],
+            data_size: page[2] as usize * 8,
+            writable: page[3] & 0xF0 == 0,
+        })
+    }
+
+    pub fn encode(&self) -> [u8; PAGE_LEN] {
+        let access = if self.writable { 0x00 } else { 0x0F };
+        [CC_MAGIC, self.version, (self.data_size / 8) as u8, access]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_ndef_after_control_tlvs() {
+        // Ultralight C: lock control TLV, an empty NDEF TLV, terminator
+        let area = [
+            0x01, 0x03, 0xA0, 0x10, 0x44, 0x03, 0x00, 0xFE, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let ndef = find_ndef(&area).unwrap();
+        assert_eq!(ndef.offset, 5);
+        assert!(ndef.value.is_empty());
+        assert_eq!(ndef_offset(&area), 5);
+
+        // blank NTAG: nothing but zeros
+        assert_eq!(find_ndef(&[0; 16]), Err(Error::NoNdef));
+        assert_eq!(ndef_offset(&[0; 16]), 0);
+    }
+
+    #[test]
+    fn writes_short_and_long_lengths() {
+        let mut area = [0xAA; 16];
+        assert_eq!(write_ndef(&[1, 2, 3], &mut area), Ok(6));
+        assert_eq!(area[..7], [NDEF, 3, 1, 2, 3, TERMINATOR, 0]);
+        assert_eq!(find_ndef(&area).unwrap().value, [1, 2, 3]);
+
+        let message = [7; 300];
+        let mut area = [0; 320];
+        assert_eq!(write_ndef(&message, &mut area), Ok(305));
+        assert_eq!(area[..4], [NDEF, 0xFF, 0x01, 0x2C]);
+        assert_eq!(find_ndef(&area).unwrap().value, message);
+        assert_eq!(area[304], TERMINATOR);
+
+        // the terminator must fit too
+        assert_eq!(write_ndef(&[0; 14], &mut [0; 16]), Err(Error::TooLong));
+        assert_eq!(write_ndef(&[0; 13], &mut [0; 16]), Ok(16));
+    }
+
+    #[test]
+    fn rejects_truncated_tlvs() {
+        assert_eq!(find_ndef(&[NDEF, 5, 1, 2]), Err(Error::Truncated));
+        assert_eq!(find_ndef(&[NDEF, 0xFF, 0x01]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn decodes_ntag213_capability_container() {
+        let cc = CapabilityContainer::decode(&[0xE1, 0x10, 0x12, 0x00]).unwrap();
+        assert_eq!(cc.data_size, 144);
+        assert!(cc.writable);
+        assert_eq!(cc.encode(), [0xE1, 0x10, 0x12, 0x00]);
+        assert_eq!(
+            CapabilityContainer::decode(&[0; 4]),
+            Err(Error::NotFormatted)
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! MIFARE Classic value blocks.
+//!
+//! A value block stores a signed 32-bit amount three times (once inverted)
+//! and a block address four times, so a card can tell a torn write from a
+//! real value. The card does the arithmetic itself: increment, decrement
+//! and restore load the result into an internal register, and transfer
+//! writes that register to a block of the same sector. Whether a key may
+//! increment or decrement depends on the access bits; factory cards allow
+//! everything with Key A.
+//!
+//! `mfrc522` has no value commands, so they go through
+//! [`Mfrc522::transceive`] with our own CRC.
+//!
+//! ```ignore
+//! value::format(&mut rfid, 5, 100)?;
+//! value::add(&mut rfid, 5, -20)?;
+//! assert_eq!(value::read(&mut rfid, 5)?.value, 80);
+//! ```
+
+use crate::crc::crc_a;
+use mfrc522::Mfrc522;
+
+// MIFARE Classic commands
+const INCREMENT: u8 = 0xC1;
+const DECREMENT: u8 = 0xC0;
+const RESTORE: u8 = 0xC2;
+const TRANSFER: u8 = 0xB0;
+
+// The card answers with 4 bits, 0xA meaning ACK
+const ACK: u8 = 0x0A;
+
+#[derive(Debug, PartialEq)]
+pub enum Error<E> {
+    Driver(mfrc522::Error<E>),
+    /// The card refused the command, for example because the access bits
+    /// don't allow it; holds the 4-bit answer
+    Nak(u8),
+    /// The block doesn't hold a value, or its copies disagree
+    NotValueBlock,
+}
+
+impl<E> From<mfrc522::Error<E>> for Error<E> {
+    fn from(e: mfrc522::Error<E>) -> Self {
+        Error::Driver(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueBlock {
+    pub value: i32,
+    /// Free for the application; usually the number of the block, or of a
+    /// backup block for the same value
+    pub address: u8,
+}
+
+impl ValueBlock {
+    pub const fn new(value: i32, address: u8) -> Self {
+        Self { value, address }
+    }
+
+    pub fn encode(&self) -> [u8; 16] {
+        let value = self.value.to_le_bytes();
+        let inverted = (!self.value).to_le_bytes();
+        let mut block = [0u8; 16];
+        block[0..4].copy_from_slice(&value);
+        block[4..8].copy_from_slice(&inverted);
+        block[8..12].copy_from_slice(&value);
+        block[12..16].copy_from_slice(&[self.address, !self.address, self.address, !self.address]);
+        block
+    }
+
+    /// Check all the redundant copies agree
+    pub fn decode(block: &[u8; 16]) -> Option<Self> {
+        let word =
+            |i: usize| i32::from_le_bytes([block[i], block[i + 1], block[i + 2], block[i + 3]]);
+        let value = word(0);
+        let address = block[12];
+        let valid = word(4) == !value
+            && word(8) == value
+            && block[13] == !address
+            && block[14] == address
+            && block[15] == !address;
+        valid.then_some(Self { value, address })
+    }
+}
+
+// a command frame: command, block and CRC
+fn frame(command: u8, block: u8) -> [u8; 4] {
+    let crc = crc_a(&[command, block]);
+    [command, block, crc[0], crc[1]]
+}
+
+/// Read `block` and check it holds a value. The sector must be
+/// authenticated.
+pub fn read<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    block: u8,
+) -> Result<ValueBlock, Error<E>> {
+    ValueBlock::decode(&rfid.mf_read(block)?).ok_or(Error::NotValueBlock)
+}
+
+/// Turn `block` into a value block holding `value`, with its own number as
+/// the address
+pub fn format<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    block: u8,
+    value: i32,
+) -> Result<(), Error<E>> {
+    rfid.mf_write(block, ValueBlock::new(value, block).encode())?;
+    Ok(())
+}
+
+/// Load the value of `block` plus `amount` into the card's register
+pub fn increment<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    block: u8,
+    amount: u32,
+) -> Result<(), Error<E>> {
+    operation(rfid, INCREMENT, block, amount)
+}
+
+/// Load the value of `block` minus `amount` into the card's register
+pub fn decrement<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    block: u8,
+    amount: u32,
+) -> Result<(), Error<E>> {
+    operation(rfid, DECREMENT, block, amount)
+}
+
+/// Load the value of `block` into the card's register, to copy it to
+/// another block with [`transfer`]
+pub fn restore<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    block: u8,
+) -> Result<(), Error<E>> {
+    operation(rfid, RESTORE, block, 0)
+}
+
+/// Write the card's register to `block`
+pub fn transfer<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    block: u8,
+) -> Result<(), Error<E>> {
+    command(rfid, TRANSFER, block)
+}
+
+/// Add `amount` (negative to take away) to the value in `block`
+pub fn add<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    block: u8,
+    amount: i32,
+) -> Result<(), Error<E>> {
+    if amount >= 0 {
+        increment(rfid, block, amount.unsigned_abs())?;
+    } else {
+        decrement(rfid, block, amount.unsigned_abs())?;
+    }
+    transfer(rfid, block)
+}
+
+// Send a command the card acknowledges
+fn command<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    command: u8,
+    block: u8,
+) -> Result<(), Error<E>> {
+    let answer = rfid.transceive::<1>(&frame(command, block), 0, 0)?;
+    match answer.buffer[0] & 0x0F {
+        ACK if answer.valid_bytes == 1 && answer.valid_bits == 4 => Ok(()),
+        nak => Err(Error::Nak(nak)),
+    }
+}
+
+// Increment, decrement and restore: the command, then the operand, which
+// the card only answers when it refuses it
+fn operation<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+    code: u8,
+    block: u8,
+    operand: u32,
+) -> Result<(), Error<E>> {
+    command(rfid, code, block)?;
+
+    let operand = operand.to_le_bytes();
+    let crc = crc_a(&operand);
+    let tx = [
+        operand[0], operand[1], operand[2], operand[3], crc[0], crc[1],
+    ];
+    match rfid.transceive::<1>(&tx, 0, 0) {
+        Err(mfrc522::Error::Timeout) => Ok(()),
+        Err(e) => Err(Error::Driver(e)),
+        Ok(answer) => Err(Error::Nak(answer.buffer[0] & 0x0F)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rfid_emulator::{Card, Emulator, NAK, connect};
+
+    use super::*;
+    use crate::reader;
+
+    #[test]
+    fn builds_command_frames() {
+        assert_eq!(frame(TRANSFER, 5)[..2], [0xB0, 0x05]);
+        assert_eq!(frame(TRANSFER, 5)[2..], crc_a(&[0xB0, 0x05]));
+    }
+
+    #[test]
+    fn encodes_redundant_copies() {
+        let block = ValueBlock::new(100, 5).encode();
+        assert_eq!(
+            block,
+            [
+                0x64, 0, 0, 0, 0x9B, 0xFF, 0xFF, 0xFF, 0x64, 0, 0, 0, 0x05, 0xFA, 0x05, 0xFA
+            ]
+        );
+        assert_eq!(ValueBlock::decode(&block), Some(ValueBlock::new(100, 5)));
+
+        let negative = ValueBlock::new(-1, 0);
+        assert_eq!(ValueBlock::decode(&negative.encode()), Some(negative));
+    }
+
+    #[test]
+    fn rejects_broken_copies() {
+        let good = ValueBlock::new(1234, 8).encode();
+        for byte in 0..16 {
+            let mut bad = good;
+            bad[byte] ^= 0x10;
+            assert_eq!(ValueBlock::decode(&bad), None, "byte {}", byte);
+        }
+        assert_eq!(ValueBlock::decode(&[0; 16]), None);
+    }
+
+    #[test]
+    fn keeps_balances_on_a_card() {
+        let mut card = Card::new([0xDE, 0xAD, 0xBE, 0xEF]);
+        // sector 1 as a purse: Key A may only take away, Key B tops up
+        card.set_trailer(1, [0xFF; 6], [0x08, 0x77, 0x8F], [0x42; 6]);
+        card.set_block(4, ValueBlock::new(10, 4).encode());
+        let mut chip = Emulator::new(card);
+        let mut rfid = connect(&mut chip);
+        let uid = reader::detect(&mut rfid).unwrap();
+
+        reader::authenticate(&uid, 8, &[0xFF; 6], &mut rfid).unwrap();
+        format(&mut rfid, 8, 100).unwrap();
+        add(&mut rfid, 8, 25).unwrap();
+        // a backup copy in the next block
+        restore(&mut rfid, 8).unwrap();
+        transfer(&mut rfid, 9).unwrap();
+        assert_eq!(read(&mut rfid, 9), Ok(ValueBlock::new(125, 8)));
+        assert_eq!(increment(&mut rfid, 10, 1), Err(Error::Nak(NAK)));
+
+        reader::recover(&uid, &mut rfid).unwrap();
+        reader::authenticate(&uid, 4, &[0xFF; 6], &mut rfid).unwrap();
+        add(&mut rfid, 4, -3).unwrap();
+        assert_eq!(read(&mut rfid, 4), Ok(ValueBlock::new(7, 4)));
+        assert_eq!(add(&mut rfid, 4, 5), Err(Error::Nak(NAK)));
+
+        let card = chip.card().unwrap();
+        assert_eq!(
+            ValueBlock::decode(card.block(4)),
+            Some(ValueBlock::new(7, 4))
+        );
+        assert_eq!(
+            ValueBlock::decode(card.block(8)),
+            Some(ValueBlock::new(125, 8))
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Audit log lines.
+//!
+//! Every card presented and every change of mode is appended to
+//! `AUDIT.CSV` on the SD card, one line per event:
+//!
+//! ```text
+//! time,uid,name,event
+//! 2026-10-19 09:00:12,DEADBEEF,Ferris,granted
+//! 2026-10-19 09:03:40,CAFEBABE,,denied: unknown card
+//! ```
+//!
+//! The time is left empty while the clock isn't set.
+
+use core::fmt;
+
+use crate::controller::Outcome;
+use crate::whitelist::{CardId, Name, Now};
+
+/// First line of a new log
+pub const HEADER: &str = "time,uid,name,event\n";
+
+pub struct Event {
+    /// When it happened, if the clock is set
+    pub time: Option<Now>,
+    /// The card, if the event came from one
+    pub id: Option<CardId>,
+    /// The name of the card, if it is on the list
+    pub name: Option<Name>,
+    pub outcome: Outcome,
+}
+
+/// One CSV line, with its line break
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(time) = self.time {
+            write!(f, "{}", time)?;
+        }
+        f.write_str(",")?;
+        if let Some(id) = self.id {
+            write!(f, "{}", id)?;
+        }
+        // names never hold commas or quotes
+        let name = self.name.as_ref().map_or("", Name::as_str);
+        writeln!(f, ",{},{}", name, self.outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::string::ToString;
+
+    use super::*;
+    use crate::whitelist::{Date, Denied};
+
+    const TIME: Now = Now {
+        date: Date {
+            year: 2026,
+            month: 10,
+            day: 19,
+        },
+        weekday: 0,
+        hour: 9,
+        minute: 3,
+        second: 40,
+    };
+
+    #[test]
+    fn formats_csv_lines() {
+        let granted = Event {
+            time: Some(TIME),
+            id: CardId::new(&[0xDE, 0xAD, 0xBE, 0xEF]),
+            name: Name::new("Ferris"),
+            outcome: Outcome::Granted,
+        };
+        assert_eq!(
+            granted.to_string(),
+            "2026-10-19 09:03:40,DEADBEEF,Ferris,granted\n"
+        );
+
+        let unknown = Event {
+            id: CardId::new(&[0x04, 1, 2, 3, 4, 5, 6]),
+            name: None,
+            outcome: Outcome::Denied(Denied::Unknown),
+            ..granted
+        };
+        assert_eq!(
+            unknown.to_string(),
+            "2026-10-19 09:03:40,04010203040506,,denied: unknown card\n"
+        );
+
+        let timeout = Event {
+            id: None,
+            name: None,
+            outcome: Outcome::EnrollEnded,
+            time: Some(TIME),
+        };
+        assert_eq!(
+            timeout.to_string(),
+            "2026-10-19 09:03:40,,,enrollment ended\n"
+        );
+
+        let unset = Event {
+            time: None,
+            ..timeout
+        };
+        assert_eq!(unset.to_string(), ",,,enrollment ended\n");
+    }
+}
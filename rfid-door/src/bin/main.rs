@@ -0,0 +1,469 @@
+#![no_std]
+#![no_main]
+#![deny(
+    clippy::mem_forget,
+    reason = "mem::forget is generally not safe to do with esp_hal types, especially those \
+    holding buffers for the duration of a data transfer."
+)]
+
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::clock::CpuClock;
+use esp_hal::timer::timg::TimerGroup;
+use esp_println as _;
+
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+
+// SPI
+use esp_hal::gpio::{Input, InputConfig, Level, Output, OutputConfig, Pull};
+use esp_hal::spi;
+use esp_hal::spi::master::Spi;
+use esp_hal::time::Rate;
+
+// RFID Reader
+use embedded_hal_bus::spi::RefCellDevice;
+use esp_hal::delay::Delay;
+use mfrc522::Mfrc522;
+use mfrc522::comm::blocking::spi::SpiInterface;
+use rfid_door::audit::{self, Event};
+use rfid_door::controller::{Controller, Outcome};
+use rfid_door::ds3231;
+use rfid_door::mk_static;
+use rfid_door::presence::{self, Detector, Shared};
+use rfid_door::whitelist::{CardId, Date, Now, Whitelist};
+
+// SD card, shares the SPI bus with the reader
+use embedded_sdmmc::{Mode, SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+
+// For time, from a DS3231 module
+use esp_hal::i2c::master::{Config as I2cConfig, I2c};
+use esp_hal::rtc_cntl::Rtc;
+
+use esp_println::{self as _, print, println};
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+// This creates a default app-descriptor required by the esp-idf bootloader.
+// For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
+esp_bootloader_esp_idf::esp_app_desc!();
+
+// The card list and the audit log, in the root directory of the SD card
+const CARDS_FILE: &str = "CARDS.TXT";
+const AUDIT_FILE: &str = "AUDIT.CSV";
+// Enough for a full list with long names and rules
+const MAX_CARDS_FILE: usize = 4096;
+
+// How long the strike stays open
+const OPEN_TIME: Duration = Duration::from_secs(3);
+
+static TZ: jiff::tz::TimeZone = jiff::tz::get!("America/New_York");
+
+/// Local time from the RTC, for the card rules and the file timestamps
+#[derive(Clone, Copy)]
+struct Clock {
+    rtc: &'static Rtc<'static>,
+    // whether the RTC was set from the DS3231
+    set: bool,
+}
+
+impl Clock {
+    /// The time, `None` while it isn't set
+    fn now(&self) -> Option<Now> {
+        if !self.set {
+            return None;
+        }
+        let now_us = self.rtc.current_time_us();
+        let now = jiff::Timestamp::from_microsecond(now_us as i64).unwrap();
+        let now = now.to_zoned(TZ.clone());
+
+        Some(Now {
+            date: Date {
+                year: now.year() as u16,
+                month: now.month() as u8,
+                day: now.day() as u8,
+            },
+            weekday: now.weekday().to_monday_zero_offset() as u8,
+            hour: now.hour() as u8,
+            minute: now.minute() as u8,
+            second: now.second() as u8,
+        })
+    }
+}
+
+impl TimeSource for Clock {
+    fn get_timestamp(&self) -> Timestamp {
+        // files written before the clock is set date from 1970
+        let Some(now) = self.now() else {
+            return Timestamp {
+                year_since_1970: 0,
+                zero_indexed_month: 0,
+                zero_indexed_day: 0,
+                hours: 0,
+                minutes: 0,
+                seconds: 0,
+            };
+        };
+        Timestamp {
+            year_since_1970: now.date.year.saturating_sub(1970) as u8,
+            zero_indexed_month: now.date.month - 1,
+            zero_indexed_day: now.date.day - 1,
+            hours: now.hour,
+            minutes: now.minute,
+            seconds: now.second,
+        }
+    }
+}
+
+/// The strike relay, the LEDs and the buzzer
+struct Door {
+    strike: Output<'static>,
+    green: Output<'static>,
+    red: Output<'static>,
+    buzzer: Output<'static>,
+}
+
+impl Door {
+    async fn beep(&mut self, times: u32, length: Duration) {
+        for _ in 0..times {
+            self.buzzer.set_high();
+            Timer::after(length).await;
+            self.buzzer.set_low();
+            Timer::after(Duration::from_millis(100)).await;
+        }
+    }
+
+    async fn show(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Granted => {
+                self.green.set_high();
+                self.strike.set_high();
+                self.beep(1, Duration::from_millis(100)).await;
+                Timer::after(OPEN_TIME).await;
+                self.strike.set_low();
+                self.green.set_low();
+            }
+            Outcome::Denied(_) | Outcome::EnrollFailed => {
+                self.red.set_high();
+                self.beep(3, Duration::from_millis(300)).await;
+                self.red.set_low();
+            }
+            Outcome::MasterEnrolled | Outcome::Enrolled | Outcome::AlreadyEnrolled => {
+                self.green.set_low();
+                self.beep(2, Duration::from_millis(100)).await;
+                self.green.set_high();
+            }
+            Outcome::EnrollStarted => {
+                // both LEDs stay on while enrolling
+                self.green.set_high();
+                self.red.set_high();
+                self.beep(2, Duration::from_millis(100)).await;
+            }
+            Outcome::EnrollEnded => {
+                self.green.set_low();
+                self.red.set_low();
+                self.beep(1, Duration::from_millis(300)).await;
+            }
+        }
+    }
+}
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
+    // generator version: 1.0.0
+
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    info!("Embassy initialized!");
+
+    // TODO: Spawn some tasks
+    let _ = spawner;
+
+    let mut i2c = I2c::new(
+        peripherals.I2C0,
+        I2cConfig::default().with_frequency(Rate::from_khz(100)),
+    )
+    .unwrap()
+    .with_scl(peripherals.GPIO22)
+    .with_sda(peripherals.GPIO32);
+    let rtc = mk_static!(Rtc<'static>, Rtc::new(peripherals.LPWR));
+    let set = match ds3231::read(&mut i2c) {
+        Ok(Some(now)) => {
+            let local = jiff::civil::datetime(
+                now.date.year as i16,
+                now.date.month as i8,
+                now.date.day as i8,
+                now.hour as i8,
+                now.minute as i8,
+                now.second as i8,
+                0,
+            );
+            match local.to_zoned(TZ.clone()) {
+                Ok(zoned) => {
+                    rtc.set_current_time_us(zoned.timestamp().as_microsecond() as u64);
+                    println!("Time: {}", now);
+                    true
+                }
+                Err(_) => false,
+            }
+        }
+        Ok(None) => {
+            println!("The DS3231 isn't set, card time rules are off");
+            false
+        }
+        Err(e) => {
+            println!("No DS3231, card time rules are off: {:?}", e);
+            false
+        }
+    };
+    let clock = Clock { rtc, set };
+
+    let mut door = Door {
+        strike: Output::new(peripherals.GPIO26, Level::Low, OutputConfig::default()),
+        green: Output::new(peripherals.GPIO25, Level::Low, OutputConfig::default()),
+        red: Output::new(peripherals.GPIO27, Level::Low, OutputConfig::default()),
+        buzzer: Output::new(peripherals.GPIO33, Level::Low, OutputConfig::default()),
+    };
+
+    // The SD card must be initialized at 400 kHz
+    let spi_bus = Spi::new(
+        peripherals.SPI2,
+        spi::master::Config::default()
+            .with_frequency(Rate::from_khz(400))
+            .with_mode(spi::Mode::_0),
+    )
+    .unwrap()
+    .with_sck(peripherals.GPIO18)
+    .with_mosi(peripherals.GPIO23)
+    .with_miso(peripherals.GPIO19)
+    .into_async();
+    let spi_bus = mk_static!(RefCell<Spi<'static, esp_hal::Async>>, RefCell::new(spi_bus));
+
+    let sd_cs = Output::new(peripherals.GPIO4, Level::High, OutputConfig::default());
+    let rfid_cs = Output::new(peripherals.GPIO5, Level::High, OutputConfig::default());
+
+    let sdcard = SdCard::new(
+        RefCellDevice::new(spi_bus, sd_cs, Delay::new()).unwrap(),
+        Delay::new(),
+    );
+    match sdcard.num_bytes() {
+        Ok(size) => println!("SD card: {} bytes", size),
+        Err(e) => println!("No SD card, nothing will be saved: {:?}", e),
+    }
+    spi_bus
+        .borrow_mut()
+        .apply_config(
+            &spi::master::Config::default()
+                .with_frequency(Rate::from_mhz(5))
+                .with_mode(spi::Mode::_0),
+        )
+        .unwrap();
+    let volume_mgr = VolumeManager::new(sdcard, clock);
+
+    let list = mk_static!(Whitelist, Whitelist::new());
+    let mut controller = Controller::new();
+    let loaded = match volume_mgr.open_volume(VolumeIdx(0)) {
+        Ok(volume) => load_list(&volume, list),
+        Err(e) => {
+            println!("Can't open the SD card: {:?}", e);
+            false
+        }
+    };
+    if !loaded {
+        println!("Enrollment is off until {} can be read", CARDS_FILE);
+        controller.lock();
+    } else if list.has_master() {
+        println!("{} cards on the list", list.len());
+    } else if list.is_empty() {
+        println!("No cards yet, the first card presented becomes the master card");
+    } else {
+        println!(
+            "{} cards on the list but no master card, enrollment is off until {} has one",
+            list.len(),
+            CARDS_FILE
+        );
+    }
+
+    let delay = Delay::new();
+    let spi_dev = RefCell::new(RefCellDevice::new(spi_bus, rfid_cs, delay).unwrap());
+
+    let spi_interface = SpiInterface::new(Shared::new(&spi_dev));
+    let mut rfid = Mfrc522::new(spi_interface).init().unwrap();
+
+    // The reader sleeps between field bursts and wakes the task on its IRQ pin
+    let irq = Input::new(
+        peripherals.GPIO21,
+        InputConfig::default().with_pull(Pull::Up),
+    );
+    let mut detector = Detector::new(Shared::new(&spi_dev), irq, presence::Config::default());
+
+    loop {
+        let arrived = detector.burst().await.unwrap_or_else(|e| {
+            println!("Card detection failed: {:?}", e);
+            false
+        });
+        if !arrived {
+            if let Some(outcome) = controller.tick(Instant::now().as_millis()) {
+                let event = Event {
+                    time: clock.now(),
+                    id: None,
+                    name: None,
+                    outcome,
+                };
+                log(&volume_mgr, &event);
+                door.show(outcome).await;
+            }
+            Timer::after(detector.interval()).await;
+            continue;
+        }
+        let Ok(atqa) = rfid.reqa() else {
+            continue;
+        };
+        let Ok(uid) = rfid.select(&atqa) else {
+            continue;
+        };
+        let _ = rfid.hlta();
+        let Some(id) = CardId::new(uid.as_bytes()) else {
+            continue;
+        };
+
+        let outcome = controller.card(list, id, clock.now().as_ref(), Instant::now().as_millis());
+        let event = Event {
+            time: clock.now(),
+            id: Some(id),
+            name: list.find(&id).map(|entry| entry.name),
+            outcome,
+        };
+        log(&volume_mgr, &event);
+        if outcome.changes_list() {
+            save_list(&volume_mgr, list);
+        }
+        door.show(outcome).await;
+    }
+}
+
+/// Read the card list. Returns whether the list may be changed: an SD card
+/// without the file starts an empty one, but a file that can't be read whole
+/// or parsed leaves `list` empty and must not be overwritten.
+fn load_list<D, T>(volume: &embedded_sdmmc::Volume<'_, D, T, 4, 4, 1>, list: &mut Whitelist) -> bool
+where
+    D: embedded_sdmmc::BlockDevice,
+    T: TimeSource,
+    D::Error: core::fmt::Debug,
+{
+    let text = mk_static!([u8; MAX_CARDS_FILE], [0; MAX_CARDS_FILE]);
+    let result = volume.open_root_dir().and_then(|root_dir| {
+        let file = root_dir.open_file_in_dir(CARDS_FILE, Mode::ReadOnly)?;
+        let mut len = 0;
+        while !file.is_eof() && len < text.len() {
+            len += file.read(&mut text[len..])?;
+        }
+        let whole = file.is_eof();
+        file.close()?;
+        Ok((len, whole))
+    });
+    let len = match result {
+        Ok((len, true)) => len,
+        Ok((_, false)) => {
+            println!("{} is over {} bytes", CARDS_FILE, MAX_CARDS_FILE);
+            return false;
+        }
+        Err(embedded_sdmmc::Error::NotFound) => {
+            println!("No {} yet", CARDS_FILE);
+            return true;
+        }
+        Err(e) => {
+            println!("Can't read {}: {:?}", CARDS_FILE, e);
+            return false;
+        }
+    };
+    let Ok(text) = core::str::from_utf8(&text[..len]) else {
+        println!("{} isn't text", CARDS_FILE);
+        return false;
+    };
+    match Whitelist::parse(text) {
+        Ok(parsed) => {
+            *list = parsed;
+            true
+        }
+        Err((line, e)) => {
+            println!("{} line {}: {:?}", CARDS_FILE, line, e);
+            false
+        }
+    }
+}
+
+/// Write the card list back, after enrollment changed it
+fn save_list<D, T>(volume_mgr: &VolumeManager<D, T>, list: &Whitelist)
+where
+    D: embedded_sdmmc::BlockDevice,
+    T: TimeSource,
+    D::Error: core::fmt::Debug,
+{
+    let result = volume_mgr.open_volume(VolumeIdx(0)).and_then(|volume| {
+        let root_dir = volume.open_root_dir()?;
+        let file = root_dir.open_file_in_dir(CARDS_FILE, Mode::ReadWriteCreateOrTruncate)?;
+        let mut error = None;
+        let mut out =
+            FileWriter(|bytes: &[u8]| file.write(bytes).map_err(|e| error = Some(e)).is_ok());
+        // the writer only fails when the file does
+        let _ = write!(out, "{}", list);
+        if let Some(e) = error {
+            return Err(e);
+        }
+        file.close()
+    });
+    if let Err(e) = result {
+        println!("Can't save {}: {:?}", CARDS_FILE, e);
+    }
+}
+
+/// Print `event` and append it to the audit log
+fn log<D, T>(volume_mgr: &VolumeManager<D, T>, event: &Event)
+where
+    D: embedded_sdmmc::BlockDevice,
+    T: TimeSource,
+    D::Error: core::fmt::Debug,
+{
+    print!("{}", event);
+
+    let result = volume_mgr.open_volume(VolumeIdx(0)).and_then(|volume| {
+        let root_dir = volume.open_root_dir()?;
+        let file = root_dir.open_file_in_dir(AUDIT_FILE, Mode::ReadWriteCreateOrAppend)?;
+        if file.length() == 0 {
+            file.write(audit::HEADER.as_bytes())?;
+        }
+        let mut error = None;
+        let mut out =
+            FileWriter(|bytes: &[u8]| file.write(bytes).map_err(|e| error = Some(e)).is_ok());
+        let _ = write!(out, "{}", event);
+        if let Some(e) = error {
+            return Err(e);
+        }
+        file.close()
+    });
+    if let Err(e) = result {
+        println!("Can't write {}: {:?}", AUDIT_FILE, e);
+    }
+}
+
+// Lets `write!` go straight into a file
+struct FileWriter<F>(F);
+
+impl<F: FnMut(&[u8]) -> bool> fmt::Write for FileWriter<F> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if (self.0)(s.as_bytes()) {
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
+    }
+}
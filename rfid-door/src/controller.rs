@@ -0,0 +1,348 @@
+//! What the door does with a card.
+//!
+//! Listed cards open the door when their rules allow it. A master card
+//! starts enrollment instead: the next unknown card is added to the list,
+//! until the master card comes back or [`ENROLL_TIMEOUT_MS`] passes. While
+//! the list is empty, the first card presented becomes a master card, so a
+//! fresh SD card can be set up at the door. A list with cards but no master
+//! card is never added to: a card that isn't on it is denied with
+//! [`Denied::NoMaster`], so the audit log says why.
+//!
+//! A [locked](Controller::lock) controller only checks cards, for when the
+//! list on the SD card couldn't be read and mustn't be overwritten. Until
+//! the clock is set, only cards without a validity window, days or hours
+//! open the door; the others are denied with [`Denied::ClockNotSet`].
+
+use core::fmt::{self, Write};
+
+use crate::whitelist::{CardId, Denied, Entry, Name, Now, Whitelist};
+
+/// Enrollment ends by itself after this long without a card
+pub const ENROLL_TIMEOUT_MS: u64 = 15_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Granted,
+    Denied(Denied),
+    /// The card became the master card of an empty list
+    MasterEnrolled,
+    EnrollStarted,
+    Enrolled,
+    AlreadyEnrolled,
+    /// The list is full, the card couldn't be added
+    EnrollFailed,
+    EnrollEnded,
+}
+
+impl Outcome {
+    pub fn opens_door(&self) -> bool {
+        matches!(self, Outcome::Granted)
+    }
+
+    /// Whether the list changed and should be saved
+    pub fn changes_list(&self) -> bool {
+        matches!(self, Outcome::MasterEnrolled | Outcome::Enrolled)
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Granted => f.write_str("granted"),
+            Outcome::Denied(reason) => write!(f, "denied: {}", reason),
+            Outcome::MasterEnrolled => f.write_str("enrolled as master"),
+            Outcome::EnrollStarted => f.write_str("enrollment started"),
+            Outcome::Enrolled => f.write_str("enrolled"),
+            Outcome::AlreadyEnrolled => f.write_str("already enrolled"),
+            Outcome::EnrollFailed => f.write_str("enrollment failed: list full"),
+            Outcome::EnrollEnded => f.write_str("enrollment ended"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Controller {
+    // when enrollment started or last added a card
+    enrolling_since: Option<u64>,
+    locked: bool,
+}
+
+impl Controller {
+    pub const fn new() -> Self {
+        Self {
+            enrolling_since: None,
+            locked: false,
+        }
+    }
+
+    /// Never change the list: no master card for an empty list, and master
+    /// cards don't start enrollment
+    pub fn lock(&mut self) {
+        self.locked = true;
+        self.enrolling_since = None;
+    }
+
+    pub fn is_enrolling(&self) -> bool {
+        self.enrolling_since.is_some()
+    }
+
+    /// End enrollment once it timed out; `now_ms` is any monotonic clock
+    pub fn tick(&mut self, now_ms: u64) -> Option<Outcome> {
+        match self.enrolling_since {
+            Some(since) if now_ms.saturating_sub(since) >= ENROLL_TIMEOUT_MS => {
+                self.enrolling_since = None;
+                Some(Outcome::EnrollEnded)
+            }
+            _ => None,
+        }
+    }
+
+    /// Handle card `id` presented at `now`, `None` while the clock isn't set
+    pub fn card(
+        &mut self,
+        list: &mut Whitelist,
+        id: CardId,
+        now: Option<&Now>,
+        now_ms: u64,
+    ) -> Outcome {
+        // a card after the timeout is checked like any other
+        self.tick(now_ms);
+
+        if self.locked {
+            return check(list, &id, now);
+        }
+
+        if list.is_empty() {
+            let entry = Entry {
+                master: true,
+                ..Entry::new(id, Name::new("Master").unwrap())
+            };
+            return match list.add(entry) {
+                Ok(()) => Outcome::MasterEnrolled,
+                Err(_) => Outcome::EnrollFailed,
+            };
+        }
+
+        let known = list.find(&id).copied();
+        if !list.has_master() {
+            return match known {
+                Some(_) => check(list, &id, now),
+                None => Outcome::Denied(Denied::NoMaster),
+            };
+        }
+
+        if self.is_enrolling() {
+            return match known {
+                Some(entry) if entry.master => {
+                    self.enrolling_since = None;
+                    Outcome::EnrollEnded
+                }
+                Some(_) => Outcome::AlreadyEnrolled,
+                None => {
+                    self.enrolling_since = Some(now_ms);
+                    match list.add(Entry::new(id, new_name(list.len()))) {
+                        Ok(()) => Outcome::Enrolled,
+                        Err(_) => Outcome::EnrollFailed,
+                    }
+                }
+            };
+        }
+
+        match known {
+            Some(entry) if entry.master => {
+                self.enrolling_since = Some(now_ms);
+                Outcome::EnrollStarted
+            }
+            _ => check(list, &id, now),
+        }
+    }
+}
+
+fn check(list: &Whitelist, id: &CardId, now: Option<&Now>) -> Outcome {
+    let result = match now {
+        Some(now) => list.check(id, now).map(|_| ()),
+        // fail closed: rules that can't be checked deny
+        None => match list.find(id) {
+            Some(entry) if entry.has_time_rules() => Err(Denied::ClockNotSet),
+            Some(_) => Ok(()),
+            None => Err(Denied::Unknown),
+        },
+    };
+    match result {
+        Ok(()) => Outcome::Granted,
+        Err(reason) => Outcome::Denied(reason),
+    }
+}
+
+// "Card 7": enrolled cards get a number, to be renamed in CARDS.TXT
+fn new_name(count: usize) -> Name {
+    let mut name = Name::default();
+    let _ = write!(name, "Card {}", count + 1);
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::whitelist::Date;
+
+    const NOW: Now = Now {
+        date: Date {
+            year: 2026,
+            month: 10,
+            day: 19,
+        },
+        weekday: 0,
+        hour: 9,
+        minute: 0,
+        second: 0,
+    };
+
+    fn id(byte: u8) -> CardId {
+        CardId::new(&[byte; 4]).unwrap()
+    }
+
+    #[test]
+    fn first_card_becomes_master() {
+        let mut list = Whitelist::new();
+        let mut door = Controller::new();
+        assert_eq!(
+            door.card(&mut list, id(1), Some(&NOW), 0),
+            Outcome::MasterEnrolled
+        );
+        assert!(list.find(&id(1)).unwrap().master);
+        assert_eq!(
+            door.card(&mut list, id(2), Some(&NOW), 0),
+            Outcome::Denied(Denied::Unknown)
+        );
+    }
+
+    #[test]
+    fn master_enrolls_cards() {
+        let mut list = Whitelist::new();
+        let mut door = Controller::new();
+        door.card(&mut list, id(1), Some(&NOW), 0);
+
+        assert_eq!(
+            door.card(&mut list, id(1), Some(&NOW), 1_000),
+            Outcome::EnrollStarted
+        );
+        assert_eq!(
+            door.card(&mut list, id(2), Some(&NOW), 2_000),
+            Outcome::Enrolled
+        );
+        assert_eq!(list.find(&id(2)).unwrap().name.as_str(), "Card 2");
+        assert_eq!(
+            door.card(&mut list, id(2), Some(&NOW), 3_000),
+            Outcome::AlreadyEnrolled
+        );
+        assert_eq!(
+            door.card(&mut list, id(1), Some(&NOW), 4_000),
+            Outcome::EnrollEnded
+        );
+
+        assert_eq!(
+            door.card(&mut list, id(2), Some(&NOW), 5_000),
+            Outcome::Granted
+        );
+        assert!(Outcome::Granted.opens_door());
+        assert!(Outcome::Enrolled.changes_list());
+    }
+
+    #[test]
+    fn enrollment_times_out() {
+        let mut list = Whitelist::new();
+        let mut door = Controller::new();
+        door.card(&mut list, id(1), Some(&NOW), 0);
+        door.card(&mut list, id(1), Some(&NOW), 1_000);
+
+        assert_eq!(door.tick(1_000 + ENROLL_TIMEOUT_MS - 1), None);
+        assert_eq!(
+            door.tick(1_000 + ENROLL_TIMEOUT_MS),
+            Some(Outcome::EnrollEnded)
+        );
+        assert!(!door.is_enrolling());
+
+        // a late card is just checked
+        door.card(&mut list, id(1), Some(&NOW), 100_000);
+        assert_eq!(
+            door.card(&mut list, id(3), Some(&NOW), 100_000 + ENROLL_TIMEOUT_MS),
+            Outcome::Denied(Denied::Unknown)
+        );
+    }
+
+    #[test]
+    fn locked_controller_only_checks() {
+        let mut list = Whitelist::new();
+        let mut door = Controller::new();
+        door.lock();
+        assert_eq!(
+            door.card(&mut list, id(1), Some(&NOW), 0),
+            Outcome::Denied(Denied::Unknown)
+        );
+        assert!(list.is_empty());
+
+        list.add(Entry {
+            master: true,
+            ..Entry::new(id(1), Name::new("Master").unwrap())
+        })
+        .unwrap();
+        assert_eq!(door.card(&mut list, id(1), Some(&NOW), 0), Outcome::Granted);
+        assert!(!door.is_enrolling());
+    }
+
+    #[test]
+    fn unset_clock_denies_time_rules() {
+        let mut list = Whitelist::new();
+        let mut door = Controller::new();
+        door.card(&mut list, id(1), None, 0);
+        list.add(Entry {
+            days: 0,
+            ..Entry::new(id(2), Name::new("Never").unwrap())
+        })
+        .unwrap();
+        list.add(Entry {
+            valid_from: Some(NOW.date),
+            ..Entry::new(id(3), Name::new("From today").unwrap())
+        })
+        .unwrap();
+        list.add(Entry::new(id(4), Name::new("Always").unwrap()))
+            .unwrap();
+
+        assert_eq!(
+            door.card(&mut list, id(2), Some(&NOW), 0),
+            Outcome::Denied(Denied::WrongDay)
+        );
+        assert_eq!(
+            door.card(&mut list, id(2), None, 0),
+            Outcome::Denied(Denied::ClockNotSet)
+        );
+        assert_eq!(door.card(&mut list, id(3), Some(&NOW), 0), Outcome::Granted);
+        assert_eq!(
+            door.card(&mut list, id(3), None, 0),
+            Outcome::Denied(Denied::ClockNotSet)
+        );
+        assert_eq!(door.card(&mut list, id(4), None, 0), Outcome::Granted);
+        assert_eq!(
+            door.card(&mut list, id(5), None, 0),
+            Outcome::Denied(Denied::Unknown)
+        );
+    }
+
+    #[test]
+    fn only_an_empty_list_gets_a_master() {
+        let mut list = Whitelist::new();
+        list.add(Entry::new(id(1), Name::new("Ferris").unwrap()))
+            .unwrap();
+        let mut door = Controller::new();
+
+        assert_eq!(
+            door.card(&mut list, id(2), Some(&NOW), 0),
+            Outcome::Denied(Denied::NoMaster)
+        );
+        assert_eq!(list.len(), 1);
+        assert!(!list.has_master());
+        // the cards on the list still work
+        assert_eq!(door.card(&mut list, id(1), Some(&NOW), 0), Outcome::Granted);
+    }
+}
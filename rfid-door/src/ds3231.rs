@@ -0,0 +1,122 @@
+//! Local time from a DS3231 real-time clock module.
+//!
+//! The module keeps time on its coin cell while the board is off, so the
+//! door knows the time as soon as it starts. It holds the local time, set
+//! once with any DS3231 tool; the day of the week it keeps is ignored and
+//! worked out from the date instead.
+//!
+//! ```ignore
+//! match ds3231::read(&mut i2c) {
+//!     Ok(Some(now)) => println!("It is {}", now),
+//!     Ok(None) => println!("The clock was never set"),
+//!     Err(e) => println!("No clock: {:?}", e),
+//! }
+//! ```
+
+use embedded_hal::i2c::I2c;
+
+use crate::whitelist::{Date, Now};
+
+pub const ADDRESS: u8 = 0x68;
+
+// First time register and the status register
+const SECONDS: u8 = 0x00;
+const STATUS: u8 = 0x0F;
+// Set when the oscillator stopped, the time is wrong until it's set again
+const OSCILLATOR_STOPPED: u8 = 0x80;
+
+/// Read the time, or `None` when the clock isn't set
+pub fn read<I: I2c>(i2c: &mut I) -> Result<Option<Now>, I::Error> {
+    let mut status = [0u8];
+    i2c.write_read(ADDRESS, &[STATUS], &mut status)?;
+    if status[0] & OSCILLATOR_STOPPED != 0 {
+        return Ok(None);
+    }
+    let mut registers = [0u8; 7];
+    i2c.write_read(ADDRESS, &[SECONDS], &mut registers)?;
+    Ok(decode(&registers))
+}
+
+/// The time in registers 0 to 6, or `None` if they don't hold a valid one
+pub fn decode(registers: &[u8; 7]) -> Option<Now> {
+    let [seconds, minutes, hours, _, day, month, year] = *registers;
+    let hour = if hours & 0x40 != 0 {
+        // 12-hour mode, with bit 5 set in the afternoon
+        let hour = bcd(hours & 0x1F)?;
+        if !(1..=12).contains(&hour) {
+            return None;
+        }
+        hour % 12 + if hours & 0x20 != 0 { 12 } else { 0 }
+    } else {
+        bcd(hours & 0x3F)?
+    };
+    // bit 7 of the month flips when the year wraps past 99
+    let century = if month & 0x80 != 0 { 2100 } else { 2000 };
+    let date = Date {
+        year: century + bcd(year)? as u16,
+        month: bcd(month & 0x1F)?,
+        day: bcd(day)?,
+    };
+    let weekday = jiff::civil::Date::new(date.year as i16, date.month as i8, date.day as i8)
+        .ok()?
+        .weekday()
+        .to_monday_zero_offset() as u8;
+    let now = Now {
+        date,
+        weekday,
+        hour,
+        minute: bcd(minutes)?,
+        second: bcd(seconds)?,
+    };
+    (now.hour < 24 && now.minute < 60 && now.second < 60).then_some(now)
+}
+
+// Two BCD digits
+fn bcd(value: u8) -> Option<u8> {
+    let (tens, ones) = (value >> 4, value & 0x0F);
+    (tens < 10 && ones < 10).then_some(tens * 10 + ones)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_registers() {
+        // Monday 2026-10-19 09:03:40, in 24-hour mode
+        let now = decode(&[0x40, 0x03, 0x09, 0x01, 0x19, 0x10, 0x26]).unwrap();
+        assert_eq!(
+            now.date,
+            Date {
+                year: 2026,
+                month: 10,
+                day: 19
+            }
+        );
+        assert_eq!(
+            (now.weekday, now.hour, now.minute, now.second),
+            (0, 9, 3, 40)
+        );
+
+        // 12-hour mode: 12 AM, 12 PM and 9 PM
+        assert_eq!(decode(&[0, 0, 0x52, 1, 0x19, 0x10, 0x26]).unwrap().hour, 0);
+        assert_eq!(decode(&[0, 0, 0x72, 1, 0x19, 0x10, 0x26]).unwrap().hour, 12);
+        assert_eq!(decode(&[0, 0, 0x69, 1, 0x19, 0x10, 0x26]).unwrap().hour, 21);
+
+        // past 2099
+        assert_eq!(
+            decode(&[0, 0, 0, 1, 0x01, 0x81, 0x00]).unwrap().date.year,
+            2100
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        // not BCD
+        assert!(decode(&[0x4A, 0x03, 0x09, 1, 0x19, 0x10, 0x26]).is_none());
+        // 25 o'clock
+        assert!(decode(&[0, 0, 0x25, 1, 0x19, 0x10, 0x26]).is_none());
+        // February 30th
+        assert!(decode(&[0, 0, 0, 1, 0x30, 0x02, 0x26]).is_none());
+    }
+}
@@ -0,0 +1,17 @@
+#![no_std]
+pub mod audit;
+pub mod controller;
+pub mod ds3231;
+pub mod whitelist;
+
+pub use rfid_common::presence;
+
+#[macro_export]
+macro_rules! mk_static {
+    ($t:ty,$val:expr) => {{
+        static STATIC_CELL: static_cell::StaticCell<$t> = static_cell::StaticCell::new();
+        #[deny(unused_attributes)]
+        let x = STATIC_CELL.uninit().write(($val));
+        x
+    }};
+}
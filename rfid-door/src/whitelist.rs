@@ -0,0 +1,639 @@
+//! Cards allowed through the door.
+//!
+//! The list lives in `CARDS.TXT` on the SD card, one card per line:
+//!
+//! ```text
+//! # uid, name, valid from, valid until, days, hours, role
+//! DEADBEEF,Ferris,2026-01-01,2026-12-31,MTWTF--,08:00-18:00
+//! 04A2B3C4D5E6F7,Night shift,-,-,*,22:00-06:00
+//! 11223344,Admin,-,-,*,*,master
+//! ```
+//!
+//! Dates are inclusive and `-` leaves that side open. The days run from
+//! Monday to Sunday, `-` forbidding a day and any other letter allowing it.
+//! Hours may wrap past midnight; the day checked is always the current one.
+//! Master cards switch enrollment on instead of opening the door.
+
+use core::fmt;
+
+pub const MAX_CARDS: usize = 32;
+pub const NAME_LEN: usize = 20;
+const MAX_UID_LEN: usize = 10;
+
+/// Every day of the week, Monday in bit 0
+pub const ALL_DAYS: u8 = 0x7F;
+const DAY_LETTERS: &[u8; 7] = b"MTWTFSS";
+
+pub const HEADER: &str = "# uid, name, valid from, valid until, days, hours, role\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A line needs at least six fields
+    Fields,
+    Uid,
+    Name,
+    Date,
+    Days,
+    Hours,
+    Role,
+    /// There's no room for another card
+    Full,
+}
+
+/// Why a card doesn't open the door
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denied {
+    Unknown,
+    NotYetValid,
+    Expired,
+    WrongDay,
+    OutsideHours,
+    /// The card has time rules and the clock isn't set to check them
+    ClockNotSet,
+    /// The list has cards but no master card to enroll more
+    NoMaster,
+}
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Denied::Unknown => "unknown card",
+            Denied::NotYetValid => "not valid yet",
+            Denied::Expired => "expired",
+            Denied::WrongDay => "not allowed today",
+            Denied::OutsideHours => "outside allowed hours",
+            Denied::ClockNotSet => "clock not set",
+            Denied::NoMaster => "no master card on the list",
+        })
+    }
+}
+
+/// The UID of a card, 4, 7 or 10 bytes long
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CardId {
+    bytes: [u8; MAX_UID_LEN],
+    len: u8,
+}
+
+impl CardId {
+    pub fn new(uid: &[u8]) -> Option<Self> {
+        if !matches!(uid.len(), 4 | 7 | 10) {
+            return None;
+        }
+        let mut bytes = [0u8; MAX_UID_LEN];
+        bytes[..uid.len()].copy_from_slice(uid);
+        Some(Self {
+            bytes,
+            len: uid.len() as u8,
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    fn parse(hex: &str) -> Option<Self> {
+        if !hex.len().is_multiple_of(2) || hex.len() > 2 * MAX_UID_LEN {
+            return None;
+        }
+        let mut bytes = [0u8; MAX_UID_LEN];
+        for (i, byte) in bytes.iter_mut().take(hex.len() / 2).enumerate() {
+            *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+        }
+        Self::new(&bytes[..hex.len() / 2])
+    }
+}
+
+impl fmt::Display for CardId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.as_bytes() {
+            write!(f, "{:02X}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CardId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// A card holder's name, without commas or line breaks so it fits a CSV
+/// field as is. Write to it with `write!`.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct Name {
+    bytes: [u8; NAME_LEN],
+    len: u8,
+}
+
+impl Name {
+    pub fn new(name: &str) -> Option<Self> {
+        let mut new = Self::default();
+        fmt::Write::write_str(&mut new, name).ok()?;
+        Some(new)
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole strs are ever copied in
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap()
+    }
+}
+
+impl fmt::Write for Name {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let start = self.len as usize;
+        let end = start + s.len();
+        if end > NAME_LEN || s.contains([',', '"', '\r', '\n']) {
+            return Err(fmt::Error);
+        }
+        self.bytes[start..end].copy_from_slice(s.as_bytes());
+        self.len = end as u8;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.split('-');
+        let date = Self {
+            year: parts.next()?.parse().ok()?,
+            month: parts.next()?.parse().ok()?,
+            day: parts.next()?.parse().ok()?,
+        };
+        let valid = parts.next().is_none()
+            && (1..=12).contains(&date.month)
+            && (1..=31).contains(&date.day);
+        valid.then_some(date)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Local wall-clock time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Now {
+    pub date: Date,
+    /// 0 for Monday to 6 for Sunday
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Now {
+    fn minute_of_day(&self) -> u16 {
+        self.hour as u16 * 60 + self.minute as u16
+    }
+}
+
+impl fmt::Display for Now {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:02}:{:02}:{:02}",
+            self.date, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Time of day a card works, in minutes from midnight. The end is excluded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hours {
+    pub from: u16,
+    pub to: u16,
+}
+
+impl Hours {
+    pub fn contains(&self, minute: u16) -> bool {
+        if self.from <= self.to {
+            (self.from..self.to).contains(&minute)
+        } else {
+            // wraps past midnight
+            minute >= self.from || minute < self.to
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let minutes = |hhmm: &str| -> Option<u16> {
+            let (hours, minutes) = hhmm.split_once(':')?;
+            let (hours, minutes): (u16, u16) = (hours.parse().ok()?, minutes.parse().ok()?);
+            (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+        };
+        let (from, to) = text.split_once('-')?;
+        Some(Self {
+            from: minutes(from)?,
+            to: minutes(to)?,
+        })
+    }
+}
+
+impl fmt::Display for Hours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.from / 60,
+            self.from % 60,
+            self.to / 60,
+            self.to % 60
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub id: CardId,
+    pub name: Name,
+    pub valid_from: Option<Date>,
+    pub valid_until: Option<Date>,
+    /// Days the card works, Monday in bit 0
+    pub days: u8,
+    /// Time of day the card works, any time if `None`
+    pub hours: Option<Hours>,
+    pub master: bool,
+}
+
+impl Entry {
+    /// A card that works any day at any time
+    pub fn new(id: CardId, name: Name) -> Self {
+        Self {
+            id,
+            name,
+            valid_from: None,
+            valid_until: None,
+            days: ALL_DAYS,
+            hours: None,
+            master: false,
+        }
+    }
+
+    /// Parse one line of `CARDS.TXT`
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let mut fields = line.split(',').map(str::trim);
+        let mut field = || fields.next().ok_or(Error::Fields);
+
+        let id = CardId::parse(field()?).ok_or(Error::Uid)?;
+        let name = Name::new(field()?).ok_or(Error::Name)?;
+        let date = |text: &str| match text {
+            "-" => Ok(None),
+            text => Date::parse(text).map(Some).ok_or(Error::Date),
+        };
+        let valid_from = date(field()?)?;
+        let valid_until = date(field()?)?;
+        let days = match field()? {
+            "*" => ALL_DAYS,
+            days if days.len() == DAY_LETTERS.len() => days
+                .bytes()
+                .enumerate()
+                .filter(|&(_, letter)| letter != b'-')
+                .fold(0, |mask, (day, _)| mask | 1 << day),
+            _ => return Err(Error::Days),
+        };
+        let hours = match field()? {
+            "*" => None,
+            hours => Some(Hours::parse(hours).ok_or(Error::Hours)?),
+        };
+        let master = match fields.next() {
+            None | Some("") | Some("user") => false,
+            Some("master") => true,
+            Some(_) => return Err(Error::Role),
+        };
+
+        Ok(Self {
+            id,
+            name,
+            valid_from,
+            valid_until,
+            days,
+            hours,
+            master,
+        })
+    }
+
+    /// Whether the card is limited to a validity window, days or hours
+    pub fn has_time_rules(&self) -> bool {
+        self.valid_from.is_some()
+            || self.valid_until.is_some()
+            || self.days != ALL_DAYS
+            || self.hours.is_some()
+    }
+
+    /// Whether the card opens the door at `now`
+    pub fn check(&self, now: &Now) -> Result<(), Denied> {
+        if self.valid_from.is_some_and(|from| now.date < from) {
+            return Err(Denied::NotYetValid);
+        }
+        if self.valid_until.is_some_and(|until| now.date > until) {
+            return Err(Denied::Expired);
+        }
+        if self.days & 1 << now.weekday == 0 {
+            return Err(Denied::WrongDay);
+        }
+        if self
+            .hours
+            .is_some_and(|hours| !hours.contains(now.minute_of_day()))
+        {
+            return Err(Denied::OutsideHours);
+        }
+        Ok(())
+    }
+}
+
+/// The line of `CARDS.TXT`, without the line break
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},", self.id, self.name.as_str())?;
+        for date in [self.valid_from, self.valid_until] {
+            match date {
+                Some(date) => write!(f, "{},", date)?,
+                None => f.write_str("-,")?,
+            }
+        }
+        if self.days == ALL_DAYS {
+            f.write_str("*,")?;
+        } else {
+            for (day, letter) in DAY_LETTERS.iter().enumerate() {
+                let letter = if self.days & 1 << day != 0 {
+                    *letter
+                } else {
+                    b'-'
+                };
+                write!(f, "{}", letter as char)?;
+            }
+            f.write_str(",")?;
+        }
+        match self.hours {
+            Some(hours) => write!(f, "{}", hours)?,
+            None => f.write_str("*")?,
+        }
+        if self.master {
+            f.write_str(",master")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Whitelist {
+    entries: [Option<Entry>; MAX_CARDS],
+}
+
+impl Default for Whitelist {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Whitelist {
+    pub const fn new() -> Self {
+        Self {
+            entries: [None; MAX_CARDS],
+        }
+    }
+
+    /// Parse a whole `CARDS.TXT`, skipping blank lines and comments. Errors
+    /// come with their line number.
+    pub fn parse(text: &str) -> Result<Self, (usize, Error)> {
+        let mut list = Self::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            Entry::parse(line)
+                .and_then(|entry| list.add(entry))
+                .map_err(|e| (number + 1, e))?;
+        }
+        Ok(list)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn has_master(&self) -> bool {
+        self.iter().any(|entry| entry.master)
+    }
+
+    pub fn find(&self, id: &CardId) -> Option<&Entry> {
+        self.iter().find(|entry| entry.id == *id)
+    }
+
+    /// The entry of `id` if it opens the door at `now`
+    pub fn check(&self, id: &CardId, now: &Now) -> Result<&Entry, Denied> {
+        let entry = self.find(id).ok_or(Denied::Unknown)?;
+        entry.check(now)?;
+        Ok(entry)
+    }
+
+    /// Add a card, replacing the entry with the same UID if there is one
+    pub fn add(&mut self, entry: Entry) -> Result<(), Error> {
+        let slot = match self
+            .entries
+            .iter()
+            .position(|e| e.is_some_and(|e| e.id == entry.id))
+        {
+            Some(i) => i,
+            None => self
+                .entries
+                .iter()
+                .position(Option::is_none)
+                .ok_or(Error::Full)?,
+        };
+        self.entries[slot] = Some(entry);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: &CardId) -> bool {
+        match self
+            .entries
+            .iter_mut()
+            .find(|e| e.is_some_and(|e| e.id == *id))
+        {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The whole `CARDS.TXT`
+impl fmt::Display for Whitelist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(HEADER)?;
+        for entry in self.iter() {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::string::ToString;
+
+    use super::*;
+
+    const CARDS: &str = "\
+# uid, name, valid from, valid until, days, hours, role
+DEADBEEF,Ferris,2026-01-01,2026-12-31,MTWTF--,08:00-18:00
+
+04A2B3C4D5E6F7,Night shift,-,-,*,22:00-06:00
+11223344, Admin ,-,-,*,*,master
+";
+
+    fn at(date: (u16, u8, u8), weekday: u8, hour: u8, minute: u8) -> Now {
+        Now {
+            date: Date {
+                year: date.0,
+                month: date.1,
+                day: date.2,
+            },
+            weekday,
+            hour,
+            minute,
+            second: 0,
+        }
+    }
+
+    fn id(hex: &str) -> CardId {
+        CardId::parse(hex).unwrap()
+    }
+
+    #[test]
+    fn parses_and_writes_back() {
+        let list = Whitelist::parse(CARDS).unwrap();
+        assert_eq!(list.len(), 3);
+        let ferris = list.find(&id("DEADBEEF")).unwrap();
+        assert_eq!(ferris.name.as_str(), "Ferris");
+        assert_eq!(ferris.days, 0b0011111);
+        assert_eq!(
+            ferris.hours,
+            Some(Hours {
+                from: 480,
+                to: 1080
+            })
+        );
+        assert!(list.find(&id("11223344")).unwrap().master);
+        assert!(list.has_master());
+
+        let text = list.to_string();
+        assert!(text.starts_with(HEADER));
+        assert!(text.contains("DEADBEEF,Ferris,2026-01-01,2026-12-31,MTWTF--,08:00-18:00\n"));
+        assert!(text.contains("11223344,Admin,-,-,*,*,master\n"));
+        let again = Whitelist::parse(&text).unwrap();
+        assert!(list.iter().eq(again.iter()));
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        assert_eq!(
+            Whitelist::parse("DEADBEEF,x,-,-,*").err(),
+            Some((1, Error::Fields))
+        );
+        assert_eq!(Entry::parse("DEADBE,x,-,-,*,*"), Err(Error::Uid));
+        assert_eq!(
+            Entry::parse("DEADBEEF,x,2026-13-01,-,*,*"),
+            Err(Error::Date)
+        );
+        assert_eq!(Entry::parse("DEADBEEF,x,-,-,MTW,*"), Err(Error::Days));
+        assert_eq!(Entry::parse("DEADBEEF,x,-,-,*,8-18"), Err(Error::Hours));
+        assert_eq!(Entry::parse("DEADBEEF,x,-,-,*,*,boss"), Err(Error::Role));
+        assert_eq!(
+            Entry::parse("DEADBEEF,a name that is far too long,-,-,*,*"),
+            Err(Error::Name)
+        );
+        assert_eq!(
+            Whitelist::parse("# ok\n\nDEADBEEF,x,-,-,*,25:00-26:00").err(),
+            Some((3, Error::Hours))
+        );
+    }
+
+    #[test]
+    fn applies_rules() {
+        let list = Whitelist::parse(CARDS).unwrap();
+        let ferris = id("DEADBEEF");
+        // Wednesday
+        assert!(list.check(&ferris, &at((2026, 10, 14), 2, 9, 30)).is_ok());
+        assert_eq!(
+            list.check(&ferris, &at((2026, 10, 14), 2, 18, 0)).err(),
+            Some(Denied::OutsideHours)
+        );
+        assert_eq!(
+            list.check(&ferris, &at((2026, 10, 17), 5, 9, 30)).err(),
+            Some(Denied::WrongDay)
+        );
+        assert_eq!(
+            list.check(&ferris, &at((2025, 12, 31), 2, 9, 30)).err(),
+            Some(Denied::NotYetValid)
+        );
+        assert!(list.check(&ferris, &at((2026, 12, 31), 3, 9, 30)).is_ok());
+        assert_eq!(
+            list.check(&ferris, &at((2027, 1, 1), 4, 9, 30)).err(),
+            Some(Denied::Expired)
+        );
+        assert_eq!(
+            list.check(&id("CAFEBABE"), &at((2026, 10, 14), 2, 9, 30))
+                .err(),
+            Some(Denied::Unknown)
+        );
+
+        let night = id("04A2B3C4D5E6F7");
+        assert!(list.check(&night, &at((2026, 10, 14), 2, 23, 0)).is_ok());
+        assert!(list.check(&night, &at((2026, 10, 15), 3, 5, 59)).is_ok());
+        assert!(list.check(&night, &at((2026, 10, 15), 3, 6, 0)).is_err());
+    }
+
+    #[test]
+    fn adds_and_removes() {
+        let mut list = Whitelist::new();
+        for i in 0..MAX_CARDS as u32 {
+            let card = Entry::new(CardId::new(&i.to_be_bytes()).unwrap(), Name::default());
+            list.add(card).unwrap();
+        }
+        let extra = Entry::new(id("DEADBEEF"), Name::new("Ferris").unwrap());
+        assert_eq!(list.add(extra), Err(Error::Full));
+
+        // same UID: replaced, not added
+        let first = CardId::new(&[0; 4]).unwrap();
+        list.add(Entry {
+            master: true,
+            ..Entry::new(first, Name::default())
+        })
+        .unwrap();
+        assert!(list.find(&first).unwrap().master);
+
+        assert!(list.remove(&first));
+        assert!(!list.remove(&first));
+        assert_eq!(list.add(extra), Ok(()));
+        assert_eq!(list.len(), MAX_CARDS);
+    }
+}
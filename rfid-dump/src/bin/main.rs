@@ -13,6 +13,9 @@ use esp_hal::clock::CpuClock;
 use esp_hal::timer::timg::TimerGroup;
 use esp_println as _;
 
+use core::cell::RefCell;
+use core::fmt;
+
 // SPI
 use esp_hal::gpio::{Level, Output, OutputConfig};
 use esp_hal::spi;
@@ -20,10 +23,18 @@ use esp_hal::spi::master::Spi;
 use esp_hal::time::Rate;
 
 // RFID Reader
-use embedded_hal_bus::spi::ExclusiveDevice;
+use embedded_hal_bus::spi::RefCellDevice;
 use esp_hal::delay::Delay;
 use mfrc522::Mfrc522;
 use mfrc522::comm::blocking::spi::SpiInterface;
+use rfid_dump::access::AccessConditions;
+use rfid_dump::image::{CardSize, Dump, MAX_SECTORS, first_block, is_trailer, trailer_of};
+use rfid_dump::keys::{self, DEFAULT_KEYS, Key, KeyChoice, KeySelect, SectorKeys};
+use rfid_dump::mk_static;
+use rfid_dump::reader::{self, Error};
+
+// SD card, shares the SPI bus with the reader
+use embedded_sdmmc::{Mode, SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager};
 
 use esp_println::{self as _, print, println};
 
@@ -36,6 +47,25 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
 esp_bootloader_esp_idf::esp_app_desc!();
 
+#[derive(Default)]
+pub struct DummyTimesource();
+
+impl TimeSource for DummyTimesource {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 0,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+// Our own keys to try besides the common ones, like those `rfid-change-key` writes
+const EXTRA_KEYS: &[Key] = &[*b"Rusted", *b"Ferris"];
+
 #[esp_rtos::main]
 async fn main(spawner: Spawner) -> ! {
     // generator version: 1.0.0
@@ -51,10 +81,11 @@ async fn main(spawner: Spawner) -> ! {
     // TODO: Spawn some tasks
     let _ = spawner;
 
+    // The SD card must be initialized at 400 kHz
     let spi_bus = Spi::new(
         peripherals.SPI2,
         spi::master::Config::default()
-            .with_frequency(Rate::from_mhz(5))
+            .with_frequency(Rate::from_khz(400))
             .with_mode(spi::Mode::_0),
     )
     .unwrap()
@@ -62,51 +93,174 @@ async fn main(spawner: Spawner) -> ! {
     .with_mosi(peripherals.GPIO23)
     .with_miso(peripherals.GPIO19)
     .into_async();
+    let spi_bus = mk_static!(RefCell<Spi<'static, esp_hal::Async>>, RefCell::new(spi_bus));
 
-    let sd_cs = Output::new(peripherals.GPIO5, Level::High, OutputConfig::default());
+    let sd_cs = Output::new(peripherals.GPIO4, Level::High, OutputConfig::default());
+    let rfid_cs = Output::new(peripherals.GPIO5, Level::High, OutputConfig::default());
+
+    let sdcard = SdCard::new(
+        RefCellDevice::new(spi_bus, sd_cs, Delay::new()).unwrap(),
+        Delay::new(),
+    );
+    match sdcard.num_bytes() {
+        Ok(size) => println!("SD card: {} bytes", size),
+        Err(e) => println!("No SD card, dumps won't be saved: {:?}", e),
+    }
+    spi_bus
+        .borrow_mut()
+        .apply_config(
+            &spi::master::Config::default()
+                .with_frequency(Rate::from_mhz(5))
+                .with_mode(spi::Mode::_0),
+        )
+        .unwrap();
+    let volume_mgr = VolumeManager::new(sdcard, DummyTimesource::default());
 
     let delay = Delay::new();
-    let spi_dev = ExclusiveDevice::new(spi_bus, sd_cs, delay).unwrap();
+    // KeySelect lets us authenticate with Key B too
+    let choice = KeyChoice::new();
+    let spi_dev = KeySelect::new(
+        RefCellDevice::new(spi_bus, rfid_cs, delay).unwrap(),
+        &choice,
+    );
 
     let spi_interface = SpiInterface::new(spi_dev);
     let mut rfid = Mfrc522::new(spi_interface).init().unwrap();
 
+    let dump = mk_static!(Dump, Dump::new(CardSize::K1));
+
     loop {
-        if let Ok(atqa) = rfid.reqa() {
-            println!("Got atqa");
-            Timer::after(Duration::from_millis(50)).await;
-            if let Ok(uid) = rfid.select(&atqa) {
-                dump_memory(&uid, &mut rfid);
-                rfid.hlta().unwrap();
-                rfid.stop_crypto1().unwrap();
+        let result = reader::with_card(&mut rfid, |uid, rfid| {
+            let Ok(size) = CardSize::of(uid) else {
+                println!("\n\nNot a MIFARE Classic card, nothing to dump");
+                return Ok(None);
+            };
+            dump.clear(size);
+            dump.set_uid(uid.as_bytes());
+            let keys = dump_memory(uid, dump, &choice, rfid);
+            if let Err(e) = keys {
+                println!("\n\nDump failed, nothing saved: {}", e);
+            }
+            keys.map(Some)
+        });
+        if let Ok(Some(keys)) = result {
+            print_summary(&keys[..dump.size().sectors() as usize]);
+            match volume_mgr.open_volume(VolumeIdx(0)) {
+                Ok(volume) => save_dump(&volume, dump),
+                Err(e) => println!("\nCan't open the SD card: {:?}", e),
             }
         }
+        Timer::after(Duration::from_millis(50)).await;
+    }
+}
+
+/// Save `dump` as a `.MFD` image and as JSON in the root directory
+fn save_dump<D, T>(volume: &embedded_sdmmc::Volume<'_, D, T, 4, 4, 1>, dump: &Dump)
+where
+    D: embedded_sdmmc::BlockDevice,
+    T: TimeSource,
+    D::Error: core::fmt::Debug,
+{
+    let Ok(root_dir) = volume.open_root_dir() else {
+        println!("\nCan't open the root directory");
+        return;
+    };
+    for extension in ["MFD", "JSN"] {
+        let name = dump.file_name(extension);
+        let result = root_dir
+            .open_file_in_dir(name.as_str(), Mode::ReadWriteCreateOrTruncate)
+            .and_then(|file| {
+                if extension == "MFD" {
+                    file.write(dump.mfd())?;
+                } else {
+                    let mut error = None;
+                    let mut out = FileWriter(|bytes: &[u8]| {
+                        file.write(bytes).map_err(|e| error = Some(e)).is_ok()
+                    });
+                    // the writer only fails when the file does
+                    let _ = dump.write_json(&mut out);
+                    if let Some(e) = error {
+                        return Err(e);
+                    }
+                }
+                file.close()
+            });
+        match result {
+            Ok(()) => println!("Saved {}", name.as_str()),
+            Err(e) => println!("Can't save {}: {:?}", name.as_str(), e),
+        }
+    }
+}
+
+/// Lets `write!` and friends write to a file
+struct FileWriter<F>(F);
+
+impl<F: FnMut(&[u8]) -> bool> fmt::Write for FileWriter<F> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if (self.0)(s.as_bytes()) {
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
     }
 }
 
 fn read_sector<E, COMM: mfrc522::comm::Interface<Error = E>>(
     uid: &mfrc522::Uid,
     sector: u8,
+    keys: &SectorKeys,
+    dump: &mut Dump,
+    choice: &KeyChoice,
     rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) {
-    const AUTH_KEY: [u8; 6] = [0xFF; 6];
-
-    let block_offset = sector * 4;
-    rfid.mf_authenticate(uid, block_offset, &AUTH_KEY)
-        .map_err(|_| "Auth failed")
-        .unwrap();
+) -> Result<(), Error> {
+    let Some((key_type, key)) = keys.any() else {
+        print!("\nNo key found, skipping");
+        return Ok(());
+    };
 
-    for abs_block in block_offset..block_offset + 4 {
+    let block_offset = first_block(sector);
+    for abs_block in block_offset..=trailer_of(sector) {
         let rel_block = abs_block - block_offset;
-        let data = rfid.mf_read(abs_block).map_err(|_| "Read failed").unwrap();
 
         // Prining the Block absolute and relative numbers
         print!("\nBLOCK {} (REL: {}) | ", abs_block, rel_block);
+
+        // the key may not be allowed to read every block, and a failed read
+        // ends the authentication, so every block authenticates again
+        let result = reader::retry(uid, rfid, |rfid| {
+            keys::authenticate(uid, block_offset, key_type, &key, choice, rfid)
+                .map_err(|_| Error::AuthFailed { sector })?;
+            rfid.mf_read(abs_block)
+                .map_err(|e| Error::from_driver(e, Error::ReadFailed { block: abs_block }))
+        });
+        let data = match result {
+            Ok(data) => data,
+            // the card is gone, the rest of the dump would be empty
+            Err(Error::NoCard) => return Err(Error::NoCard),
+            Err(e) => {
+                print!("-- {} with key {:?} --", e, key_type);
+                continue;
+            }
+        };
         print_hex_bytes(&data);
+        dump.set_block(abs_block, data);
 
         // Printing block type
-        let block_type = get_block_type(sector, rel_block);
+        let block_type = get_block_type(abs_block);
         print!("| {} ", block_type);
+
+        if is_trailer(abs_block) {
+            print_access_conditions(&data);
+        }
+    }
+    reader::recover(uid, rfid)
+}
+
+fn print_access_conditions(trailer: &[u8; 16]) {
+    // key A always reads back as zeros, the access bits follow it
+    match AccessConditions::decode(&[trailer[6], trailer[7], trailer[8]]) {
+        Ok(conditions) => print!("\n\n{}", conditions),
+        Err(e) => print!("\n\nInvalid access bits: {:?}", e),
     }
 }
 
@@ -116,22 +270,54 @@ fn print_hex_bytes(data: &[u8]) {
     }
 }
 
-const fn get_block_type(sector: u8, rel_block: u8) -> &'static str {
-    match rel_block {
-        0 if sector == 0 => "MFD",
-        3 => "TRAILER",
+const fn get_block_type(block: u8) -> &'static str {
+    match block {
+        0 => "MFD",
+        _ if is_trailer(block) => "TRAILER",
         _ => "DATA",
     }
 }
 
 fn dump_memory<E, COMM: mfrc522::comm::Interface<Error = E>>(
     uid: &mfrc522::Uid,
+    dump: &mut Dump,
+    choice: &KeyChoice,
     rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) {
-    for sector in 0..16 {
+) -> Result<[SectorKeys; MAX_SECTORS], Error> {
+    let mut keys = [SectorKeys::default(); MAX_SECTORS];
+    for (sector, keys) in (0..dump.size().sectors()).zip(keys.iter_mut()) {
         // Printing the Sector number
         println!("\n\n-----------SECTOR {}-----------", sector);
 
-        read_sector(uid, sector, rfid);
+        *keys = keys::find_keys(
+            uid,
+            first_block(sector),
+            &[DEFAULT_KEYS, EXTRA_KEYS],
+            choice,
+            rfid,
+        );
+        read_sector(uid, sector, keys, dump, choice, rfid)?;
+        dump.set_keys(sector, keys.a, keys.b);
+    }
+    // SAK and ATQA are in the manufacturer block
+    dump.identify();
+    Ok(keys)
+}
+
+fn print_summary(keys: &[SectorKeys]) {
+    println!("\n\nSECTOR  KEY A         KEY B");
+    for (sector, keys) in keys.iter().enumerate() {
+        print!("{:<7} ", sector);
+        print_key(keys.a);
+        print!(" ");
+        print_key(keys.b);
+        println!("");
+    }
+}
+
+fn print_key(key: Option<Key>) {
+    match key {
+        Some(key) => key.iter().for_each(|b| print!("{:02x}", b)),
+        None => print!("------------"),
     }
 }
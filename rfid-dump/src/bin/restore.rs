@@ -0,0 +1,293 @@
+#![no_std]
+#![no_main]
+#![deny(
+    clippy::mem_forget,
+    reason = "mem::forget is generally not safe to do with esp_hal types, especially those \
+    holding buffers for the duration of a data transfer."
+)]
+
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
+use esp_hal::clock::CpuClock;
+use esp_hal::timer::timg::TimerGroup;
+use esp_println as _;
+
+use core::cell::RefCell;
+
+// SPI
+use esp_hal::gpio::{Level, Output, OutputConfig};
+use esp_hal::spi;
+use esp_hal::spi::master::Spi;
+use esp_hal::time::Rate;
+
+// RFID Reader
+use embedded_hal_bus::spi::RefCellDevice;
+use esp_hal::delay::Delay;
+use mfrc522::Mfrc522;
+use mfrc522::comm::blocking::spi::SpiInterface;
+use rfid_dump::image::{
+    self, CardSize, Dump, JsonReader, RestoreOptions, first_block, is_trailer, trailer_of,
+};
+use rfid_dump::keys::{self, DEFAULT_KEYS, Key, KeyChoice, KeySelect, KeyType};
+use rfid_dump::mk_static;
+
+// SD card, shares the SPI bus with the reader
+use embedded_sdmmc::{Mode, SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+
+use esp_println::{self as _, print, println};
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+// This creates a default app-descriptor required by the esp-idf bootloader.
+// For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[derive(Default)]
+pub struct DummyTimesource();
+
+impl TimeSource for DummyTimesource {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 0,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+// Image saved by rfid-dump (.MFD or .JSN), or copied from another tool
+const IMAGE: &str = "DEADBEEF.MFD";
+
+const OPTIONS: RestoreOptions = RestoreOptions {
+    // Also write the sector trailers, replacing the keys and access bits of
+    // the card with those of the image
+    trailers: false,
+    // Write the manufacturer block to clone the UID; only "magic" cards allow it
+    block_0: false,
+    // Write trailers even if they lock their sector for good
+    force: false,
+};
+
+// Keys to try on the target card besides the common ones
+const EXTRA_KEYS: &[Key] = &[*b"Rusted", *b"Ferris"];
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
+    // generator version: 1.0.0
+
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    info!("Embassy initialized!");
+
+    // TODO: Spawn some tasks
+    let _ = spawner;
+
+    // The SD card must be initialized at 400 kHz
+    let spi_bus = Spi::new(
+        peripherals.SPI2,
+        spi::master::Config::default()
+            .with_frequency(Rate::from_khz(400))
+            .with_mode(spi::Mode::_0),
+    )
+    .unwrap()
+    .with_sck(peripherals.GPIO18)
+    .with_mosi(peripherals.GPIO23)
+    .with_miso(peripherals.GPIO19)
+    .into_async();
+    let spi_bus = mk_static!(RefCell<Spi<'static, esp_hal::Async>>, RefCell::new(spi_bus));
+
+    let sd_cs = Output::new(peripherals.GPIO4, Level::High, OutputConfig::default());
+    let rfid_cs = Output::new(peripherals.GPIO5, Level::High, OutputConfig::default());
+
+    let sdcard = SdCard::new(
+        RefCellDevice::new(spi_bus, sd_cs, Delay::new()).unwrap(),
+        Delay::new(),
+    );
+    sdcard.num_bytes().unwrap();
+    spi_bus
+        .borrow_mut()
+        .apply_config(
+            &spi::master::Config::default()
+                .with_frequency(Rate::from_mhz(5))
+                .with_mode(spi::Mode::_0),
+        )
+        .unwrap();
+
+    let dump = mk_static!(Dump, Dump::new(CardSize::K1));
+    {
+        let volume_mgr = VolumeManager::new(sdcard, DummyTimesource::default());
+        let volume0 = volume_mgr.open_volume(VolumeIdx(0)).unwrap();
+        let root_dir = volume0.open_root_dir().unwrap();
+        let file = root_dir.open_file_in_dir(IMAGE, Mode::ReadOnly).unwrap();
+
+        let mut buf = [0u8; 64];
+        let loaded = if IMAGE.ends_with(".MFD") {
+            dump.mfd_reader(file.length() as usize)
+                .and_then(|mut reader| {
+                    while let Ok(len @ 1..) = file.read(&mut buf) {
+                        reader.feed(&buf[..len])?;
+                    }
+                    reader.finish()
+                })
+        } else {
+            let mut reader = JsonReader::new(dump);
+            let mut result = Ok(());
+            while let Ok(len @ 1..) = file.read(&mut buf) {
+                result = reader.feed(&buf[..len]);
+                if result.is_err() {
+                    break;
+                }
+            }
+            result.and_then(|_| reader.finish())
+        };
+        match loaded {
+            Ok(()) => println!("Loaded {}, {:?} card", IMAGE, dump.size()),
+            Err(e) => {
+                println!("Can't load {}: {:?}", IMAGE, e);
+                loop {
+                    Timer::after(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    let delay = Delay::new();
+    // KeySelect lets us authenticate with Key B too
+    let choice = KeyChoice::new();
+    let spi_dev = KeySelect::new(
+        RefCellDevice::new(spi_bus, rfid_cs, delay).unwrap(),
+        &choice,
+    );
+
+    let spi_interface = SpiInterface::new(spi_dev);
+    let mut rfid = Mfrc522::new(spi_interface).init().unwrap();
+
+    println!("Present the card to write");
+    loop {
+        if let Ok(atqa) = rfid.reqa() {
+            println!("Got atqa");
+            Timer::after(Duration::from_millis(50)).await;
+            if let Ok(uid) = rfid.select(&atqa) {
+                let mut totals = Counts::default();
+                // block 0 changes the UID the other sectors authenticate
+                // with, so it goes last
+                for sector in (0..dump.size().sectors()).rev() {
+                    let counts = restore_sector(&uid, sector, dump, &choice, &mut rfid);
+                    totals.written += counts.written;
+                    totals.skipped += counts.skipped;
+                    totals.failed += counts.failed;
+                }
+                println!(
+                    "\nDone: {} written and verified, {} skipped, {} failed",
+                    totals.written, totals.skipped, totals.failed
+                );
+                let _ = rfid.hlta();
+                let _ = rfid.stop_crypto1();
+                println!("Present the next card to write it too");
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    written: u16,
+    skipped: u16,
+    failed: u16,
+}
+
+fn restore_sector<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &mfrc522::Uid,
+    sector: u8,
+    dump: &Dump,
+    choice: &KeyChoice,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Counts {
+    let mut counts = Counts::default();
+    let keys = keys::find_keys(
+        uid,
+        first_block(sector),
+        &[DEFAULT_KEYS, EXTRA_KEYS],
+        choice,
+        rfid,
+    );
+    let Some((key_type, key)) = keys.any() else {
+        println!("SECTOR {}: no key found", sector);
+        counts.failed += (first_block(sector)..=trailer_of(sector))
+            .filter(|&block| dump.check_write(block, &OPTIONS).is_ok())
+            .count() as u16;
+        return counts;
+    };
+
+    let mut authenticated = false;
+    for block in first_block(sector)..=trailer_of(sector) {
+        print!("BLOCK {}: ", block);
+        let data = match dump.check_write(block, &OPTIONS) {
+            Ok(data) => *data,
+            Err(skip) => {
+                println!("skipped ({:?})", skip);
+                counts.skipped += 1;
+                continue;
+            }
+        };
+
+        if !authenticated {
+            authenticated =
+                keys::authenticate(uid, first_block(sector), key_type, &key, choice, rfid).is_ok();
+        }
+        let written = authenticated && rfid.mf_write(block, data).is_ok();
+        let verified = written
+            && if is_trailer(block) {
+                verify_trailer(uid, block, &data, choice, rfid)
+            } else {
+                rfid.mf_read(block).is_ok_and(|read| read == data)
+            };
+
+        if verified {
+            println!("written");
+            counts.written += 1;
+        } else {
+            println!(
+                "{}",
+                if written {
+                    "verify failed"
+                } else {
+                    "write failed"
+                }
+            );
+            counts.failed += 1;
+            keys::reselect(rfid);
+            authenticated = false;
+        }
+    }
+    keys::reselect(rfid);
+    counts
+}
+
+/// Authenticate with the new Key A and compare the access bits. The keys
+/// themselves don't read back.
+fn verify_trailer<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &mfrc522::Uid,
+    block: u8,
+    data: &image::Block,
+    choice: &KeyChoice,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> bool {
+    keys::reselect(rfid);
+    let key_a: Key = data[..6].try_into().unwrap();
+    keys::authenticate(uid, block, KeyType::A, &key_a, choice, rfid).is_ok()
+        && rfid
+            .mf_read(block)
+            .is_ok_and(|read| read[6..10] == data[6..10])
+}
@@ -1 +1,15 @@
 #![no_std]
+pub use rfid_common::access;
+pub use rfid_common::image;
+pub use rfid_common::keys;
+pub use rfid_common::reader;
+
+#[macro_export]
+macro_rules! mk_static {
+    ($t:ty,$val:expr) => {{
+        static STATIC_CELL: static_cell::StaticCell<$t> = static_cell::StaticCell::new();
+        #[deny(unused_attributes)]
+        let x = STATIC_CELL.uninit().write(($val));
+        x
+    }};
+}
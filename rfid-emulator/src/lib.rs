@@ -0,0 +1,880 @@
+//! A software MFRC522 with a MIFARE Classic 1K card in its field, so the
+//! card code the RFID projects share runs in `cargo test`. `rfid-common`
+//! takes it as a dev-dependency.
+//!
+//! `mfrc522` keeps its register type private, so the emulator sits under
+//! [`SpiInterface`](mfrc522::comm::blocking::spi::SpiInterface) and answers
+//! the driver's SPI transactions like the chip does: registers, FIFO, CRC
+//! coprocessor, Transceive and MFAuthent. The card behind it goes through
+//! the ISO/IEC 14443-3 states, checks keys and access bits like a real card,
+//! and acknowledges or refuses writes and value operations.
+//!
+//! Crypto1 itself isn't computed. What matters to the driver is that the
+//! reader's encryption and the card's authentication agree: when they don't,
+//! the card doesn't understand the reader any more and drops back to idle,
+//! like a real one.
+//!
+//! ```ignore
+//! let mut chip = Emulator::new(Card::new([0xDE, 0xAD, 0xBE, 0xEF]));
+//! let mut rfid = connect(&mut chip);
+//! // ...
+//! assert_eq!(chip.card().unwrap().block(4), &[0; 16]);
+//! ```
+
+#![no_std]
+
+use core::convert::Infallible;
+
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+use mfrc522::comm::blocking::spi::{DummyDelay, SpiInterface};
+use mfrc522::{Initialized, Mfrc522};
+
+use rfid_common::crc::crc_a;
+
+pub type Block = [u8; 16];
+
+/// A sector trailer as it leaves the factory: keys FF, transport access bits
+pub const FACTORY_TRAILER: Block = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x07, 0x80, 0x69, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+/// 4-bit answers of the card
+pub const ACK: u8 = 0x0A;
+pub const NAK: u8 = 0x04;
+
+const BLOCKS: usize = 64;
+const SAK: u8 = 0x08;
+// the SAK of a cascade level that isn't the last
+const SAK_INCOMPLETE: u8 = 0x04;
+const ATQA: [u8; 2] = [0x04, 0x00];
+const ATQA_DOUBLE: [u8; 2] = [0x44, 0x00];
+// cascade tag, first byte of cascade level 1 for a 7-byte UID
+const CT: u8 = 0x88;
+
+// registers
+const COMMAND: u8 = 0x01;
+const COM_IRQ: u8 = 0x04;
+const DIV_IRQ: u8 = 0x05;
+const ERROR: u8 = 0x06;
+const STATUS2: u8 = 0x08;
+const FIFO_DATA: u8 = 0x09;
+const FIFO_LEVEL: u8 = 0x0A;
+const CONTROL: u8 = 0x0C;
+const BIT_FRAMING: u8 = 0x0D;
+const TX_CONTROL: u8 = 0x14;
+const CRC_RESULT_HIGH: u8 = 0x21;
+const CRC_RESULT_LOW: u8 = 0x22;
+const VERSION: u8 = 0x37;
+
+// chip commands
+const CALC_CRC: u8 = 0x03;
+const TRANSCEIVE: u8 = 0x0C;
+const MF_AUTHENT: u8 = 0x0E;
+const SOFT_RESET: u8 = 0x0F;
+
+// register bits
+const TIMER_IRQ: u8 = 0x01;
+const IDLE_IRQ: u8 = 0x10;
+const RX_IRQ: u8 = 0x20;
+const CRC_IRQ: u8 = 0x04;
+const SET_IRQ: u8 = 0x80;
+const MF_CRYPTO1_ON: u8 = 0x08;
+const FLUSH_BUFFER: u8 = 0x80;
+const START_SEND: u8 = 0x80;
+const ANTENNA_ON: u8 = 0x03;
+
+// card commands
+const REQA: u8 = 0x26;
+const WUPA: u8 = 0x52;
+const SEL_CL1: u8 = 0x93;
+const SEL_CL2: u8 = 0x95;
+const ANTICOLLISION: u8 = 0x20;
+const SELECT: u8 = 0x70;
+const HLTA: u8 = 0x50;
+const AUTH_KEY_A: u8 = 0x60;
+const AUTH_KEY_B: u8 = 0x61;
+const READ: u8 = 0x30;
+const WRITE: u8 = 0xA0;
+const DECREMENT: u8 = 0xC0;
+const INCREMENT: u8 = 0xC1;
+const RESTORE: u8 = 0xC2;
+const TRANSFER: u8 = 0xB0;
+
+// Who may do what under each access condition, indexed by C1 C2 C3 as a
+// number, with the columns of the datasheet
+const KEY_A: u8 = 1;
+const KEY_B: u8 = 2;
+const AB: u8 = KEY_A | KEY_B;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Read,
+    Write,
+    Increment,
+    /// Decrement, transfer and restore
+    Decrement,
+}
+
+const DATA: [[u8; 4]; 8] = [
+    [AB, AB, AB, AB],
+    [AB, 0, 0, AB],
+    [AB, 0, 0, 0],
+    [KEY_B, KEY_B, 0, 0],
+    [AB, KEY_B, 0, 0],
+    [KEY_B, 0, 0, 0],
+    [AB, KEY_B, KEY_B, AB],
+    [0, 0, 0, 0],
+];
+
+const KEY_A_WRITE: usize = 0;
+const ACCESS_READ: usize = 1;
+const ACCESS_WRITE: usize = 2;
+const KEY_B_READ: usize = 3;
+const KEY_B_WRITE: usize = 4;
+
+const TRAILER: [[u8; 5]; 8] = [
+    [KEY_A, KEY_A, 0, KEY_A, KEY_A],
+    [KEY_A, KEY_A, KEY_A, KEY_A, KEY_A],
+    [0, KEY_A, 0, KEY_A, 0],
+    [KEY_B, AB, KEY_B, 0, KEY_B],
+    [KEY_B, AB, 0, 0, KEY_B],
+    [0, AB, KEY_B, 0, 0],
+    [0, AB, 0, 0, 0],
+    [0, AB, 0, 0, 0],
+];
+
+/// ISO/IEC 14443-3 state of the card
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    /// Answered REQA or WUPA, waiting for anticollision and select
+    Ready,
+    /// Selected
+    Active,
+    /// Selected, and a key opened `sector`
+    Authenticated {
+        sector: u8,
+        key_b: bool,
+    },
+    /// Only WUPA wakes it
+    Halt,
+}
+
+// The second frame of a write or value operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    Write(u8),
+    Value(u8, u8),
+}
+
+/// A MIFARE Classic 1K card with a 4- or 7-byte UID
+#[derive(Debug, Clone)]
+pub struct Card {
+    uid: [u8; 7],
+    uid_len: usize,
+    // cascade level the card is at while Ready
+    level: usize,
+    blocks: [Block; BLOCKS],
+    state: State,
+    pending: Option<Pending>,
+    // value and address loaded by increment, decrement or restore
+    register: Option<(i32, u8)>,
+}
+
+impl Card {
+    /// A blank card: data blocks zeroed, factory trailers
+    pub fn new(uid: [u8; 4]) -> Self {
+        let mut card = Self::blank(&uid);
+        card.blocks[0][..4].copy_from_slice(&uid);
+        card.blocks[0][4] = bcc(&uid);
+        card.blocks[0][5] = SAK;
+        card.blocks[0][6..8].copy_from_slice(&ATQA);
+        card.blocks[0][8..].copy_from_slice(b"EMULATED");
+        card
+    }
+
+    /// A blank card with a 7-byte UID, selected in two cascade levels
+    pub fn double(uid: [u8; 7]) -> Self {
+        let mut card = Self::blank(&uid);
+        card.blocks[0][..7].copy_from_slice(&uid);
+        card.blocks[0][7] = SAK;
+        card.blocks[0][8..10].copy_from_slice(&ATQA_DOUBLE);
+        card.blocks[0][10..].copy_from_slice(b"EMULAT");
+        card
+    }
+
+    fn blank(uid: &[u8]) -> Self {
+        let mut blocks = [[0u8; 16]; BLOCKS];
+        for sector in 0..BLOCKS / 4 {
+            blocks[sector * 4 + 3] = FACTORY_TRAILER;
+        }
+        let mut stored = [0u8; 7];
+        stored[..uid.len()].copy_from_slice(uid);
+        Self {
+            uid: stored,
+            uid_len: uid.len(),
+            level: 0,
+            blocks,
+            state: State::Idle,
+            pending: None,
+            register: None,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// The block as stored, keys included
+    pub fn block(&self, block: u8) -> &Block {
+        &self.blocks[block as usize]
+    }
+
+    /// Store `data` whatever the access bits say, to set a card up
+    pub fn set_block(&mut self, block: u8, data: Block) {
+        self.blocks[block as usize] = data;
+    }
+
+    /// Give `sector` new keys and access bits, keeping the user byte
+    pub fn set_trailer(&mut self, sector: u8, key_a: [u8; 6], access: [u8; 3], key_b: [u8; 6]) {
+        let trailer = &mut self.blocks[sector as usize * 4 + 3];
+        trailer[..6].copy_from_slice(&key_a);
+        trailer[6..9].copy_from_slice(&access);
+        trailer[10..].copy_from_slice(&key_b);
+    }
+
+    // The UID bytes sent at cascade `level`, without the BCC: the whole
+    // UID of a 4-byte card, the cascade tag and 3 bytes then the last 4
+    // of a 7-byte one
+    fn cascade(&self, level: usize) -> Option<[u8; 4]> {
+        let uid = &self.uid[..self.uid_len];
+        let mut bytes = [0u8; 4];
+        match (uid.len(), level) {
+            (4, 0) => bytes.copy_from_slice(uid),
+            (7, 0) => {
+                bytes[0] = CT;
+                bytes[1..].copy_from_slice(&uid[..3]);
+            }
+            (7, 1) => bytes.copy_from_slice(&uid[3..]),
+            _ => return None,
+        }
+        Some(bytes)
+    }
+
+    // A frame of `bits` bits in the last byte, 0 for whole bytes
+    fn receive(&mut self, frame: &[u8], bits: u8, encrypted: bool) -> Option<Reply> {
+        if encrypted != matches!(self.state, State::Authenticated { .. }) {
+            self.confuse();
+            return None;
+        }
+        match (self.state, frame) {
+            (State::Idle, &[REQA]) | (State::Idle | State::Halt, &[WUPA]) if bits == 7 => {
+                self.state = State::Ready;
+                self.level = 0;
+                match self.uid_len {
+                    4 => Some(Reply::frame(&ATQA)),
+                    _ => Some(Reply::frame(&ATQA_DOUBLE)),
+                }
+            }
+            (State::Ready, &[sel @ (SEL_CL1 | SEL_CL2), ANTICOLLISION])
+                if sel == SEL_CL1 + 2 * self.level as u8 =>
+            {
+                let bytes = self.cascade(self.level)?;
+                let mut answer = [0u8; 5];
+                answer[..4].copy_from_slice(&bytes);
+                answer[4] = bcc(&bytes);
+                Some(Reply::frame(&answer))
+            }
+            (State::Ready, &[sel @ (SEL_CL1 | SEL_CL2), SELECT, ref uid @ .., _, _, _])
+                if sel == SEL_CL1 + 2 * self.level as u8
+                    && crc_ok(frame)
+                    && Some(uid) == self.cascade(self.level).as_ref().map(|b| &b[..]) =>
+            {
+                self.level += 1;
+                if self.cascade(self.level).is_some() {
+                    return Some(Reply::with_crc(&[SAK_INCOMPLETE]));
+                }
+                self.state = State::Active;
+                Some(Reply::with_crc(&[SAK]))
+            }
+            (State::Active | State::Authenticated { .. }, _) => self.command(frame),
+            _ => {
+                self.confuse();
+                None
+            }
+        }
+    }
+
+    // Commands of a selected card
+    fn command(&mut self, frame: &[u8]) -> Option<Reply> {
+        if let Some(pending) = self.pending.take() {
+            return self.complete(pending, frame);
+        }
+        let &[code, block, _, _] = frame else {
+            self.confuse();
+            return None;
+        };
+        if !crc_ok(frame) {
+            return None;
+        }
+        match code {
+            HLTA if block == 0 => {
+                self.state = State::Halt;
+                None
+            }
+            READ => self.read(block),
+            WRITE => self.start(block, Op::Write, Pending::Write(block)),
+            INCREMENT => self.start(block, Op::Increment, Pending::Value(code, block)),
+            DECREMENT | RESTORE => self.start(block, Op::Decrement, Pending::Value(code, block)),
+            TRANSFER => match self.register {
+                Some((value, address)) if self.may(block, Op::Decrement) => {
+                    self.blocks[block as usize] = encode_value(value, address);
+                    Some(Reply::nibble(ACK))
+                }
+                _ => self.nak(),
+            },
+            _ => {
+                self.confuse();
+                None
+            }
+        }
+    }
+
+    fn read(&mut self, block: u8) -> Option<Reply> {
+        let Some((key, condition)) = self.session(block) else {
+            return self.nak();
+        };
+        if !is_trailer(block) {
+            if DATA[condition][Op::Read as usize] & key == 0 {
+                return self.nak();
+            }
+            return Some(Reply::with_crc(&self.blocks[block as usize]));
+        }
+
+        // Key A never reads back, Key B only when the access bits say so
+        let permissions = TRAILER[condition];
+        if permissions[ACCESS_READ] & key == 0 {
+            return self.nak();
+        }
+        let trailer = &self.blocks[block as usize];
+        let mut data = [0u8; 16];
+        data[6..10].copy_from_slice(&trailer[6..10]);
+        if permissions[KEY_B_READ] & key != 0 {
+            data[10..].copy_from_slice(&trailer[10..]);
+        }
+        Some(Reply::with_crc(&data))
+    }
+
+    // The first frame of a write or value operation
+    fn start(&mut self, block: u8, op: Op, pending: Pending) -> Option<Reply> {
+        let allowed = match self.session(block) {
+            Some((key, condition)) if is_trailer(block) => {
+                let permissions = TRAILER[condition];
+                op == Op::Write
+                    && [KEY_A_WRITE, ACCESS_WRITE, KEY_B_WRITE]
+                        .iter()
+                        .any(|&field| permissions[field] & key != 0)
+            }
+            // the manufacturer block is read-only
+            Some(_) if block == 0 => false,
+            Some((key, condition)) => DATA[condition][op as usize] & key != 0,
+            None => false,
+        };
+        if !allowed {
+            return self.nak();
+        }
+        self.pending = Some(pending);
+        Some(Reply::nibble(ACK))
+    }
+
+    // The second frame: the data of a write, the operand of a value
+    // operation, which the card only answers when it refuses it
+    fn complete(&mut self, pending: Pending, frame: &[u8]) -> Option<Reply> {
+        match pending {
+            Pending::Write(block) if frame.len() == 18 && crc_ok(frame) => {
+                let mut data = [0u8; 16];
+                data.copy_from_slice(&frame[..16]);
+                self.store(block, data);
+                Some(Reply::nibble(ACK))
+            }
+            Pending::Value(code, block) if frame.len() == 6 && crc_ok(frame) => {
+                let operand = i32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]);
+                let Some((value, address)) = decode_value(&self.blocks[block as usize]) else {
+                    return self.nak();
+                };
+                let value = match code {
+                    INCREMENT => value.wrapping_add(operand),
+                    DECREMENT => value.wrapping_sub(operand),
+                    _ => value,
+                };
+                self.register = Some((value, address));
+                None
+            }
+            _ => self.nak(),
+        }
+    }
+
+    // A trailer write only changes the parts the key may change
+    fn store(&mut self, block: u8, data: Block) {
+        let Some((key, condition)) = self.session(block) else {
+            return;
+        };
+        let stored = &mut self.blocks[block as usize];
+        if !is_trailer(block) {
+            *stored = data;
+            return;
+        }
+        let permissions = TRAILER[condition];
+        for (field, range) in [
+            (KEY_A_WRITE, 0..6),
+            (ACCESS_WRITE, 6..10),
+            (KEY_B_WRITE, 10..16),
+        ] {
+            if permissions[field] & key != 0 {
+                stored[range.clone()].copy_from_slice(&data[range]);
+            }
+        }
+    }
+
+    // MFAuthent: command, block, key and UID
+    fn authenticate(&mut self, command: &[u8; 12], encrypted: bool) -> bool {
+        let selected = match self.state {
+            State::Active => !encrypted,
+            State::Authenticated { .. } => encrypted,
+            _ => false,
+        };
+        let block = command[1];
+        let key_b = command[0] == AUTH_KEY_B;
+        let opened = selected
+            && (command[0] == AUTH_KEY_A || key_b)
+            && (block as usize) < BLOCKS
+            // the last 4 bytes of a 7-byte UID
+            && command[8..] == self.uid[self.uid_len - 4..self.uid_len]
+            && {
+                let trailer = &self.blocks[(block as usize) / 4 * 4 + 3];
+                let key = if key_b { &trailer[10..] } else { &trailer[..6] };
+                command[2..8] == *key
+            };
+        self.pending = None;
+        self.register = None;
+        if opened {
+            self.state = State::Authenticated {
+                sector: block / 4,
+                key_b,
+            };
+        } else {
+            self.confuse();
+        }
+        opened
+    }
+
+    // The key of the session as a KEY_A or KEY_B bit, and the access
+    // condition of `block`. A readable Key B opens nothing.
+    fn session(&self, block: u8) -> Option<(u8, usize)> {
+        let State::Authenticated { sector, key_b } = self.state else {
+            return None;
+        };
+        if block as usize >= BLOCKS || block / 4 != sector {
+            return None;
+        }
+        let conditions = access_conditions(&self.blocks[sector as usize * 4 + 3])?;
+        let key = match key_b {
+            false => KEY_A,
+            true if TRAILER[conditions[3]][KEY_B_READ] != 0 => 0,
+            true => KEY_B,
+        };
+        Some((key, conditions[block as usize % 4]))
+    }
+
+    fn may(&self, block: u8, op: Op) -> bool {
+        match self.session(block) {
+            Some((key, condition)) => !is_trailer(block) && DATA[condition][op as usize] & key != 0,
+            None => false,
+        }
+    }
+
+    fn nak(&mut self) -> Option<Reply> {
+        self.confuse();
+        Some(Reply::nibble(NAK))
+    }
+
+    // A frame the card doesn't expect sends it back to idle
+    fn confuse(&mut self) {
+        self.pending = None;
+        if self.state != State::Halt {
+            self.state = State::Idle;
+        }
+    }
+}
+
+/// The driver, initialized, talking to `chip`
+pub fn connect(
+    chip: &mut Emulator,
+) -> Mfrc522<SpiInterface<&mut Emulator, DummyDelay>, Initialized> {
+    Mfrc522::new(SpiInterface::new(chip)).init().unwrap()
+}
+
+/// The MFRC522, with or without a card in its field
+pub struct Emulator {
+    registers: [u8; 0x40],
+    fifo: [u8; 64],
+    fifo_len: usize,
+    card: Option<Card>,
+    // frames the card misses
+    lost: usize,
+    // frames the card hears before leaving
+    leave_after: Option<usize>,
+}
+
+impl Emulator {
+    pub fn new(card: Card) -> Self {
+        let mut emulator = Self::empty();
+        emulator.card = Some(card);
+        emulator
+    }
+
+    /// No card in the field
+    pub fn empty() -> Self {
+        Self {
+            registers: [0; 0x40],
+            fifo: [0; 64],
+            fifo_len: 0,
+            card: None,
+            lost: 0,
+            leave_after: None,
+        }
+    }
+
+    pub fn card(&self) -> Option<&Card> {
+        self.card.as_ref()
+    }
+
+    /// Put a card in the field; it starts idle
+    pub fn insert(&mut self, mut card: Card) {
+        card.state = State::Idle;
+        card.pending = None;
+        self.card = Some(card);
+    }
+
+    pub fn remove(&mut self) -> Option<Card> {
+        self.card.take()
+    }
+
+    /// The card misses the next `frames` frames, as if they were lost
+    pub fn lose_frames(&mut self, frames: usize) {
+        self.lost = frames;
+    }
+
+    /// The card leaves the field after hearing `frames` more frames
+    pub fn remove_after(&mut self, frames: usize) {
+        self.leave_after = Some(frames);
+    }
+
+    fn read(&mut self, register: u8) -> u8 {
+        match register {
+            FIFO_DATA => {
+                let byte = self.fifo[0];
+                if self.fifo_len > 0 {
+                    self.fifo.copy_within(1..self.fifo_len, 0);
+                    self.fifo_len -= 1;
+                }
+                byte
+            }
+            FIFO_LEVEL => self.fifo_len as u8,
+            VERSION => 0x92,
+            _ => self.registers[register as usize],
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        match register {
+            COMMAND => self.command(value & 0x0F),
+            // bit 7 says whether the marked bits are set or cleared
+            COM_IRQ | DIV_IRQ => {
+                let bits = &mut self.registers[register as usize];
+                if value & SET_IRQ != 0 {
+                    *bits |= value & !SET_IRQ;
+                } else {
+                    *bits &= !value;
+                }
+            }
+            FIFO_DATA => {
+                if self.fifo_len < self.fifo.len() {
+                    self.fifo[self.fifo_len] = value;
+                    self.fifo_len += 1;
+                }
+            }
+            FIFO_LEVEL => {
+                if value & FLUSH_BUFFER != 0 {
+                    self.fifo_len = 0;
+                }
+            }
+            BIT_FRAMING => {
+                self.registers[BIT_FRAMING as usize] = value & !START_SEND;
+                if value & START_SEND != 0 && self.registers[COMMAND as usize] == TRANSCEIVE {
+                    self.transceive();
+                }
+            }
+            _ => self.registers[register as usize] = value,
+        }
+    }
+
+    fn command(&mut self, command: u8) {
+        self.registers[COMMAND as usize] = command;
+        match command {
+            SOFT_RESET => {
+                self.registers = [0; 0x40];
+                self.fifo_len = 0;
+            }
+            CALC_CRC => {
+                let crc = crc_a(&self.fifo[..self.fifo_len]);
+                self.registers[CRC_RESULT_LOW as usize] = crc[0];
+                self.registers[CRC_RESULT_HIGH as usize] = crc[1];
+                self.registers[DIV_IRQ as usize] |= CRC_IRQ;
+            }
+            MF_AUTHENT => self.authenticate(),
+            _ => {}
+        }
+    }
+
+    // Send the FIFO to the card and put its answer in its place
+    fn transceive(&mut self) {
+        let frame = self.fifo;
+        let len = self.fifo_len;
+        self.fifo_len = 0;
+        let bits = self.registers[BIT_FRAMING as usize] & 0x07;
+        let encrypted = self.encrypted();
+        let reply = self
+            .reach()
+            .and_then(|card| card.receive(&frame[..len], bits, encrypted));
+
+        self.registers[ERROR as usize] = 0;
+        match reply {
+            Some(reply) => {
+                self.fifo[..reply.len].copy_from_slice(&reply.bytes[..reply.len]);
+                self.fifo_len = reply.len;
+                self.registers[CONTROL as usize] = reply.bits;
+                self.registers[COM_IRQ as usize] |= RX_IRQ | IDLE_IRQ;
+            }
+            // the timer runs out waiting
+            None => self.registers[COM_IRQ as usize] |= TIMER_IRQ,
+        }
+    }
+
+    fn authenticate(&mut self) {
+        let mut command = [0u8; 12];
+        let complete = self.fifo_len == command.len();
+        command.copy_from_slice(&self.fifo[..12]);
+        self.fifo_len = 0;
+        let encrypted = self.encrypted();
+        let opened = complete
+            && self
+                .reach()
+                .is_some_and(|card| card.authenticate(&command, encrypted));
+
+        self.registers[ERROR as usize] = 0;
+        if opened {
+            self.registers[STATUS2 as usize] |= MF_CRYPTO1_ON;
+            self.registers[COM_IRQ as usize] |= IDLE_IRQ;
+        } else {
+            self.registers[STATUS2 as usize] &= !MF_CRYPTO1_ON;
+            self.registers[COM_IRQ as usize] |= TIMER_IRQ;
+        }
+    }
+
+    fn encrypted(&self) -> bool {
+        self.registers[STATUS2 as usize] & MF_CRYPTO1_ON != 0
+    }
+
+    // The card, if it hears the next frame
+    fn reach(&mut self) -> Option<&mut Card> {
+        if self.registers[TX_CONTROL as usize] & ANTENNA_ON != ANTENNA_ON {
+            return None;
+        }
+        match self.leave_after {
+            Some(0) => {
+                self.card = None;
+                self.leave_after = None;
+            }
+            Some(frames) => self.leave_after = Some(frames - 1),
+            None => {}
+        }
+        if self.lost > 0 {
+            self.lost -= 1;
+            return None;
+        }
+        self.card.as_mut()
+    }
+
+    // One byte on the bus: the first is the address, bit 7 set to read.
+    // While reading, every byte sent is the next address and the byte
+    // received the value at the previous one.
+    fn clock(&mut self, address: &mut Option<u8>, mosi: u8) -> u8 {
+        match *address {
+            None => {
+                *address = Some(mosi);
+                0
+            }
+            Some(at) if at & 0x80 != 0 => {
+                *address = Some(mosi);
+                self.read((at >> 1) & 0x3F)
+            }
+            Some(at) => {
+                self.write((at >> 1) & 0x3F, mosi);
+                0
+            }
+        }
+    }
+}
+
+impl ErrorType for Emulator {
+    type Error = Infallible;
+}
+
+impl SpiDevice for Emulator {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        let mut address = None;
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    for &byte in bytes.iter() {
+                        self.clock(&mut address, byte);
+                    }
+                }
+                Operation::TransferInPlace(bytes) => {
+                    for byte in bytes.iter_mut() {
+                        *byte = self.clock(&mut address, *byte);
+                    }
+                }
+                Operation::Transfer(read, write) => {
+                    for i in 0..read.len().max(write.len()) {
+                        let miso = self.clock(&mut address, write.get(i).copied().unwrap_or(0));
+                        if let Some(byte) = read.get_mut(i) {
+                            *byte = miso;
+                        }
+                    }
+                }
+                Operation::Read(bytes) => {
+                    for byte in bytes.iter_mut() {
+                        *byte = self.clock(&mut address, 0);
+                    }
+                }
+                Operation::DelayNs(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+// What the card sends back
+struct Reply {
+    bytes: [u8; 18],
+    len: usize,
+    // bits of the last byte, 0 for a whole byte
+    bits: u8,
+}
+
+impl Reply {
+    fn frame(data: &[u8]) -> Self {
+        let mut bytes = [0u8; 18];
+        bytes[..data.len()].copy_from_slice(data);
+        Self {
+            bytes,
+            len: data.len(),
+            bits: 0,
+        }
+    }
+
+    fn with_crc(data: &[u8]) -> Self {
+        let mut reply = Self::frame(data);
+        let crc = crc_a(data);
+        reply.bytes[data.len()..data.len() + 2].copy_from_slice(&crc);
+        reply.len += 2;
+        reply
+    }
+
+    fn nibble(answer: u8) -> Self {
+        Self {
+            bits: 4,
+            ..Self::frame(&[answer])
+        }
+    }
+}
+
+fn is_trailer(block: u8) -> bool {
+    block % 4 == 3
+}
+
+/// C1 C2 C3 of the four blocks of a sector as numbers, `None` when the
+/// inverted copies don't match
+fn access_conditions(trailer: &Block) -> Option<[usize; 4]> {
+    let c1 = trailer[7] >> 4;
+    let c2 = trailer[8] & 0x0F;
+    let c3 = trailer[8] >> 4;
+    let valid = trailer[6] & 0x0F == !c1 & 0x0F
+        && trailer[6] >> 4 == !c2 & 0x0F
+        && trailer[7] & 0x0F == !c3 & 0x0F;
+    let bit = |c: u8, block: usize| ((c >> block) & 1) as usize;
+    valid.then(|| core::array::from_fn(|b| bit(c1, b) << 2 | bit(c2, b) << 1 | bit(c3, b)))
+}
+
+fn encode_value(value: i32, address: u8) -> Block {
+    let mut block = [0u8; 16];
+    block[0..4].copy_from_slice(&value.to_le_bytes());
+    block[4..8].copy_from_slice(&(!value).to_le_bytes());
+    block[8..12].copy_from_slice(&value.to_le_bytes());
+    block[12..].copy_from_slice(&[address, !address, address, !address]);
+    block
+}
+
+fn decode_value(block: &Block) -> Option<(i32, u8)> {
+    let value = i32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+    let address = block[12];
+    (encode_value(value, address) == *block).then_some((value, address))
+}
+
+// Block check character: the XOR of the UID bytes of a cascade level
+fn bcc(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |bcc, byte| bcc ^ byte)
+}
+
+fn crc_ok(frame: &[u8]) -> bool {
+    let (data, crc) = frame.split_at(frame.len().saturating_sub(2));
+    crc.len() == 2 && crc_a(data) == crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_access_bits() {
+        assert_eq!(access_conditions(&FACTORY_TRAILER), Some([0, 0, 0, 1]));
+        // data blocks written with Key B only, trailer 011
+        let mut trailer = FACTORY_TRAILER;
+        trailer[6..9].copy_from_slice(&[0x78, 0x77, 0x88]);
+        assert_eq!(access_conditions(&trailer), Some([4, 4, 4, 3]));
+        trailer[6] ^= 1;
+        assert_eq!(access_conditions(&trailer), None);
+    }
+
+    #[test]
+    fn keeps_value_blocks_redundant() {
+        let block = encode_value(-5, 9);
+        assert_eq!(decode_value(&block), Some((-5, 9)));
+        let mut broken = block;
+        broken[9] ^= 1;
+        assert_eq!(decode_value(&broken), None);
+    }
+
+    #[test]
+    fn selects_a_7_byte_uid_in_two_cascade_levels() {
+        let uid = [0x04, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+        let mut chip = Emulator::new(Card::double(uid));
+        let mut rfid = connect(&mut chip);
+        let atqa = rfid.reqa().unwrap();
+        let selected = rfid.select(&atqa).unwrap();
+        assert_eq!(selected.as_bytes(), uid);
+        assert_eq!(chip.card().unwrap().state(), State::Active);
+    }
+}
@@ -24,6 +24,8 @@ use embedded_hal_bus::spi::ExclusiveDevice;
 use esp_hal::delay::Delay;
 use mfrc522::Mfrc522;
 use mfrc522::comm::blocking::spi::SpiInterface;
+use rfid_read::card::{self, Selected};
+use rfid_read::reader;
 
 use esp_println::{self as _, print, println};
 
@@ -76,32 +78,33 @@ async fn main(spawner: Spawner) -> ! {
         if let Ok(atqa) = rfid.reqa() {
             println!("Got atqa");
             Timer::after(Duration::from_millis(50)).await;
-            if let Ok(uid) = rfid.select(&atqa) {
-                println!("Reading sector: {}", sector_num);
-                read_sector(&uid, sector_num, &mut rfid);
-                rfid.hlta().unwrap();
-                rfid.stop_crypto1().unwrap();
+            if let Ok(selected) = card::select(&atqa, &mut rfid) {
+                println!("{}", selected.card);
+                if selected.card.is_classic() {
+                    println!("Reading sector: {}", sector_num);
+                    if let Err(e) = read_sector(&selected, sector_num, &mut rfid) {
+                        println!("{}", e);
+                    }
+                } else {
+                    println!("{} has no MIFARE Classic sectors to read", selected.card);
+                }
+                reader::release(&mut rfid);
             }
         }
     }
 }
 
 fn read_sector<E, COMM: mfrc522::comm::Interface<Error = E>>(
-    uid: &mfrc522::Uid,
+    selected: &Selected,
     sector: u8,
     rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) {
+) -> Result<(), reader::Error> {
     const AUTH_KEY: [u8; 6] = [0xFF; 6];
 
-    let block_offset = sector * 4;
-    rfid.mf_authenticate(uid, block_offset, &AUTH_KEY)
-        .map_err(|_| "Auth failed")
-        .unwrap();
-
-    for abs_block in block_offset..block_offset + 4 {
-        let data = rfid.mf_read(abs_block).map_err(|_| "Read failed").unwrap();
+    for data in reader::read_sector(&selected.uid, sector, &AUTH_KEY, rfid)? {
         print_hex_bytes(&data);
     }
+    Ok(())
 }
 
 fn print_hex_bytes(data: &[u8]) {
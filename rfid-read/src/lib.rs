@@ -1 +1,3 @@
 #![no_std]
+pub use rfid_common::card;
+pub use rfid_common::reader;
@@ -6,6 +6,8 @@
     holding buffers for the duration of a data transfer."
 )]
 
+use core::cell::RefCell;
+
 use defmt::info;
 use embassy_executor::Spawner;
 use embassy_time::{Duration, Timer};
@@ -14,7 +16,7 @@ use esp_hal::timer::timg::TimerGroup;
 use esp_println as _;
 
 // SPI
-use esp_hal::gpio::{Level, Output, OutputConfig};
+use esp_hal::gpio::{Input, InputConfig, Level, Output, OutputConfig, Pull};
 use esp_hal::spi;
 use esp_hal::spi::master::Spi;
 use esp_hal::time::Rate;
@@ -24,6 +26,8 @@ use embedded_hal_bus::spi::ExclusiveDevice;
 use esp_hal::delay::Delay;
 use mfrc522::Mfrc522;
 use mfrc522::comm::blocking::spi::SpiInterface;
+use rfid_uid::card;
+use rfid_uid::presence::{self, Detector, Shared};
 
 use esp_println::{self as _, print, println};
 
@@ -66,18 +70,39 @@ async fn main(spawner: Spawner) -> ! {
     let sd_cs = Output::new(peripherals.GPIO5, Level::High, OutputConfig::default());
 
     let delay = Delay::new();
-    let spi_dev = ExclusiveDevice::new(spi_bus, sd_cs, delay).unwrap();
+    let spi_dev = RefCell::new(ExclusiveDevice::new(spi_bus, sd_cs, delay).unwrap());
 
-    let spi_interface = SpiInterface::new(spi_dev);
+    let spi_interface = SpiInterface::new(Shared::new(&spi_dev));
     let mut rfid = Mfrc522::new(spi_interface).init().unwrap();
 
+    // The reader sleeps between short field bursts and its IRQ pin tells
+    // when a card answers
+    let irq = Input::new(
+        peripherals.GPIO21,
+        InputConfig::default().with_pull(Pull::Up),
+    );
+    let mut detector = Detector::new(Shared::new(&spi_dev), irq, presence::Config::default());
+
     loop {
+        if let Err(e) = detector.wait_for_card().await {
+            println!("Card detection failed: {:?}", e);
+            continue;
+        }
         if let Ok(atqa) = rfid.reqa() {
             println!("Answer To reQuest code A");
             Timer::after(Duration::from_millis(50)).await;
-            if let Ok(uid) = rfid.select(&atqa) {
-                print_hex_bytes(uid.as_bytes());
-                Timer::after(Duration::from_millis(500)).await;
+            match card::select(&atqa, &mut rfid) {
+                Ok(selected) => {
+                    println!("{}", selected.card);
+                    println!(
+                        "ATQA {:02x}{:02x}, SAK {:02x}",
+                        selected.atqa[1], selected.atqa[0], selected.sak
+                    );
+                    print!("UID ({} bytes): ", selected.uid.as_bytes().len());
+                    print_hex_bytes(selected.uid.as_bytes());
+                    Timer::after(Duration::from_millis(500)).await;
+                }
+                Err(e) => println!("Select failed: {}", e),
             }
         }
     }
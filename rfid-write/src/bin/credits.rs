@@ -0,0 +1,127 @@
+#![no_std]
+#![no_main]
+#![deny(
+    clippy::mem_forget,
+    reason = "mem::forget is generally not safe to do with esp_hal types, especially those \
+    holding buffers for the duration of a data transfer."
+)]
+
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
+use esp_hal::clock::CpuClock;
+use esp_hal::timer::timg::TimerGroup;
+use esp_println as _;
+
+// SPI
+use esp_hal::gpio::{Level, Output, OutputConfig};
+use esp_hal::spi;
+use esp_hal::spi::master::Spi;
+use esp_hal::time::Rate;
+
+// RFID Reader
+use embedded_hal_bus::spi::ExclusiveDevice;
+use esp_hal::delay::Delay;
+use mfrc522::Mfrc522;
+use mfrc522::comm::blocking::spi::SpiInterface;
+use rfid_write::value::{self, Error};
+
+use esp_println::{self as _, println};
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+// This creates a default app-descriptor required by the esp-idf bootloader.
+// For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
+    // generator version: 1.0.0
+
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    info!("Embassy initialized!");
+
+    // TODO: Spawn some tasks
+    let _ = spawner;
+
+    let spi_bus = Spi::new(
+        peripherals.SPI2,
+        spi::master::Config::default()
+            .with_frequency(Rate::from_mhz(5))
+            .with_mode(spi::Mode::_0),
+    )
+    .unwrap()
+    .with_sck(peripherals.GPIO18)
+    .with_mosi(peripherals.GPIO23)
+    .with_miso(peripherals.GPIO19)
+    .into_async();
+
+    let sd_cs = Output::new(peripherals.GPIO5, Level::High, OutputConfig::default());
+
+    let delay = Delay::new();
+    let spi_dev = ExclusiveDevice::new(spi_bus, sd_cs, delay).unwrap();
+
+    let spi_interface = SpiInterface::new(spi_dev);
+    let mut rfid = Mfrc522::new(spi_interface).init().unwrap();
+
+    // sector 4, block 1 holds the balance
+    const SECTOR: u8 = 4;
+    const BLOCK: u8 = SECTOR * 4 + 1;
+    const AUTH_KEY: [u8; 6] = [0xFF; 6];
+    const TOP_UP: i32 = 100;
+    const PRICE: i32 = 5;
+
+    loop {
+        if let Ok(atqa) = rfid.reqa() {
+            println!("Got atqa");
+            Timer::after(Duration::from_millis(50)).await;
+            if let Ok(uid) = rfid.select(&atqa) {
+                if rfid.mf_authenticate(&uid, SECTOR * 4, &AUTH_KEY).is_err() {
+                    println!("Auth failed");
+                } else {
+                    match charge(BLOCK, TOP_UP, PRICE, &mut rfid) {
+                        Ok(balance) => println!("Balance: {}", balance),
+                        Err(e) => println!("Payment failed: {:?}", e),
+                    }
+                }
+                let _ = rfid.hlta();
+                let _ = rfid.stop_crypto1();
+            }
+        }
+    }
+}
+
+/// Take `price` from the balance in `block`, giving new cards `top_up` to
+/// start with. Returns the balance left.
+fn charge<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    block: u8,
+    top_up: i32,
+    price: i32,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<i32, Error<E>> {
+    let balance = match value::read(rfid, block) {
+        Ok(balance) => balance.value,
+        Err(Error::NotValueBlock) => {
+            println!("New card, topping up with {}", top_up);
+            value::format(rfid, block, top_up)?;
+            top_up
+        }
+        Err(e) => return Err(e),
+    };
+    if balance < price {
+        println!("Not enough credit");
+        return Ok(balance);
+    }
+
+    value::add(rfid, block, -price)?;
+    // the card did the arithmetic, check what it wrote
+    Ok(value::read(rfid, block)?.value)
+}
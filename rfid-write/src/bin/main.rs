@@ -24,6 +24,7 @@ use embedded_hal_bus::spi::ExclusiveDevice;
 use esp_hal::delay::Delay;
 use mfrc522::Mfrc522;
 use mfrc522::comm::blocking::spi::SpiInterface;
+use rfid_write::reader::{self, Error};
 
 use esp_println::{self as _, print, println};
 
@@ -79,21 +80,22 @@ async fn main(spawner: Spawner) -> ! {
     ];
 
     loop {
-        if let Ok(atqa) = rfid.reqa() {
-            println!("Got atqa");
-            Timer::after(Duration::from_millis(50)).await;
-            if let Ok(uid) = rfid.select(&atqa) {
-                println!("\r\n----Before Write----");
-                read_sector(&uid, target_sector, &mut rfid);
-
-                write_block(&uid, target_sector, rel_block, DATA, &mut rfid);
-
-                println!("\r\n----After Write----");
-                read_sector(&uid, target_sector, &mut rfid);
-                rfid.hlta().unwrap();
-                rfid.stop_crypto1().unwrap();
-            }
+        let result = reader::with_card(&mut rfid, |uid, rfid| {
+            println!("\r\n----Before Write----");
+            read_sector(uid, target_sector, rfid)?;
+
+            write_block(uid, target_sector, rel_block, DATA, rfid)?;
+
+            println!("\r\n----After Write----");
+            read_sector(uid, target_sector, rfid)
+        });
+        match result {
+            Ok(()) => {}
+            // nothing in the field
+            Err(Error::NoCard) => {}
+            Err(e) => println!("{}", e),
         }
+        Timer::after(Duration::from_millis(50)).await;
     }
 }
 
@@ -103,37 +105,26 @@ fn write_block<E, COMM: mfrc522::comm::Interface<Error = E>>(
     rel_block: u8,
     data: [u8; 16],
     rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) {
+) -> Result<(), Error> {
     const AUTH_KEY: [u8; 6] = [0xFF; 6];
 
     let block_offset = sector * 4;
     let abs_block = block_offset + rel_block;
 
-    rfid.mf_authenticate(uid, block_offset, &AUTH_KEY)
-        .map_err(|_| "Auth failed")
-        .unwrap();
-
-    rfid.mf_write(abs_block, data)
-        .map_err(|_| "Write failed")
-        .unwrap();
+    reader::write_block(uid, abs_block, &AUTH_KEY, data, rfid)
 }
 
 fn read_sector<E, COMM: mfrc522::comm::Interface<Error = E>>(
     uid: &mfrc522::Uid,
     sector: u8,
     rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
-) {
+) -> Result<(), Error> {
     const AUTH_KEY: [u8; 6] = [0xFF; 6];
 
-    let block_offset = sector * 4;
-    rfid.mf_authenticate(uid, block_offset, &AUTH_KEY)
-        .map_err(|_| "Auth failed")
-        .unwrap();
-
-    for abs_block in block_offset..block_offset + 4 {
-        let data = rfid.mf_read(abs_block).map_err(|_| "Read failed").unwrap();
+    for data in reader::read_sector(uid, sector, &AUTH_KEY, rfid)? {
         print_hex_bytes(&data);
     }
+    Ok(())
 }
 
 fn print_hex_bytes(data: &[u8]) {
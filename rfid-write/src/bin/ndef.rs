@@ -0,0 +1,302 @@
+#![no_std]
+#![no_main]
+#![deny(
+    clippy::mem_forget,
+    reason = "mem::forget is generally not safe to do with esp_hal types, especially those \
+    holding buffers for the duration of a data transfer."
+)]
+
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
+use esp_hal::clock::CpuClock;
+use esp_hal::timer::timg::TimerGroup;
+use esp_println as _;
+
+// SPI
+use esp_hal::gpio::{Level, Output, OutputConfig};
+use esp_hal::spi;
+use esp_hal::spi::master::Spi;
+use esp_hal::time::Rate;
+
+// RFID Reader
+use embedded_hal_bus::spi::ExclusiveDevice;
+use esp_hal::delay::Delay;
+use mfrc522::Mfrc522;
+use mfrc522::comm::blocking::spi::SpiInterface;
+use mfrc522::{Type, Uid};
+use rfid_write::mad::{self, Mad};
+use rfid_write::ndef::{self, Record};
+use rfid_write::tlv::{self, CapabilityContainer};
+
+use esp_println::{self as _, println};
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+// This creates a default app-descriptor required by the esp-idf bootloader.
+// For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
+    // generator version: 1.0.0
+
+    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    let peripherals = esp_hal::init(config);
+
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    info!("Embassy initialized!");
+
+    // TODO: Spawn some tasks
+    let _ = spawner;
+
+    let spi_bus = Spi::new(
+        peripherals.SPI2,
+        spi::master::Config::default()
+            .with_frequency(Rate::from_mhz(5))
+            .with_mode(spi::Mode::_0),
+    )
+    .unwrap()
+    .with_sck(peripherals.GPIO18)
+    .with_mosi(peripherals.GPIO23)
+    .with_miso(peripherals.GPIO19)
+    .into_async();
+
+    let sd_cs = Output::new(peripherals.GPIO5, Level::High, OutputConfig::default());
+
+    let delay = Delay::new();
+    let spi_dev = ExclusiveDevice::new(spi_bus, sd_cs, delay).unwrap();
+
+    let spi_interface = SpiInterface::new(spi_dev);
+    let mut rfid = Mfrc522::new(spi_interface).init().unwrap();
+
+    // A phone that touches the tag opens the link
+    let mut message = [0u8; 64];
+    let len = ndef::encode(
+        &[
+            Record::uri("https://implrust.com"),
+            Record::text("en", "Written by an ESP32"),
+        ],
+        &mut message,
+    )
+    .unwrap();
+    let message = &message[..len];
+
+    let mut area = [0u8; AREA_LEN];
+    loop {
+        if let Ok(atqa) = rfid.reqa() {
+            println!("Got atqa");
+            Timer::after(Duration::from_millis(50)).await;
+            if let Ok(uid) = rfid.select(&atqa) {
+                let card = uid.get_type();
+                let written = match card {
+                    Type::MifareUL => write_type2(message, &mut area, &mut rfid),
+                    Type::Mifare1k => write_classic(&uid, message, &mut area, &mut rfid),
+                    _ => Err("NDEF needs an NTAG/Ultralight or a Classic 1K card"),
+                };
+                match written {
+                    Ok(()) => println!("NDEF message written to {:?}", card),
+                    Err(e) => println!("{}", e),
+                }
+
+                reselect(&mut rfid);
+                let read = match card {
+                    Type::MifareUL => read_type2(&mut area, &mut rfid),
+                    Type::Mifare1k => read_classic(&uid, &mut area, &mut rfid),
+                    _ => Err("Nothing to read"),
+                };
+                match read {
+                    Ok(len) => print_message(&area[..len]),
+                    Err(e) => println!("{}", e),
+                }
+
+                let _ = rfid.hlta();
+                let _ = rfid.stop_crypto1();
+            }
+        }
+    }
+}
+
+// Largest data area: 888 bytes on an NTAG216, 720 on a Classic 1K
+const AREA_LEN: usize = 888;
+const BLOCK_LEN: usize = 16;
+const SECTOR_DATA_LEN: usize = 3 * BLOCK_LEN;
+const TRANSPORT_KEY: [u8; 6] = [0xFF; 6];
+
+fn print_message(area: &[u8]) {
+    let tlv = match tlv::find_ndef(area) {
+        Ok(tlv) => tlv,
+        Err(e) => {
+            println!("No NDEF message: {:?}", e);
+            return;
+        }
+    };
+    println!("NDEF message, {} bytes:", tlv.value.len());
+    for record in ndef::records(tlv.value) {
+        match record {
+            Ok(record) => println!("  {}", record),
+            Err(e) => println!("  bad record: {:?}", e),
+        }
+    }
+}
+
+/// Halt the card and wake it up again, after a failed authentication or to
+/// start over with another sector
+fn reselect<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) {
+    let _ = rfid.hlta();
+    let _ = rfid.stop_crypto1();
+    if let Ok(atqa) = rfid.wupa() {
+        let _ = rfid.select(&atqa);
+    }
+}
+
+// NTAG21x and Ultralight: no keys, 4-byte pages. A read returns four
+// pages; a write of 16 bytes only stores the first four.
+
+fn capability_container<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<CapabilityContainer, &'static str> {
+    let pages = rfid.mf_read(tlv::CC_PAGE).map_err(|_| "Read failed")?;
+    CapabilityContainer::decode(&[pages[0], pages[1], pages[2], pages[3]])
+        .map_err(|_| "Tag is not NDEF formatted")
+}
+
+fn write_type2<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    message: &[u8],
+    area: &mut [u8; AREA_LEN],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), &'static str> {
+    let cc = capability_container(rfid)?;
+    if !cc.writable {
+        return Err("Tag is read-only");
+    }
+    let area = &mut area[..cc.data_size.min(AREA_LEN)];
+
+    // lock and memory control TLVs at the start of the area must stay
+    let head = rfid.mf_read(tlv::DATA_PAGE).map_err(|_| "Read failed")?;
+    area.fill(0);
+    let head_len = area.len().min(BLOCK_LEN);
+    area[..head_len].copy_from_slice(&head[..head_len]);
+    let offset = tlv::ndef_offset(area);
+    let used = tlv::write_ndef(message, &mut area[offset..]).map_err(|_| "Message too long")?;
+
+    for (i, page) in area[..offset + used].chunks(tlv::PAGE_LEN).enumerate() {
+        let mut data = [0u8; BLOCK_LEN];
+        data[..page.len()].copy_from_slice(page);
+        rfid.mf_write(tlv::DATA_PAGE + i as u8, data)
+            .map_err(|_| "Write failed")?;
+    }
+    Ok(())
+}
+
+fn read_type2<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    area: &mut [u8; AREA_LEN],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<usize, &'static str> {
+    let len = capability_container(rfid)?.data_size.min(AREA_LEN);
+    for (i, chunk) in area[..len].chunks_mut(BLOCK_LEN).enumerate() {
+        let page = tlv::DATA_PAGE + (i * BLOCK_LEN / tlv::PAGE_LEN) as u8;
+        let data = rfid.mf_read(page).map_err(|_| "Read failed")?;
+        chunk.copy_from_slice(&data[..chunk.len()]);
+    }
+    Ok(len)
+}
+
+// MIFARE Classic 1K: the directory in sector 0, the message in the data
+// blocks of the sectors after it
+
+/// The directory, if the card has one
+fn read_mad<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Option<Mad> {
+    if rfid.mf_authenticate(uid, 0, &mad::MAD_KEY_A).is_err() {
+        reselect(rfid);
+        return None;
+    }
+    let blocks = [rfid.mf_read(1).ok()?, rfid.mf_read(2).ok()?];
+    reselect(rfid);
+    Mad::decode(&blocks).ok()
+}
+
+fn write_classic<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    message: &[u8],
+    area: &mut [u8; AREA_LEN],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<(), &'static str> {
+    let area = &mut area[..mad::SECTORS * SECTOR_DATA_LEN];
+    let used = tlv::write_ndef(message, area).map_err(|_| "Message too long")?;
+    let sectors = used.div_ceil(SECTOR_DATA_LEN);
+
+    match read_mad(uid, rfid) {
+        Some(mad) if mad.ndef_sectors().count() >= sectors => {}
+        // changing an existing directory needs key B
+        Some(_) => return Err("The directory has too few NDEF sectors"),
+        None => {
+            // a new card: sector 0 still opens with the transport key
+            rfid.mf_authenticate(uid, 0, &TRANSPORT_KEY)
+                .map_err(|_| "Auth failed on sector 0")?;
+            let [block_1, block_2] = Mad::ndef(sectors).encode();
+            rfid.mf_write(1, block_1).map_err(|_| "Write failed")?;
+            rfid.mf_write(2, block_2).map_err(|_| "Write failed")?;
+            rfid.mf_write(3, mad::MAD_TRAILER)
+                .map_err(|_| "Write failed")?;
+            reselect(rfid);
+        }
+    }
+
+    for (i, data) in area.chunks(SECTOR_DATA_LEN).take(sectors).enumerate() {
+        let first_block = (i as u8 + 1) * 4;
+        // sectors already given to NDEF open with the NDEF key
+        let new = if rfid
+            .mf_authenticate(uid, first_block, &mad::NDEF_KEY_A)
+            .is_ok()
+        {
+            false
+        } else {
+            reselect(rfid);
+            rfid.mf_authenticate(uid, first_block, &TRANSPORT_KEY)
+                .map_err(|_| "Auth failed")?;
+            true
+        };
+        for (block, data) in data.chunks(BLOCK_LEN).enumerate() {
+            rfid.mf_write(first_block + block as u8, data.try_into().unwrap())
+                .map_err(|_| "Write failed")?;
+        }
+        if new {
+            rfid.mf_write(first_block + 3, mad::NDEF_TRAILER)
+                .map_err(|_| "Write failed")?;
+        }
+        reselect(rfid);
+    }
+    Ok(())
+}
+
+fn read_classic<E, COMM: mfrc522::comm::Interface<Error = E>>(
+    uid: &Uid,
+    area: &mut [u8; AREA_LEN],
+    rfid: &mut Mfrc522<COMM, mfrc522::Initialized>,
+) -> Result<usize, &'static str> {
+    let mad = read_mad(uid, rfid).ok_or("No application directory")?;
+    let mut len = 0;
+    for sector in mad.ndef_sectors() {
+        let first_block = sector * 4;
+        rfid.mf_authenticate(uid, first_block, &mad::NDEF_KEY_A)
+            .map_err(|_| "Auth failed")?;
+        for block in first_block..first_block + 3 {
+            let data = rfid.mf_read(block).map_err(|_| "Read failed")?;
+            area[len..len + BLOCK_LEN].copy_from_slice(&data);
+            len += BLOCK_LEN;
+        }
+        reselect(rfid);
+    }
+    Ok(len)
+}
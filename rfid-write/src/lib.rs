@@ -1 +1,7 @@
 #![no_std]
+pub use rfid_common::mad;
+pub use rfid_common::ndef;
+pub use rfid_common::payload;
+pub use rfid_common::reader;
+pub use rfid_common::tlv;
+pub use rfid_common::value;
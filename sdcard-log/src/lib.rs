@@ -0,0 +1,9 @@
+//! Sensor records and the rotating log files they go to on the SD card.
+//! Nothing here touches esp-hal, so the tests run on the host with
+//! `cargo test`.
+//!
+//! - [`record`]: a sensor reading, as CSV or InfluxDB line protocol
+//! - [`logger`]: buffered writes to log files rotated by size or by day
+#![no_std]
+pub mod logger;
+pub mod record;
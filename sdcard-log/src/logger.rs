@@ -0,0 +1,654 @@
+//! Rotating log files on the SD card.
+//!
+//! [`Logger`] appends [`Record`]s to files in the root directory. Lines
+//! collect in RAM and go to the card only when the buffer is full or its
+//! oldest line is `max_age` old, so the card rewrites its last block
+//! once per buffer instead of once per record. The age is checked by
+//! [`Logger::poll`], which belongs in the same timer loop as the sensor
+//! readings. [`Logger::flush`] writes the buffer and the directory entry
+//! out straight away; call it when the power is about to go.
+//!
+//! A file is closed once it reaches `max_size`, and with daily rotation at
+//! midnight UTC as well. The names fit in 8.3:
+//!
+//! ```text
+//! LOG00000.CSV LOG00001.CSV ...   by size
+//! 26101900.CSV 26101901.CSV ...   daily: YYMMDD, then the part of the day
+//! ```
+//!
+//! After a restart the logger appends to the newest file it finds.
+
+use core::fmt::{self, Write};
+
+use embassy_time::Duration;
+use embedded_sdmmc::{
+    BlockDevice, Mode, RawDirectory, RawFile, ShortFileName, TimeSource, VolumeIdx, VolumeManager,
+};
+
+use crate::record::{Format, Record};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Size,
+    Daily,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub format: Format,
+    pub rotation: Rotation,
+    /// Size at which a file is closed and the next one started
+    pub max_size: u32,
+    /// Longest a line waits in RAM
+    pub max_age: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            format: Format::Csv,
+            rotation: Rotation::Daily,
+            max_size: 1024 * 1024,
+            max_age: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error<E: fmt::Debug> {
+    Sd(embedded_sdmmc::Error<E>),
+    /// The record doesn't fit in the buffer
+    TooLong,
+}
+
+impl<E: fmt::Debug> From<embedded_sdmmc::Error<E>> for Error<E> {
+    fn from(e: embedded_sdmmc::Error<E>) -> Self {
+        Error::Sd(e)
+    }
+}
+
+// Which file a record goes to. Sorts in the order the files were written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Slot {
+    /// YYMMDD, 0 when rotating by size only
+    day: u32,
+    index: u32,
+}
+
+impl Rotation {
+    fn day(self, time_us: u64) -> u32 {
+        let Rotation::Daily = self else {
+            return 0;
+        };
+        let Ok(time) = jiff::Timestamp::from_microsecond(time_us as i64) else {
+            return 0;
+        };
+        let date = jiff::tz::Offset::UTC.to_datetime(time).date();
+        (date.year() % 100) as u32 * 10000 + date.month() as u32 * 100 + date.day() as u32
+    }
+
+    // The last file takes everything once the numbers run out
+    fn last_index(self) -> u32 {
+        match self {
+            Rotation::Size => 99_999,
+            Rotation::Daily => 99,
+        }
+    }
+
+    fn name(self, format: Format, slot: Slot) -> Buffer<12> {
+        let mut name = Buffer::new();
+        let _ = match self {
+            Rotation::Size => write!(name, "LOG{:05}.{}", slot.index, format.extension()),
+            Rotation::Daily => write!(
+                name,
+                "{:06}{:02}.{}",
+                slot.day,
+                slot.index,
+                format.extension()
+            ),
+        };
+        name
+    }
+
+    fn parse(self, format: Format, name: &ShortFileName) -> Option<Slot> {
+        if name.extension() != format.extension().as_bytes() {
+            return None;
+        }
+        let base = name.base_name();
+        match self {
+            Rotation::Size => Some(Slot {
+                day: 0,
+                index: number(base.strip_prefix(b"LOG")?, 5)?,
+            }),
+            Rotation::Daily if base.len() == 8 => Some(Slot {
+                day: number(&base[..6], 6)?,
+                index: number(&base[6..], 2)?,
+            }),
+            Rotation::Daily => None,
+        }
+    }
+}
+
+// `digits` decimal digits
+fn number(bytes: &[u8], digits: usize) -> Option<u32> {
+    if bytes.len() != digits || !bytes.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(
+        bytes
+            .iter()
+            .fold(0, |n, digit| n * 10 + (digit - b'0') as u32),
+    )
+}
+
+/// Text collected in RAM
+pub struct Buffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Buffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only ever filled from `str`s
+        core::str::from_utf8(self.as_bytes()).unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
+impl<const N: usize> Default for Buffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fails without writing anything when `s` doesn't fit
+impl<const N: usize> Write for Buffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > N {
+            return Err(fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Appends records to the log files, buffering up to `N` bytes
+pub struct Logger<'v, D: BlockDevice, T: TimeSource, const N: usize> {
+    volume_mgr: &'v VolumeManager<D, T>,
+    root: RawDirectory,
+    config: Config,
+    slot: Slot,
+    file: Option<RawFile>,
+    /// Length of the file with the buffered lines
+    size: u32,
+    buffer: Buffer<N>,
+    /// Time of the oldest buffered line
+    oldest: Option<u64>,
+}
+
+impl<'v, D, T, const N: usize> Logger<'v, D, T, N>
+where
+    D: BlockDevice,
+    T: TimeSource,
+    D::Error: fmt::Debug,
+{
+    /// Log to the first volume of the card, after the newest file there
+    pub fn new(
+        volume_mgr: &'v VolumeManager<D, T>,
+        config: Config,
+    ) -> Result<Self, Error<D::Error>> {
+        let volume = volume_mgr.open_raw_volume(VolumeIdx(0))?;
+        let root = volume_mgr.open_root_dir(volume)?;
+        let mut newest = None;
+        volume_mgr.iterate_dir(root, |entry| {
+            let slot = config.rotation.parse(config.format, &entry.name);
+            newest = newest.max(slot);
+        })?;
+
+        Ok(Self {
+            volume_mgr,
+            root,
+            config,
+            slot: newest.unwrap_or(Slot { day: 0, index: 0 }),
+            file: None,
+            size: 0,
+            buffer: Buffer::new(),
+            oldest: None,
+        })
+    }
+
+    fn file_name(&self) -> Buffer<12> {
+        self.config.rotation.name(self.config.format, self.slot)
+    }
+
+    pub fn log(&mut self, record: &Record<'_>) -> Result<(), Error<D::Error>> {
+        let day = self.config.rotation.day(record.time_us);
+        if day != self.slot.day {
+            self.rotate(Slot { day, index: 0 })?;
+        }
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if self.size >= self.config.max_size && self.slot.index < self.config.rotation.last_index()
+        {
+            self.rotate(Slot {
+                index: self.slot.index + 1,
+                ..self.slot
+            })?;
+            self.open()?;
+        }
+
+        let format = self.config.format;
+        // a new file starts with the header
+        if let Some(header) = record.header(format).filter(|_| self.size == 0) {
+            self.push(|buffer| write!(buffer, "{}", header))?;
+        }
+        self.push(|buffer| write!(buffer, "{}", record.line(format)))?;
+        self.oldest.get_or_insert(record.time_us);
+        Ok(())
+    }
+
+    /// Flush once the oldest buffered line is `max_age` old at `now_us`
+    pub fn poll(&mut self, now_us: u64) -> Result<(), Error<D::Error>> {
+        match self.oldest {
+            Some(oldest) if now_us.saturating_sub(oldest) >= self.config.max_age.as_micros() => {
+                self.flush()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the buffered lines to the card, and the new length of the file
+    /// to its directory entry
+    pub fn flush(&mut self) -> Result<(), Error<D::Error>> {
+        let Some(file) = self.file else {
+            return Ok(());
+        };
+        if !self.buffer.is_empty() {
+            self.volume_mgr.write(file, self.buffer.as_bytes())?;
+            self.buffer.truncate(0);
+        }
+        self.oldest = None;
+        self.volume_mgr.flush_file(file)?;
+        Ok(())
+    }
+
+    /// Flush and close the file; the next record opens it again
+    pub fn close(&mut self) -> Result<(), Error<D::Error>> {
+        self.flush()?;
+        if let Some(file) = self.file.take() {
+            self.volume_mgr.close_file(file)?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self, slot: Slot) -> Result<(), Error<D::Error>> {
+        self.close()?;
+        self.slot = slot;
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<(), Error<D::Error>> {
+        let name = self.file_name();
+        let file = self.volume_mgr.open_file_in_dir(
+            self.root,
+            name.as_str(),
+            Mode::ReadWriteCreateOrAppend,
+        )?;
+        self.size = self.volume_mgr.file_length(file)?;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    // Add to the buffer what `write` writes, making room for it first if it
+    // doesn't fit
+    fn push(
+        &mut self,
+        write: impl Fn(&mut Buffer<N>) -> fmt::Result,
+    ) -> Result<(), Error<D::Error>> {
+        let start = self.buffer.len();
+        if write(&mut self.buffer).is_err() {
+            self.buffer.truncate(start);
+            self.flush()?;
+            if write(&mut self.buffer).is_err() {
+                self.buffer.truncate(0);
+                return Err(Error::TooLong);
+            }
+        }
+        self.size += (self.buffer.len() - start) as u32;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::cell::{Cell, RefCell};
+    use std::collections::BTreeMap;
+    use std::string::String;
+    use std::vec::Vec;
+
+    use embedded_sdmmc::{Block, BlockCount, BlockIdx, Timestamp};
+
+    use super::*;
+    use crate::record::Value;
+
+    // 2026-10-19 13:03:40 UTC
+    const TIME: u64 = 1_792_415_020_000_000;
+    const SECOND: u64 = 1_000_000;
+
+    // An SD card holding one FAT16 partition, blocks of zeros until written
+    struct Card {
+        blocks: RefCell<BTreeMap<u32, [u8; 512]>>,
+        writes: Cell<usize>,
+    }
+
+    impl Card {
+        // MBR, boot sector, FAT, root directory and 4200 clusters of a block
+        const CLUSTERS: u32 = 4200;
+        const FAT_BLOCKS: u16 = 17;
+        const ROOT_ENTRIES: u16 = 512;
+
+        fn new() -> Self {
+            let volume_blocks = 1 + Self::FAT_BLOCKS as u32 + 32 + Self::CLUSTERS;
+            let mut mbr = [0u8; 512];
+            // one FAT16 partition from block 1
+            mbr[446 + 4] = 0x06;
+            mbr[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+            mbr[446 + 12..446 + 16].copy_from_slice(&volume_blocks.to_le_bytes());
+            mbr[510..].copy_from_slice(&[0x55, 0xAA]);
+
+            let mut boot = [0u8; 512];
+            boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+            boot[13] = 1;
+            boot[14..16].copy_from_slice(&1u16.to_le_bytes());
+            boot[16] = 1;
+            boot[17..19].copy_from_slice(&Self::ROOT_ENTRIES.to_le_bytes());
+            boot[19..21].copy_from_slice(&(volume_blocks as u16).to_le_bytes());
+            boot[21] = 0xF8;
+            boot[22..24].copy_from_slice(&Self::FAT_BLOCKS.to_le_bytes());
+            boot[43..54].copy_from_slice(b"LOGGER     ");
+            boot[510..].copy_from_slice(&[0x55, 0xAA]);
+
+            // the two reserved entries of the FAT
+            let mut fat = [0u8; 512];
+            fat[..4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]);
+
+            Self {
+                blocks: RefCell::new(BTreeMap::from([(0, mbr), (1, boot), (2, fat)])),
+                writes: Cell::new(0),
+            }
+        }
+    }
+
+    impl BlockDevice for &Card {
+        type Error = ();
+
+        fn read(&self, blocks: &mut [Block], start: BlockIdx) -> Result<(), ()> {
+            let stored = self.blocks.borrow();
+            for (block, index) in blocks.iter_mut().zip(start.0..) {
+                block.contents = stored.get(&index).copied().unwrap_or([0; 512]);
+            }
+            Ok(())
+        }
+
+        fn write(&self, blocks: &[Block], start: BlockIdx) -> Result<(), ()> {
+            let mut stored = self.blocks.borrow_mut();
+            for (block, index) in blocks.iter().zip(start.0..) {
+                stored.insert(index, block.contents);
+            }
+            self.writes.set(self.writes.get() + 1);
+            Ok(())
+        }
+
+        fn num_blocks(&self) -> Result<BlockCount, ()> {
+            Ok(BlockCount(
+                2 + Card::FAT_BLOCKS as u32 + 32 + Card::CLUSTERS,
+            ))
+        }
+    }
+
+    struct Clock;
+
+    impl TimeSource for Clock {
+        fn get_timestamp(&self) -> Timestamp {
+            Timestamp::from_calendar(2026, 10, 19, 13, 3, 40).unwrap()
+        }
+    }
+
+    fn reading(time_us: u64) -> Record<'static> {
+        Record {
+            time_us,
+            measurement: "ldr",
+            tags: &[],
+            fields: &[("light", Value::Int(2817))],
+        }
+    }
+
+    // Every log file on the card and what it holds, as the card would be
+    // read after a power cut
+    fn files(card: &Card) -> Vec<(String, String)> {
+        let volume_mgr: VolumeManager<_, _> = VolumeManager::new(card, Clock);
+        let volume = volume_mgr.open_volume(VolumeIdx(0)).unwrap();
+        let root = volume.open_root_dir().unwrap();
+        let mut names = Vec::new();
+        root.iterate_dir(|entry| {
+            if !entry.attributes.is_volume() {
+                names.push(entry.name.clone());
+            }
+        })
+        .unwrap();
+        names
+            .into_iter()
+            .map(|name| {
+                let file = root.open_file_in_dir(&name, Mode::ReadOnly).unwrap();
+                let mut text = std::vec![0u8; file.length() as usize];
+                let mut len = 0;
+                while !file.is_eof() {
+                    len += file.read(&mut text[len..]).unwrap();
+                }
+                (std::format!("{}", name), String::from_utf8(text).unwrap())
+            })
+            .collect()
+    }
+
+    fn short(name: &str) -> ShortFileName {
+        ShortFileName::create_from_str(name).unwrap()
+    }
+
+    #[test]
+    fn names_files_in_8_3() {
+        let slot = Slot { day: 0, index: 42 };
+        let name = Rotation::Size.name(Format::Csv, slot);
+        assert_eq!(name.as_str(), "LOG00042.CSV");
+        assert_eq!(
+            Rotation::Size.parse(Format::Csv, &short(name.as_str())),
+            Some(slot)
+        );
+
+        // 2026-10-19 13:03:40 UTC
+        let day = Rotation::Daily.day(1_792_415_020_000_000);
+        assert_eq!(day, 261019);
+        let slot = Slot { day, index: 3 };
+        let name = Rotation::Daily.name(Format::LineProtocol, slot);
+        assert_eq!(name.as_str(), "26101903.LP");
+        assert_eq!(
+            Rotation::Daily.parse(Format::LineProtocol, &short(name.as_str())),
+            Some(slot)
+        );
+        assert_eq!(Rotation::Size.day(1_792_415_020_000_000), 0);
+
+        // other files on the card
+        assert_eq!(
+            Rotation::Daily.parse(Format::Csv, &short("26101903.LP")),
+            None
+        );
+        assert_eq!(
+            Rotation::Daily.parse(Format::Csv, &short("FERRIS.TXT")),
+            None
+        );
+        assert_eq!(
+            Rotation::Size.parse(Format::Csv, &short("LOGBOOK.CSV")),
+            None
+        );
+        assert_eq!(
+            Rotation::Daily.parse(Format::Csv, &short("2610190.CSV")),
+            None
+        );
+
+        // later files sort last
+        assert!(
+            Slot {
+                day: 261019,
+                index: 99
+            } < Slot {
+                day: 261020,
+                index: 0
+            }
+        );
+    }
+
+    #[test]
+    fn buffers_only_what_fits() {
+        let mut buffer = Buffer::<8>::new();
+        assert!(write!(buffer, "{},", 1234).is_ok());
+        assert!(buffer.write_str("hello").is_err());
+        assert_eq!(buffer.as_str(), "1234,");
+        buffer.truncate(2);
+        assert_eq!(buffer.as_bytes(), b"12");
+    }
+
+    #[test]
+    fn buffers_lines_until_flushed() {
+        let card = Card::new();
+        let volume_mgr: VolumeManager<_, _> = VolumeManager::new(&card, Clock);
+        let config = Config {
+            rotation: Rotation::Size,
+            ..Config::default()
+        };
+        let mut logger: Logger<_, _, 256> = Logger::new(&volume_mgr, config).unwrap();
+
+        logger.log(&reading(TIME)).unwrap();
+        // creating the file is all that reached the card
+        let writes = card.writes.get();
+        logger.log(&reading(TIME + SECOND)).unwrap();
+        logger.poll(TIME + 59 * SECOND).unwrap();
+        assert_eq!(card.writes.get(), writes);
+        assert_eq!(files(&card), [("LOG00000.CSV".into(), String::new())]);
+
+        logger.flush().unwrap();
+        assert_eq!(
+            files(&card),
+            [(
+                "LOG00000.CSV".into(),
+                "time,measurement,light\n\
+                 2026-10-19T13:03:40.000Z,ldr,2817\n\
+                 2026-10-19T13:03:41.000Z,ldr,2817\n"
+                    .into()
+            )]
+        );
+    }
+
+    #[test]
+    fn writes_old_lines_when_polled() {
+        let card = Card::new();
+        let volume_mgr: VolumeManager<_, _> = VolumeManager::new(&card, Clock);
+        let mut logger: Logger<_, _, 256> = Logger::new(&volume_mgr, Config::default()).unwrap();
+
+        logger.log(&reading(TIME)).unwrap();
+        logger.poll(TIME + 59 * SECOND).unwrap();
+        assert_eq!(files(&card)[0].1, "");
+        logger.poll(TIME + 60 * SECOND).unwrap();
+        assert_eq!(
+            files(&card),
+            [(
+                "26101900.CSV".into(),
+                "time,measurement,light\n2026-10-19T13:03:40.000Z,ldr,2817\n".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn rotates_by_size_and_resumes() {
+        let card = Card::new();
+        // the header and one line are 57 bytes
+        let config = Config {
+            rotation: Rotation::Size,
+            max_size: 80,
+            ..Config::default()
+        };
+        {
+            let volume_mgr: VolumeManager<_, _> = VolumeManager::new(&card, Clock);
+            let mut logger: Logger<_, _, 256> = Logger::new(&volume_mgr, config).unwrap();
+            for second in 0..3 {
+                logger.log(&reading(TIME + second * SECOND)).unwrap();
+            }
+            logger.close().unwrap();
+        }
+        // a restart carries on in the newest file
+        {
+            let volume_mgr: VolumeManager<_, _> = VolumeManager::new(&card, Clock);
+            let mut logger: Logger<_, _, 256> = Logger::new(&volume_mgr, config).unwrap();
+            logger.log(&reading(TIME + 3 * SECOND)).unwrap();
+            logger.close().unwrap();
+        }
+
+        let files = files(&card);
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["LOG00000.CSV", "LOG00001.CSV"]);
+        for (_, text) in &files {
+            assert!(text.starts_with("time,measurement,light\n"));
+        }
+        assert_eq!(files[0].1.lines().count(), 3);
+        assert_eq!(files[1].1.lines().count(), 3);
+        assert!(files[1].1.ends_with("2026-10-19T13:03:43.000Z,ldr,2817\n"));
+    }
+
+    #[test]
+    fn rotates_daily() {
+        let card = Card::new();
+        let volume_mgr: VolumeManager<_, _> = VolumeManager::new(&card, Clock);
+        let mut logger: Logger<_, _, 256> = Logger::new(&volume_mgr, Config::default()).unwrap();
+        logger.log(&reading(TIME)).unwrap();
+        logger.log(&reading(TIME + 11 * 3600 * SECOND)).unwrap();
+        logger.close().unwrap();
+
+        let files = files(&card);
+        assert_eq!(files[0].0, "26101900.CSV");
+        assert!(files[0].1.ends_with(",ldr,2817\n"));
+        assert_eq!(files[1].0, "26102000.CSV");
+        assert!(files[1].1.ends_with("2026-10-20T00:03:40.000Z,ldr,2817\n"));
+    }
+
+    #[test]
+    fn refuses_records_longer_than_the_buffer() {
+        let card = Card::new();
+        let volume_mgr: VolumeManager<_, _> = VolumeManager::new(&card, Clock);
+        let mut logger: Logger<_, _, 32> = Logger::new(&volume_mgr, Config::default()).unwrap();
+        assert!(matches!(logger.log(&reading(TIME)), Err(Error::TooLong)));
+    }
+}
@@ -0,0 +1,256 @@
+//! Sensor records and the two formats they are logged in.
+//!
+//! A [`Record`] is one reading of a sensor: a measurement name, tags that
+//! say where it comes from and the values it read. CSV files start with a
+//! header taken from the first record, so every record of a file should
+//! have the same tags and fields:
+//!
+//! ```text
+//! time,measurement,room,light,dark
+//! 2026-10-19T13:03:40.123Z,ldr,office,2817,false
+//! ```
+//!
+//! InfluxDB line protocol carries the names on every line, with the time in
+//! nanoseconds:
+//!
+//! ```text
+//! ldr,room=office light=2817i,dark=false 1792415020123000000
+//! ```
+
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    LineProtocol,
+}
+
+impl Format {
+    /// File name extension
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "CSV",
+            Format::LineProtocol => "LP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Float(f32),
+    Int(i64),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Record<'a> {
+    /// Unix time in microseconds
+    pub time_us: u64,
+    pub measurement: &'a str,
+    pub tags: &'a [(&'a str, &'a str)],
+    pub fields: &'a [(&'a str, Value)],
+}
+
+impl<'a> Record<'a> {
+    /// The record as one line of a `format` file, with its line break
+    pub fn line(&self, format: Format) -> Line<'_, 'a> {
+        Line {
+            record: self,
+            format,
+        }
+    }
+
+    /// First line of a `format` file that starts with this record, if the
+    /// format has one
+    pub fn header(&self, format: Format) -> Option<Header<'_, 'a>> {
+        match format {
+            Format::Csv => Some(Header(self)),
+            Format::LineProtocol => None,
+        }
+    }
+}
+
+pub struct Line<'r, 'a> {
+    record: &'r Record<'a>,
+    format: Format,
+}
+
+impl fmt::Display for Line<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.format {
+            Format::Csv => csv_line(self.record, f),
+            Format::LineProtocol => line_protocol(self.record, f),
+        }
+    }
+}
+
+pub struct Header<'r, 'a>(&'r Record<'a>);
+
+impl fmt::Display for Header<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "time,measurement")?;
+        for (key, _) in self.0.tags {
+            write!(f, ",{}", Quoted(key))?;
+        }
+        for (key, _) in self.0.fields {
+            write!(f, ",{}", Quoted(key))?;
+        }
+        writeln!(f)
+    }
+}
+
+// Readings a sensor couldn't take, NaN or infinite, are left empty
+fn csv_line(record: &Record<'_>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match jiff::Timestamp::from_microsecond(record.time_us as i64) {
+        Ok(time) => write!(f, "{:.3}", time)?,
+        Err(_) => write!(f, "{}", record.time_us)?,
+    }
+    write!(f, ",{}", Quoted(record.measurement))?;
+    for (_, value) in record.tags {
+        write!(f, ",{}", Quoted(value))?;
+    }
+    for (_, value) in record.fields {
+        write!(f, ",")?;
+        match *value {
+            Value::Float(x) if !x.is_finite() => {}
+            Value::Float(x) => write!(f, "{}", x)?,
+            Value::Int(n) => write!(f, "{}", n)?,
+            Value::Bool(b) => write!(f, "{}", b)?,
+        }
+    }
+    writeln!(f)
+}
+
+// The protocol has no NaN, such fields are left out, and with them the
+// whole line if no field is left
+fn line_protocol(record: &Record<'_>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut fields = record
+        .fields
+        .iter()
+        .filter(|(_, value)| !matches!(value, Value::Float(x) if !x.is_finite()))
+        .peekable();
+    if fields.peek().is_none() {
+        return Ok(());
+    }
+
+    write!(f, "{}", Escaped(record.measurement, ", "))?;
+    for (key, value) in record.tags {
+        write!(f, ",{}={}", Escaped(key, ",= "), Escaped(value, ",= "))?;
+    }
+    for (i, (key, value)) in fields.enumerate() {
+        let separator = if i == 0 { ' ' } else { ',' };
+        write!(f, "{}{}=", separator, Escaped(key, ",= "))?;
+        match value {
+            Value::Float(x) => write!(f, "{}", x)?,
+            Value::Int(n) => write!(f, "{}i", n)?,
+            Value::Bool(b) => write!(f, "{}", b)?,
+        }
+    }
+    writeln!(f, " {}", record.time_us * 1000)
+}
+
+// A CSV cell, quoted when it holds a separator, a quote or a line break
+struct Quoted<'a>(&'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.0.contains([',', '"', '\n', '\r']) {
+            return f.write_str(self.0);
+        }
+        f.write_str("\"")?;
+        for (i, part) in self.0.split('"').enumerate() {
+            if i > 0 {
+                f.write_str("\"\"")?;
+            }
+            f.write_str(part)?;
+        }
+        f.write_str("\"")
+    }
+}
+
+// A line protocol name, with a backslash before each of `special`
+struct Escaped<'a>(&'a str, &'static str);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            if self.1.contains(c) {
+                f.write_str("\\")?;
+            }
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::string::ToString;
+
+    use super::*;
+
+    // 2026-10-19 13:03:40.123456 UTC
+    const TIME: u64 = 1_792_415_020_123_456;
+
+    const LDR: Record = Record {
+        time_us: TIME,
+        measurement: "ldr",
+        tags: &[("room", "office")],
+        fields: &[("light", Value::Int(2817)), ("dark", Value::Bool(false))],
+    };
+
+    #[test]
+    fn formats_csv() {
+        assert_eq!(
+            LDR.header(Format::Csv).unwrap().to_string(),
+            "time,measurement,room,light,dark\n"
+        );
+        assert_eq!(
+            LDR.line(Format::Csv).to_string(),
+            "2026-10-19T13:03:40.123Z,ldr,office,2817,false\n"
+        );
+
+        let odd = Record {
+            tags: &[("place", "hall, \"north\"")],
+            fields: &[
+                ("temperature", Value::Float(21.5)),
+                ("humidity", Value::Float(f32::NAN)),
+            ],
+            ..LDR
+        };
+        assert_eq!(
+            odd.line(Format::Csv).to_string(),
+            "2026-10-19T13:03:40.123Z,ldr,\"hall, \"\"north\"\"\",21.5,\n"
+        );
+    }
+
+    #[test]
+    fn formats_line_protocol() {
+        assert!(LDR.header(Format::LineProtocol).is_none());
+        assert_eq!(
+            LDR.line(Format::LineProtocol).to_string(),
+            "ldr,room=office light=2817i,dark=false 1792415020123456000\n"
+        );
+
+        let odd = Record {
+            measurement: "air quality",
+            tags: &[("place", "hall, north"), ("a=b", "c")],
+            fields: &[
+                ("humidity", Value::Float(f32::NAN)),
+                ("temperature", Value::Float(21.5)),
+            ],
+            ..LDR
+        };
+        assert_eq!(
+            odd.line(Format::LineProtocol).to_string(),
+            "air\\ quality,place=hall\\,\\ north,a\\=b=c temperature=21.5 1792415020123456000\n"
+        );
+
+        let failed = Record {
+            fields: &[("humidity", Value::Float(f32::NAN))],
+            ..LDR
+        };
+        assert_eq!(failed.line(Format::LineProtocol).to_string(), "");
+    }
+}
@@ -8,6 +8,7 @@
 
 use defmt::info;
 use embassy_executor::Spawner;
+use embassy_futures::select::{Either, select};
 use embassy_time::{Delay, Duration, Timer};
 use esp_hal::clock::CpuClock;
 use esp_hal::timer::timg::TimerGroup;
@@ -15,59 +16,56 @@ use esp_println::{self as _, println};
 
 // SPI
 use embedded_hal_bus::spi::ExclusiveDevice;
-use esp_hal::gpio::{Level, Output, OutputConfig};
+use esp_hal::gpio::{Input, InputConfig, Level, Output, OutputConfig, Pull};
 use esp_hal::spi::{self, master::Spi};
 use esp_hal::time::Rate;
 
 // SD card reader
-use embedded_sdmmc::{SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+use embedded_sdmmc::{SdCard, VolumeManager};
+use sdcard_write::logger::{self, Logger};
+use sdcard_write::record::{Record, Value};
+
+// Light sensor
+use esp_hal::analog::adc::{Adc, AdcConfig, Attenuation};
 
 // For time
 use esp_hal::rtc_cntl::Rtc;
 
+// Network time
+use embassy_net::{DhcpConfig, StackResources};
+use esp_hal::rng::Rng;
+use wifi_clock::clock::{self, Clock, SdTimeSource};
+use wifi_clock::wifi;
+
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
     loop {}
 }
 
+extern crate alloc;
+
 // This creates a default app-descriptor required by the esp-idf bootloader.
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
 esp_bootloader_esp_idf::esp_app_desc!();
 
-struct SdTimeSource {
-    timer: Rtc<'static>,
+// If you are okay with using a nightly compiler, you can use the macro provided by the static_cell crate: https://docs.rs/static_cell/latest/static_cell/macro.make_static.html
+macro_rules! mk_static {
+    ($t:ty,$val:expr) => {{
+        static STATIC_CELL: static_cell::StaticCell<$t> = static_cell::StaticCell::new();
+        #[deny(unused_attributes)]
+        let x = STATIC_CELL.uninit().write(($val));
+        x
+    }};
 }
 
-impl SdTimeSource {
-    fn new(timer: Rtc<'static>) -> Self {
-        Self { timer }
-    }
-
-    fn current_time(&self) -> u64 {
-        self.timer.current_time_us()
-    }
-}
-
-static TZ: jiff::tz::TimeZone = jiff::tz::get!("America/New_York");
-
-impl TimeSource for SdTimeSource {
-    fn get_timestamp(&self) -> Timestamp {
-        let now_us = self.current_time();
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
 
-        // Convert to jiff Time
-        let now = jiff::Timestamp::from_microsecond(now_us as i64).unwrap();
-        let now = now.to_zoned(TZ.clone());
+// Time between two readings of the sensor
+const SAMPLE_PERIOD: Duration = Duration::from_secs(10);
 
-        Timestamp {
-            year_since_1970: (now.year() - 1970).unsigned_abs() as u8,
-            zero_indexed_month: now.month().wrapping_sub(1) as u8,
-            zero_indexed_day: now.day().wrapping_sub(1) as u8,
-            hours: now.hour() as u8,
-            minutes: now.minute() as u8,
-            seconds: now.second() as u8,
-        }
-    }
-}
+// Lines kept in RAM before they go to the card
+const LOG_BUFFER: usize = 4096;
 
 #[esp_rtos::main]
 async fn main(spawner: Spawner) -> ! {
@@ -76,13 +74,40 @@ async fn main(spawner: Spawner) -> ! {
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
 
+    esp_alloc::heap_allocator!(#[unsafe(link_section = ".dram2_uninit")] size: 98767);
+
     let timg0 = TimerGroup::new(peripherals.TIMG0);
     esp_rtos::start(timg0.timer0);
 
     info!("Embassy initialized!");
 
-    // TODO: Spawn some tasks
-    let _ = spawner;
+    // Timer for sdcard, set over WiFi
+    let clock = &*mk_static!(Clock, Clock::new(Rtc::new(peripherals.LPWR)));
+
+    let radio_init = &*mk_static!(
+        esp_radio::Controller<'static>,
+        esp_radio::init().expect("Failed to initialize Wi-Fi/BLE controller")
+    );
+    let (wifi_controller, interfaces) =
+        esp_radio::wifi::new(radio_init, peripherals.WIFI, Default::default())
+            .expect("Failed to initialize Wi-Fi controller");
+
+    let rng = Rng::new();
+    let net_seed = rng.random() as u64 | ((rng.random() as u64) << 32);
+
+    // DHCP, DNS and the SNTP socket
+    let (stack, runner) = embassy_net::new(
+        interfaces.sta,
+        embassy_net::Config::dhcpv4(DhcpConfig::default()),
+        mk_static!(StackResources<3>, StackResources::<3>::new()),
+        net_seed,
+    );
+
+    spawner
+        .spawn(wifi::connection(wifi_controller, SSID, PASSWORD))
+        .ok();
+    spawner.spawn(wifi::net_task(runner)).ok();
+    spawner.spawn(clock::sync_clock(stack, clock)).ok();
 
     let spi_bus = Spi::new(
         peripherals.SPI2,
@@ -99,14 +124,7 @@ async fn main(spawner: Spawner) -> ! {
     let sd_cs = Output::new(peripherals.GPIO5, Level::High, OutputConfig::default());
     let spi_dev = ExclusiveDevice::new(spi_bus, sd_cs, Delay).unwrap();
 
-    // Timer for sdcard
-    let rtc = Rtc::new(peripherals.LPWR);
-    let current_time_us: u64 = env!("CURRENT_TIME_US")
-        .parse()
-        .expect("Invalid microseconds");
-    rtc.set_current_time_us(current_time_us);
-
-    let sd_timer = SdTimeSource::new(rtc);
+    let sd_timer = SdTimeSource::new(clock);
 
     let sdcard = SdCard::new(spi_dev, Delay);
 
@@ -118,28 +136,52 @@ async fn main(spawner: Spawner) -> ! {
     // To do this we need a Volume Manager. It will take ownership of the block device.
     let volume_mgr = VolumeManager::new(sdcard, sd_timer);
 
-    // Try and access Volume 0 (i.e. the first partition).
-    // The volume object holds information about the filesystem on that volume.
-    let volume0 = volume_mgr.open_volume(VolumeIdx(0)).unwrap();
+    let mut logger: Logger<_, _, LOG_BUFFER> =
+        Logger::new(&volume_mgr, logger::Config::default()).unwrap();
 
-    let root_dir = volume0.open_root_dir().unwrap();
+    let mut adc1_config = AdcConfig::new();
+    let mut ldr = adc1_config.enable_pin(peripherals.GPIO34, Attenuation::_11dB);
+    let mut adc1 = Adc::new(peripherals.ADC1, adc1_config);
 
-    let my_file = root_dir
-        .open_file_in_dir(
-            "FERRIS.TXT",
-            embedded_sdmmc::Mode::ReadWriteCreateOrTruncate,
-        )
-        .unwrap();
-
-    let line = "Hello, Ferris!";
-    if let Ok(()) = my_file.write(line.as_bytes()) {
-        my_file.flush().unwrap();
-        println!("Written Data");
-    } else {
-        println!("Not wrote");
-    }
+    // Pulled low by a supply supervisor when the power is going
+    let mut power_fail = Input::new(
+        peripherals.GPIO4,
+        InputConfig::default().with_pull(Pull::Up),
+    );
 
     loop {
-        Timer::after(Duration::from_secs(30)).await;
+        match select(
+            Timer::after(SAMPLE_PERIOD),
+            power_fail.wait_for_falling_edge(),
+        )
+        .await
+        {
+            Either::First(()) => {
+                let light: u16 = nb::block!(adc1.read_oneshot(&mut ldr)).unwrap();
+                let Some(time_us) = clock.now_us() else {
+                    println!("Clock not set yet, reading dropped");
+                    continue;
+                };
+                let record = Record {
+                    time_us,
+                    measurement: "ldr",
+                    tags: &[("pin", "34")],
+                    fields: &[("light", Value::Int(light.into()))],
+                };
+                if let Err(e) = logger.log(&record) {
+                    println!("Can't log: {:?}", e);
+                }
+                if let Err(e) = logger.poll(time_us) {
+                    println!("Can't write the log: {:?}", e);
+                }
+            }
+            Either::Second(()) => {
+                match logger.flush() {
+                    Ok(()) => println!("Power failing, log flushed"),
+                    Err(e) => println!("Power failing, can't flush the log: {:?}", e),
+                }
+                power_fail.wait_for_high().await;
+            }
+        }
     }
 }
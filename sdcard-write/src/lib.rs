@@ -1 +1,3 @@
 #![no_std]
+pub use sdcard_log::logger;
+pub use sdcard_log::record;
@@ -16,6 +16,16 @@ use esp_hal::mcpwm::timer::PwmWorkingMode;
 use esp_hal::mcpwm::{McPwm, PeripheralClockConfig};
 use esp_hal::time::Rate;
 
+// LEDC
+use esp_hal::gpio::DriveMode;
+use esp_hal::ledc::channel::ChannelIFace;
+use esp_hal::ledc::timer::TimerIFace;
+use esp_hal::ledc::{HighSpeed, Ledc, channel, timer};
+
+// Sequencer
+use servo_mcpwm::sequence::{Keyframe, Player, Sequence};
+use servo_mcpwm::servo::{Joint, Servo, apply_pose};
+
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
     loop {}
@@ -25,6 +35,22 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
 esp_bootloader_esp_idf::esp_app_desc!();
 
+// Update the servos every 20 ms (one PWM period at 50 Hz)
+const FRAME_MS: u32 = 20;
+
+// Pick up an object on the left and drop it on the right.
+// Joints: base, shoulder, elbow, gripper
+const ARM_SEQUENCE: &str = "
+joints 4
+mode pingpong
+# duration_ms base shoulder elbow gripper
+1000  90  90  90  0
+1000  30 120  60  0
+500   30 120  60  70
+1500 150  90  90  70
+500  150 120  60  0
+";
+
 #[main]
 fn main() -> ! {
     // generator version: 1.0.0
@@ -37,12 +63,19 @@ fn main() -> ! {
     let clock_cfg = PeripheralClockConfig::with_frequency(Rate::from_mhz(32)).unwrap();
     let mut mcpwm = McPwm::new(peripherals.MCPWM0, clock_cfg);
 
-    // connect operator0 to timer0
+    // connect operator0 and operator1 to timer0
     mcpwm.operator0.set_timer(&mcpwm.timer0);
-    // connect operator0 to pin
-    let mut pwm_pin = mcpwm
-        .operator0
-        .with_pin_a(peripherals.GPIO33, PwmPinConfig::UP_ACTIVE_HIGH);
+    mcpwm.operator1.set_timer(&mcpwm.timer0);
+    // operator0 drives two servos (A and B outputs), operator1 one more
+    let (base_pin, shoulder_pin) = mcpwm.operator0.with_pins(
+        peripherals.GPIO33,
+        PwmPinConfig::UP_ACTIVE_HIGH,
+        peripherals.GPIO32,
+        PwmPinConfig::UP_ACTIVE_HIGH,
+    );
+    let elbow_pin = mcpwm
+        .operator1
+        .with_pin_a(peripherals.GPIO25, PwmPinConfig::UP_ACTIVE_HIGH);
 
     // start timer with timestamp values in the range of 0..=19999 and a frequency
     // of 50 Hz
@@ -51,17 +84,42 @@ fn main() -> ! {
         .unwrap();
     mcpwm.timer0.start(timer_clock_cfg);
 
-    loop {
-        // 0 degree (2.5% of 20_000 => 500)
-        pwm_pin.set_timestamp(500);
-        delay.delay_millis(1500);
+    // The gripper runs on a LEDC channel, like in the servo-motor example
+    let ledc = Ledc::new(peripherals.LEDC);
+    let mut hstimer0 = ledc.timer::<HighSpeed>(timer::Number::Timer0);
+    hstimer0
+        .configure(timer::config::Config {
+            duty: timer::config::Duty::Duty12Bit,
+            clock_source: timer::HSClockSource::APBClk,
+            frequency: Rate::from_hz(50),
+        })
+        .unwrap();
 
-        // 90 degree (7.5% of 20_000 => 1500)
-        pwm_pin.set_timestamp(1500);
-        delay.delay_millis(1500);
+    let mut channel0 = ledc.channel(channel::Number::Channel0, peripherals.GPIO26);
+    channel0
+        .configure(channel::config::Config {
+            timer: &hstimer0,
+            duty_pct: 10,
+            drive_mode: DriveMode::PushPull,
+        })
+        .unwrap();
+
+    let mut base = Servo::new(base_pin);
+    let mut shoulder = Servo::new(shoulder_pin);
+    let mut elbow = Servo::new(elbow_pin);
+    let mut gripper = Servo::new(channel0);
+    let mut joints: [&mut dyn Joint; 4] = [&mut base, &mut shoulder, &mut elbow, &mut gripper];
+
+    let mut frames = [Keyframe::EMPTY; 8];
+    let sequence = Sequence::parse_text(ARM_SEQUENCE, &mut frames).unwrap();
 
-        // 180 degree (12.5% of 20_000 => 2500)
-        pwm_pin.set_timestamp(2500);
-        delay.delay_millis(1500);
+    // Assume the arm rests in the middle position at power on
+    let mut player = Player::new(sequence, [900; 6]);
+    apply_pose(&mut joints, &player.pose()).unwrap();
+
+    loop {
+        let pose = player.advance(FRAME_MS);
+        apply_pose(&mut joints, &pose).unwrap();
+        delay.delay_millis(FRAME_MS);
     }
 }
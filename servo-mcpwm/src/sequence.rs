@@ -0,0 +1,543 @@
+//! Keyframe sequences for driving several servos in sync.
+//!
+//! A sequence is a list of keyframes. Each keyframe holds one target angle per
+//! joint and the time (in ms) it takes to travel there from the previous pose.
+//! All joints are interpolated over the same duration, so they start and
+//! arrive together.
+//!
+//! Sequences can be written as `const` arrays (kept in flash), or parsed from a
+//! small text or binary format, for example from a file on the SD card.
+//!
+//! Text format (one keyframe per line, `#` starts a comment):
+//!
+//! ```text
+//! joints 4
+//! mode pingpong
+//! # duration_ms angle0 angle1 angle2 angle3
+//! 1000 90 90 90 90
+//! 500  0  45 90 180
+//! ```
+//!
+//! Binary format (little endian):
+//!
+//! ```text
+//! b"SQ" version(1) joints(u8) mode(u8) frame_count(u8)
+//! then per frame: duration_ms(u16) angle(u8) * joints
+//! ```
+
+/// Maximum number of joints a sequence can drive (6 DOF arm)
+pub const MAX_JOINTS: usize = 6;
+
+/// Maximum angle of a joint in degrees
+pub const MAX_ANGLE: u8 = 180;
+
+const MAGIC: [u8; 2] = *b"SQ";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 6;
+
+/// Position of every joint, in tenths of a degree (0..=1800)
+pub type Pose = [u16; MAX_JOINTS];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// Binary data does not start with the `SQ` magic
+    BadMagic,
+    /// Binary format version is not supported
+    UnsupportedVersion,
+    /// Joint count is zero or larger than `MAX_JOINTS`
+    InvalidJoints,
+    /// Unknown playback mode
+    InvalidMode,
+    /// Angle is larger than `MAX_ANGLE`
+    InvalidAngle,
+    /// Keyframe line or record is malformed
+    InvalidFrame,
+    /// Data ended before all keyframes were read
+    Truncated,
+    /// The keyframe buffer (or output buffer) is too small
+    BufferFull,
+    /// Sequence has no keyframes
+    Empty,
+    /// Looping or ping-pong sequence whose keyframes all take 0 ms, so it
+    /// would repeat forever without time passing
+    NoDuration,
+}
+
+/// What to do after the last keyframe is reached
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlayMode {
+    /// Stop and hold the last pose
+    Once,
+    /// Travel back to the first keyframe and start over
+    Loop,
+    /// Play the keyframes backwards, then forwards again
+    PingPong,
+}
+
+impl PlayMode {
+    fn from_byte(byte: u8) -> Result<Self, Error> {
+        match byte {
+            0 => Ok(PlayMode::Once),
+            1 => Ok(PlayMode::Loop),
+            2 => Ok(PlayMode::PingPong),
+            _ => Err(Error::InvalidMode),
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            PlayMode::Once => 0,
+            PlayMode::Loop => 1,
+            PlayMode::PingPong => 2,
+        }
+    }
+
+    fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "once" => Ok(PlayMode::Once),
+            "loop" => Ok(PlayMode::Loop),
+            "pingpong" => Ok(PlayMode::PingPong),
+            _ => Err(Error::InvalidMode),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    /// Time to travel from the previous pose to this one
+    pub duration_ms: u16,
+    /// Target angle of each joint in degrees; unused joints are ignored
+    pub angles: [u8; MAX_JOINTS],
+}
+
+impl Keyframe {
+    pub const fn new(duration_ms: u16, angles: [u8; MAX_JOINTS]) -> Self {
+        Self {
+            duration_ms,
+            angles,
+        }
+    }
+
+    pub const EMPTY: Keyframe = Keyframe::new(0, [0; MAX_JOINTS]);
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sequence<'a> {
+    joints: usize,
+    mode: PlayMode,
+    frames: &'a [Keyframe],
+}
+
+impl<'a> Sequence<'a> {
+    pub fn new(joints: usize, mode: PlayMode, frames: &'a [Keyframe]) -> Result<Self, Error> {
+        if joints == 0 || joints > MAX_JOINTS {
+            return Err(Error::InvalidJoints);
+        }
+        if frames.is_empty() {
+            return Err(Error::Empty);
+        }
+        if frames
+            .iter()
+            .any(|frame| frame.angles[..joints].iter().any(|&a| a > MAX_ANGLE))
+        {
+            return Err(Error::InvalidAngle);
+        }
+        // Frames the player keeps coming back to; ping-pong only passes
+        // the first one on the way in
+        let repeated = match mode {
+            PlayMode::Once => &[][..],
+            PlayMode::Loop => frames,
+            PlayMode::PingPong => &frames[1..],
+        };
+        if !repeated.is_empty() && repeated.iter().all(|frame| frame.duration_ms == 0) {
+            return Err(Error::NoDuration);
+        }
+        Ok(Self {
+            joints,
+            mode,
+            frames,
+        })
+    }
+
+    /// Parse the text format, storing the keyframes in `buf`
+    pub fn parse_text(text: &str, buf: &'a mut [Keyframe]) -> Result<Self, Error> {
+        let mut joints = 0;
+        let mut mode = PlayMode::Once;
+        let mut count = 0;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut fields = line.split_whitespace();
+            let Some(first) = fields.next() else {
+                continue;
+            };
+
+            match first {
+                "joints" => {
+                    joints = parse_field(fields.next())?;
+                    if joints == 0 || joints > MAX_JOINTS {
+                        return Err(Error::InvalidJoints);
+                    }
+                }
+                "mode" => {
+                    mode = PlayMode::from_name(fields.next().ok_or(Error::InvalidMode)?)?;
+                }
+                _ => {
+                    if joints == 0 {
+                        return Err(Error::InvalidJoints);
+                    }
+                    let slot = buf.get_mut(count).ok_or(Error::BufferFull)?;
+                    *slot = Keyframe::EMPTY;
+                    slot.duration_ms = parse_field(Some(first))?;
+                    for angle in slot.angles[..joints].iter_mut() {
+                        *angle = parse_field(fields.next())?;
+                    }
+                    if fields.next().is_some() {
+                        return Err(Error::InvalidFrame);
+                    }
+                    count += 1;
+                }
+            }
+        }
+
+        Sequence::new(joints, mode, &buf[..count])
+    }
+
+    /// Parse the binary format, storing the keyframes in `buf`
+    pub fn parse_binary(bytes: &[u8], buf: &'a mut [Keyframe]) -> Result<Self, Error> {
+        let header = bytes.get(..HEADER_LEN).ok_or(Error::Truncated)?;
+        if header[..2] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        if header[2] != VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+        let joints = header[3] as usize;
+        if joints == 0 || joints > MAX_JOINTS {
+            return Err(Error::InvalidJoints);
+        }
+        let mode = PlayMode::from_byte(header[4])?;
+        let count = header[5] as usize;
+        if count > buf.len() {
+            return Err(Error::BufferFull);
+        }
+
+        let record_len = 2 + joints;
+        let body = &bytes[HEADER_LEN..];
+        if body.len() < count * record_len {
+            return Err(Error::Truncated);
+        }
+
+        for (slot, record) in buf
+            .iter_mut()
+            .zip(body.chunks_exact(record_len).take(count))
+        {
+            *slot = Keyframe::EMPTY;
+            slot.duration_ms = u16::from_le_bytes([record[0], record[1]]);
+            slot.angles[..joints].copy_from_slice(&record[2..]);
+        }
+
+        Sequence::new(joints, mode, &buf[..count])
+    }
+
+    /// Encode the sequence in the binary format; returns the number of bytes written
+    pub fn write_binary(&self, out: &mut [u8]) -> Result<usize, Error> {
+        let count = u8::try_from(self.frames.len()).map_err(|_| Error::BufferFull)?;
+        let record_len = 2 + self.joints;
+        let len = HEADER_LEN + self.frames.len() * record_len;
+        let out = out.get_mut(..len).ok_or(Error::BufferFull)?;
+
+        out[..2].copy_from_slice(&MAGIC);
+        out[2] = VERSION;
+        out[3] = self.joints as u8;
+        out[4] = self.mode.to_byte();
+        out[5] = count;
+
+        for (record, frame) in out[HEADER_LEN..]
+            .chunks_exact_mut(record_len)
+            .zip(self.frames)
+        {
+            record[..2].copy_from_slice(&frame.duration_ms.to_le_bytes());
+            record[2..].copy_from_slice(&frame.angles[..self.joints]);
+        }
+
+        Ok(len)
+    }
+
+    pub fn joints(&self) -> usize {
+        self.joints
+    }
+
+    pub fn mode(&self) -> PlayMode {
+        self.mode
+    }
+
+    pub fn frames(&self) -> &'a [Keyframe] {
+        self.frames
+    }
+}
+
+fn parse_field<T: core::str::FromStr>(field: Option<&str>) -> Result<T, Error> {
+    field
+        .ok_or(Error::InvalidFrame)?
+        .parse()
+        .map_err(|_| Error::InvalidFrame)
+}
+
+/// Plays a sequence, producing the interpolated pose for a point in time.
+pub struct Player<'a> {
+    sequence: Sequence<'a>,
+    from: Pose,
+    target: usize,
+    forward: bool,
+    elapsed_ms: u32,
+    finished: bool,
+}
+
+impl<'a> Player<'a> {
+    /// `start` is the current pose of the arm; the first keyframe is reached from it
+    pub fn new(sequence: Sequence<'a>, start: Pose) -> Self {
+        Self {
+            sequence,
+            from: start,
+            target: 0,
+            forward: true,
+            elapsed_ms: 0,
+            finished: false,
+        }
+    }
+
+    /// Move the playback forward by `dt_ms` and return the new pose
+    pub fn advance(&mut self, dt_ms: u32) -> Pose {
+        self.elapsed_ms = self.elapsed_ms.saturating_add(dt_ms);
+
+        while !self.finished {
+            let duration = self.segment_duration();
+            if self.elapsed_ms < duration {
+                break;
+            }
+            self.elapsed_ms -= duration;
+            self.from = pose_of(&self.sequence.frames[self.target]);
+            self.next_target();
+        }
+
+        self.pose()
+    }
+
+    /// Pose at the current playback position
+    pub fn pose(&self) -> Pose {
+        if self.finished {
+            return self.from;
+        }
+
+        let to = pose_of(&self.sequence.frames[self.target]);
+        let duration = self.segment_duration();
+        let mut pose = self.from;
+        for (current, &target) in pose.iter_mut().zip(to.iter()).take(self.sequence.joints) {
+            *current = lerp(*current, target, self.elapsed_ms, duration);
+        }
+        pose
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Restart from the current pose towards the first keyframe
+    pub fn restart(&mut self) {
+        self.from = self.pose();
+        self.target = 0;
+        self.forward = true;
+        self.elapsed_ms = 0;
+        self.finished = false;
+    }
+
+    fn segment_duration(&self) -> u32 {
+        let frames = self.sequence.frames;
+        // Going backwards, a segment takes as long as it did going forwards
+        let index = if self.forward {
+            self.target
+        } else {
+            self.target + 1
+        };
+        frames[index].duration_ms as u32
+    }
+
+    fn next_target(&mut self) {
+        let last = self.sequence.frames.len() - 1;
+
+        match (self.sequence.mode, self.forward) {
+            (_, true) if self.target < last => self.target += 1,
+            (_, false) if self.target > 0 => self.target -= 1,
+            (PlayMode::Once, _) => self.finished = true,
+            (PlayMode::Loop, _) => self.target = 0,
+            (PlayMode::PingPong, _) if last == 0 => self.finished = true,
+            (PlayMode::PingPong, true) => {
+                self.forward = false;
+                self.target = last - 1;
+            }
+            (PlayMode::PingPong, false) => {
+                self.forward = true;
+                self.target = 1;
+            }
+        }
+    }
+}
+
+fn pose_of(frame: &Keyframe) -> Pose {
+    let mut pose = [0; MAX_JOINTS];
+    for (p, &angle) in pose.iter_mut().zip(frame.angles.iter()) {
+        *p = angle as u16 * 10;
+    }
+    pose
+}
+
+fn lerp(from: u16, to: u16, elapsed: u32, duration: u32) -> u16 {
+    if duration == 0 || elapsed >= duration {
+        return to;
+    }
+    let from = from as i32;
+    let delta = to as i32 - from;
+    (from + delta * elapsed as i32 / duration as i32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAMES: [Keyframe; 3] = [
+        Keyframe::new(1000, [0, 180, 0, 0, 0, 0]),
+        Keyframe::new(1000, [90, 90, 0, 0, 0, 0]),
+        Keyframe::new(500, [180, 0, 0, 0, 0, 0]),
+    ];
+
+    #[test]
+    fn joints_arrive_together() {
+        let seq = Sequence::new(2, PlayMode::Once, &FRAMES).unwrap();
+        let mut player = Player::new(seq, [0, 1800, 0, 0, 0, 0]);
+
+        assert_eq!(player.advance(1000)[..2], [0, 1800]);
+        assert_eq!(player.advance(500)[..2], [450, 1350]);
+        assert_eq!(player.advance(500)[..2], [900, 900]);
+        assert_eq!(player.advance(250)[..2], [1350, 450]);
+        assert_eq!(player.advance(250)[..2], [1800, 0]);
+        assert!(player.is_finished());
+        assert_eq!(player.advance(1000)[..2], [1800, 0]);
+    }
+
+    #[test]
+    fn loop_returns_to_first_frame() {
+        let seq = Sequence::new(1, PlayMode::Loop, &FRAMES).unwrap();
+        let mut player = Player::new(seq, [0; MAX_JOINTS]);
+
+        assert_eq!(player.advance(2500)[0], 1800);
+        // back to frame 0 using its duration
+        assert_eq!(player.advance(500)[0], 900);
+        assert_eq!(player.advance(500)[0], 0);
+        assert!(!player.is_finished());
+    }
+
+    #[test]
+    fn pingpong_reverses() {
+        let seq = Sequence::new(1, PlayMode::PingPong, &FRAMES).unwrap();
+        let mut player = Player::new(seq, [0; MAX_JOINTS]);
+
+        assert_eq!(player.advance(2500)[0], 1800);
+        // 2 -> 1 takes the 500 ms of frame 2
+        assert_eq!(player.advance(250)[0], 1350);
+        assert_eq!(player.advance(250)[0], 900);
+        // 1 -> 0 takes the 1000 ms of frame 1
+        assert_eq!(player.advance(1000)[0], 0);
+        assert_eq!(player.advance(1000)[0], 900);
+    }
+
+    #[test]
+    fn parse_text_format() {
+        let text = "joints 2\nmode loop\n# comment\n1000 10 20\n\n500 30 40 # trailing\n";
+        let mut buf = [Keyframe::EMPTY; 4];
+        let seq = Sequence::parse_text(text, &mut buf).unwrap();
+
+        assert_eq!(seq.joints(), 2);
+        assert_eq!(seq.mode(), PlayMode::Loop);
+        assert_eq!(
+            seq.frames(),
+            &[
+                Keyframe::new(1000, [10, 20, 0, 0, 0, 0]),
+                Keyframe::new(500, [30, 40, 0, 0, 0, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_text_errors() {
+        let mut buf = [Keyframe::EMPTY; 1];
+        assert_eq!(
+            Sequence::parse_text("100 1 2", &mut buf).unwrap_err(),
+            Error::InvalidJoints
+        );
+        assert_eq!(
+            Sequence::parse_text("joints 2\n100 1", &mut buf).unwrap_err(),
+            Error::InvalidFrame
+        );
+        assert_eq!(
+            Sequence::parse_text("joints 1\n100 181", &mut buf).unwrap_err(),
+            Error::InvalidAngle
+        );
+        assert_eq!(
+            Sequence::parse_text("joints 1\n100 1\n100 2", &mut buf).unwrap_err(),
+            Error::BufferFull
+        );
+        assert_eq!(
+            Sequence::parse_text("joints 1\nmode bounce", &mut buf).unwrap_err(),
+            Error::InvalidMode
+        );
+    }
+
+    #[test]
+    fn repeating_sequences_take_time() {
+        let mut buf = [Keyframe::EMPTY; 2];
+        assert_eq!(
+            Sequence::parse_text("joints 1\nmode loop\n0 90\n0 10\n", &mut buf).unwrap_err(),
+            Error::NoDuration
+        );
+        // ping-pong never goes back to the first frame
+        assert_eq!(
+            Sequence::parse_text("joints 1\nmode pingpong\n500 90\n0 10\n", &mut buf).unwrap_err(),
+            Error::NoDuration
+        );
+        // played once, or with a single frame to hold, it just ends
+        assert!(Sequence::parse_text("joints 1\n0 90\n0 10\n", &mut buf).is_ok());
+        assert!(Sequence::parse_text("joints 1\nmode pingpong\n0 90\n", &mut buf).is_ok());
+
+        let seq = Sequence::parse_text("joints 1\nmode loop\n0 90\n20 10\n", &mut buf).unwrap();
+        let mut player = Player::new(seq, [0; MAX_JOINTS]);
+        // the jump back to the first frame takes no time
+        assert_eq!(player.advance(10)[0], 500);
+        assert_eq!(player.advance(20)[0], 500);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let seq = Sequence::new(3, PlayMode::PingPong, &FRAMES).unwrap();
+        let mut bytes = [0u8; 64];
+        let len = seq.write_binary(&mut bytes).unwrap();
+        assert_eq!(len, HEADER_LEN + 3 * 5);
+        assert_eq!(&bytes[..6], &[b'S', b'Q', 1, 3, 2, 3]);
+
+        let mut buf = [Keyframe::EMPTY; 3];
+        let decoded = Sequence::parse_binary(&bytes[..len], &mut buf).unwrap();
+        assert_eq!(decoded.joints(), 3);
+        assert_eq!(decoded.mode(), PlayMode::PingPong);
+        assert_eq!(decoded.frames(), &FRAMES);
+
+        assert_eq!(
+            Sequence::parse_binary(&bytes[..len - 1], &mut buf).unwrap_err(),
+            Error::Truncated
+        );
+        bytes[0] = b'X';
+        assert_eq!(
+            Sequence::parse_binary(&bytes[..len], &mut buf).unwrap_err(),
+            Error::BadMagic
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Servo outputs that can be driven from a [`Player`](crate::sequence::Player).
+//!
+//! Both LEDC channels and MCPWM pins implement `embedded_hal::pwm::SetDutyCycle`,
+//! so a [`Servo`] can wrap either of them. The timer behind it must run at 50 Hz.
+
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::sequence::{MAX_JOINTS, Pose};
+
+/// Minimum pulse width (2.5% of 20 ms => 0.5 ms)
+const MIN_DUTY_PER_MILLE: u32 = 25;
+/// Maximum pulse width (12.5% of 20 ms => 2.5 ms)
+const MAX_DUTY_PER_MILLE: u32 = 125;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServoError;
+
+/// Object safe interface, so servos on different peripherals can be mixed in one arm
+pub trait Joint {
+    /// Move to the given angle, in tenths of a degree (0..=1800)
+    fn set_angle(&mut self, deci_degrees: u16) -> Result<(), ServoError>;
+}
+
+pub struct Servo<P> {
+    pwm: P,
+    min_duty: u32,
+    duty_gap: u32,
+}
+
+impl<P: SetDutyCycle> Servo<P> {
+    pub fn new(pwm: P) -> Self {
+        let max_duty_cycle = pwm.max_duty_cycle() as u32;
+        let min_duty = (MIN_DUTY_PER_MILLE * max_duty_cycle) / 1000;
+        let max_duty = (MAX_DUTY_PER_MILLE * max_duty_cycle) / 1000;
+        Self {
+            pwm,
+            min_duty,
+            duty_gap: max_duty - min_duty,
+        }
+    }
+
+    pub fn release(self) -> P {
+        self.pwm
+    }
+}
+
+impl<P: SetDutyCycle> Joint for Servo<P> {
+    fn set_angle(&mut self, deci_degrees: u16) -> Result<(), ServoError> {
+        let deci_degrees = deci_degrees.min(1800) as u32;
+        let duty = self.min_duty + (deci_degrees * self.duty_gap) / 1800;
+        self.pwm.set_duty_cycle(duty as u16).map_err(|_| ServoError)
+    }
+}
+
+/// Write a pose to the joints; joint `n` gets `pose[n]`
+pub fn apply_pose(joints: &mut [&mut dyn Joint], pose: &Pose) -> Result<(), ServoError> {
+    for (joint, &angle) in joints.iter_mut().zip(pose.iter()).take(MAX_JOINTS) {
+        joint.set_angle(angle)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+//! SNTP (RFC 4330) packets, for the network clock of the SD card projects.
+//! Nothing here touches esp-hal or the network stack, so the tests run on
+//! the host with `cargo test`; `wifi-clock` sends and receives them.
+//!
+//! A client sends a 48-byte [`request`] with its own clock in the transmit
+//! field; the server copies it into the reply, next to the times it got the
+//! request and sent the answer. [`Reply::parse`] checks the answer belongs
+//! to the request and [`Reply::offset`] says how far our clock is off,
+//! taking out the time the packets spent on the way.
+#![no_std]
+
+pub const PORT: u16 = 123;
+pub const PACKET_LEN: usize = 48;
+
+// Seconds from 1900, where NTP time starts, to 1970
+const UNIX_EPOCH: u64 = 2_208_988_800;
+
+// first byte: leap indicator, version and mode
+const VERSION: u8 = 4;
+const CLIENT: u8 = 3;
+const SERVER: u8 = 4;
+const NOT_SYNCHRONIZED: u8 = 3;
+
+// field offsets
+const STRATUM: usize = 1;
+const ORIGINATE: usize = 24;
+const RECEIVE: usize = 32;
+const TRANSMIT: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The server name didn't resolve
+    Dns,
+    /// The request couldn't be sent
+    Network,
+    /// No reply in time
+    Timeout,
+    /// The reply isn't an answer to the request
+    Invalid,
+    /// The server's clock isn't set, or it wants us to stop asking
+    Unsynchronized,
+}
+
+/// A client request. `now_us` is our clock, as Unix time in microseconds or
+/// anything else; it only has to come back in the reply.
+pub fn request(now_us: u64) -> [u8; PACKET_LEN] {
+    let mut packet = [0; PACKET_LEN];
+    packet[0] = (VERSION << 3) | CLIENT;
+    packet[TRANSMIT..].copy_from_slice(&to_ntp(now_us).to_be_bytes());
+    packet
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reply {
+    pub stratum: u8,
+    /// When the server got the request, Unix time in microseconds
+    pub receive_us: u64,
+    /// When the server sent the reply
+    pub transmit_us: u64,
+}
+
+impl Reply {
+    /// Read the reply of a server to `request`
+    pub fn parse(packet: &[u8], request: &[u8; PACKET_LEN]) -> Result<Self, Error> {
+        if packet.len() < PACKET_LEN
+            || packet[0] & 0x07 != SERVER
+            || !(3..=4).contains(&((packet[0] >> 3) & 0x07))
+            || packet[ORIGINATE..RECEIVE] != request[TRANSMIT..]
+        {
+            return Err(Error::Invalid);
+        }
+        // stratum 0 is a kiss-o'-death: the server refuses to answer
+        let stratum = packet[STRATUM];
+        if packet[0] >> 6 == NOT_SYNCHRONIZED || !(1..=15).contains(&stratum) {
+            return Err(Error::Unsynchronized);
+        }
+        let transmit = timestamp(&packet[TRANSMIT..]);
+        if transmit == 0 {
+            return Err(Error::Invalid);
+        }
+
+        Ok(Self {
+            stratum,
+            receive_us: from_ntp(timestamp(&packet[RECEIVE..])).ok_or(Error::Invalid)?,
+            transmit_us: from_ntp(transmit).ok_or(Error::Invalid)?,
+        })
+    }
+
+    /// What to add to our clock, in microseconds, for a request sent at
+    /// `sent_us` whose reply came in at `received_us`, both on our clock.
+    /// The way there is taken to be as long as the way back.
+    pub fn offset(&self, sent_us: u64, received_us: u64) -> i64 {
+        let there = self.receive_us as i64 - sent_us as i64;
+        let back = self.transmit_us as i64 - received_us as i64;
+        (there + back) / 2
+    }
+}
+
+// Seconds since 1900 in the high half, fractions of a second in the low
+fn timestamp(bytes: &[u8]) -> u64 {
+    let mut raw = [0; 8];
+    raw.copy_from_slice(&bytes[..8]);
+    u64::from_be_bytes(raw)
+}
+
+fn to_ntp(unix_us: u64) -> u64 {
+    let seconds = (unix_us / 1_000_000 + UNIX_EPOCH) & 0xFFFF_FFFF;
+    let fraction = ((unix_us % 1_000_000) << 32) / 1_000_000;
+    (seconds << 32) | fraction
+}
+
+// The seconds wrap in 2036. Times before 1968 never come up, so small
+// values are taken to be after the wrap. Rounds to the closest
+// microsecond, which `to_ntp` gives back unchanged. `None` for the times
+// left, 1968 and 1969, before Unix time starts.
+fn from_ntp(ntp: u64) -> Option<u64> {
+    let mut seconds = ntp >> 32;
+    if seconds < 0x8000_0000 {
+        seconds += 1 << 32;
+    }
+    let micros = ((ntp & 0xFFFF_FFFF) * 1_000_000 + (1 << 31)) >> 32;
+    Some(seconds.checked_sub(UNIX_EPOCH)? * 1_000_000 + micros)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    use super::*;
+
+    // 2026-10-19 13:03:40.25 UTC
+    const TIME: u64 = 1_792_415_020_250_000;
+
+    // Answers one request the way a stratum 1 server whose clock reads
+    // `server_us` would, after `delay_us` of processing
+    fn stand_in_server(server_us: u64, delay_us: u64) -> (UdpSocket, thread::JoinHandle<()>) {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.connect(server.local_addr().unwrap()).unwrap();
+        let handle = thread::spawn(move || {
+            let mut packet = [0; PACKET_LEN];
+            let (len, from) = server.recv_from(&mut packet).unwrap();
+            assert_eq!(len, PACKET_LEN);
+            assert_eq!(packet[0], 0x23, "version 4, client");
+
+            let mut reply = [0; PACKET_LEN];
+            reply[0] = (VERSION << 3) | SERVER;
+            reply[STRATUM] = 1;
+            reply[ORIGINATE..RECEIVE].copy_from_slice(&packet[TRANSMIT..]);
+            reply[RECEIVE..TRANSMIT].copy_from_slice(&to_ntp(server_us).to_be_bytes());
+            reply[TRANSMIT..].copy_from_slice(&to_ntp(server_us + delay_us).to_be_bytes());
+            server.send_to(&reply, from).unwrap();
+        });
+        (client, handle)
+    }
+
+    #[test]
+    fn sets_the_clock_from_a_server() {
+        let (client, server) = stand_in_server(TIME, 1_000);
+
+        // our clock only counts from boot, and the exchange takes 21 ms of
+        // which 1 ms on the server
+        let sent_us = 5_000_000;
+        let request = request(sent_us);
+        client.send(&request).unwrap();
+        let mut packet = [0; PACKET_LEN];
+        let len = client.recv(&mut packet).unwrap();
+        server.join().unwrap();
+
+        let reply = Reply::parse(&packet[..len], &request).unwrap();
+        assert_eq!(reply.stratum, 1);
+        let received_us = sent_us + 21_000;
+        let offset = reply.offset(sent_us, received_us);
+        // 10 ms each way
+        assert_eq!(received_us.saturating_add_signed(offset), TIME + 11_000);
+    }
+
+    #[test]
+    fn rejects_bad_replies() {
+        let request = request(TIME);
+        let mut reply = [0; PACKET_LEN];
+        reply[0] = (VERSION << 3) | SERVER;
+        reply[STRATUM] = 2;
+        reply[ORIGINATE..RECEIVE].copy_from_slice(&request[TRANSMIT..]);
+        reply[RECEIVE..TRANSMIT].copy_from_slice(&to_ntp(TIME).to_be_bytes());
+        reply[TRANSMIT..].copy_from_slice(&to_ntp(TIME).to_be_bytes());
+        assert!(Reply::parse(&reply, &request).is_ok());
+
+        assert_eq!(Reply::parse(&reply[..47], &request), Err(Error::Invalid));
+        // an answer to someone else's request
+        assert_eq!(Reply::parse(&reply, &self::request(0)), Err(Error::Invalid));
+        // a request, not a reply
+        assert_eq!(Reply::parse(&request, &request), Err(Error::Invalid));
+
+        let mut kiss = reply;
+        kiss[STRATUM] = 0;
+        assert_eq!(Reply::parse(&kiss, &request), Err(Error::Unsynchronized));
+        let mut unset = reply;
+        unset[0] |= NOT_SYNCHRONIZED << 6;
+        assert_eq!(Reply::parse(&unset, &request), Err(Error::Unsynchronized));
+
+        // 1969, before Unix time
+        let before_1970 = ((UNIX_EPOCH - 1) << 32).to_be_bytes();
+        let mut early = reply;
+        early[RECEIVE..TRANSMIT].copy_from_slice(&before_1970);
+        assert_eq!(Reply::parse(&early, &request), Err(Error::Invalid));
+        let mut early = reply;
+        early[TRANSMIT..].copy_from_slice(&before_1970);
+        assert_eq!(Reply::parse(&early, &request), Err(Error::Invalid));
+    }
+
+    #[test]
+    fn converts_timestamps() {
+        assert_eq!(from_ntp(to_ntp(TIME)), Some(TIME));
+        assert_eq!(to_ntp(0), UNIX_EPOCH << 32);
+        // half a second
+        assert_eq!(to_ntp(500_000) & 0xFFFF_FFFF, 0x8000_0000);
+        // 2036-02-07 06:28:16 UTC, when the seconds wrap
+        assert_eq!(from_ntp(0), Some(2_085_978_496_000_000));
+        assert_eq!(
+            from_ntp(to_ntp(2_085_978_497_000_000)),
+            Some(2_085_978_497_000_000)
+        );
+        // 1968, the earliest time before the wrap
+        assert_eq!(from_ntp(0x8000_0000 << 32), None);
+        assert_eq!(from_ntp((UNIX_EPOCH << 32) - 1), None);
+    }
+}
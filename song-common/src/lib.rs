@@ -0,0 +1,21 @@
+//! Music parsing and audio processing shared by the buzzer-song players.
+//! Nothing here touches esp-hal, so the tests run on the host with
+//! `cargo test`.
+//!
+//! - [`music`]: pitches, note lengths and songs
+//! - [`rtttl`]: Nokia ring tones
+//! - [`midi`]: Standard MIDI Files, streamed from any [`midi::ByteSource`]
+//!   such as a file on the SD card
+//! - [`score`]: several parts played together
+//! - [`voices`]: which buzzer plays which note
+//! - [`wav`]: PCM WAV files
+//! - [`resample`]: sample rate conversion and volume for the DAC and I2S
+#![no_std]
+pub mod midi;
+pub mod music;
+pub mod resample;
+pub mod rtttl;
+pub mod score;
+mod sd;
+pub mod voices;
+pub mod wav;
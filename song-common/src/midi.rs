@@ -0,0 +1,570 @@
+//! Streaming Standard MIDI File (SMF) reader.
+//!
+//! Reads format 0 and 1 files without loading them into RAM: every track
+//! keeps its own file position and a small read buffer, and the tracks are
+//! merged by time as they are read. [`Monophonic`] turns the events into the
+//! single note a buzzer can play.
+//!
+//! Only note on/off and tempo events are reported; controllers, SysEx and
+//! other meta events are skipped.
+
+/// Tracks a file may have; format 1 songs usually need far fewer
+pub const MAX_TRACKS: usize = 16;
+
+// Read buffer per track
+const BUFFER_LEN: usize = 32;
+
+// 120 beats per minute, the SMF default
+const DEFAULT_TEMPO: u32 = 500_000;
+
+/// Random-access byte storage, such as a file on the SD card
+pub trait ByteSource {
+    type Error;
+
+    /// Read up to `buf.len()` bytes at `offset`; returns 0 past the end
+    fn read_at(&mut self, offset: u32, buf: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+impl ByteSource for &[u8] {
+    type Error = core::convert::Infallible;
+
+    fn read_at(&mut self, offset: u32, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let data = self.get(offset as usize..).unwrap_or(&[]);
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error<E> {
+    Io(E),
+    /// The file does not start with an `MThd` chunk
+    NotMidi,
+    /// Only formats 0 and 1 are supported
+    UnsupportedFormat(u16),
+    /// SMPTE time division instead of ticks per quarter note
+    SmpteTiming,
+    /// More than `MAX_TRACKS` tracks
+    TooManyTracks(u16),
+    /// A chunk or event runs past the end of the file
+    Truncated,
+    /// Data byte without a running status, or a bad length
+    Malformed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub format: u16,
+    pub tracks: u16,
+    pub ticks_per_quarter: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// `channel` is 0..=15 (MIDI channel 1 is 0)
+    NoteOn { channel: u8, key: u8, velocity: u8 },
+    /// Also reported for a note on with velocity 0
+    NoteOff { channel: u8, key: u8 },
+    /// Microseconds per quarter note
+    Tempo(u32),
+}
+
+/// An event and its time in ticks from the start of the song
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedEvent {
+    pub tick: u64,
+    pub event: Event,
+}
+
+#[derive(Clone, Copy)]
+struct Track {
+    pos: u32,
+    end: u32,
+    /// Running status
+    status: u8,
+    /// Time of the next event, once its delta has been read
+    tick: u64,
+    done: bool,
+    buf: [u8; BUFFER_LEN],
+    buf_start: u32,
+    buf_len: u8,
+}
+
+impl Track {
+    const EMPTY: Track = Track {
+        pos: 0,
+        end: 0,
+        status: 0,
+        tick: 0,
+        done: true,
+        buf: [0; BUFFER_LEN],
+        buf_start: 0,
+        buf_len: 0,
+    };
+
+    fn byte<S: ByteSource>(&mut self, source: &mut S) -> Result<u8, Error<S::Error>> {
+        if self.pos >= self.end {
+            return Err(Error::Truncated);
+        }
+        let buffered = self.buf_start..self.buf_start + self.buf_len as u32;
+        if !buffered.contains(&self.pos) {
+            let want = ((self.end - self.pos) as usize).min(BUFFER_LEN);
+            let len = source
+                .read_at(self.pos, &mut self.buf[..want])
+                .map_err(Error::Io)?;
+            if len == 0 {
+                return Err(Error::Truncated);
+            }
+            self.buf_start = self.pos;
+            self.buf_len = len as u8;
+        }
+        let byte = self.buf[(self.pos - self.buf_start) as usize];
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn skip<E>(&mut self, len: u32) -> Result<(), Error<E>> {
+        if len > self.end - self.pos {
+            return Err(Error::Truncated);
+        }
+        self.pos += len;
+        Ok(())
+    }
+
+    /// Variable-length quantity: 7 bits per byte, high bit set on all but the last
+    fn vlq<S: ByteSource>(&mut self, source: &mut S) -> Result<u32, Error<S::Error>> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.byte(source)?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::Malformed)
+    }
+
+    /// Read the delta time of the next event, or mark the track done at its end
+    fn advance<S: ByteSource>(&mut self, source: &mut S) -> Result<(), Error<S::Error>> {
+        if self.pos >= self.end {
+            self.done = true;
+        } else {
+            self.tick += self.vlq(source)? as u64;
+        }
+        Ok(())
+    }
+
+    /// Read one event; `None` for the events we skip
+    fn event<S: ByteSource>(&mut self, source: &mut S) -> Result<Option<Event>, Error<S::Error>> {
+        let mut first = self.byte(source)?;
+        let status = if first & 0x80 != 0 {
+            let status = first;
+            if status < 0xF0 {
+                self.status = status;
+                first = self.byte(source)?;
+            }
+            status
+        } else if self.status != 0 {
+            self.status
+        } else {
+            return Err(Error::Malformed);
+        };
+
+        match status {
+            0x80..=0xEF => {
+                let channel = status & 0x0F;
+                let kind = status & 0xF0;
+                // program change and channel pressure have one data byte
+                let second = if matches!(kind, 0xC0 | 0xD0) {
+                    0
+                } else {
+                    self.byte(source)?
+                };
+                Ok(match (kind, second) {
+                    (0x90, 0) | (0x80, _) => Some(Event::NoteOff {
+                        channel,
+                        key: first,
+                    }),
+                    (0x90, velocity) => Some(Event::NoteOn {
+                        channel,
+                        key: first,
+                        velocity,
+                    }),
+                    _ => None,
+                })
+            }
+            0xF0 | 0xF7 => {
+                // SysEx cancels the running status
+                self.status = 0;
+                let len = self.vlq(source)?;
+                self.skip(len)?;
+                Ok(None)
+            }
+            0xFF => {
+                let kind = self.byte(source)?;
+                let len = self.vlq(source)?;
+                match (kind, len) {
+                    (0x51, 3) => {
+                        let mut tempo = 0;
+                        for _ in 0..3 {
+                            tempo = (tempo << 8) | self.byte(source)? as u32;
+                        }
+                        Ok(Some(Event::Tempo(tempo)))
+                    }
+                    (0x51, _) => Err(Error::Malformed),
+                    (0x2F, _) => {
+                        // End of track; ignore anything after it
+                        self.skip(len)?;
+                        self.end = self.pos;
+                        Ok(None)
+                    }
+                    _ => {
+                        self.skip(len)?;
+                        Ok(None)
+                    }
+                }
+            }
+            _ => Err(Error::Malformed),
+        }
+    }
+}
+
+pub struct Smf<S: ByteSource> {
+    source: S,
+    header: Header,
+    tracks: [Track; MAX_TRACKS],
+}
+
+impl<S: ByteSource> Smf<S> {
+    /// Read the header and find the tracks
+    pub fn open(mut source: S) -> Result<Self, Error<S::Error>> {
+        let mut chunk = [0u8; 14];
+        read_exact(&mut source, 0, &mut chunk)?;
+        if &chunk[0..4] != b"MThd" {
+            return Err(Error::NotMidi);
+        }
+        let header_len = be32(&chunk[4..8]);
+        if header_len < 6 {
+            return Err(Error::Malformed);
+        }
+        let format = be16(&chunk[8..10]);
+        let track_count = be16(&chunk[10..12]);
+        let division = be16(&chunk[12..14]);
+        if format > 1 {
+            return Err(Error::UnsupportedFormat(format));
+        }
+        if division & 0x8000 != 0 {
+            return Err(Error::SmpteTiming);
+        }
+        if division == 0 {
+            return Err(Error::Malformed);
+        }
+        if track_count as usize > MAX_TRACKS {
+            return Err(Error::TooManyTracks(track_count));
+        }
+
+        let mut tracks = [Track::EMPTY; MAX_TRACKS];
+        let mut offset = 8 + header_len;
+        let mut found = 0;
+        while found < track_count as usize {
+            let mut chunk = [0u8; 8];
+            read_exact(&mut source, offset, &mut chunk)?;
+            let len = be32(&chunk[4..8]);
+            let start = offset + 8;
+            offset = start.checked_add(len).ok_or(Error::Malformed)?;
+            // unknown chunk types must be skipped
+            if &chunk[0..4] != b"MTrk" {
+                continue;
+            }
+            let track = &mut tracks[found];
+            *track = Track {
+                pos: start,
+                end: start + len,
+                done: false,
+                ..Track::EMPTY
+            };
+            track.advance(&mut source)?;
+            found += 1;
+        }
+
+        Ok(Self {
+            source,
+            header: Header {
+                format,
+                tracks: track_count,
+                ticks_per_quarter: division,
+            },
+            tracks,
+        })
+    }
+
+    pub fn header(&self) -> Header {
+        self.header
+    }
+
+    /// Next event from any track, in time order
+    pub fn next_event(&mut self) -> Result<Option<TimedEvent>, Error<S::Error>> {
+        loop {
+            // Earliest track first; on a tie the lower track, so the tempo
+            // track of a format 1 file goes before the notes
+            let Some(track) = self
+                .tracks
+                .iter_mut()
+                .filter(|t| !t.done)
+                .min_by_key(|t| t.tick)
+            else {
+                return Ok(None);
+            };
+            let tick = track.tick;
+            let event = track.event(&mut self.source)?;
+            track.advance(&mut self.source)?;
+            if let Some(event) = event {
+                return Ok(Some(TimedEvent { tick, event }));
+            }
+        }
+    }
+}
+
+/// Sounding note changes: `key` starts at `at_ms`, `None` is silence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Change {
+    pub at_ms: u32,
+    pub key: Option<u8>,
+}
+
+/// Reduces one channel to a single voice: while several notes are held, the
+/// highest one sounds.
+pub struct Monophonic<S: ByteSource> {
+    smf: Smf<S>,
+    channel: u8,
+    /// One bit per key that is held down
+    held: u128,
+    sounding: Option<u8>,
+    /// Event read ahead while collecting everything at one tick
+    peeked: Option<TimedEvent>,
+    tempo: u32,
+    last_tick: u64,
+    time_us: u64,
+}
+
+impl<S: ByteSource> Monophonic<S> {
+    /// `channel` is 0..=15 (MIDI channel 1 is 0)
+    pub fn new(smf: Smf<S>, channel: u8) -> Self {
+        Self {
+            smf,
+            channel,
+            held: 0,
+            sounding: None,
+            peeked: None,
+            tempo: DEFAULT_TEMPO,
+            last_tick: 0,
+            time_us: 0,
+        }
+    }
+
+    pub fn next_change(&mut self) -> Result<Option<Change>, Error<S::Error>> {
+        loop {
+            let next = match self.peeked.take() {
+                Some(event) => Some(event),
+                None => self.smf.next_event()?,
+            };
+            let Some(TimedEvent { tick, event }) = next else {
+                // report the last change, then silence at the end
+                if let Some(change) = self.update() {
+                    return Ok(Some(change));
+                }
+                self.held = 0;
+                return Ok(self.update());
+            };
+
+            if tick != self.last_tick {
+                // All events at the previous tick are in; report the result
+                // only now, so a chord or note change does not glitch
+                if let Some(change) = self.update() {
+                    self.peeked = Some(TimedEvent { tick, event });
+                    return Ok(Some(change));
+                }
+                let ticks_per_quarter = self.smf.header.ticks_per_quarter as u64;
+                self.time_us += (tick - self.last_tick) * self.tempo as u64 / ticks_per_quarter;
+                self.last_tick = tick;
+            }
+
+            match event {
+                Event::Tempo(tempo) => self.tempo = tempo,
+                Event::NoteOn { channel, key, .. } if channel == self.channel => {
+                    self.held |= 1 << (key & 0x7F);
+                }
+                Event::NoteOff { channel, key } if channel == self.channel => {
+                    self.held &= !(1 << (key & 0x7F));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn update(&mut self) -> Option<Change> {
+        let highest = (self.held != 0).then(|| 127 - self.held.leading_zeros() as u8);
+        if highest == self.sounding {
+            return None;
+        }
+        self.sounding = highest;
+        Some(Change {
+            at_ms: (self.time_us / 1000) as u32,
+            key: highest,
+        })
+    }
+}
+
+fn read_exact<S: ByteSource>(
+    source: &mut S,
+    mut offset: u32,
+    mut buf: &mut [u8],
+) -> Result<(), Error<S::Error>> {
+    while !buf.is_empty() {
+        let len = source.read_at(offset, buf).map_err(Error::Io)?;
+        if len == 0 {
+            return Err(Error::Truncated);
+        }
+        offset += len as u32;
+        buf = &mut buf[len..];
+    }
+    Ok(())
+}
+
+fn be16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    const FORMAT0: &[u8] = include_bytes!("../fixtures/format0.mid");
+    const FORMAT1: &[u8] = include_bytes!("../fixtures/format1.mid");
+
+    /// Hands out at most three bytes per read, like a slow stream
+    struct Trickle<'a>(&'a [u8]);
+
+    impl ByteSource for Trickle<'_> {
+        type Error = ();
+
+        fn read_at(&mut self, offset: u32, buf: &mut [u8]) -> Result<usize, ()> {
+            let len = buf.len().min(3);
+            Ok(self.0.read_at(offset, &mut buf[..len]).unwrap())
+        }
+    }
+
+    fn changes<S: ByteSource>(source: S, channel: u8) -> Vec<(u32, Option<u8>)>
+    where
+        S::Error: core::fmt::Debug,
+    {
+        let mut mono = Monophonic::new(Smf::open(source).unwrap(), channel);
+        let mut changes = Vec::new();
+        while let Some(change) = mono.next_change().unwrap() {
+            changes.push((change.at_ms, change.key));
+        }
+        changes
+    }
+
+    #[test]
+    fn format0_events() {
+        let mut smf = Smf::open(FORMAT0).unwrap();
+        assert_eq!(
+            smf.header(),
+            Header {
+                format: 0,
+                tracks: 1,
+                ticks_per_quarter: 96
+            }
+        );
+
+        let mut events = Vec::new();
+        while let Some(event) = smf.next_event().unwrap() {
+            events.push((event.tick, event.event));
+        }
+        let on = |key| Event::NoteOn {
+            channel: 0,
+            key,
+            velocity: 0x40,
+        };
+        let off = |key| Event::NoteOff { channel: 0, key };
+        assert_eq!(
+            events,
+            [
+                (0, Event::Tempo(500_000)),
+                (0, on(60)),
+                // velocity 0 is a note off, then running status
+                (96, off(60)),
+                (96, on(62)),
+                // two byte delta time
+                (576, off(62)),
+            ]
+        );
+    }
+
+    #[test]
+    fn format0_melody() {
+        // C4 for a quarter at 120 bpm, then D4 for five quarters
+        let expected = [(0, Some(60)), (500, Some(62)), (3000, None)];
+        assert_eq!(changes(FORMAT0, 0), expected);
+        assert_eq!(changes(Trickle(FORMAT0), 0), expected);
+        // nothing plays on another channel
+        assert_eq!(changes(FORMAT0, 1), []);
+    }
+
+    #[test]
+    fn format1_merges_tracks() {
+        let smf = Smf::open(FORMAT1).unwrap();
+        assert_eq!(smf.header().format, 1);
+        assert_eq!(smf.header().tracks, 3);
+
+        // 60 bpm for two quarters, then 120 bpm from the tempo track.
+        // E4 wins over C4 while both are held; the same-tick C4 off / G4 on
+        // does not produce a gap.
+        let expected = [
+            (0, Some(64)),
+            (1000, Some(60)),
+            (2000, Some(67)),
+            (2500, None),
+        ];
+        assert_eq!(changes(FORMAT1, 0), expected);
+        assert_eq!(changes(Trickle(FORMAT1), 0), expected);
+
+        // channel 2 holds A5 for the whole song, past the SysEx message
+        assert_eq!(changes(FORMAT1, 1), [(0, Some(81)), (2500, None)]);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let err = |data: &[u8]| Smf::open(data).err().unwrap();
+        assert_eq!(err(b"RIFF\0\0\0\x06\0\0\0\x01\0\x60"), Error::NotMidi);
+        assert_eq!(
+            err(b"MThd\0\0\0\x06\0\x02\0\x01\0\x60"),
+            Error::UnsupportedFormat(2)
+        );
+        assert_eq!(err(b"MThd\0\0\0\x06\0\0\0\x01\xE7\x28"), Error::SmpteTiming);
+        assert_eq!(
+            err(b"MThd\0\0\0\x06\0\x01\0\x20\0\x60"),
+            Error::TooManyTracks(32)
+        );
+        assert_eq!(err(&FORMAT0[..10]), Error::Truncated);
+
+        // the last event is cut off
+        let mut smf = Smf::open(&FORMAT0[..FORMAT0.len() - 6]).unwrap();
+        let result = loop {
+            match smf.next_event() {
+                Ok(Some(_)) => continue,
+                other => break other,
+            }
+        };
+        assert_eq!(result, Err(Error::Truncated));
+    }
+}
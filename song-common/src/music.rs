@@ -0,0 +1,432 @@
+//! Notes, note lengths and songs.
+//!
+//! A [`Pitch`] is a note name with an accidental and an octave (`C#4`), and
+//! its frequency comes from equal temperament around A4. A [`NoteLength`] is
+//! a plain, dotted or triplet whole/half/quarter/... note, and a [`Song`]
+//! turns both into milliseconds and Hertz for a tempo, transposition and
+//! tuning.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Letter {
+    C,
+    D,
+    E,
+    F,
+    G,
+    A,
+    B,
+}
+
+impl Letter {
+    /// Semitones above C
+    pub const fn semitone(self) -> i16 {
+        match self {
+            Letter::C => 0,
+            Letter::D => 2,
+            Letter::E => 4,
+            Letter::F => 5,
+            Letter::G => 7,
+            Letter::A => 9,
+            Letter::B => 11,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accidental {
+    Flat,
+    Natural,
+    Sharp,
+}
+
+impl Accidental {
+    pub const fn offset(self) -> i16 {
+        match self {
+            Accidental::Flat => -1,
+            Accidental::Natural => 0,
+            Accidental::Sharp => 1,
+        }
+    }
+}
+
+/// Standard concert pitch for A4
+pub const A4_HZ: f32 = 440.0;
+
+// MIDI note number of A4
+const A4_MIDI: i16 = 69;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pitch {
+    pub letter: Letter,
+    pub accidental: Accidental,
+    /// Scientific pitch notation: C4 is middle C, A4 is 440 Hz
+    pub octave: i8,
+}
+
+impl Pitch {
+    pub const fn new(letter: Letter, accidental: Accidental, octave: i8) -> Self {
+        Self {
+            letter,
+            accidental,
+            octave,
+        }
+    }
+
+    /// MIDI note number; C4 is 60. Enharmonic pitches (C#4, Db4) are equal.
+    pub const fn midi(self) -> i16 {
+        (self.octave as i16 + 1) * 12 + self.letter.semitone() + self.accidental.offset()
+    }
+
+    /// Pitch for a MIDI note number, spelled with sharps
+    pub const fn from_midi(midi: i16) -> Self {
+        const SPELLING: [(Letter, Accidental); 12] = [
+            (Letter::C, Accidental::Natural),
+            (Letter::C, Accidental::Sharp),
+            (Letter::D, Accidental::Natural),
+            (Letter::D, Accidental::Sharp),
+            (Letter::E, Accidental::Natural),
+            (Letter::F, Accidental::Natural),
+            (Letter::F, Accidental::Sharp),
+            (Letter::G, Accidental::Natural),
+            (Letter::G, Accidental::Sharp),
+            (Letter::A, Accidental::Natural),
+            (Letter::A, Accidental::Sharp),
+            (Letter::B, Accidental::Natural),
+        ];
+        let (letter, accidental) = SPELLING[midi.rem_euclid(12) as usize];
+        Self::new(letter, accidental, (midi.div_euclid(12) - 1) as i8)
+    }
+
+    /// Move up (or down, if negative) by `semitones`
+    pub const fn transpose(self, semitones: i8) -> Self {
+        Self::from_midi(self.midi() + semitones as i16)
+    }
+
+    /// Equal temperament frequency with A4 tuned to `a4_hz`
+    pub fn frequency(self, a4_hz: f32) -> f32 {
+        midi_frequency(self.midi(), a4_hz)
+    }
+}
+
+fn midi_frequency(midi: i16, a4_hz: f32) -> f32 {
+    a4_hz * libm::powf(2.0, (midi - A4_MIDI) as f32 / 12.0)
+}
+
+/// Undotted note values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Base {
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+}
+
+impl Base {
+    /// Notes of this value that fit in a whole note
+    pub const fn divider(self) -> u32 {
+        match self {
+            Base::Whole => 1,
+            Base::Half => 2,
+            Base::Quarter => 4,
+            Base::Eighth => 8,
+            Base::Sixteenth => 16,
+            Base::ThirtySecond => 32,
+        }
+    }
+
+    /// Value for a divider (1, 2, 4, .. 32)
+    pub const fn from_divider(divider: u32) -> Option<Self> {
+        match divider {
+            1 => Some(Base::Whole),
+            2 => Some(Base::Half),
+            4 => Some(Base::Quarter),
+            8 => Some(Base::Eighth),
+            16 => Some(Base::Sixteenth),
+            32 => Some(Base::ThirtySecond),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteLength {
+    Plain(Base),
+    /// One and a half times as long
+    Dotted(Base),
+    /// Three in the time of two
+    Triplet(Base),
+}
+
+impl NoteLength {
+    /// Length as a fraction of a whole note
+    pub const fn fraction(self) -> (u32, u32) {
+        match self {
+            NoteLength::Plain(base) => (1, base.divider()),
+            NoteLength::Dotted(base) => (3, base.divider() * 2),
+            NoteLength::Triplet(base) => (2, base.divider() * 3),
+        }
+    }
+}
+
+/// A note to play, or a rest when `pitch` is `None`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    pub pitch: Option<Pitch>,
+    pub length: NoteLength,
+}
+
+impl Note {
+    pub const fn tone(pitch: Pitch, length: NoteLength) -> Self {
+        Self {
+            pitch: Some(pitch),
+            length,
+        }
+    }
+
+    pub const fn rest(length: NoteLength) -> Self {
+        Self {
+            pitch: None,
+            length,
+        }
+    }
+}
+
+pub struct Song {
+    tempo: u16,
+    tempo_percent: u16,
+    transpose: i8,
+    a4_hz: f32,
+}
+
+impl Song {
+    /// `tempo` is in quarter notes per minute
+    pub fn new(tempo: u16) -> Self {
+        Self {
+            tempo,
+            tempo_percent: 100,
+            transpose: 0,
+            a4_hz: A4_HZ,
+        }
+    }
+
+    /// Play faster or slower; 100 is the written tempo, 200 twice as fast
+    pub fn with_tempo_scale(mut self, percent: u16) -> Self {
+        self.tempo_percent = percent.max(1);
+        self
+    }
+
+    /// Shift every note by `semitones`
+    pub fn with_transpose(mut self, semitones: i8) -> Self {
+        self.transpose = semitones;
+        self
+    }
+
+    /// Tune A4 to something other than 440 Hz
+    pub fn with_reference(mut self, a4_hz: f32) -> Self {
+        self.a4_hz = a4_hz;
+        self
+    }
+
+    /// Quarter notes per minute after scaling
+    pub fn tempo(&self) -> u32 {
+        (self.tempo as u32 * self.tempo_percent as u32 / 100).max(1)
+    }
+
+    pub fn duration_ms(&self, length: NoteLength) -> u32 {
+        let whole_note = 60_000 * 4;
+        let (numerator, denominator) = length.fraction();
+        whole_note * numerator / (self.tempo() * denominator)
+    }
+
+    pub fn frequency(&self, pitch: Pitch) -> f32 {
+        midi_frequency(pitch.midi() + self.transpose as i16, self.a4_hz)
+    }
+}
+
+// Pitches of the piano keys, spelled with sharps
+pub const NOTE_B0: Pitch = Pitch::new(Letter::B, Accidental::Natural, 0);
+pub const NOTE_C1: Pitch = Pitch::new(Letter::C, Accidental::Natural, 1);
+pub const NOTE_CS1: Pitch = Pitch::new(Letter::C, Accidental::Sharp, 1);
+pub const NOTE_D1: Pitch = Pitch::new(Letter::D, Accidental::Natural, 1);
+pub const NOTE_DS1: Pitch = Pitch::new(Letter::D, Accidental::Sharp, 1);
+pub const NOTE_E1: Pitch = Pitch::new(Letter::E, Accidental::Natural, 1);
+pub const NOTE_F1: Pitch = Pitch::new(Letter::F, Accidental::Natural, 1);
+pub const NOTE_FS1: Pitch = Pitch::new(Letter::F, Accidental::Sharp, 1);
+pub const NOTE_G1: Pitch = Pitch::new(Letter::G, Accidental::Natural, 1);
+pub const NOTE_GS1: Pitch = Pitch::new(Letter::G, Accidental::Sharp, 1);
+pub const NOTE_A1: Pitch = Pitch::new(Letter::A, Accidental::Natural, 1);
+pub const NOTE_AS1: Pitch = Pitch::new(Letter::A, Accidental::Sharp, 1);
+pub const NOTE_B1: Pitch = Pitch::new(Letter::B, Accidental::Natural, 1);
+pub const NOTE_C2: Pitch = Pitch::new(Letter::C, Accidental::Natural, 2);
+pub const NOTE_CS2: Pitch = Pitch::new(Letter::C, Accidental::Sharp, 2);
+pub const NOTE_D2: Pitch = Pitch::new(Letter::D, Accidental::Natural, 2);
+pub const NOTE_DS2: Pitch = Pitch::new(Letter::D, Accidental::Sharp, 2);
+pub const NOTE_E2: Pitch = Pitch::new(Letter::E, Accidental::Natural, 2);
+pub const NOTE_F2: Pitch = Pitch::new(Letter::F, Accidental::Natural, 2);
+pub const NOTE_FS2: Pitch = Pitch::new(Letter::F, Accidental::Sharp, 2);
+pub const NOTE_G2: Pitch = Pitch::new(Letter::G, Accidental::Natural, 2);
+pub const NOTE_GS2: Pitch = Pitch::new(Letter::G, Accidental::Sharp, 2);
+pub const NOTE_A2: Pitch = Pitch::new(Letter::A, Accidental::Natural, 2);
+pub const NOTE_AS2: Pitch = Pitch::new(Letter::A, Accidental::Sharp, 2);
+pub const NOTE_B2: Pitch = Pitch::new(Letter::B, Accidental::Natural, 2);
+pub const NOTE_C3: Pitch = Pitch::new(Letter::C, Accidental::Natural, 3);
+pub const NOTE_CS3: Pitch = Pitch::new(Letter::C, Accidental::Sharp, 3);
+pub const NOTE_D3: Pitch = Pitch::new(Letter::D, Accidental::Natural, 3);
+pub const NOTE_DS3: Pitch = Pitch::new(Letter::D, Accidental::Sharp, 3);
+pub const NOTE_E3: Pitch = Pitch::new(Letter::E, Accidental::Natural, 3);
+pub const NOTE_F3: Pitch = Pitch::new(Letter::F, Accidental::Natural, 3);
+pub const NOTE_FS3: Pitch = Pitch::new(Letter::F, Accidental::Sharp, 3);
+pub const NOTE_G3: Pitch = Pitch::new(Letter::G, Accidental::Natural, 3);
+pub const NOTE_GS3: Pitch = Pitch::new(Letter::G, Accidental::Sharp, 3);
+pub const NOTE_A3: Pitch = Pitch::new(Letter::A, Accidental::Natural, 3);
+pub const NOTE_AS3: Pitch = Pitch::new(Letter::A, Accidental::Sharp, 3);
+pub const NOTE_B3: Pitch = Pitch::new(Letter::B, Accidental::Natural, 3);
+pub const NOTE_C4: Pitch = Pitch::new(Letter::C, Accidental::Natural, 4);
+pub const NOTE_CS4: Pitch = Pitch::new(Letter::C, Accidental::Sharp, 4);
+pub const NOTE_D4: Pitch = Pitch::new(Letter::D, Accidental::Natural, 4);
+pub const NOTE_DS4: Pitch = Pitch::new(Letter::D, Accidental::Sharp, 4);
+pub const NOTE_E4: Pitch = Pitch::new(Letter::E, Accidental::Natural, 4);
+pub const NOTE_F4: Pitch = Pitch::new(Letter::F, Accidental::Natural, 4);
+pub const NOTE_FS4: Pitch = Pitch::new(Letter::F, Accidental::Sharp, 4);
+pub const NOTE_G4: Pitch = Pitch::new(Letter::G, Accidental::Natural, 4);
+pub const NOTE_GS4: Pitch = Pitch::new(Letter::G, Accidental::Sharp, 4);
+pub const NOTE_A4: Pitch = Pitch::new(Letter::A, Accidental::Natural, 4);
+pub const NOTE_AS4: Pitch = Pitch::new(Letter::A, Accidental::Sharp, 4);
+pub const NOTE_B4: Pitch = Pitch::new(Letter::B, Accidental::Natural, 4);
+pub const NOTE_C5: Pitch = Pitch::new(Letter::C, Accidental::Natural, 5);
+pub const NOTE_CS5: Pitch = Pitch::new(Letter::C, Accidental::Sharp, 5);
+pub const NOTE_D5: Pitch = Pitch::new(Letter::D, Accidental::Natural, 5);
+pub const NOTE_DS5: Pitch = Pitch::new(Letter::D, Accidental::Sharp, 5);
+pub const NOTE_E5: Pitch = Pitch::new(Letter::E, Accidental::Natural, 5);
+pub const NOTE_F5: Pitch = Pitch::new(Letter::F, Accidental::Natural, 5);
+pub const NOTE_FS5: Pitch = Pitch::new(Letter::F, Accidental::Sharp, 5);
+pub const NOTE_G5: Pitch = Pitch::new(Letter::G, Accidental::Natural, 5);
+pub const NOTE_GS5: Pitch = Pitch::new(Letter::G, Accidental::Sharp, 5);
+pub const NOTE_A5: Pitch = Pitch::new(Letter::A, Accidental::Natural, 5);
+pub const NOTE_AS5: Pitch = Pitch::new(Letter::A, Accidental::Sharp, 5);
+pub const NOTE_B5: Pitch = Pitch::new(Letter::B, Accidental::Natural, 5);
+pub const NOTE_C6: Pitch = Pitch::new(Letter::C, Accidental::Natural, 6);
+pub const NOTE_CS6: Pitch = Pitch::new(Letter::C, Accidental::Sharp, 6);
+pub const NOTE_D6: Pitch = Pitch::new(Letter::D, Accidental::Natural, 6);
+pub const NOTE_DS6: Pitch = Pitch::new(Letter::D, Accidental::Sharp, 6);
+pub const NOTE_E6: Pitch = Pitch::new(Letter::E, Accidental::Natural, 6);
+pub const NOTE_F6: Pitch = Pitch::new(Letter::F, Accidental::Natural, 6);
+pub const NOTE_FS6: Pitch = Pitch::new(Letter::F, Accidental::Sharp, 6);
+pub const NOTE_G6: Pitch = Pitch::new(Letter::G, Accidental::Natural, 6);
+pub const NOTE_GS6: Pitch = Pitch::new(Letter::G, Accidental::Sharp, 6);
+pub const NOTE_A6: Pitch = Pitch::new(Letter::A, Accidental::Natural, 6);
+pub const NOTE_AS6: Pitch = Pitch::new(Letter::A, Accidental::Sharp, 6);
+pub const NOTE_B6: Pitch = Pitch::new(Letter::B, Accidental::Natural, 6);
+pub const NOTE_C7: Pitch = Pitch::new(Letter::C, Accidental::Natural, 7);
+pub const NOTE_CS7: Pitch = Pitch::new(Letter::C, Accidental::Sharp, 7);
+pub const NOTE_D7: Pitch = Pitch::new(Letter::D, Accidental::Natural, 7);
+pub const NOTE_DS7: Pitch = Pitch::new(Letter::D, Accidental::Sharp, 7);
+pub const NOTE_E7: Pitch = Pitch::new(Letter::E, Accidental::Natural, 7);
+pub const NOTE_F7: Pitch = Pitch::new(Letter::F, Accidental::Natural, 7);
+pub const NOTE_FS7: Pitch = Pitch::new(Letter::F, Accidental::Sharp, 7);
+pub const NOTE_G7: Pitch = Pitch::new(Letter::G, Accidental::Natural, 7);
+pub const NOTE_GS7: Pitch = Pitch::new(Letter::G, Accidental::Sharp, 7);
+pub const NOTE_A7: Pitch = Pitch::new(Letter::A, Accidental::Natural, 7);
+pub const NOTE_AS7: Pitch = Pitch::new(Letter::A, Accidental::Sharp, 7);
+pub const NOTE_B7: Pitch = Pitch::new(Letter::B, Accidental::Natural, 7);
+pub const NOTE_C8: Pitch = Pitch::new(Letter::C, Accidental::Natural, 8);
+pub const NOTE_CS8: Pitch = Pitch::new(Letter::C, Accidental::Sharp, 8);
+pub const NOTE_D8: Pitch = Pitch::new(Letter::D, Accidental::Natural, 8);
+pub const NOTE_DS8: Pitch = Pitch::new(Letter::D, Accidental::Sharp, 8);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Base::*;
+    use NoteLength::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.01
+    }
+
+    #[test]
+    fn midi_numbers() {
+        assert_eq!(NOTE_C4.midi(), 60);
+        assert_eq!(NOTE_A4.midi(), 69);
+        assert_eq!(NOTE_B0.midi(), 23);
+        let d_flat = Pitch::new(Letter::D, Accidental::Flat, 4);
+        assert_eq!(d_flat.midi(), NOTE_CS4.midi());
+        // Cb4 is B3 and B#3 is C4
+        let c_flat = Pitch::new(Letter::C, Accidental::Flat, 4);
+        assert_eq!(Pitch::from_midi(c_flat.midi()), NOTE_B3);
+        let b_sharp = Pitch::new(Letter::B, Accidental::Sharp, 3);
+        assert_eq!(Pitch::from_midi(b_sharp.midi()), NOTE_C4);
+    }
+
+    #[test]
+    fn transpose_crosses_octaves() {
+        assert_eq!(NOTE_B4.transpose(1), NOTE_C5);
+        assert_eq!(NOTE_C4.transpose(-1), NOTE_B3);
+        assert_eq!(NOTE_E4.transpose(12), NOTE_E5);
+        assert_eq!(NOTE_DS4.transpose(-27), NOTE_C2);
+        assert_eq!(
+            Pitch::from_midi(-1),
+            Pitch::new(Letter::B, Accidental::Natural, -2)
+        );
+    }
+
+    #[test]
+    fn equal_temperament() {
+        assert!(close(NOTE_A4.frequency(A4_HZ), 440.0));
+        assert!(close(NOTE_A5.frequency(A4_HZ), 880.0));
+        assert!(close(NOTE_C4.frequency(A4_HZ), 261.63));
+        assert!(close(NOTE_DS8.frequency(A4_HZ), 4978.03));
+        // baroque tuning
+        assert!(close(NOTE_A4.frequency(415.0), 415.0));
+        assert!(close(NOTE_E5.frequency(415.0), 621.80));
+    }
+
+    #[test]
+    fn every_length_at_120_bpm() {
+        let song = Song::new(120);
+        let expected = [
+            (Whole, 2000, 3000, 1333),
+            (Half, 1000, 1500, 666),
+            (Quarter, 500, 750, 333),
+            (Eighth, 250, 375, 166),
+            (Sixteenth, 125, 187, 83),
+            (ThirtySecond, 62, 93, 41),
+        ];
+        for (base, plain, dotted, triplet) in expected {
+            assert_eq!(song.duration_ms(Plain(base)), plain, "{base:?}");
+            assert_eq!(song.duration_ms(Dotted(base)), dotted, "{base:?}");
+            assert_eq!(song.duration_ms(Triplet(base)), triplet, "{base:?}");
+        }
+    }
+
+    #[test]
+    fn three_triplets_fill_two_notes() {
+        let song = Song::new(90);
+        let (n, d) = Triplet(Eighth).fraction();
+        let (qn, qd) = Plain(Quarter).fraction();
+        assert_eq!(3 * n * qd, qn * d);
+        assert_eq!(song.duration_ms(Plain(Quarter)), 666);
+    }
+
+    #[test]
+    fn divider_round_trip() {
+        for base in [Whole, Half, Quarter, Eighth, Sixteenth, ThirtySecond] {
+            assert_eq!(Base::from_divider(base.divider()), Some(base));
+        }
+        assert_eq!(Base::from_divider(3), None);
+    }
+
+    #[test]
+    fn song_scales_tempo_and_transposes() {
+        let song = Song::new(120).with_tempo_scale(200);
+        assert_eq!(song.tempo(), 240);
+        assert_eq!(song.duration_ms(Plain(Quarter)), 250);
+
+        let song = Song::new(120).with_transpose(12);
+        assert!(close(song.frequency(NOTE_A4), 880.0));
+        let song = Song::new(120).with_transpose(-12).with_reference(432.0);
+        assert!(close(song.frequency(NOTE_A4), 216.0));
+    }
+}
@@ -0,0 +1,157 @@
+//! Sample rate conversion and volume for PCM audio.
+//!
+//! [`Resampler`] converts a stream from the file's rate to the output's rate
+//! with linear interpolation. That is cheap enough to run per sample on the
+//! ESP32 and sounds fine for speech and sound effects; it does not filter,
+//! so downsampling music can alias.
+
+// Fractional position in 1/65536 of an input sample
+const FRACTION_BITS: u32 = 16;
+const ONE: u32 = 1 << FRACTION_BITS;
+
+pub struct Resampler {
+    /// Input samples per output sample, 16.16 fixed point
+    step: u32,
+    /// Position of the next output sample after `previous`
+    position: u32,
+    previous: i16,
+    /// `previous` holds a real sample (the stream has started)
+    primed: bool,
+}
+
+impl Resampler {
+    pub fn new(from_hz: u32, to_hz: u32) -> Self {
+        let step = ((from_hz as u64) << FRACTION_BITS) / to_hz.max(1) as u64;
+        Self {
+            step: (step as u32).max(1),
+            position: 0,
+            previous: 0,
+            primed: false,
+        }
+    }
+
+    /// Input and output rates are the same
+    pub fn is_passthrough(&self) -> bool {
+        self.step == ONE
+    }
+
+    /// Convert as much of `input` as fits into `output`.
+    ///
+    /// Returns the number of input samples used and output samples written;
+    /// pass the unused input again on the next call.
+    pub fn process(&mut self, input: &[i16], output: &mut [i16]) -> (usize, usize) {
+        let mut used = 0;
+        let mut written = 0;
+        if !self.primed {
+            let Some(&first) = input.first() else {
+                return (0, 0);
+            };
+            self.previous = first;
+            self.primed = true;
+            used = 1;
+        }
+
+        while written < output.len() {
+            // move along until the output position lies between `previous`
+            // and the next input sample
+            while self.position >= ONE {
+                let Some(&sample) = input.get(used) else {
+                    return (used, written);
+                };
+                self.previous = sample;
+                self.position -= ONE;
+                used += 1;
+            }
+            let Some(&next) = input.get(used) else {
+                break;
+            };
+            let delta = (next as i64 - self.previous as i64) * self.position as i64;
+            output[written] = (self.previous as i64 + (delta >> FRACTION_BITS)) as i16;
+            written += 1;
+            self.position += self.step;
+        }
+        (used, written)
+    }
+}
+
+/// Scale `samples` by `volume` percent
+pub fn apply_volume(samples: &mut [i16], volume: u8) {
+    let volume = volume.min(100) as i32;
+    for sample in samples {
+        *sample = (*sample as i32 * volume / 100) as i16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Feed `input` in chunks of `chunk` samples into a small output buffer
+    fn run(resampler: &mut Resampler, input: &[i16], chunk: usize) -> Vec<i16> {
+        let mut result = Vec::new();
+        let mut out = [0i16; 3];
+        let mut pending = input;
+        loop {
+            let end = pending.len().min(chunk);
+            let (used, written) = resampler.process(&pending[..end], &mut out);
+            result.extend_from_slice(&out[..written]);
+            pending = &pending[used..];
+            if used == 0 && written == 0 {
+                return result;
+            }
+        }
+    }
+
+    #[test]
+    fn same_rate_passes_through() {
+        let mut resampler = Resampler::new(16000, 16000);
+        assert!(resampler.is_passthrough());
+        let input = [0, 100, -100, 32767, -32768, 5];
+        // the last sample is held back until the next one arrives
+        assert_eq!(run(&mut resampler, &input, 4), input[..5]);
+    }
+
+    #[test]
+    fn upsampling_interpolates() {
+        let mut resampler = Resampler::new(8000, 16000);
+        assert!(!resampler.is_passthrough());
+        assert_eq!(
+            run(&mut resampler, &[0, 100, 300, -100], 2),
+            [0, 50, 100, 200, 300, 100]
+        );
+
+        // full scale jumps must not overflow
+        let mut resampler = Resampler::new(8000, 16000);
+        assert_eq!(
+            run(&mut resampler, &[-32768, 32767, -32768], 8),
+            [-32768, -1, 32767, -1]
+        );
+    }
+
+    #[test]
+    fn downsampling_skips() {
+        let mut resampler = Resampler::new(44100, 22050);
+        let input: Vec<i16> = (0..10).map(|i| i * 10).collect();
+        assert_eq!(run(&mut resampler, &input, 3), [0, 20, 40, 60, 80]);
+
+        // odd ratios keep their average rate over a long stream
+        let mut resampler = Resampler::new(22050, 16000);
+        let input = [0i16; 2205];
+        let produced = run(&mut resampler, &input, 100).len();
+        assert!((1598..=1600).contains(&produced));
+    }
+
+    #[test]
+    fn volume_scales() {
+        let mut samples = [1000, -1000, 32767];
+        apply_volume(&mut samples, 50);
+        assert_eq!(samples, [500, -500, 16383]);
+        apply_volume(&mut samples, 150);
+        assert_eq!(samples, [500, -500, 16383]);
+        apply_volume(&mut samples, 0);
+        assert_eq!(samples, [0; 3]);
+    }
+}
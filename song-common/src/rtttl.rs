@@ -0,0 +1,325 @@
+//! RTTTL (Nokia ring tone) parser.
+//!
+//! A tune looks like `"PinkPanther:d=4,o=5,b=160:8d#,8e,2p,8f#"`: a name, the
+//! default duration, octave and tempo, then comma separated notes. Every note
+//! is `[duration]letter[#][.][octave][.]`, where `p` is a pause.
+//!
+//! Notes come out as the same [`Note`]s as the hand-written melodies:
+//!
+//! ```ignore
+//! let tune = Rtttl::parse("Beep:d=8,o=5,b=120:c,e,g")?;
+//! let song = Song::new(tune.tempo());
+//! for note in tune.notes() {
+//!     let note = note?;
+//!     let ms = song.duration_ms(note.length);
+//!     // ...
+//! }
+//! ```
+
+use crate::music::{Accidental, Base, Letter, Note, NoteLength, Pitch};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The name, defaults and notes sections are not separated by `:`
+    MissingSection,
+    /// A `d=`, `o=` or `b=` value is unknown or out of range
+    InvalidDefault,
+    /// Note at the given index (counting from 0) could not be parsed
+    InvalidNote(usize),
+}
+
+/// Values used when a note leaves out its duration or octave
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Defaults {
+    pub duration: u8,
+    pub octave: u8,
+    pub bpm: u16,
+}
+
+impl Default for Defaults {
+    // Defaults from the RTTTL specification
+    fn default() -> Self {
+        Self {
+            duration: 4,
+            octave: 6,
+            bpm: 63,
+        }
+    }
+}
+
+pub struct Rtttl<'a> {
+    pub name: &'a str,
+    pub defaults: Defaults,
+    notes: &'a str,
+}
+
+impl<'a> Rtttl<'a> {
+    pub fn parse(text: &'a str) -> Result<Self, Error> {
+        let mut sections = text.splitn(3, ':');
+        let name = sections.next().ok_or(Error::MissingSection)?.trim();
+        let control = sections.next().ok_or(Error::MissingSection)?;
+        let notes = sections.next().ok_or(Error::MissingSection)?;
+
+        let mut defaults = Defaults::default();
+        for setting in control.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting.split_once('=').ok_or(Error::InvalidDefault)?;
+            let value: u16 = value.trim().parse().map_err(|_| Error::InvalidDefault)?;
+            match key.trim() {
+                "d" if is_duration(value) => defaults.duration = value as u8,
+                "o" if is_octave(value) => defaults.octave = value as u8,
+                "b" if value > 0 => defaults.bpm = value,
+                _ => return Err(Error::InvalidDefault),
+            }
+        }
+
+        Ok(Self {
+            name,
+            defaults,
+            notes,
+        })
+    }
+
+    /// Tempo for `Song::new`; RTTTL beats are quarter notes, like `Song`
+    pub fn tempo(&self) -> u16 {
+        self.defaults.bpm
+    }
+
+    pub fn notes(&self) -> Notes<'a> {
+        Notes {
+            notes: self.notes.split(','),
+            defaults: self.defaults,
+            index: 0,
+        }
+    }
+}
+
+pub struct Notes<'a> {
+    notes: core::str::Split<'a, char>,
+    defaults: Defaults,
+    index: usize,
+}
+
+impl Iterator for Notes<'_> {
+    type Item = Result<Note, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let note = self.notes.next()?.trim();
+            // tolerate trailing commas and blank entries
+            if note.is_empty() {
+                continue;
+            }
+            let index = self.index;
+            self.index += 1;
+            return Some(parse_note(note, &self.defaults).ok_or(Error::InvalidNote(index)));
+        }
+    }
+}
+
+fn is_duration(value: u16) -> bool {
+    Base::from_divider(value as u32).is_some()
+}
+
+fn is_octave(value: u16) -> bool {
+    (4..=7).contains(&value)
+}
+
+fn parse_note(note: &str, defaults: &Defaults) -> Option<Note> {
+    let bytes = note.as_bytes();
+    let mut pos = 0;
+
+    let mut duration = defaults.duration as u16;
+    let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits > 0 {
+        duration = note[..digits].parse().ok()?;
+        pos = digits;
+    }
+    let base = Base::from_divider(duration as u32)?;
+
+    let letter = match bytes.get(pos)?.to_ascii_lowercase() {
+        b'c' => Some(Letter::C),
+        b'd' => Some(Letter::D),
+        b'e' => Some(Letter::E),
+        b'f' => Some(Letter::F),
+        b'g' => Some(Letter::G),
+        b'a' => Some(Letter::A),
+        // some tunes use the German "h" for b
+        b'b' | b'h' => Some(Letter::B),
+        b'p' => None,
+        _ => return None,
+    };
+    pos += 1;
+
+    let mut accidental = Accidental::Natural;
+    if bytes.get(pos) == Some(&b'#') {
+        letter?;
+        accidental = Accidental::Sharp;
+        pos += 1;
+    }
+
+    // The dot is allowed before or after the octave ("8c#.5" and "8c#5.")
+    let mut dotted = false;
+    if bytes.get(pos) == Some(&b'.') {
+        dotted = true;
+        pos += 1;
+    }
+
+    let mut octave = defaults.octave;
+    if let Some(&digit) = bytes.get(pos).filter(|b| b.is_ascii_digit()) {
+        octave = digit - b'0';
+        if !is_octave(octave as u16) {
+            return None;
+        }
+        pos += 1;
+    }
+
+    if !dotted && bytes.get(pos) == Some(&b'.') {
+        dotted = true;
+        pos += 1;
+    }
+
+    if pos != bytes.len() {
+        return None;
+    }
+
+    let length = if dotted {
+        NoteLength::Dotted(base)
+    } else {
+        NoteLength::Plain(base)
+    };
+    Some(match letter {
+        Some(letter) => Note::tone(Pitch::new(letter, accidental, octave as i8), length),
+        None => Note::rest(length),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::Base::*;
+    use crate::music::NoteLength::*;
+    use crate::music::*;
+
+    fn notes<const N: usize>(text: &str) -> [Note; N] {
+        let tune = Rtttl::parse(text).unwrap();
+        let mut notes = tune.notes();
+        let result = core::array::from_fn(|_| notes.next().unwrap().unwrap());
+        assert!(notes.next().is_none());
+        result
+    }
+
+    #[test]
+    fn header_and_defaults() {
+        let tune = Rtttl::parse("PinkPanther:d=8,o=4,b=160:c").unwrap();
+        assert_eq!(tune.name, "PinkPanther");
+        assert_eq!(
+            tune.defaults,
+            Defaults {
+                duration: 8,
+                octave: 4,
+                bpm: 160
+            }
+        );
+        assert_eq!(tune.tempo(), 160);
+
+        // missing values fall back to d=4, o=6, b=63
+        let tune = Rtttl::parse("Empty::c").unwrap();
+        assert_eq!(tune.defaults, Defaults::default());
+        assert_eq!(
+            notes::<1>("Empty::c"),
+            [Note::tone(NOTE_C6, Plain(Quarter))]
+        );
+    }
+
+    #[test]
+    fn notes_use_defaults_unless_given() {
+        assert_eq!(
+            notes::<4>("T:d=8,o=5,b=120:c,2d,e7,16p"),
+            [
+                Note::tone(NOTE_C5, Plain(Eighth)),
+                Note::tone(NOTE_D5, Plain(Half)),
+                Note::tone(NOTE_E7, Plain(Eighth)),
+                Note::rest(Plain(Sixteenth)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sharps_and_octaves() {
+        assert_eq!(
+            notes::<4>("T:d=4,o=5,b=100:c#,8d#6,a#4,g#7"),
+            [
+                Note::tone(NOTE_CS5, Plain(Quarter)),
+                Note::tone(NOTE_DS6, Plain(Eighth)),
+                Note::tone(NOTE_AS4, Plain(Quarter)),
+                Note::tone(NOTE_GS7, Plain(Quarter)),
+            ]
+        );
+        // b# sounds like C in the next octave
+        let [note] = notes::<1>("T:o=4:b#");
+        assert_eq!(note.pitch.unwrap().midi(), NOTE_C5.midi());
+    }
+
+    #[test]
+    fn dotted_notes() {
+        assert_eq!(
+            notes::<4>("T:d=4,o=5,b=100:c.,8d#.6,8d#6.,p."),
+            [
+                Note::tone(NOTE_C5, Dotted(Quarter)),
+                Note::tone(NOTE_DS6, Dotted(Eighth)),
+                Note::tone(NOTE_DS6, Dotted(Eighth)),
+                Note::rest(Dotted(Quarter)),
+            ]
+        );
+    }
+
+    #[test]
+    fn whitespace_and_case() {
+        assert_eq!(
+            notes::<3>("T: d=4, o=5, b=100 : C, 8E ,h,"),
+            [
+                Note::tone(NOTE_C5, Plain(Quarter)),
+                Note::tone(NOTE_E5, Plain(Eighth)),
+                Note::tone(NOTE_B5, Plain(Quarter)),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            Rtttl::parse("no sections"),
+            Err(Error::MissingSection)
+        ));
+        assert!(matches!(
+            Rtttl::parse("T:d=3:c"),
+            Err(Error::InvalidDefault)
+        ));
+        assert!(matches!(
+            Rtttl::parse("T:o=9:c"),
+            Err(Error::InvalidDefault)
+        ));
+        assert!(matches!(
+            Rtttl::parse("T:x=1:c"),
+            Err(Error::InvalidDefault)
+        ));
+
+        let tune = Rtttl::parse("T::c,x,3c,c9,p#").unwrap();
+        let mut notes = tune.notes();
+        assert_eq!(notes.next(), Some(Ok(Note::tone(NOTE_C6, Plain(Quarter)))));
+        assert_eq!(notes.next(), Some(Err(Error::InvalidNote(1))));
+        assert_eq!(notes.next(), Some(Err(Error::InvalidNote(2))));
+        assert_eq!(notes.next(), Some(Err(Error::InvalidNote(3))));
+        assert_eq!(notes.next(), Some(Err(Error::InvalidNote(4))));
+        assert_eq!(notes.next(), None);
+    }
+
+    #[test]
+    fn durations_match_song() {
+        let tune = Rtttl::parse("T:d=4,o=5,b=120:8c.").unwrap();
+        let song = Song::new(tune.tempo());
+        let note = tune.notes().next().unwrap().unwrap();
+        // eighth note at 120 bpm is 250 ms, dotted 375 ms
+        assert_eq!(song.duration_ms(note.length), 375);
+    }
+}
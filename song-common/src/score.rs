@@ -0,0 +1,220 @@
+//! Scores with several parts played at the same time.
+//!
+//! Every part is a melody of [`Note`]s like `pink_panther::MELODY`. A duet is
+//! two parts; a chord is written as one note in each of several parts:
+//!
+//! ```ignore
+//! const MELODY: [Note; 2] = [
+//!     Note::tone(NOTE_E5, Plain(Half)),
+//!     Note::tone(NOTE_D5, Plain(Half)),
+//! ];
+//! const BASS: [Note; 1] = [Note::tone(NOTE_C3, Plain(Whole))];
+//! let score = Score::new(120, [&MELODY, &BASS]);
+//! for event in score.events() {
+//!     // NoteOn / NoteOff with the time in ms from the start
+//! }
+//! ```
+
+use crate::music::{Note, Pitch, Song};
+
+pub struct Score<'a, const P: usize> {
+    /// Quarter notes per minute
+    pub tempo: u16,
+    pub parts: [&'a [Note]; P],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    NoteOn { part: usize, pitch: Pitch },
+    NoteOff { part: usize, pitch: Pitch },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedEvent {
+    /// Milliseconds from the start of the score
+    pub at_ms: u32,
+    pub event: Event,
+}
+
+impl<'a, const P: usize> Score<'a, P> {
+    pub const fn new(tempo: u16, parts: [&'a [Note]; P]) -> Self {
+        Self { tempo, parts }
+    }
+
+    pub fn song(&self) -> Song {
+        Song::new(self.tempo)
+    }
+
+    /// Note on and off events of all parts, in time order
+    pub fn events(&self) -> Events<'a, P> {
+        Events {
+            song: self.song(),
+            parts: self.parts.map(|notes| Part {
+                notes,
+                next: 0,
+                at_ms: 0,
+                sounding: None,
+            }),
+        }
+    }
+
+    /// Length of the longest part
+    pub fn duration_ms(&self) -> u32 {
+        let song = self.song();
+        self.parts
+            .iter()
+            .map(|notes| notes.iter().map(|n| song.duration_ms(n.length)).sum())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+struct Part<'a> {
+    notes: &'a [Note],
+    /// Index of the next note to start
+    next: usize,
+    /// When the next note starts
+    at_ms: u32,
+    /// Pitch that is sounding and when it stops
+    sounding: Option<(Pitch, u32)>,
+}
+
+impl Part<'_> {
+    /// Time of the next event of this part
+    fn due(&self) -> Option<u32> {
+        match self.sounding {
+            Some((_, off_ms)) => Some(off_ms),
+            None => (self.next < self.notes.len()).then_some(self.at_ms),
+        }
+    }
+}
+
+pub struct Events<'a, const P: usize> {
+    song: Song,
+    parts: [Part<'a>; P],
+}
+
+impl<const P: usize> Iterator for Events<'_, P> {
+    type Item = TimedEvent;
+
+    fn next(&mut self) -> Option<TimedEvent> {
+        loop {
+            // Earliest part first. A part's note off is always due before its
+            // next note on, and on a tie every note off goes first, so a voice
+            // is free again before the next chord needs it.
+            let (index, at_ms) = self
+                .parts
+                .iter()
+                .enumerate()
+                .filter_map(|(i, part)| part.due().map(|at| (i, at)))
+                .min_by_key(|&(i, at)| (at, self.parts[i].sounding.is_none(), i))?;
+            let part = &mut self.parts[index];
+
+            if let Some((pitch, _)) = part.sounding.take() {
+                return Some(TimedEvent {
+                    at_ms,
+                    event: Event::NoteOff { part: index, pitch },
+                });
+            }
+
+            let note = part.notes[part.next];
+            part.next += 1;
+            let duration = self.song.duration_ms(note.length);
+            part.at_ms += duration;
+            // rests only move the part along
+            if let Some(pitch) = note.pitch {
+                // Stop 10% early so repeated notes are heard separately
+                part.sounding = Some((pitch, at_ms + duration - duration / 10));
+                return Some(TimedEvent {
+                    at_ms,
+                    event: Event::NoteOn { part: index, pitch },
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::music::Base::*;
+    use crate::music::NoteLength::*;
+    use crate::music::*;
+
+    fn on(at_ms: u32, part: usize, pitch: Pitch) -> TimedEvent {
+        TimedEvent {
+            at_ms,
+            event: Event::NoteOn { part, pitch },
+        }
+    }
+
+    fn off(at_ms: u32, part: usize, pitch: Pitch) -> TimedEvent {
+        TimedEvent {
+            at_ms,
+            event: Event::NoteOff { part, pitch },
+        }
+    }
+
+    #[test]
+    fn single_part_with_rest() {
+        // 120 bpm: a quarter note is 500 ms
+        let melody = [
+            Note::tone(NOTE_C4, Plain(Quarter)),
+            Note::rest(Plain(Quarter)),
+            Note::tone(NOTE_C4, Plain(Eighth)),
+        ];
+        let score = Score::new(120, [&melody[..]]);
+        let events: Vec<_> = score.events().collect();
+        assert_eq!(
+            events,
+            [
+                on(0, 0, NOTE_C4),
+                off(450, 0, NOTE_C4),
+                on(1000, 0, NOTE_C4),
+                off(1225, 0, NOTE_C4),
+            ]
+        );
+        assert_eq!(score.duration_ms(), 1250);
+    }
+
+    #[test]
+    fn chord_and_duet_interleave() {
+        let melody = [
+            Note::tone(NOTE_E5, Plain(Half)),
+            Note::tone(NOTE_D5, Plain(Half)),
+        ];
+        let bass = [Note::tone(NOTE_C3, Plain(Whole))];
+        let inner = [
+            Note::tone(NOTE_G4, Plain(Half)),
+            Note::tone(NOTE_F4, Plain(Half)),
+        ];
+        let score = Score::new(120, [&melody[..], &bass, &inner]);
+        let events: Vec<_> = score.events().collect();
+        assert_eq!(
+            events,
+            [
+                on(0, 0, NOTE_E5),
+                on(0, 1, NOTE_C3),
+                on(0, 2, NOTE_G4),
+                off(900, 0, NOTE_E5),
+                off(900, 2, NOTE_G4),
+                on(1000, 0, NOTE_D5),
+                on(1000, 2, NOTE_F4),
+                off(1800, 1, NOTE_C3),
+                off(1900, 0, NOTE_D5),
+                off(1900, 2, NOTE_F4),
+            ]
+        );
+        assert_eq!(score.duration_ms(), 2000);
+    }
+
+    #[test]
+    fn empty_parts() {
+        let score = Score::new(120, [&[][..]; 2]);
+        assert_eq!(score.events().next(), None);
+        assert_eq!(score.duration_ms(), 0);
+    }
+}
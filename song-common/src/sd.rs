@@ -0,0 +1,23 @@
+//! Read songs from a FAT volume on the SD card.
+
+use embedded_sdmmc::{BlockDevice, File, TimeSource};
+
+use crate::midi::ByteSource;
+
+impl<D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize> ByteSource
+    for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+where
+    D: BlockDevice,
+    T: TimeSource,
+{
+    type Error = embedded_sdmmc::Error<D::Error>;
+
+    fn read_at(&mut self, offset: u32, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        // seeking past the end is an error, reading there is not
+        if offset >= self.length() {
+            return Ok(0);
+        }
+        self.seek_from_start(offset)?;
+        self.read(buf)
+    }
+}
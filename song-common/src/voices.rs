@@ -0,0 +1,159 @@
+//! Voice allocation for playing several notes at once.
+//!
+//! Each voice is one LEDC timer and channel, so it can sound a single note.
+//! The allocator hands a free voice to every new note; when all of them are
+//! busy the note that started longest ago is cut off.
+
+/// Result of [`VoiceAllocator::note_on`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation<K> {
+    pub voice: usize,
+    /// Key that was playing on the voice and has been cut off
+    pub stolen: Option<K>,
+}
+
+/// `K` identifies a note, for example its MIDI key, or the part and key when
+/// two parts may play the same pitch
+pub struct VoiceAllocator<K, const N: usize> {
+    /// Key sounding on each voice
+    keys: [Option<K>; N],
+    /// When each voice was last started or released, counted in calls
+    changed: [u32; N],
+    clock: u32,
+}
+
+impl<K: Copy + PartialEq, const N: usize> Default for VoiceAllocator<K, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Copy + PartialEq, const N: usize> VoiceAllocator<K, N> {
+    pub const fn new() -> Self {
+        Self {
+            keys: [None; N],
+            changed: [0; N],
+            clock: 0,
+        }
+    }
+
+    /// Pick a voice for `key`; `None` only if there are no voices at all.
+    ///
+    /// A key that is already sounding keeps its voice, otherwise the voice
+    /// that has been free the longest is used, and with no free voice the
+    /// oldest note is stolen.
+    pub fn note_on(&mut self, key: K) -> Option<Allocation<K>> {
+        let voice = match self.voice(key) {
+            Some(voice) => voice,
+            None => self
+                .oldest(|k| k.is_none())
+                .or_else(|| self.oldest(|_| true))?,
+        };
+        let stolen = self.keys[voice].filter(|&k| k != key);
+        self.keys[voice] = Some(key);
+        self.touch(voice);
+        Some(Allocation { voice, stolen })
+    }
+
+    /// Release `key`; returns the voice that should go silent
+    pub fn note_off(&mut self, key: K) -> Option<usize> {
+        let voice = self.voice(key)?;
+        self.keys[voice] = None;
+        self.touch(voice);
+        Some(voice)
+    }
+
+    pub fn all_off(&mut self) {
+        self.keys = [None; N];
+    }
+
+    /// Voice that is sounding `key`
+    pub fn voice(&self, key: K) -> Option<usize> {
+        self.keys.iter().position(|&k| k == Some(key))
+    }
+
+    /// Key sounding on `voice`
+    pub fn key(&self, voice: usize) -> Option<K> {
+        self.keys.get(voice).copied().flatten()
+    }
+
+    fn oldest(&self, filter: impl Fn(Option<K>) -> bool) -> Option<usize> {
+        (0..N)
+            .filter(|&v| filter(self.keys[v]))
+            .min_by_key(|&v| self.changed[v])
+    }
+
+    fn touch(&mut self, voice: usize) {
+        self.clock += 1;
+        self.changed[voice] = self.clock;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voice(allocation: Option<Allocation<i16>>) -> usize {
+        allocation.unwrap().voice
+    }
+
+    #[test]
+    fn chord_uses_separate_voices() {
+        let mut voices = VoiceAllocator::<i16, 4>::new();
+        assert_eq!(voice(voices.note_on(60)), 0);
+        assert_eq!(voice(voices.note_on(64)), 1);
+        assert_eq!(voice(voices.note_on(67)), 2);
+        assert_eq!(voices.key(1), Some(64));
+
+        assert_eq!(voices.note_off(64), Some(1));
+        assert_eq!(voices.note_off(64), None);
+        assert_eq!(voices.key(1), None);
+    }
+
+    #[test]
+    fn repeated_key_keeps_its_voice() {
+        let mut voices = VoiceAllocator::<i16, 2>::new();
+        voices.note_on(60);
+        voices.note_on(62);
+        assert_eq!(
+            voices.note_on(60),
+            Some(Allocation {
+                voice: 0,
+                stolen: None
+            })
+        );
+    }
+
+    #[test]
+    fn longest_free_voice_is_reused() {
+        let mut voices = VoiceAllocator::<i16, 3>::new();
+        voices.note_on(60);
+        voices.note_on(62);
+        voices.note_on(64);
+        voices.note_off(62);
+        voices.note_off(60);
+        // voice 1 was released first
+        assert_eq!(voice(voices.note_on(65)), 1);
+        assert_eq!(voice(voices.note_on(67)), 0);
+    }
+
+    #[test]
+    fn oldest_note_is_stolen() {
+        let mut voices = VoiceAllocator::<i16, 2>::new();
+        voices.note_on(60);
+        voices.note_on(62);
+        assert_eq!(
+            voices.note_on(64),
+            Some(Allocation {
+                voice: 0,
+                stolen: Some(60)
+            })
+        );
+        assert_eq!(voices.voice(60), None);
+        assert_eq!(voices.note_off(60), None);
+
+        voices.all_off();
+        assert_eq!(voices.key(0), None);
+        assert_eq!(VoiceAllocator::<i16, 0>::new().note_on(60), None);
+    }
+}
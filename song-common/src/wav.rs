@@ -0,0 +1,252 @@
+//! Streaming reader for uncompressed WAV files.
+//!
+//! Only mono PCM with 8 or 16 bits per sample is supported, which is what
+//! the DAC and a single I2S amplifier can play anyway. Samples come out as
+//! signed 16-bit values whatever the file stores, and are read straight from
+//! the [`ByteSource`] a block at a time.
+
+use crate::midi::ByteSource;
+
+// WAVE_FORMAT_PCM and WAVE_FORMAT_EXTENSIBLE
+const FORMAT_PCM: u16 = 1;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error<E> {
+    Io(E),
+    /// Not a RIFF/WAVE file, or the `fmt ` chunk is missing
+    NotWav,
+    /// Compressed, stereo, or not 8/16 bits per sample
+    Unsupported,
+    /// A chunk runs past the end of the file
+    Truncated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Format {
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+}
+
+pub struct Wav<S> {
+    source: S,
+    format: Format,
+    /// Position of the next sample in the file
+    pos: u32,
+    /// End of the `data` chunk
+    end: u32,
+}
+
+impl<S: ByteSource> Wav<S> {
+    /// Read the header and find the samples
+    pub fn open(mut source: S) -> Result<Self, Error<S::Error>> {
+        let mut riff = [0u8; 12];
+        read_exact(&mut source, 0, &mut riff).map_err(|e| match e {
+            Error::Truncated => Error::NotWav,
+            e => e,
+        })?;
+        if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+            return Err(Error::NotWav);
+        }
+
+        // Walk the chunks; `fmt ` must come before `data`, anything else
+        // (LIST, fact, cue, ...) is skipped
+        let mut format = None;
+        let mut pos = 12u32;
+        loop {
+            let mut header = [0u8; 8];
+            match read_exact(&mut source, pos, &mut header) {
+                Err(Error::Truncated) if format.is_none() => return Err(Error::NotWav),
+                result => result?,
+            }
+            let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            let body = pos + 8;
+            match &header[0..4] {
+                b"fmt " => format = Some(read_format(&mut source, body, len)?),
+                b"data" => {
+                    let format = format.ok_or(Error::NotWav)?;
+                    return Ok(Self {
+                        source,
+                        format,
+                        pos: body,
+                        end: body.checked_add(len).ok_or(Error::Truncated)?,
+                    });
+                }
+                _ => {}
+            }
+            // chunks are padded to an even length
+            pos = body
+                .checked_add(len)
+                .and_then(|end| end.checked_add(len & 1))
+                .ok_or(Error::Truncated)?;
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Samples left to read
+    pub fn remaining(&self) -> u32 {
+        (self.end - self.pos) / self.bytes_per_sample()
+    }
+
+    /// Fill `out` with the next samples; returns how many were read, 0 at
+    /// the end. A file cut short ends early instead of failing.
+    pub fn read(&mut self, out: &mut [i16]) -> Result<usize, Error<S::Error>> {
+        let bytes_per_sample = self.bytes_per_sample();
+        let mut buf = [0u8; 64];
+        let mut count = 0;
+        while count < out.len() && self.pos < self.end {
+            let want = ((out.len() - count) as u32 * bytes_per_sample)
+                .min(self.end - self.pos)
+                .min(buf.len() as u32) as usize;
+            let len = self
+                .source
+                .read_at(self.pos, &mut buf[..want])
+                .map_err(Error::Io)?;
+            // a short read ending mid-sample means the file was cut off there
+            let len = len - len % bytes_per_sample as usize;
+            if len == 0 {
+                self.end = self.pos;
+                break;
+            }
+            for bytes in buf[..len].chunks_exact(bytes_per_sample as usize) {
+                out[count] = match *bytes {
+                    // 8-bit samples are unsigned around 128
+                    [b] => (b as i16 - 128) << 8,
+                    [lo, hi] => i16::from_le_bytes([lo, hi]),
+                    _ => unreachable!(),
+                };
+                count += 1;
+            }
+            self.pos += len as u32;
+        }
+        Ok(count)
+    }
+
+    pub fn release(self) -> S {
+        self.source
+    }
+
+    fn bytes_per_sample(&self) -> u32 {
+        self.format.bits_per_sample as u32 / 8
+    }
+}
+
+fn read_format<S: ByteSource>(
+    source: &mut S,
+    pos: u32,
+    len: u32,
+) -> Result<Format, Error<S::Error>> {
+    // WAVEFORMATEX; the extensible header adds the real format further on
+    let mut fmt = [0u8; 26];
+    if len < 16 {
+        return Err(Error::NotWav);
+    }
+    let fmt = &mut fmt[..(len as usize).min(26)];
+    read_exact(source, pos, fmt)?;
+    let u16_at = |i: usize| u16::from_le_bytes([fmt[i], fmt[i + 1]]);
+
+    let mut tag = u16_at(0);
+    if tag == FORMAT_EXTENSIBLE && fmt.len() >= 26 {
+        // first two bytes of the sub-format GUID
+        tag = u16_at(24);
+    }
+    let channels = u16_at(2);
+    let sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+    let bits_per_sample = u16_at(14);
+    if tag != FORMAT_PCM || channels != 1 || sample_rate == 0 || !matches!(bits_per_sample, 8 | 16)
+    {
+        return Err(Error::Unsupported);
+    }
+    Ok(Format {
+        sample_rate,
+        bits_per_sample,
+    })
+}
+
+fn read_exact<S: ByteSource>(
+    source: &mut S,
+    mut pos: u32,
+    mut buf: &mut [u8],
+) -> Result<(), Error<S::Error>> {
+    while !buf.is_empty() {
+        let len = source.read_at(pos, buf).map_err(Error::Io)?;
+        if len == 0 {
+            return Err(Error::Truncated);
+        }
+        pos += len as u32;
+        buf = &mut buf[len..];
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONO8: &[u8] = include_bytes!("../fixtures/mono8.wav");
+    const MONO16: &[u8] = include_bytes!("../fixtures/mono16.wav");
+
+    #[test]
+    fn eight_bit_is_widened() {
+        let mut wav = Wav::open(MONO8).unwrap();
+        assert_eq!(
+            wav.format(),
+            Format {
+                sample_rate: 8000,
+                bits_per_sample: 8
+            }
+        );
+        assert_eq!(wav.remaining(), 5);
+        let mut samples = [0; 8];
+        assert_eq!(wav.read(&mut samples).unwrap(), 5);
+        assert_eq!(samples[..5], [0, 127 << 8, -128 << 8, 64 << 8, -64 << 8]);
+        assert_eq!(wav.read(&mut samples).unwrap(), 0);
+    }
+
+    #[test]
+    fn sixteen_bit_skips_other_chunks() {
+        let mut wav = Wav::open(MONO16).unwrap();
+        assert_eq!(wav.format().sample_rate, 22050);
+        assert_eq!(wav.format().bits_per_sample, 16);
+
+        // read in small pieces
+        let mut samples = [0; 4];
+        assert_eq!(wav.read(&mut samples).unwrap(), 4);
+        assert_eq!(samples, [0, 32767, -32768, 1000]);
+        assert_eq!(wav.read(&mut samples).unwrap(), 2);
+        assert_eq!(samples[..2], [-1000, 5]);
+        assert_eq!(wav.remaining(), 0);
+    }
+
+    #[test]
+    fn truncated_data_ends_early() {
+        // the data chunk claims 6 samples, but the last one and a half are gone
+        let cut = &MONO16[..MONO16.len() - 3];
+        let mut wav = Wav::open(cut).unwrap();
+        let mut samples = [0; 8];
+        assert_eq!(wav.read(&mut samples).unwrap(), 4);
+        assert_eq!(wav.read(&mut samples).unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let midi: &[u8] = include_bytes!("../fixtures/format0.mid");
+        assert_eq!(Wav::open(midi).err(), Some(Error::NotWav));
+        assert_eq!(Wav::open(&MONO8[..20]).err(), Some(Error::Truncated));
+
+        // stereo
+        let mut stereo = [0u8; 44];
+        stereo.copy_from_slice(&MONO8[..44]);
+        stereo[22] = 2;
+        assert_eq!(Wav::open(&stereo[..]).err(), Some(Error::Unsupported));
+
+        // 24 bits per sample
+        let mut wide = [0u8; 44];
+        wide.copy_from_slice(&MONO8[..44]);
+        wide[34] = 24;
+        assert_eq!(Wav::open(&wide[..]).err(), Some(Error::Unsupported));
+    }
+}
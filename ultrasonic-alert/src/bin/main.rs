@@ -6,10 +6,18 @@
     holding buffers for the duration of a data transfer."
 )]
 
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
 use esp_hal::clock::CpuClock;
 use esp_hal::gpio::{Input, InputConfig, Level, Output, OutputConfig, Pull};
-use esp_hal::main;
-use esp_hal::time::{Duration, Instant};
+use esp_hal::time;
+use esp_hal::timer::timg::TimerGroup;
+
+use buzzer_effects::buzzer::{self, ActiveBuzzer};
+use buzzer_effects::effect::{self, Effect, Repeat};
+
+// Beep fast while something is in range
+const TOO_CLOSE: Effect = effect::WARBLE.repeat(Repeat::Forever);
 
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
@@ -20,36 +28,42 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
 esp_bootloader_esp_idf::esp_app_desc!();
 
-#[main]
-fn main() -> ! {
+#[esp_rtos::main]
+async fn main(spawner: Spawner) -> ! {
     // generator version: 1.0.0
 
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
 
-    let mut buzzer = Output::new(peripherals.GPIO33, Level::Low, OutputConfig::default());
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    esp_rtos::start(timg0.timer0);
+
+    let buzzer = Output::new(peripherals.GPIO33, Level::Low, OutputConfig::default());
+    spawner.must_spawn(buzzer::active_buzzer_task(ActiveBuzzer(buzzer)));
 
     // For HC-SR04 Ultrasonic
     let mut trig = Output::new(peripherals.GPIO5, Level::Low, OutputConfig::default());
-    let echo = Input::new(
+    let mut echo = Input::new(
         peripherals.GPIO18,
         InputConfig::default().with_pull(Pull::Down),
     );
 
+    let mut alerting = false;
     loop {
-        blocking_delay(Duration::from_millis(5));
+        Timer::after(Duration::from_millis(5)).await;
 
         // Trigger ultrasonic waves
         trig.set_low();
-        blocking_delay(Duration::from_micros(2));
+        blocking_delay(time::Duration::from_micros(2));
         trig.set_high();
-        blocking_delay(Duration::from_micros(10));
+        blocking_delay(time::Duration::from_micros(10));
         trig.set_low();
 
-        // Measure the duration the signal remains high
-        while echo.is_low() {}
-        let time1 = Instant::now();
-        while echo.is_high() {}
+        // Measure the duration the signal remains high; waiting asynchronously
+        // lets the buzzer task keep its rhythm
+        echo.wait_for_high().await;
+        let time1 = time::Instant::now();
+        echo.wait_for_low().await;
         let pulse_width = time1.elapsed().as_micros();
 
         // Derive distance from the pulse width
@@ -57,17 +71,22 @@ fn main() -> ! {
         // esp_println::println!("Pulse Width: {}", pulse_width);
         // esp_println::println!("Distance: {}", distance);
 
-        if distance < 30.0 {
-            buzzer.set_high();
-        } else {
-            buzzer.set_low();
+        // Only tell the buzzer task when the state changes, a new request
+        // would restart the effect
+        let close = distance < 30.0;
+        if close && !alerting {
+            buzzer::play(TOO_CLOSE);
+        } else if !close && alerting {
+            buzzer::stop();
         }
+        alerting = close;
 
-        blocking_delay(Duration::from_millis(60));
+        Timer::after(Duration::from_millis(60)).await;
     }
 }
 
-fn blocking_delay(duration: Duration) {
-    let delay_start = Instant::now();
+// Busy wait for the few microseconds of the trigger pulse
+fn blocking_delay(duration: time::Duration) {
+    let delay_start = time::Instant::now();
     while delay_start.elapsed() < duration {}
 }
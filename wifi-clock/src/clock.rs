@@ -0,0 +1,118 @@
+//! The RTC, set over the network.
+//!
+//! ```ignore
+//! let clock = &*mk_static!(Clock, Clock::new(Rtc::new(peripherals.LPWR)));
+//! spawner.spawn(clock::sync_clock(stack, clock)).ok();
+//! let volume_mgr = VolumeManager::new(sdcard, SdTimeSource::new(clock));
+//! ```
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_net::Stack;
+use embassy_time::{Duration, Timer};
+use embedded_sdmmc::{TimeSource, Timestamp};
+use esp_hal::rtc_cntl::Rtc;
+use esp_println::println;
+
+use crate::sntp;
+use crate::wifi::wait_for_connection;
+
+// Where the clock is set from
+const NTP_SERVER: &str = "pool.ntp.org";
+// Time between two syncs; the RTC drifts by seconds a day
+const SYNC_PERIOD: Duration = Duration::from_secs(60 * 60);
+// Time before trying again after a failed sync
+const RETRY_PERIOD: Duration = Duration::from_secs(30);
+
+static TZ: jiff::tz::TimeZone = jiff::tz::get!("America/New_York");
+
+/// The RTC, set over the network. Until the first sync it only counts from
+/// boot.
+pub struct Clock {
+    rtc: Rtc<'static>,
+    synced: AtomicBool,
+}
+
+impl Clock {
+    pub fn new(rtc: Rtc<'static>) -> Self {
+        Self {
+            rtc,
+            synced: AtomicBool::new(false),
+        }
+    }
+
+    /// Unix time in microseconds, once the clock is set
+    pub fn now_us(&self) -> Option<u64> {
+        self.synced
+            .load(Ordering::Relaxed)
+            .then(|| self.rtc.current_time_us())
+    }
+
+    fn adjust(&self, offset_us: i64) {
+        let now_us = self.rtc.current_time_us();
+        self.rtc
+            .set_current_time_us(now_us.saturating_add_signed(offset_us));
+        self.synced.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Timestamps for the files on the card, in local time
+pub struct SdTimeSource {
+    clock: &'static Clock,
+}
+
+impl SdTimeSource {
+    pub fn new(clock: &'static Clock) -> Self {
+        Self { clock }
+    }
+}
+
+impl TimeSource for SdTimeSource {
+    fn get_timestamp(&self) -> Timestamp {
+        // The earliest date FAT can hold, until the clock is set
+        let Some(now_us) = self.clock.now_us() else {
+            return Timestamp {
+                year_since_1970: 10,
+                zero_indexed_month: 0,
+                zero_indexed_day: 0,
+                hours: 0,
+                minutes: 0,
+                seconds: 0,
+            };
+        };
+
+        // Convert to jiff Time
+        let now = jiff::Timestamp::from_microsecond(now_us as i64).unwrap();
+        let now = now.to_zoned(TZ.clone());
+
+        Timestamp {
+            year_since_1970: (now.year() - 1970).unsigned_abs() as u8,
+            zero_indexed_month: now.month().wrapping_sub(1) as u8,
+            zero_indexed_day: now.day().wrapping_sub(1) as u8,
+            hours: now.hour() as u8,
+            minutes: now.minute() as u8,
+            seconds: now.second() as u8,
+        }
+    }
+}
+
+/// Sets `clock` from a time server once the network is up, and again every
+/// hour
+#[embassy_executor::task]
+pub async fn sync_clock(stack: Stack<'static>, clock: &'static Clock) {
+    wait_for_connection(stack).await;
+    loop {
+        let period = match sntp::query(stack, NTP_SERVER, || clock.rtc.current_time_us()).await {
+            Ok(offset_us) => {
+                println!("Clock set, it was off by {} ms", offset_us / 1000);
+                clock.adjust(offset_us);
+                SYNC_PERIOD
+            }
+            Err(e) => {
+                println!("Can't get the time from {}: {:?}", NTP_SERVER, e);
+                RETRY_PERIOD
+            }
+        };
+        Timer::after(period).await;
+    }
+}
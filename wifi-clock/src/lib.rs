@@ -0,0 +1,10 @@
+//! Network time for the SD card projects.
+//!
+//! - [`wifi`]: tasks joining a WiFi network and running the network stack
+//! - [`sntp`]: asks a time server how far our clock is off
+//! - [`clock`]: the RTC, kept set over SNTP, and the FAT timestamps read
+//!   from it
+#![no_std]
+pub mod clock;
+pub mod sntp;
+pub mod wifi;
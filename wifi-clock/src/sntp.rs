@@ -0,0 +1,60 @@
+//! Network time over SNTP (RFC 4330).
+//!
+//! The packets are built and checked by the host-tested `sntp-packet`
+//! crate, re-exported here. [`query`] does the whole exchange over an
+//! embassy-net UDP socket:
+//!
+//! ```ignore
+//! let offset = sntp::query(stack, "pool.ntp.org", || rtc.current_time_us()).await?;
+//! rtc.set_current_time_us(rtc.current_time_us().saturating_add_signed(offset));
+//! ```
+
+use embassy_net::dns::DnsQueryType;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{IpEndpoint, Stack};
+use embassy_time::{Duration, with_timeout};
+
+pub use sntp_packet::{Error, PACKET_LEN, PORT, Reply, request};
+
+/// How long to wait for the reply
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Ask `server`, a name or an address, for the time, and return what to
+/// add to the clock `now_us` reads, in microseconds
+pub async fn query(stack: Stack<'_>, server: &str, now_us: impl Fn() -> u64) -> Result<i64, Error> {
+    let address = *stack
+        .dns_query(server, DnsQueryType::A)
+        .await
+        .map_err(|_| Error::Dns)?
+        .first()
+        .ok_or(Error::Dns)?;
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0; PACKET_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0; PACKET_LEN];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(0).map_err(|_| Error::Network)?;
+
+    let sent_us = now_us();
+    let request = request(sent_us);
+    socket
+        .send_to(&request, IpEndpoint::new(address, PORT))
+        .await
+        .map_err(|_| Error::Network)?;
+
+    let mut packet = [0; PACKET_LEN];
+    let (len, _) = with_timeout(TIMEOUT, socket.recv_from(&mut packet))
+        .await
+        .map_err(|_| Error::Timeout)?
+        .map_err(|_| Error::Invalid)?;
+    let received_us = now_us();
+
+    Reply::parse(&packet[..len], &request).map(|reply| reply.offset(sent_us, received_us))
+}